@@ -0,0 +1,68 @@
+//! The Claude CLI, driven through `--print` and stream-json
+
+use shared::ClaudeStreamMessage;
+use std::process::Command;
+
+use super::{AgentBackend, AgentSession};
+use crate::config::ClaudeSettings;
+
+pub struct ClaudeBackend {
+    path: String,
+    settings: ClaudeSettings,
+}
+
+impl ClaudeBackend {
+    pub fn new(path: &str, settings: ClaudeSettings) -> Self {
+        Self {
+            path: path.to_string(),
+            settings,
+        }
+    }
+
+    /// `--print` plus the pane's settings. The settings go before the session
+    /// flags so the tool lists can't swallow a trailing prompt.
+    fn command(&self, session: &AgentSession, stream_input: bool) -> Command {
+        let mut command = Command::new(&self.path);
+        command.arg("--print");
+        if stream_input {
+            command.args(["--input-format", "stream-json"]);
+        }
+        command
+            .args(["--output-format", "stream-json", "--verbose", "--dangerously-skip-permissions"])
+            .args(self.settings.args(session.model))
+            .args([if session.resume { "--resume" } else { "--session-id" }, &session.id.to_string()]);
+        command
+    }
+}
+
+impl AgentBackend for ClaudeBackend {
+    fn name(&self) -> &str {
+        "claude"
+    }
+
+    fn iteration_command(&self, session: &AgentSession, prompt: &str) -> Command {
+        let mut command = self.command(session, false);
+        command.arg(prompt);
+        command
+    }
+
+    fn conversation_command(&self, session: &AgentSession) -> Command {
+        self.command(session, true)
+    }
+
+    fn encode_message(&self, text: &str) -> String {
+        serde_json::json!({
+            "type": "user",
+            "message": { "role": "user", "content": [{ "type": "text", "text": text }] },
+        })
+        .to_string()
+    }
+
+    fn parse_event(&self, line: &str) -> Option<ClaudeStreamMessage> {
+        serde_json::from_str(line).ok()
+    }
+
+    fn is_missing_session(&self, stderr: &str) -> bool {
+        stderr.contains("No conversation found with session ID")
+    }
+}
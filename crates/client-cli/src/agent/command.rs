@@ -0,0 +1,269 @@
+//! A generic agent: any program that speaks a small NDJSON protocol
+//!
+//! For a deadloop iteration the program is run as `program args… <prompt>`
+//! and should exit when the iteration is done. For the interactive pane it
+//! is run once with `APAS_CONVERSATION=1` and gets one
+//! `{"type":"user","text":"…"}` line on stdin per message. Either way the
+//! environment carries `APAS_SESSION_ID`, `APAS_RESUME=1` when the session
+//! is being continued, and `APAS_MODEL` when one was picked in the web UI.
+//!
+//! Each stdout line that is one of these events is shown as such; any other
+//! line is shown as plain output:
+//!
+//! ```text
+//! {"type":"start","model":"my-agent"}
+//! {"type":"text","text":"Looking at the failing test"}
+//! {"type":"tool_use","id":"1","name":"Bash","input":{"command":"cargo test"}}
+//! {"type":"tool_result","tool_use_id":"1","content":"ok","is_error":false}
+//! {"type":"result","text":"Fixed it","is_error":false,"cost_usd":0.0,"duration_ms":1200}
+//! ```
+
+use serde::Deserialize;
+use shared::{ClaudeAssistantMessage, ClaudeContentBlock, ClaudeStreamMessage, ClaudeUserMessage};
+use std::process::Command;
+
+use super::{AgentBackend, AgentSession};
+
+pub struct CommandBackend {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandBackend {
+    pub fn new(program: &str, args: &[String]) -> Self {
+        Self {
+            program: program.to_string(),
+            args: args.to_vec(),
+        }
+    }
+
+    fn command(&self, session: &AgentSession) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).env("APAS_SESSION_ID", session.id.to_string());
+        if session.resume {
+            command.env("APAS_RESUME", "1");
+        }
+        if let Some(model) = session.model {
+            command.env("APAS_MODEL", model);
+        }
+        command
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    Start {
+        #[serde(default)]
+        model: String,
+    },
+    Text {
+        text: String,
+    },
+    ToolUse {
+        #[serde(default)]
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    ToolResult {
+        #[serde(default)]
+        tool_use_id: String,
+        #[serde(default)]
+        content: String,
+        #[serde(default)]
+        is_error: bool,
+    },
+    Result {
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        is_error: bool,
+        #[serde(default)]
+        cost_usd: f64,
+        #[serde(default)]
+        duration_ms: u64,
+    },
+}
+
+impl Event {
+    fn into_stream_message(self) -> ClaudeStreamMessage {
+        // The process has no Claude session of its own to report
+        let session_id = String::new();
+        let extra = serde_json::json!({});
+        let assistant = |block| ClaudeStreamMessage::Assistant {
+            message: ClaudeAssistantMessage {
+                content: vec![block],
+                model: String::new(),
+                extra: serde_json::json!({}),
+            },
+            session_id: String::new(),
+            extra: serde_json::json!({}),
+        };
+        match self {
+            Event::Start { model } => ClaudeStreamMessage::System {
+                subtype: "init".to_string(),
+                session_id,
+                tools: Vec::new(),
+                model,
+                cwd: None,
+                extra,
+            },
+            Event::Text { text } => assistant(ClaudeContentBlock::Text { text }),
+            Event::ToolUse { id, name, input } => assistant(ClaudeContentBlock::ToolUse { id, name, input }),
+            Event::ToolResult { tool_use_id, content, is_error } => ClaudeStreamMessage::User {
+                message: ClaudeUserMessage {
+                    content: vec![ClaudeContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                        blob: None,
+                    }],
+                    role: "user".to_string(),
+                },
+                session_id,
+                tool_use_result: None,
+                extra,
+            },
+            Event::Result { text, is_error, cost_usd, duration_ms } => ClaudeStreamMessage::Result {
+                subtype: if is_error { "error" } else { "success" }.to_string(),
+                result: text,
+                total_cost_usd: cost_usd,
+                duration_ms,
+                session_id,
+                is_error,
+                extra,
+            },
+        }
+    }
+}
+
+impl AgentBackend for CommandBackend {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn iteration_command(&self, session: &AgentSession, prompt: &str) -> Command {
+        let mut command = self.command(session);
+        command.arg(prompt);
+        command
+    }
+
+    fn conversation_command(&self, session: &AgentSession) -> Command {
+        let mut command = self.command(session);
+        command.env("APAS_CONVERSATION", "1");
+        command
+    }
+
+    fn encode_message(&self, text: &str) -> String {
+        serde_json::json!({ "type": "user", "text": text }).to_string()
+    }
+
+    fn parse_event(&self, line: &str) -> Option<ClaudeStreamMessage> {
+        serde_json::from_str::<Event>(line).ok().map(Event::into_stream_message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn parse(line: &str) -> Option<ClaudeStreamMessage> {
+        CommandBackend::new("agent", &[]).parse_event(line)
+    }
+
+    fn only_block(message: Option<ClaudeStreamMessage>) -> ClaudeContentBlock {
+        match message {
+            Some(ClaudeStreamMessage::Assistant { message, .. }) => message.content.into_iter().next().unwrap(),
+            Some(ClaudeStreamMessage::User { message, .. }) => message.content.into_iter().next().unwrap(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_events_map_to_stream_messages() {
+        let start = parse(r#"{"type":"start","model":"my-agent"}"#);
+        assert!(matches!(start, Some(ClaudeStreamMessage::System { subtype, model, .. }) if subtype == "init" && model == "my-agent"));
+
+        let text = only_block(parse(r#"{"type":"text","text":"Looking at the failing test"}"#));
+        assert!(matches!(text, ClaudeContentBlock::Text { text } if text == "Looking at the failing test"));
+
+        let tool_use = only_block(parse(r#"{"type":"tool_use","id":"1","name":"Bash","input":{"command":"cargo test"}}"#));
+        let ClaudeContentBlock::ToolUse { id, name, input } = tool_use else { panic!("unexpected {:?}", tool_use) };
+        assert_eq!((id.as_str(), name.as_str()), ("1", "Bash"));
+        assert_eq!(input["command"], "cargo test");
+
+        let tool_result = only_block(parse(r#"{"type":"tool_result","tool_use_id":"1","content":"ok","is_error":true}"#));
+        assert!(matches!(
+            tool_result,
+            ClaudeContentBlock::ToolResult { tool_use_id, content, is_error: true, blob: None } if tool_use_id == "1" && content == "ok"
+        ));
+
+        let result = parse(r#"{"type":"result","text":"Fixed it","is_error":false,"cost_usd":0.5,"duration_ms":1200}"#);
+        let Some(ClaudeStreamMessage::Result { subtype, result, total_cost_usd, duration_ms, is_error, .. }) = result else {
+            panic!("unexpected {:?}", result)
+        };
+        assert_eq!((subtype.as_str(), result.as_str(), is_error), ("success", "Fixed it", false));
+        assert_eq!((total_cost_usd, duration_ms), (0.5, 1200));
+    }
+
+    #[test]
+    fn test_optional_fields_default() {
+        let start = parse(r#"{"type":"start"}"#);
+        assert!(matches!(start, Some(ClaudeStreamMessage::System { model, .. }) if model.is_empty()));
+
+        let tool_use = only_block(parse(r#"{"type":"tool_use","name":"Read"}"#));
+        assert!(matches!(tool_use, ClaudeContentBlock::ToolUse { id, input, .. } if id.is_empty() && input.is_null()));
+
+        let tool_result = only_block(parse(r#"{"type":"tool_result"}"#));
+        assert!(matches!(tool_result, ClaudeContentBlock::ToolResult { content, is_error: false, .. } if content.is_empty()));
+
+        let result = parse(r#"{"type":"result","is_error":true}"#);
+        assert!(matches!(
+            result,
+            Some(ClaudeStreamMessage::Result { subtype, result, is_error: true, duration_ms: 0, .. }) if subtype == "error" && result.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_malformed_lines_are_not_events() {
+        for line in [
+            "",
+            "Compiling apas v0.1.0",
+            "{not json",
+            r#"{"text":"no type"}"#,
+            r#"{"type":"thinking","text":"unknown type"}"#,
+            r#"{"type":"text"}"#,
+            r#"{"type":"tool_use","id":"1"}"#,
+            r#"{"type":"result","cost_usd":"free"}"#,
+            r#"["type","text"]"#,
+        ] {
+            assert!(parse(line).is_none(), "{:?} parsed as an event", line);
+        }
+    }
+
+    #[test]
+    fn test_commands_carry_the_session() {
+        let backend = CommandBackend::new("agent", &["--fast".to_string()]);
+        let env = |command: &Command, key: &str| {
+            command.get_envs().find(|(k, _)| *k == key).and_then(|(_, v)| v).map(|v| v.to_string_lossy().to_string())
+        };
+
+        let id = Uuid::new_v4();
+        let fresh = backend.iteration_command(&AgentSession { id, resume: false, model: None }, "do it");
+        assert_eq!(fresh.get_args().collect::<Vec<_>>(), ["--fast", "do it"]);
+        assert_eq!(env(&fresh, "APAS_SESSION_ID"), Some(id.to_string()));
+        assert_eq!((env(&fresh, "APAS_RESUME"), env(&fresh, "APAS_MODEL")), (None, None));
+
+        let resumed = backend.conversation_command(&AgentSession { id, resume: true, model: Some("big") });
+        assert_eq!(resumed.get_args().collect::<Vec<_>>(), ["--fast"]);
+        assert_eq!(env(&resumed, "APAS_RESUME").as_deref(), Some("1"));
+        assert_eq!(env(&resumed, "APAS_MODEL").as_deref(), Some("big"));
+        assert_eq!(env(&resumed, "APAS_CONVERSATION").as_deref(), Some("1"));
+
+        let message: serde_json::Value = serde_json::from_str(&backend.encode_message("hi \"there\"")).unwrap();
+        assert_eq!(message, serde_json::json!({ "type": "user", "text": "hi \"there\"" }));
+    }
+}
@@ -0,0 +1,174 @@
+//! Coding agents the panes can drive
+//!
+//! A backend knows how to start its agent for one deadloop iteration or as a
+//! long-lived conversation, how to hand it a user message and how to read
+//! its output as stream events. Everything else (process groups and
+//! interrupts, the watchdog, the TUI and the web dashboard) works the same
+//! for every backend. Events are `ClaudeStreamMessage`s because that is what
+//! the server and web UI already understand.
+
+mod claude;
+mod command;
+
+use serde::{Deserialize, Serialize};
+use shared::ClaudeStreamMessage;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::ClaudeSettings;
+use crate::signal::{self, Signal};
+
+pub use claude::ClaudeBackend;
+pub use command::CommandBackend;
+
+/// Which coding agent the panes drive
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum AgentConfig {
+    /// The Claude CLI at `claude_path`
+    #[default]
+    Claude,
+    /// Any program that speaks the NDJSON event protocol in `command.rs`
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// The agent session a process works on
+pub struct AgentSession<'a> {
+    pub id: Uuid,
+    /// Continue the session rather than start it
+    pub resume: bool,
+    /// Model picked in the web UI, overriding the configured one
+    pub model: Option<&'a str>,
+}
+
+pub trait AgentBackend: Send + Sync {
+    /// Short name for status lines, e.g. "claude"
+    fn name(&self) -> &str;
+
+    /// Command that runs `prompt` as one deadloop iteration and exits
+    fn iteration_command(&self, session: &AgentSession, prompt: &str) -> Command;
+
+    /// Command for a long-lived process that takes user messages on stdin
+    fn conversation_command(&self, session: &AgentSession) -> Command;
+
+    /// The stdin line that carries one user message to a conversation
+    fn encode_message(&self, text: &str) -> String;
+
+    /// The event on one stdout line, or None for plain output
+    fn parse_event(&self, line: &str) -> Option<ClaudeStreamMessage>;
+
+    /// Whether the agent's stderr says the session it was asked to resume
+    /// doesn't exist (deleted, or created on another machine)
+    fn is_missing_session(&self, _stderr: &str) -> bool {
+        false
+    }
+}
+
+/// Backend for `config`. `settings` only apply to Claude.
+pub fn backend(config: &AgentConfig, claude_path: &str, settings: ClaudeSettings) -> Arc<dyn AgentBackend> {
+    match config {
+        AgentConfig::Claude => Arc::new(ClaudeBackend::new(claude_path, settings)),
+        AgentConfig::Command { program, args } => Arc::new(CommandBackend::new(program, args)),
+    }
+}
+
+/// Set up `command` to run in `working_dir` with piped output, leading its
+/// own process group so signals reach whatever the agent started too
+pub fn prepare(command: &mut Command, working_dir: &str, stdin: Stdio) {
+    command
+        .current_dir(working_dir)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    signal::new_process_group(command);
+}
+
+/// A long-lived conversation process, so a conversation doesn't pay for a
+/// new process (and a context reload) on every message and can take
+/// follow-ups mid-turn
+pub struct AgentProcess {
+    backend: Arc<dyn AgentBackend>,
+    child: std::process::Child,
+    stdin: std::process::ChildStdin,
+    lines: mpsc::Receiver<String>,
+    /// Disconnects once all of stderr has gone to `on_stderr`
+    stderr_done: mpsc::Receiver<()>,
+}
+
+impl AgentProcess {
+    /// Start the conversation; stderr lines go to `on_stderr`
+    pub fn spawn(
+        backend: Arc<dyn AgentBackend>,
+        session: &AgentSession,
+        working_dir: &str,
+        mut on_stderr: impl FnMut(String) + Send + 'static,
+    ) -> std::io::Result<Self> {
+        use std::io::BufRead;
+
+        let mut command = backend.conversation_command(session);
+        prepare(&mut command, working_dir, Stdio::piped());
+        let mut child = command.spawn()?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        // The channel disconnects when stdout closes, i.e. the process is gone
+        let (lines_tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::BufReader::new(stdout).lines().map_while(Result::ok) {
+                if lines_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let (stderr_done_tx, stderr_done) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let _done = stderr_done_tx;
+            for line in std::io::BufReader::new(stderr).lines().map_while(Result::ok) {
+                on_stderr(line);
+            }
+        });
+
+        Ok(Self { backend, child, stdin, lines, stderr_done })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Start a user turn, or queue a follow-up if one is running
+    pub fn send_user_message(&mut self, text: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        writeln!(self.stdin, "{}", self.backend.encode_message(text))?;
+        self.stdin.flush()
+    }
+
+    /// Next stdout line if one is ready. `Disconnected` means the process
+    /// exited.
+    pub fn try_next_line(&self) -> Result<String, mpsc::TryRecvError> {
+        self.lines.try_recv()
+    }
+
+    /// Wait up to `limit` for the rest of stderr to reach `on_stderr`, so
+    /// it can be checked after the process exits. Something the process
+    /// left behind may hold stderr open, hence the limit.
+    pub fn finish_stderr(&self, limit: std::time::Duration) {
+        let _ = self.stderr_done.recv_timeout(limit);
+    }
+}
+
+impl Drop for AgentProcess {
+    fn drop(&mut self) {
+        let _ = signal::signal_group(self.child.id(), Signal::Kill);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,80 @@
+//! Spending limit for the deadloop
+//!
+//! The `budget` section of `.apas` caps what the deadloop may spend, as
+//! reported in each iteration's result. Once the total reaches the cap the
+//! loop pauses; resuming it from the web UI allows another full budget.
+
+use serde::{Deserialize, Serialize};
+use shared::ClaudeStreamMessage;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Budget {
+    /// Pause once the deadloop has spent this many US dollars
+    pub max_cost_usd: Option<f64>,
+}
+
+/// Running total of what the deadloop has spent since it started or resumed
+pub struct Spend {
+    limit: Option<f64>,
+    total: f64,
+}
+
+impl Spend {
+    pub fn new(budget: &Budget) -> Self {
+        Self { limit: budget.max_cost_usd, total: 0.0 }
+    }
+
+    /// Feed one stream message from the running iteration
+    pub fn observe(&mut self, message: &ClaudeStreamMessage) {
+        if let ClaudeStreamMessage::Result { total_cost_usd, .. } = message {
+            self.total += total_cost_usd;
+        }
+    }
+
+    /// Why the loop should pause, once the budget is used up
+    pub fn exceeded(&self) -> Option<String> {
+        self.limit
+            .filter(|limit| self.total >= *limit)
+            .map(|limit| format!("spent ${:.2} of ${:.2}", self.total, limit))
+    }
+
+    /// Start a fresh budget, e.g. after the user resumes a paused loop
+    pub fn reset(&mut self) {
+        self.total = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(cost: f64) -> ClaudeStreamMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "result",
+            "subtype": "success",
+            "total_cost_usd": cost,
+            "session_id": "s",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_spend_pauses_at_the_limit() {
+        let mut spend = Spend::new(&Budget { max_cost_usd: Some(1.0) });
+        spend.observe(&result(0.6));
+        assert_eq!(spend.exceeded(), None);
+        spend.observe(&result(0.5));
+        assert_eq!(spend.exceeded().as_deref(), Some("spent $1.10 of $1.00"));
+
+        spend.reset();
+        assert_eq!(spend.exceeded(), None);
+    }
+
+    #[test]
+    fn test_no_limit_by_default() {
+        let mut spend = Spend::new(&Budget::default());
+        spend.observe(&result(1000.0));
+        assert_eq!(spend.exceeded(), None);
+    }
+}
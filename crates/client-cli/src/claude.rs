@@ -5,6 +5,8 @@ use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, ChildStdin, Command};
 use tokio::sync::mpsc;
 
+use crate::signal::{signal_group, Signal};
+
 pub struct ClaudeProcess {
     child: Child,
     stdin: ChildStdin,
@@ -16,12 +18,16 @@ impl ClaudeProcess {
         claude_path: &str,
         working_dir: &Path,
     ) -> Result<(Self, mpsc::Receiver<String>, mpsc::Receiver<String>)> {
-        let mut child = Command::new(claude_path)
+        let mut command = Command::new(claude_path);
+        command
             .current_dir(working_dir)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+            .stderr(Stdio::piped());
+        // Own process group, so signals reach the tools Claude runs too
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command.spawn()?;
 
         let stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("Failed to get stdin"))?;
         let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("Failed to get stdout"))?;
@@ -65,17 +71,15 @@ impl ClaudeProcess {
         Ok(())
     }
 
-    /// Send a signal to the process (currently only supports kill on all platforms)
+    /// Send a signal such as "SIGINT" to the process and everything it started
     pub fn send_signal(&mut self, signal: &str) -> Result<()> {
-        match signal {
-            "SIGINT" | "SIGTERM" => {
-                // On all platforms, we'll just start kill which sends SIGKILL
-                // For a gentler approach, we'd need platform-specific code
-                tracing::debug!("Signal {} requested, process will be killed", signal);
-                // Note: actual signal handling is done in kill() method
-            }
-            _ => return Err(anyhow::anyhow!("Unknown signal: {}", signal)),
-        }
+        let parsed = Signal::parse(signal).ok_or_else(|| anyhow::anyhow!("Unknown signal: {}", signal))?;
+        // No pid once the process has been reaped
+        let Some(pid) = self.child.id() else {
+            return Ok(());
+        };
+        signal_group(pid, parsed)?;
+        tracing::debug!("Sent {} to Claude process group {}", signal, pid);
         Ok(())
     }
 
@@ -83,15 +87,4 @@ impl ClaudeProcess {
     pub async fn wait(&mut self) -> Result<std::process::ExitStatus> {
         Ok(self.child.wait().await?)
     }
-
-    /// Check if the process is still running
-    pub fn try_wait(&mut self) -> Result<Option<std::process::ExitStatus>> {
-        Ok(self.child.try_wait()?)
-    }
-
-    /// Kill the process
-    pub async fn kill(&mut self) -> Result<()> {
-        self.child.kill().await?;
-        Ok(())
-    }
 }
@@ -3,6 +3,8 @@ use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::agent::AgentConfig;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
@@ -15,18 +17,111 @@ pub struct Config {
 pub struct RemoteConfig {
     pub server: Option<String>,
     pub token: Option<String>,
+    /// Display name for this machine in the web UI (defaults to the hostname)
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalConfig {
     pub claude_path: String,
+    /// Claude settings for this machine; a project's `.apas` overrides them
+    #[serde(default)]
+    pub claude: PaneSettings,
+    /// Agent the panes drive (Claude unless set); a project's `.apas` overrides it
+    #[serde(default)]
+    pub agent: Option<AgentConfig>,
 }
 
 impl Default for LocalConfig {
     fn default() -> Self {
         Self {
             claude_path: "claude".to_string(),
+            claude: PaneSettings::default(),
+            agent: None,
+        }
+    }
+}
+
+/// Claude settings for each dual-pane session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaneSettings {
+    /// The deadloop pane (and hybrid mode)
+    pub deadloop: ClaudeSettings,
+    pub interactive: ClaudeSettings,
+}
+
+impl PaneSettings {
+    /// `self` with anything unset filled in from `fallback`
+    pub fn or(&self, fallback: &PaneSettings) -> PaneSettings {
+        PaneSettings {
+            deadloop: self.deadloop.or(&fallback.deadloop),
+            interactive: self.interactive.or(&fallback.interactive),
+        }
+    }
+}
+
+/// How Claude is invoked. Unset fields leave Claude's own defaults alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClaudeSettings {
+    pub model: Option<String>,
+    pub max_turns: Option<u32>,
+    pub allowed_tools: Vec<String>,
+    pub disallowed_tools: Vec<String>,
+    pub append_system_prompt: Option<String>,
+    /// Path to an MCP server config file
+    pub mcp_config: Option<String>,
+    /// Passed to Claude as-is
+    pub extra_args: Vec<String>,
+}
+
+impl ClaudeSettings {
+    /// `self` with anything unset filled in from `fallback`. Lists are
+    /// replaced, not merged.
+    pub fn or(&self, fallback: &ClaudeSettings) -> ClaudeSettings {
+        fn list(a: &[String], b: &[String]) -> Vec<String> {
+            if a.is_empty() { b.to_vec() } else { a.to_vec() }
+        }
+        ClaudeSettings {
+            model: self.model.clone().or_else(|| fallback.model.clone()),
+            max_turns: self.max_turns.or(fallback.max_turns),
+            allowed_tools: list(&self.allowed_tools, &fallback.allowed_tools),
+            disallowed_tools: list(&self.disallowed_tools, &fallback.disallowed_tools),
+            append_system_prompt: self.append_system_prompt.clone().or_else(|| fallback.append_system_prompt.clone()),
+            mcp_config: self.mcp_config.clone().or_else(|| fallback.mcp_config.clone()),
+            extra_args: list(&self.extra_args, &fallback.extra_args),
+        }
+    }
+
+    /// Command-line arguments for these settings, with `model` (if any)
+    /// taking the place of the configured one. They go before the session
+    /// flags so the tool lists can't swallow a trailing prompt.
+    pub fn args(&self, model: Option<&str>) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(model) = model.or(self.model.as_deref()) {
+            args.extend(["--model".to_string(), model.to_string()]);
+        }
+        if let Some(max_turns) = self.max_turns {
+            args.extend(["--max-turns".to_string(), max_turns.to_string()]);
+        }
+        if !self.allowed_tools.is_empty() {
+            args.push("--allowedTools".to_string());
+            args.extend(self.allowed_tools.iter().cloned());
+        }
+        if !self.disallowed_tools.is_empty() {
+            args.push("--disallowedTools".to_string());
+            args.extend(self.disallowed_tools.iter().cloned());
+        }
+        if let Some(prompt) = &self.append_system_prompt {
+            args.extend(["--append-system-prompt".to_string(), prompt.clone()]);
+        }
+        if let Some(path) = &self.mcp_config {
+            args.extend(["--mcp-config".to_string(), path.clone()]);
         }
+        args.extend(self.extra_args.iter().cloned());
+        args
     }
 }
 
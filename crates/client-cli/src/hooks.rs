@@ -0,0 +1,91 @@
+//! Shell hooks around deadloop iterations
+//!
+//! `.apas` can name commands to run before and after each iteration, when one
+//! fails, and when the loop pauses: refreshing caches, starting a local
+//! database, uploading artifacts. They run in the project directory with
+//! `APAS_*` variables describing the session, iteration and outcome.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use crate::shell::{self, ShellOutcome};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Before Claude starts; if it fails the iteration is skipped and counts
+    /// as failed (`on_error` runs, and it counts towards stop conditions and
+    /// stuck detection) before the usual backoff
+    pub pre_iteration: Option<String>,
+    /// After every iteration, failed or not
+    pub post_iteration: Option<String>,
+    /// After an iteration fails (after `post_iteration`)
+    pub on_error: Option<String>,
+    /// When the loop pauses, whether from the web UI or because it looks stuck
+    pub on_pause: Option<String>,
+    /// Kill a hook after this long; 0 means no limit
+    pub timeout_secs: u64,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            pre_iteration: None,
+            post_iteration: None,
+            on_error: None,
+            on_pause: None,
+            timeout_secs: 10 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreIteration,
+    PostIteration,
+    OnError,
+    OnPause,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Hook::PreIteration => "pre_iteration",
+            Hook::PostIteration => "post_iteration",
+            Hook::OnError => "on_error",
+            Hook::OnPause => "on_pause",
+        })
+    }
+}
+
+impl Hooks {
+    pub fn command(&self, hook: Hook) -> Option<&str> {
+        let command = match hook {
+            Hook::PreIteration => &self.pre_iteration,
+            Hook::PostIteration => &self.post_iteration,
+            Hook::OnError => &self.on_error,
+            Hook::OnPause => &self.on_pause,
+        };
+        command.as_deref().filter(|c| !c.trim().is_empty())
+    }
+
+    /// Run `hook` if it is configured. `env` comes on top of `APAS_HOOK`.
+    pub fn run(
+        &self,
+        hook: Hook,
+        working_dir: &Path,
+        env: &[(&str, String)],
+        shutdown: &AtomicBool,
+        on_line: impl FnMut(&str),
+    ) -> Option<io::Result<ShellOutcome>> {
+        let command = self.command(hook)?;
+        let mut env = env.to_vec();
+        env.push(("APAS_HOOK", hook.to_string()));
+        let timeout = (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs));
+        Some(shell::run(command, working_dir, &env, timeout, shutdown, on_line))
+    }
+}
@@ -0,0 +1,101 @@
+//! Stable identity of this machine, reported to the server at registration so
+//! reconnects and separate `apas` processes show up as the same computer.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use shared::MachineInfo;
+use uuid::Uuid;
+
+use crate::config::Config;
+
+const MACHINE_ID_FILE: &str = "machine-id";
+
+/// Read the machine id stored at `path`, generating and saving one if missing
+fn load_or_create_id(path: &Path) -> Result<Uuid> {
+    if let Ok(text) = std::fs::read_to_string(path) {
+        if let Ok(id) = Uuid::parse_str(text.trim()) {
+            return Ok(id);
+        }
+        tracing::warn!("Ignoring malformed machine id in {}", path.display());
+    }
+
+    let id = Uuid::new_v4();
+    std::fs::write(path, format!("{}\n", id))?;
+    Ok(id)
+}
+
+fn claude_version(claude_path: &str) -> Option<String> {
+    let output = std::process::Command::new(claude_path).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!version.is_empty()).then_some(version)
+}
+
+fn detect() -> Option<MachineInfo> {
+    let config = Config::load().unwrap_or_default();
+    let id_path = match Config::config_path() {
+        Ok(path) => path.with_file_name(MACHINE_ID_FILE),
+        Err(e) => {
+            tracing::warn!("No config dir for machine id: {}", e);
+            return None;
+        }
+    };
+    let machine_id = match load_or_create_id(&id_path) {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::warn!("Failed to persist machine id at {}: {}", id_path.display(), e);
+            return None;
+        }
+    };
+
+    Some(MachineInfo {
+        machine_id,
+        name: config.remote.name,
+        hostname: hostname::get().ok().map(|h| h.to_string_lossy().to_string()),
+        os: Some(format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)),
+        claude_version: claude_version(&config.local.claude_path),
+    })
+}
+
+/// This machine's identity, detected once per process
+pub fn info() -> Option<MachineInfo> {
+    static INFO: OnceLock<Option<MachineInfo>> = OnceLock::new();
+    INFO.get_or_init(detect).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_id_is_created_once_and_reused() {
+        let path = std::env::temp_dir().join(format!("apas-machine-{}", Uuid::new_v4()));
+        let id = load_or_create_id(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("{}\n", id));
+        assert_eq!(load_or_create_id(&path).unwrap(), id);
+
+        std::fs::write(&path, "not a uuid").unwrap();
+        let replaced = load_or_create_id(&path).unwrap();
+        assert_ne!(replaced, id);
+        assert_eq!(load_or_create_id(&path).unwrap(), replaced);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_claude_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = std::env::temp_dir().join(format!("apas-claude-{}", Uuid::new_v4()));
+        std::fs::write(&script, "#!/bin/sh\necho \"  2.0.1 (Claude Code)\"\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(claude_version(script.to_str().unwrap()).as_deref(), Some("2.0.1 (Claude Code)"));
+        std::fs::remove_file(&script).unwrap();
+
+        assert_eq!(claude_version("false"), None);
+        assert_eq!(claude_version("/nonexistent/claude"), None);
+    }
+}
@@ -2,13 +2,28 @@ use anyhow::Result;
 use clap::{Parser, Subcommand};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod agent;
 mod auth;
+mod budget;
 mod config;
+mod hooks;
 mod claude;
+mod machine;
 mod mode;
 mod project;
+mod rotation;
+mod sandbox;
+mod shell;
+mod signal;
+mod stop;
+mod stuck;
+mod template;
+mod transport;
 mod tui;
 mod update;
+mod verify;
+mod watchdog;
+mod workflow;
 
 // Default server URL
 const DEFAULT_SERVER: &str = "ws://apas.mpaxos.com:8080";
@@ -69,7 +84,7 @@ enum Commands {
 enum ConfigAction {
     /// Set a configuration value
     Set {
-        /// Configuration key (server, token)
+        /// Configuration key (server, token, name, claude_path)
         key: String,
         /// Configuration value
         value: String,
@@ -225,8 +240,9 @@ async fn handle_config_command(action: ConfigAction) -> Result<()> {
             match key.as_str() {
                 "server" => config.remote.server = Some(value),
                 "token" => config.remote.token = Some(value),
+                "name" => config.remote.name = Some(value),
                 "claude_path" => config.local.claude_path = value,
-                _ => anyhow::bail!("Unknown config key: {}. Valid keys: server, token, claude_path", key),
+                _ => anyhow::bail!("Unknown config key: {}. Valid keys: server, token, name, claude_path", key),
             }
             config.save()?;
             println!("Configuration saved");
@@ -236,6 +252,7 @@ async fn handle_config_command(action: ConfigAction) -> Result<()> {
             let value = match key.as_str() {
                 "server" => config.remote.server.unwrap_or_default(),
                 "token" => config.remote.token.map(|_| "****").unwrap_or_default().to_string(),
+                "name" => config.remote.name.unwrap_or_default(),
                 "claude_path" => config.local.claude_path,
                 _ => anyhow::bail!("Unknown config key: {}", key),
             };
@@ -245,6 +262,7 @@ async fn handle_config_command(action: ConfigAction) -> Result<()> {
             let config = config::Config::load()?;
             println!("server: {}", config.remote.server.unwrap_or_default());
             println!("token: {}", config.remote.token.map(|_| "****").unwrap_or_default());
+            println!("name: {}", config.remote.name.unwrap_or_default());
             println!("claude_path: {}", config.local.claude_path);
         }
         ConfigAction::Path => {
@@ -5,10 +5,12 @@
 //! - Right pane: Interactive session for user queries
 
 use anyhow::Result;
-use shared::{CliToServer, ClaudeStreamMessage, PaneType, ServerToCli};
+use shared::transport::Compression;
+use shared::{CliToServer, ClaudeStreamMessage, PaneType, PauseReason, SandboxViolation, ServerToCli};
+use std::cell::Cell;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::Stdio;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
@@ -16,8 +18,21 @@ use std::time::{Duration, Instant};
 use tokio::sync::mpsc as tokio_mpsc;
 use uuid::Uuid;
 
-use crate::project::{get_or_create_project, save_project};
+use crate::agent::{self, AgentBackend, AgentProcess, AgentSession};
+use crate::project::{get_or_create_project, replace_claude_session_id, save_project};
+use crate::rotation::{self, ContextRotation, ContextTracker};
+use crate::sandbox::{Sandbox, SandboxMonitor};
+use crate::hooks::{Hook, Hooks};
+use crate::signal::{CurrentProcess, Signal};
+use crate::stop::{StopConditions, Stopper};
+use crate::budget::{Budget, Spend};
+use crate::stuck::{self, StuckDetection, StuckDetector};
+use crate::template::PromptContext;
+use crate::transport::send_batch;
 use crate::tui::{App, PaneOutput};
+use crate::watchdog::{self, IterationLimits, Watchdog, WatchdogAction};
+use crate::verify::Verification;
+use crate::workflow::{Stage, Workflow};
 
 const DEFAULT_PROMPT: &str = r#"Work on tasks defined in TODO.md. Do the following steps. Don't ask me for advice, just pick the best option you think that is honest, complete, and not corner-cutting:
 
@@ -45,6 +60,12 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     // Save the metadata with new session IDs if they were created
     save_project(working_dir, &metadata)?;
 
+    // The project's agent and Claude settings win over this machine's
+    let settings = metadata.claude.or(&config.local.claude);
+    let agent_config = metadata.agent.clone().or_else(|| config.local.agent.clone()).unwrap_or_default();
+    let deadloop_backend = agent::backend(&agent_config, &claude_path, settings.deadloop.clone());
+    let interactive_backend = agent::backend(&agent_config, &claude_path, settings.interactive.clone());
+
     let prompt = metadata.prompt.clone()
         .filter(|p| !p.trim().is_empty())
         .unwrap_or_else(|| DEFAULT_PROMPT.to_string());
@@ -63,13 +84,25 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     // Shutdown flag
     let shutdown = Arc::new(AtomicBool::new(false));
 
-    // Pause deadloop flag (controlled from web UI)
+    // Pause deadloop flag (controlled from web UI, or set when the loop looks stuck)
     let pause_deadloop = Arc::new(AtomicBool::new(false));
+    // Why the deadloop is paused, for the TUI status bar
+    let pause_reason: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    // Workflow stage the deadloop is in, for the TUI status bar
+    let stage_label: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
+    // Models picked from the web UI, overriding the configured ones
+    let deadloop_model: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let interactive_model: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
 
     // Shared reference to child process for cleanup
     let child_process: Arc<Mutex<Option<std::process::Child>>> = Arc::new(Mutex::new(None));
     let child_for_handler = child_process.clone();
 
+    // Process groups of the Claude processes each pane is running, for signals
+    let deadloop_process = CurrentProcess::default();
+    let interactive_process = CurrentProcess::default();
+
     // Setup Ctrl+C handler
     let shutdown_for_handler = shutdown.clone();
     ctrlc::set_handler(move || {
@@ -88,10 +121,15 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     // Spawn server connection task
     let shutdown_clone = shutdown.clone();
     let pause_clone = pause_deadloop.clone();
+    let pause_reason_clone = pause_reason.clone();
     let server_url_clone = server_url.clone();
     let token_clone = token.clone();
     let working_dir_clone = working_dir_str.clone();
     let status_output_tx = output_tx.clone();
+    let deadloop_process_clone = deadloop_process.clone();
+    let interactive_process_clone = interactive_process.clone();
+    let deadloop_model_clone = deadloop_model.clone();
+    let interactive_model_clone = interactive_model.clone();
     let server_task = tokio::spawn(async move {
         run_server_connection(
             &server_url_clone,
@@ -101,6 +139,11 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
             server_rx,
             shutdown_clone,
             pause_clone,
+            pause_reason_clone,
+            deadloop_process_clone,
+            interactive_process_clone,
+            deadloop_model_clone,
+            interactive_model_clone,
             web_input_tx,
             status_output_tx,
         )
@@ -123,21 +166,40 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     let deadloop_shutdown = shutdown.clone();
     let deadloop_pause = pause_deadloop.clone();
     let deadloop_working_dir = working_dir_str.clone();
-    let deadloop_claude_path = claude_path.clone();
     let deadloop_child = child_process.clone();
+    let deadloop_current = deadloop_process.clone();
     let deadloop_prompt = prompt.clone();
+    let deadloop_workflow = metadata.workflow.clone();
+    let deadloop_stage_label = stage_label.clone();
+    let deadloop_options = DeadloopOptions {
+        stuck_detection: metadata.stuck_detection.clone(),
+        budget: metadata.budget.clone(),
+        iteration_limits: metadata.iteration_limits.clone(),
+        verification: metadata.verify.clone(),
+        hooks: metadata.hooks.clone(),
+        stop: metadata.stop.clone(),
+        context_rotation: metadata.context_rotation.clone(),
+        sandbox: metadata.sandbox.clone(),
+    };
+    let deadloop_pause_reason = pause_reason.clone();
     let deadloop_thread = thread::spawn(move || {
         run_deadloop_session(
-            &deadloop_claude_path,
+            deadloop_backend.as_ref(),
             &deadloop_working_dir,
             session_id,
             deadloop_claude_session_id,
             &deadloop_prompt,
+            deadloop_workflow,
+            deadloop_stage_label,
             deadloop_output_tx,
             deadloop_server_tx,
             deadloop_shutdown,
             deadloop_pause,
+            deadloop_pause_reason,
             deadloop_child,
+            deadloop_current,
+            deadloop_options,
+            deadloop_model,
         )
     });
 
@@ -146,10 +208,10 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     let interactive_server_tx = server_tx.clone();
     let interactive_shutdown = shutdown.clone();
     let interactive_working_dir = working_dir_str.clone();
-    let interactive_claude_path = claude_path.clone();
+    let interactive_current = interactive_process.clone();
     let interactive_thread = thread::spawn(move || {
         run_interactive_session(
-            &interactive_claude_path,
+            interactive_backend,
             &interactive_working_dir,
             session_id,
             interactive_claude_session_id,
@@ -158,11 +220,13 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
             interactive_output_tx,
             interactive_server_tx,
             interactive_shutdown,
+            interactive_current,
+            interactive_model,
         )
     });
 
     // Run TUI in main thread
-    let mut app = App::new(input_tx, output_rx);
+    let mut app = App::new(input_tx, output_rx, pause_reason, stage_label, interactive_process);
     if let Err(e) = app.run() {
         tracing::error!("TUI error: {}", e);
     }
@@ -178,32 +242,58 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     Ok(())
 }
 
+/// How the deadloop runs, from `.apas`
+#[derive(Debug, Clone, Default)]
+struct DeadloopOptions {
+    stuck_detection: StuckDetection,
+    budget: Budget,
+    iteration_limits: IterationLimits,
+    verification: Option<Verification>,
+    hooks: Hooks,
+    stop: StopConditions,
+    context_rotation: ContextRotation,
+    sandbox: Sandbox,
+}
+
 /// Run the deadloop (autonomous) session
+#[allow(clippy::too_many_arguments)]
 fn run_deadloop_session(
-    claude_path: &str,
+    backend: &dyn AgentBackend,
     working_dir: &str,
     session_id: Uuid,
     claude_session_id: Uuid,
     prompt: &str,
+    stages: Vec<Stage>,
+    stage_label: Arc<Mutex<Option<String>>>,
     output_tx: mpsc::Sender<PaneOutput>,
     server_tx: tokio_mpsc::Sender<CliToServer>,
     shutdown: Arc<AtomicBool>,
     pause: Arc<AtomicBool>,
+    pause_reason: Arc<Mutex<Option<String>>>,
     child_process: Arc<Mutex<Option<std::process::Child>>>,
+    current_process: CurrentProcess,
+    options: DeadloopOptions,
+    model: Arc<Mutex<Option<String>>>,
 ) {
     // Wrap in panic catcher to prevent silent thread crashes
     let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         run_deadloop_session_inner(
-            claude_path,
+            backend,
             working_dir,
             session_id,
             claude_session_id,
             prompt,
+            stages,
+            stage_label,
             output_tx.clone(),
             server_tx,
             shutdown,
             pause,
+            pause_reason,
             child_process,
+            current_process,
+            options,
+            model,
         )
     }));
 
@@ -222,20 +312,27 @@ fn run_deadloop_session(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run_deadloop_session_inner(
-    claude_path: &str,
+    backend: &dyn AgentBackend,
     working_dir: &str,
     session_id: Uuid,
     claude_session_id: Uuid,
     prompt: &str,
+    stages: Vec<Stage>,
+    stage_label: Arc<Mutex<Option<String>>>,
     output_tx: mpsc::Sender<PaneOutput>,
     server_tx: tokio_mpsc::Sender<CliToServer>,
     shutdown: Arc<AtomicBool>,
     pause: Arc<AtomicBool>,
+    pause_reason: Arc<Mutex<Option<String>>>,
     child_process: Arc<Mutex<Option<std::process::Child>>>,
+    current_process: CurrentProcess,
+    options: DeadloopOptions,
+    model: Arc<Mutex<Option<String>>>,
 ) {
     let _ = output_tx.send(PaneOutput {
-        text: format!("[Deadloop session: {}]", &claude_session_id.to_string()[..8]),
+        text: format!("[Deadloop session: {} ({})]", &claude_session_id.to_string()[..8], backend.name()),
         is_deadloop: true,
     });
 
@@ -246,8 +343,95 @@ fn run_deadloop_session_inner(
     let mut last_update_check = Instant::now();
     let mut first_message = true; // Track if this is first message (use --session-id) or resume (use --resume)
     let mut was_paused = false;
+    let DeadloopOptions {
+        stuck_detection,
+        budget,
+        iteration_limits,
+        verification,
+        hooks,
+        stop,
+        context_rotation,
+        sandbox,
+    } = options;
+    // Refuse to run unsandboxed when a sandbox was asked for
+    let sandboxed = match sandbox.enabled.then(|| sandbox.backend(backend, Path::new(working_dir), session_id)) {
+        Some(Ok(sandboxed)) => Some(sandboxed),
+        Some(Err(e)) => {
+            let reason = format!("sandbox unavailable: {}", e);
+            let _ = output_tx.send(PaneOutput {
+                text: format!("[Deadloop not started, {}]", reason),
+                is_deadloop: true,
+            });
+            let _ = server_tx.try_send(CliToServer::SandboxViolation {
+                session_id,
+                kind: SandboxViolation::SetupFailed,
+                detail: e.to_string(),
+            });
+            let _ = server_tx.try_send(CliToServer::SessionEnd {
+                session_id,
+                reason,
+                pane_type: Some(PaneType::Deadloop),
+            });
+            return;
+        }
+        None => None,
+    };
+    let backend: &dyn AgentBackend = match &sandboxed {
+        Some(sandboxed) => {
+            let network = if sandbox.network { "on" } else { "off" };
+            let _ = output_tx.send(PaneOutput {
+                text: format!("[Sandboxed: writes limited to the project, network {}]", network),
+                is_deadloop: true,
+            });
+            sandboxed
+        }
+        None => backend,
+    };
+    let mut stopper = Stopper::new(stop);
+    let mut context_tracker = ContextTracker::new(context_rotation);
+    // Changes when the context is rotated
+    let claude_session_id = Cell::new(claude_session_id);
+    // Handoff summary to open the next session with
+    let mut handoff: Option<String> = None;
+    let hooks = HookRunner {
+        hooks: &hooks,
+        working_dir,
+        session_id,
+        shutdown: &shutdown,
+        output_tx: &output_tx,
+        server_tx: &server_tx,
+    };
+    // What every hook is told about the session
+    let session_env = |iteration: u32, stage: &str| {
+        vec![
+            ("APAS_SESSION_ID", session_id.to_string()),
+            ("APAS_CLAUDE_SESSION_ID", claude_session_id.get().to_string()),
+            ("APAS_ITERATION", iteration.to_string()),
+            ("APAS_STAGE", stage.to_string()),
+        ]
+    };
+    let mut stuck_detector = StuckDetector::new(stuck_detection, Path::new(working_dir));
+    let mut spend = Spend::new(&budget);
+    let mut workflow = Workflow::new(stages, prompt, Path::new(working_dir));
+    let mut context = PromptContext::default();
+    if workflow.is_staged() {
+        announce_stage(&workflow, session_id, &stage_label, &output_tx, &server_tx);
+    }
 
     while !shutdown.load(Ordering::SeqCst) {
+        if let Some(reason) = stopper.reason(Path::new(working_dir)) {
+            let _ = output_tx.send(PaneOutput {
+                text: format!("[Deadloop finished: {}]", reason),
+                is_deadloop: true,
+            });
+            let _ = server_tx.try_send(CliToServer::SessionEnd {
+                session_id,
+                reason,
+                pane_type: Some(PaneType::Deadloop),
+            });
+            break;
+        }
+
         // Check for pause before each iteration
         if pause.load(Ordering::SeqCst) {
             if !was_paused {
@@ -256,6 +440,10 @@ fn run_deadloop_session_inner(
                     text: "[Deadloop paused - waiting for resume...]".to_string(),
                     is_deadloop: true,
                 });
+                let reason = pause_reason.lock().ok().and_then(|r| r.clone()).unwrap_or_else(|| "user".to_string());
+                let mut env = session_env(iteration, &workflow.stage().name);
+                env.push(("APAS_PAUSE_REASON", reason));
+                hooks.run(Hook::OnPause, &env);
             }
             // Sleep and continue checking
             thread::sleep(Duration::from_millis(500));
@@ -263,6 +451,8 @@ fn run_deadloop_session_inner(
         } else if was_paused {
             // Just resumed
             was_paused = false;
+            stuck_detector.reset(Path::new(working_dir));
+            spend.reset();
             let _ = output_tx.send(PaneOutput {
                 text: "[Deadloop resumed]".to_string(),
                 is_deadloop: true,
@@ -275,52 +465,64 @@ fn run_deadloop_session_inner(
             is_deadloop: true,
         });
 
+        let stage = workflow.stage().clone();
+        let label = if workflow.is_staged() {
+            format!("[Iteration {} - {}]", iteration, stage.name)
+        } else {
+            format!("[Iteration {}]", iteration)
+        };
+        context.iteration = iteration;
+        context.stage = stage.name.clone();
+        let prompt = context.render(&stage.prompt, Path::new(working_dir));
+        if !hooks.run(Hook::PreIteration, &session_env(iteration, &stage.name)) {
+            // A skipped iteration is a failed one, so a hook that keeps
+            // failing stops or pauses the loop like any other failure
+            let mut env = session_env(iteration, &stage.name);
+            env.push(("APAS_RESULT", String::new()));
+            env.push(("APAS_IS_ERROR", true.to_string()));
+            hooks.run(Hook::OnError, &env);
+            context.finish_iteration(true, "");
+            let idle = stopper.finish_iteration(true, "");
+            if let Some((reason, detail)) = stuck_detector.finish_iteration(true, idle, Path::new(working_dir)) {
+                pause_itself(reason, detail, session_id, &pause, &pause_reason, &output_tx, &server_tx);
+                continue;
+            }
+            backoff_seconds = std::cmp::min(backoff_seconds * 2, MAX_BACKOFF);
+            back_off(backoff_seconds, &shutdown, &output_tx);
+            continue;
+        }
+
+        let prompt = match handoff.take() {
+            Some(summary) => rotation::seed_prompt(&summary, &prompt),
+            None => prompt,
+        };
+
         // Send user input to server
         // Use try_send to avoid blocking if channel is full
         let _ = server_tx.try_send(CliToServer::UserInput {
             session_id,
-            text: format!("[Iteration {}]\n{}", iteration, prompt),
+            text: format!("{}\n{}", label, prompt),
             pane_type: Some(PaneType::Deadloop),
         });
 
-        // Build args:
-        // - First iteration: use --session-id to create session with specific ID
-        // - Subsequent: use --resume with the session ID to continue
-        let args = if first_message {
-            first_message = false;
-            vec![
-                "--print".to_string(),
-                "--output-format".to_string(),
-                "stream-json".to_string(),
-                "--verbose".to_string(),
-                "--dangerously-skip-permissions".to_string(),
-                "--session-id".to_string(),
-                claude_session_id.to_string(),
-                prompt.to_string(),
-            ]
-        } else {
-            vec![
-                "--print".to_string(),
-                "--output-format".to_string(),
-                "stream-json".to_string(),
-                "--verbose".to_string(),
-                "--dangerously-skip-permissions".to_string(),
-                "--resume".to_string(),
-                claude_session_id.to_string(),
-                prompt.to_string(),
-            ]
+        // The first iteration creates the session, later ones continue it.
+        // A model picked in the web UI beats the stage's.
+        let model_override = model.lock().ok().and_then(|m| m.clone()).or(stage.model.clone());
+        let session = AgentSession {
+            id: claude_session_id.get(),
+            resume: !first_message,
+            model: model_override.as_deref(),
         };
+        let resuming = session.resume;
+        first_message = false;
+        let mut command = backend.iteration_command(&session, &prompt);
+        agent::prepare(&mut command, working_dir, Stdio::null());
 
-        match Command::new(claude_path)
-            .args(&args)
-            .current_dir(working_dir)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-        {
+        match command.spawn() {
             Ok(mut child) => {
                 let child_pid = child.id();
+                let mut watchdog = Watchdog::new(iteration_limits.clone());
+                current_process.set(child_pid);
 
                 // Take stdout for reading
                 let stdout = match child.stdout.take() {
@@ -367,29 +569,36 @@ fn run_deadloop_session_inner(
                 let output_tx_stderr = output_tx.clone();
                 let server_tx_stderr = server_tx.clone();
                 let session_id_stderr = session_id;
+                let activity_stderr = watchdog.activity();
+                let stderr_seen = Arc::new(Mutex::new(String::new()));
+                let stderr_seen_thread = stderr_seen.clone();
                 let stderr_thread = stderr.map(|stderr| {
                     thread::spawn(move || {
                         let reader = BufReader::new(stderr);
-                        for line in reader.lines() {
-                            if let Ok(line) = line {
-                                if !line.trim().is_empty() {
-                                    let _ = output_tx_stderr.send(PaneOutput {
-                                        text: format!("[stderr] {}", line),
-                                        is_deadloop: true,
-                                    });
-                                    // Use try_send to avoid blocking
-                                    let _ = server_tx_stderr.try_send(CliToServer::Output {
-                                        session_id: session_id_stderr,
-                                        data: format!("[stderr] {}", line),
-                                        output_type: shared::OutputType::Error,
-                                    });
-                                }
+                        for line in reader.lines().map_while(Result::ok) {
+                            watchdog::touch(&activity_stderr);
+                            remember_stderr(&stderr_seen_thread, &line);
+                            if !line.trim().is_empty() {
+                                let _ = output_tx_stderr.send(PaneOutput {
+                                    text: format!("[stderr] {}", line),
+                                    is_deadloop: true,
+                                });
+                                // Use try_send to avoid blocking
+                                let _ = server_tx_stderr.try_send(CliToServer::Output {
+                                    session_id: session_id_stderr,
+                                    data: format!("[stderr] {}", line),
+                                    output_type: shared::OutputType::Error,
+                                });
                             }
                         }
                     })
                 });
 
                 let mut had_error = false;
+                let mut exit_status = None;
+                let mut sandbox_monitor = sandboxed.as_ref().map(|s| SandboxMonitor::new(s.sandbox(), child_pid));
+                // Final result text, for the stage's exit condition
+                let mut result_text = String::new();
                 let mut process_exited = false;
                 let mut exit_was_error = false;
                 let mut timeouts_after_exit = 0;
@@ -411,6 +620,7 @@ fn run_deadloop_session_inner(
                                     Ok(Some(status)) => {
                                         // Process has exited
                                         process_exited = true;
+                                        exit_status = Some(status);
                                         if !status.success() {
                                             let _ = output_tx.send(PaneOutput {
                                                 text: format!("[Claude process exited with {}]", status),
@@ -440,23 +650,61 @@ fn run_deadloop_session_inner(
                         // If lock not available, we'll try again next iteration
                     }
 
+                    // Stop a hung iteration; once tripped, keep escalating even
+                    // if the leader is gone but its group lives on
+                    if !process_exited || watchdog.timed_out().is_some() {
+                        match watchdog.check(child_pid) {
+                            Some(WatchdogAction::Interrupted(reason)) => {
+                                let why = match reason {
+                                    shared::IterationTimeout::Duration => "ran past its time limit",
+                                    shared::IterationTimeout::Idle => "produced no output for too long",
+                                };
+                                let _ = output_tx.send(PaneOutput {
+                                    text: format!("[Iteration {}, interrupting Claude]", why),
+                                    is_deadloop: true,
+                                });
+                            }
+                            Some(WatchdogAction::Killed) => {
+                                let _ = output_tx.send(PaneOutput {
+                                    text: "[Claude ignored the interrupt, killing it]".to_string(),
+                                    is_deadloop: true,
+                                });
+                                if let Ok(mut guard) = child_process.lock() {
+                                    if let Some(ref mut child) = *guard {
+                                        let _ = child.kill();
+                                    }
+                                }
+                            }
+                            None => {}
+                        }
+                    }
+
                     // Try to receive stdout line with timeout
                     match stdout_rx.recv_timeout(check_interval) {
                         Ok(Some(line)) => {
                             // Reset timeout counter since we're receiving data
                             timeouts_after_exit = 0;
+                            watchdog.touch();
 
                             if line.trim().is_empty() {
                                 continue;
                             }
 
                             // Parse and process
-                            match serde_json::from_str::<ClaudeStreamMessage>(&line) {
-                                Ok(message) => {
-                                    if let ClaudeStreamMessage::Result { is_error, .. } = &message {
+                            match backend.parse_event(&line) {
+                                Some(message) => {
+                                    if let ClaudeStreamMessage::Result { is_error, result, .. } = &message {
                                         if *is_error {
                                             had_error = true;
                                         }
+                                        result_text = result.clone();
+                                    }
+                                    stuck_detector.observe(&message);
+                                    spend.observe(&message);
+                                    context.observe(&message);
+                                    context_tracker.observe(&message);
+                                    if let Some(monitor) = &mut sandbox_monitor {
+                                        monitor.observe(&message);
                                     }
 
                                     let display_text = format_stream_message(&message);
@@ -472,8 +720,8 @@ fn run_deadloop_session_inner(
                                         pane_type: Some(PaneType::Deadloop),
                                     });
                                 }
-                                Err(_) => {
-                                    // Non-JSON output - display and forward to server
+                                None => {
+                                    // Not an event - display and forward to server
                                     let _ = output_tx.send(PaneOutput {
                                         text: line.clone(),
                                         is_deadloop: true,
@@ -531,12 +779,27 @@ fn run_deadloop_session_inner(
                 }
 
                 // Cleanup child process (kill if still running, reap zombie)
+                current_process.clear();
                 if let Ok(mut guard) = child_process.lock() {
                     if let Some(mut child) = guard.take() {
-                        // Try to get exit status, kill if still running
-                        match child.try_wait() {
-                            Ok(Some(_)) => {
-                                // Already exited, just reap
+                        // Closed stdout usually means the process is on its way out
+                        let deadline = Instant::now() + Duration::from_secs(1);
+                        let mut status = child.try_wait();
+                        while matches!(status, Ok(None)) && Instant::now() < deadline {
+                            thread::sleep(Duration::from_millis(20));
+                            status = child.try_wait();
+                        }
+                        match status {
+                            Ok(Some(status)) => {
+                                exit_status = exit_status.or(Some(status));
+                                // The exit may not have been noticed before stdout closed
+                                if !process_exited && !status.success() {
+                                    let _ = output_tx.send(PaneOutput {
+                                        text: format!("[Claude process exited with {}]", status),
+                                        is_deadloop: true,
+                                    });
+                                    exit_was_error = true;
+                                }
                             }
                             Ok(None) => {
                                 // Still running, kill it
@@ -556,20 +819,130 @@ fn run_deadloop_session_inner(
                     }
                 }
 
-                // Backoff on error
-                if had_error || exit_was_error {
-                    backoff_seconds = std::cmp::min(backoff_seconds * 2, MAX_BACKOFF);
-                    let _ = output_tx.send(PaneOutput {
-                        text: format!("[Backing off for {}s before retry]", backoff_seconds),
-                        is_deadloop: true,
+                if let Some(reason) = watchdog.timed_out() {
+                    had_error = true;
+                    let _ = server_tx.try_send(CliToServer::IterationTimedOut {
+                        session_id,
+                        reason,
+                        duration_ms: watchdog.elapsed().as_millis() as u64,
                     });
+                }
 
-                    for _ in 0..backoff_seconds {
-                        if shutdown.load(Ordering::SeqCst) {
-                            break;
+                let stderr_text = stderr_seen.lock().map(|s| s.clone()).unwrap_or_default();
+                if let Some(monitor) = &mut sandbox_monitor {
+                    let killed = watchdog.timed_out().is_some() || shutdown.load(Ordering::SeqCst);
+                    for (kind, detail) in monitor.finish(exit_status, &stderr_text, killed) {
+                        let _ = output_tx.send(PaneOutput {
+                            text: format!("[Sandbox: {}]", detail),
+                            is_deadloop: true,
+                        });
+                        let _ = server_tx.try_send(CliToServer::SandboxViolation { session_id, kind, detail });
+                        // Blocked access is Claude's to work around; a kill fails the iteration
+                        if !matches!(kind, SandboxViolation::BlockedWrite | SandboxViolation::BlockedNetwork) {
+                            had_error = true;
                         }
-                        thread::sleep(std::time::Duration::from_secs(1));
                     }
+                }
+
+                // Claude can't resume a session it doesn't have; start a new
+                // one rather than retry the same id forever
+                if resuming && backend.is_missing_session(&stderr_text) {
+                    let next = replace_missing_session(
+                        PaneType::Deadloop,
+                        claude_session_id.get(),
+                        working_dir,
+                        session_id,
+                        &output_tx,
+                        &server_tx,
+                    );
+                    claude_session_id.set(next);
+                    first_message = true;
+                    context_tracker.reset();
+                    continue;
+                }
+
+                // Check the work ourselves; a failure counts against the iteration
+                if let Some(verification) = &verification {
+                    // The code has moved on since the last check, so its
+                    // failure no longer applies even if this one is skipped
+                    context.verification.clear();
+                    if !had_error && !exit_was_error && !shutdown.load(Ordering::SeqCst) {
+                        context.verification =
+                            verify_iteration(verification, working_dir, session_id, &shutdown, &output_tx, &server_tx);
+                        had_error = !context.verification.is_empty();
+                    }
+                }
+
+                let failed = had_error || exit_was_error;
+                let mut env = session_env(iteration, &stage.name);
+                env.push(("APAS_RESULT", result_text.clone()));
+                env.push(("APAS_IS_ERROR", failed.to_string()));
+                hooks.run(Hook::PostIteration, &env);
+                if failed {
+                    hooks.run(Hook::OnError, &env);
+                }
+
+                context.finish_iteration(had_error || exit_was_error, &result_text);
+                let idle = stopper.finish_iteration(had_error || exit_was_error, &result_text);
+
+                context_tracker.finish_iteration();
+                if let Some(reason) = context_tracker.due().filter(|_| !shutdown.load(Ordering::SeqCst)) {
+                    let _ = output_tx.send(PaneOutput {
+                        text: format!("[Rotating context ({}), asking for a handoff summary]", reason),
+                        is_deadloop: true,
+                    });
+                    let previous = claude_session_id.get();
+                    let old_session = AgentSession { id: previous, resume: true, model: model_override.as_deref() };
+                    let summary = rotation::ask_for_summary(
+                        backend,
+                        &old_session,
+                        context_tracker.policy().summary_prompt(),
+                        working_dir,
+                        &shutdown,
+                    );
+                    let next = replace_claude_session_id(Path::new(working_dir), PaneType::Deadloop).unwrap_or_else(|e| {
+                        tracing::warn!("Failed to save the new deadloop session id: {}", e);
+                        Uuid::new_v4()
+                    });
+                    let _ = output_tx.send(PaneOutput {
+                        text: match &summary {
+                            Some(summary) => format!("[New Claude session {}, handoff:]\n{}", &next.to_string()[..8], summary),
+                            None => format!("[New Claude session {}, no handoff summary]", &next.to_string()[..8]),
+                        },
+                        is_deadloop: true,
+                    });
+                    let _ = server_tx.try_send(CliToServer::ClaudeSessionRotated {
+                        session_id,
+                        pane_type: PaneType::Deadloop,
+                        previous_claude_session_id: previous,
+                        claude_session_id: next,
+                        reason,
+                        summary: summary.clone(),
+                    });
+                    claude_session_id.set(next);
+                    first_message = true;
+                    handoff = summary;
+                    context_tracker.reset();
+                }
+                if workflow.finish_iteration(had_error || exit_was_error, &result_text, Path::new(working_dir), &shutdown) {
+                    announce_stage(&workflow, session_id, &stage_label, &output_tx, &server_tx);
+                }
+
+                let summaries = stuck::commits_since(Path::new(working_dir), stuck_detector.head());
+                if !summaries.is_empty() {
+                    let _ = server_tx.try_send(CliToServer::CommitsMade { session_id, summaries });
+                }
+                let stuck = stuck_detector.finish_iteration(had_error || exit_was_error, idle, Path::new(working_dir));
+                let over_budget = spend.exceeded().map(|detail| (PauseReason::Budget, detail));
+                if let Some((reason, detail)) = stuck.or(over_budget) {
+                    pause_itself(reason, detail, session_id, &pause, &pause_reason, &output_tx, &server_tx);
+                    continue;
+                }
+
+                // Backoff on error
+                if had_error || exit_was_error {
+                    backoff_seconds = std::cmp::min(backoff_seconds * 2, MAX_BACKOFF);
+                    back_off(backoff_seconds, &shutdown, &output_tx);
                 } else {
                     backoff_seconds = 2;
                     thread::sleep(std::time::Duration::from_secs(2));
@@ -577,7 +950,7 @@ fn run_deadloop_session_inner(
             }
             Err(e) => {
                 let _ = output_tx.send(PaneOutput {
-                    text: format!("[Error starting Claude: {}]", e),
+                    text: format!("[Error starting {}: {}]", backend.name(), e),
                     is_deadloop: true,
                 });
                 thread::sleep(std::time::Duration::from_secs(5));
@@ -600,47 +973,311 @@ fn run_deadloop_session_inner(
     }
 }
 
-/// Run the interactive session using --session-id and --resume to maintain conversation context
+/// Pause the loop on its own account and tell the pane and the server why
+fn pause_itself(
+    reason: PauseReason,
+    detail: String,
+    session_id: Uuid,
+    pause: &AtomicBool,
+    pause_reason: &Mutex<Option<String>>,
+    output_tx: &mpsc::Sender<PaneOutput>,
+    server_tx: &tokio_mpsc::Sender<CliToServer>,
+) {
+    pause.store(true, Ordering::SeqCst);
+    let text = match reason {
+        PauseReason::Budget => format!("[Deadloop over budget ({}), pausing]", detail),
+        _ => format!("[Deadloop looks stuck ({}), pausing]", detail),
+    };
+    if let Ok(mut guard) = pause_reason.lock() {
+        *guard = Some(detail);
+    }
+    let _ = output_tx.send(PaneOutput { text, is_deadloop: true });
+    let _ = server_tx.try_send(CliToServer::DeadloopStatus {
+        session_id,
+        is_paused: true,
+        reason: Some(reason),
+    });
+}
+
+/// Keep the end of a process's stderr, to recognize failures by
+fn remember_stderr(seen: &Mutex<String>, line: &str) {
+    const MAX_KEPT: usize = 8 * 1024;
+    if let Ok(mut seen) = seen.lock() {
+        seen.push_str(line);
+        seen.push('\n');
+        if seen.len() > 2 * MAX_KEPT {
+            *seen = crate::shell::tail(&seen, MAX_KEPT).to_string();
+        }
+    }
+}
+
+/// Give a pane a new Claude session because the one it tried to resume is
+/// gone, and tell the pane and the server
+fn replace_missing_session(
+    pane_type: PaneType,
+    previous: Uuid,
+    working_dir: &str,
+    session_id: Uuid,
+    output_tx: &mpsc::Sender<PaneOutput>,
+    server_tx: &tokio_mpsc::Sender<CliToServer>,
+) -> Uuid {
+    let next = replace_claude_session_id(Path::new(working_dir), pane_type).unwrap_or_else(|e| {
+        tracing::warn!("Failed to save the new {:?} session id: {}", pane_type, e);
+        Uuid::new_v4()
+    });
+    let _ = output_tx.send(PaneOutput {
+        text: format!(
+            "[Claude session {} no longer exists, starting a new one: {}]",
+            &previous.to_string()[..8],
+            &next.to_string()[..8]
+        ),
+        is_deadloop: pane_type == PaneType::Deadloop,
+    });
+    let _ = server_tx.try_send(CliToServer::ClaudeSessionRotated {
+        session_id,
+        pane_type,
+        previous_claude_session_id: previous,
+        claude_session_id: next,
+        reason: "the session to resume was not found".to_string(),
+        summary: None,
+    });
+    next
+}
+
+/// Tell the pane and wait before retrying, unless shutting down
+fn back_off(seconds: u64, shutdown: &AtomicBool, output_tx: &mpsc::Sender<PaneOutput>) {
+    let _ = output_tx.send(PaneOutput {
+        text: format!("[Backing off for {}s before retry]", seconds),
+        is_deadloop: true,
+    });
+    for _ in 0..seconds {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
+
+/// Runs the project's hooks for one deadloop, forwarding their output
+struct HookRunner<'a> {
+    hooks: &'a Hooks,
+    working_dir: &'a str,
+    session_id: Uuid,
+    shutdown: &'a AtomicBool,
+    output_tx: &'a mpsc::Sender<PaneOutput>,
+    server_tx: &'a tokio_mpsc::Sender<CliToServer>,
+}
+
+impl HookRunner<'_> {
+    /// Run `hook` if configured. Returns false if it failed.
+    fn run(&self, hook: Hook, env: &[(&str, String)]) -> bool {
+        let name = hook.to_string();
+        let forward = |line: &str| {
+            let _ = self.output_tx.send(PaneOutput {
+                text: format!("[{}] {}", name, line),
+                is_deadloop: true,
+            });
+            let _ = self.server_tx.try_send(CliToServer::HookOutput {
+                session_id: self.session_id,
+                hook: name.clone(),
+                line: line.to_string(),
+            });
+        };
+        let outcome = self.hooks.run(hook, Path::new(self.working_dir), env, self.shutdown, &forward);
+        let failure = match outcome {
+            None => return true,
+            Some(Ok(outcome)) if outcome.success() => return true,
+            Some(Ok(outcome)) if outcome.timed_out => format!("timed out after {}s", outcome.duration.as_secs()),
+            Some(Ok(outcome)) => outcome.status.map_or("was killed".to_string(), |s| format!("failed ({})", s)),
+            Some(Err(e)) => format!("could not be started: {}", e),
+        };
+        forward(&format!("[hook {}]", failure));
+        false
+    }
+}
+
+/// Run the project's verification command, streaming its output to the pane
+/// and the server. Returns the failure summary for the next prompt, or an
+/// empty string if it passed.
+fn verify_iteration(
+    verification: &Verification,
+    working_dir: &str,
+    session_id: Uuid,
+    shutdown: &AtomicBool,
+    output_tx: &mpsc::Sender<PaneOutput>,
+    server_tx: &tokio_mpsc::Sender<CliToServer>,
+) -> String {
+    let _ = output_tx.send(PaneOutput {
+        text: format!("[Verifying: {}]", verification.command),
+        is_deadloop: true,
+    });
+    let outcome = verification.run(Path::new(working_dir), shutdown, |line| {
+        let _ = output_tx.send(PaneOutput {
+            text: format!("[verify] {}", line),
+            is_deadloop: true,
+        });
+        let _ = server_tx.try_send(CliToServer::VerificationOutput {
+            session_id,
+            line: line.to_string(),
+        });
+    });
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            let _ = output_tx.send(PaneOutput {
+                text: format!("[Error running verification: {}]", e),
+                is_deadloop: true,
+            });
+            return format!("The verification command `{}` could not be started: {}", verification.command, e);
+        }
+    };
+
+    let passed = outcome.success();
+    let _ = output_tx.send(PaneOutput {
+        text: if passed {
+            format!("[Verification passed in {}s]", outcome.duration.as_secs())
+        } else if outcome.timed_out {
+            format!("[Verification timed out after {}s]", outcome.duration.as_secs())
+        } else {
+            format!("[Verification failed ({})]", outcome.status.map_or("killed".to_string(), |s| s.to_string()))
+        },
+        is_deadloop: true,
+    });
+    let _ = server_tx.try_send(CliToServer::VerificationFinished {
+        session_id,
+        command: verification.command.clone(),
+        passed,
+        exit_code: outcome.exit_code(),
+        timed_out: outcome.timed_out,
+        duration_ms: outcome.duration.as_millis() as u64,
+    });
+
+    if passed {
+        String::new()
+    } else {
+        verification.failure_summary(&outcome)
+    }
+}
+
+/// Show the workflow's current stage in the TUI and tell the server about it
+fn announce_stage(
+    workflow: &Workflow,
+    session_id: Uuid,
+    stage_label: &Mutex<Option<String>>,
+    output_tx: &mpsc::Sender<PaneOutput>,
+    server_tx: &tokio_mpsc::Sender<CliToServer>,
+) {
+    let stage = workflow.stage();
+    let label = format!("{}/{} {}", workflow.index() + 1, workflow.len(), stage.name);
+    let _ = output_tx.send(PaneOutput {
+        text: format!("[Stage {}]", label),
+        is_deadloop: true,
+    });
+    if let Ok(mut guard) = stage_label.lock() {
+        *guard = Some(label);
+    }
+    let _ = server_tx.try_send(CliToServer::WorkflowStage {
+        session_id,
+        stage: stage.name.clone(),
+        index: workflow.index() as u32,
+        total: workflow.len() as u32,
+    });
+}
+
+/// Run the interactive session in one long-lived Claude process fed with
+/// stream-json input. If the process dies it is resumed with the next message.
+#[allow(clippy::too_many_arguments)]
 fn run_interactive_session(
-    claude_path: &str,
+    backend: Arc<dyn AgentBackend>,
     working_dir: &str,
     session_id: Uuid,
-    claude_session_id: Uuid,
+    mut claude_session_id: Uuid,
     tui_input_rx: mpsc::Receiver<String>,
     web_input_rx: mpsc::Receiver<String>,
     output_tx: mpsc::Sender<PaneOutput>,
     server_tx: tokio_mpsc::Sender<CliToServer>,
     shutdown: Arc<AtomicBool>,
+    current_process: CurrentProcess,
+    model: Arc<Mutex<Option<String>>>,
 ) {
-    // Use the persisted Claude session ID for conversation continuity across restarts
-    let mut first_message = true;
-
     let _ = output_tx.send(PaneOutput {
-        text: format!("[Interactive session: {}]", &claude_session_id.to_string()[..8]),
+        text: format!("[Interactive session: {} ({})]", &claude_session_id.to_string()[..8], backend.name()),
         is_deadloop: false,
     });
 
+    let mut claude: Option<AgentProcess> = None;
+    // The first process this run creates the session (--session-id), later ones resume it
+    let mut resume = false;
+    // Model override the running process was started with
+    let mut running_model: Option<String> = None;
+    // Whether the running process resumed its session, and its stderr, to
+    // notice when that session is gone
+    let mut running_resumed = false;
+    let stderr_seen = Arc::new(Mutex::new(String::new()));
+    // Last message sent, and one to send again after a failed resume
+    let mut last_prompt: Option<String> = None;
+    let mut retry: Option<String> = None;
+
     while !shutdown.load(Ordering::SeqCst) {
-        // Wait for user input from either TUI or web
-        // Track the source to avoid duplicate UserInput messages
-        let (prompt, from_tui) = {
-            // Try TUI input first
-            match tui_input_rx.recv_timeout(std::time::Duration::from_millis(50)) {
-                Ok(p) => (p, true),
-                Err(mpsc::RecvTimeoutError::Timeout) => {
-                    // Try web input
-                    match web_input_rx.recv_timeout(std::time::Duration::from_millis(50)) {
-                        Ok(p) => (p, false), // Web input - server already saved/broadcast it
-                        Err(mpsc::RecvTimeoutError::Timeout) => continue,
-                        Err(mpsc::RecvTimeoutError::Disconnected) => continue,
+        // Forward whatever Claude has printed
+        let mut exited = false;
+        if let Some(process) = &claude {
+            loop {
+                match process.try_next_line() {
+                    Ok(line) => forward_interactive_line(backend.as_ref(), line, session_id, &output_tx, &server_tx),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        exited = true;
+                        break;
                     }
                 }
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
             }
+        }
+        if exited {
+            if let Some(process) = claude.take() {
+                process.finish_stderr(Duration::from_millis(500));
+            }
+            current_process.clear();
+            let stderr_text = stderr_seen.lock().map(|s| s.clone()).unwrap_or_default();
+            if running_resumed && backend.is_missing_session(&stderr_text) {
+                claude_session_id = replace_missing_session(
+                    PaneType::Interactive,
+                    claude_session_id,
+                    working_dir,
+                    session_id,
+                    &output_tx,
+                    &server_tx,
+                );
+                resume = false;
+                retry = last_prompt.take();
+            } else {
+                let _ = output_tx.send(PaneOutput {
+                    text: "[Claude exited; the next message resumes the conversation]".to_string(),
+                    is_deadloop: false,
+                });
+            }
+        }
+
+        // Take user input from either TUI or web, or the message a failed
+        // resume swallowed. Track the source to avoid duplicate UserInput
+        // messages.
+        let (prompt, from_tui) = match retry.take() {
+            Some(p) => (p, false),
+            None => match tui_input_rx.try_recv() {
+                Ok(p) => (p, true),
+                Err(mpsc::TryRecvError::Disconnected) => break,
+                Err(mpsc::TryRecvError::Empty) => match web_input_rx.try_recv() {
+                    Ok(p) => (p, false), // Web input - server already saved/broadcast it
+                    Err(_) => {
+                        thread::sleep(Duration::from_millis(20));
+                        continue;
+                    }
+                },
+            },
         };
 
         let _ = output_tx.send(PaneOutput {
-            text: format!("> {}", &prompt[..std::cmp::min(100, prompt.len())]),
+            text: format!("> {}", truncate_string(&prompt, 100)),
             is_deadloop: false,
         });
 
@@ -654,115 +1291,127 @@ fn run_interactive_session(
             });
         }
 
-        // Build args:
-        // - First message: use --session-id to create session with specific ID
-        // - Subsequent: use --resume with the session ID to continue
-        // Note: --verbose is required when using --print with --output-format stream-json
-        let args = if first_message {
-            first_message = false;
-            vec![
-                "--print".to_string(),
-                "--output-format".to_string(),
-                "stream-json".to_string(),
-                "--verbose".to_string(),
-                "--dangerously-skip-permissions".to_string(),
-                "--session-id".to_string(),
-                claude_session_id.to_string(),
-                prompt,
-            ]
-        } else {
-            vec![
-                "--print".to_string(),
-                "--output-format".to_string(),
-                "stream-json".to_string(),
-                "--verbose".to_string(),
-                "--dangerously-skip-permissions".to_string(),
-                "--resume".to_string(),
-                claude_session_id.to_string(),
-                prompt,
-            ]
-        };
-
-        match Command::new(claude_path)
-            .args(&args)
-            .current_dir(working_dir)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-        {
-            Ok(mut child) => {
-                let stdout = child.stdout.take().unwrap();
-                let stderr = child.stderr.take().unwrap();
-                let reader = BufReader::new(stdout);
+        // A new model from the web takes a fresh process
+        let model_override = model.lock().ok().and_then(|m| m.clone());
+        if claude.is_some() && model_override != running_model {
+            claude = None;
+            current_process.clear();
+        }
 
-                // Spawn thread to capture stderr
-                let output_tx_stderr = output_tx.clone();
-                let stderr_thread = thread::spawn(move || {
-                    let reader = BufReader::new(stderr);
-                    for line in reader.lines() {
-                        if let Ok(line) = line {
-                            if !line.trim().is_empty() {
-                                let _ = output_tx_stderr.send(PaneOutput {
-                                    text: format!("[stderr] {}", line),
-                                    is_deadloop: false,
-                                });
-                            }
-                        }
+        // A write can fail if the process died since we last looked; resume
+        // it and try once more
+        for attempt in 0..2 {
+            if claude.is_none() {
+                let stderr_tx = output_tx.clone();
+                if let Ok(mut seen) = stderr_seen.lock() {
+                    seen.clear();
+                }
+                let stderr_seen = stderr_seen.clone();
+                let on_stderr = move |line: String| {
+                    remember_stderr(&stderr_seen, &line);
+                    if !line.trim().is_empty() {
+                        let _ = stderr_tx.send(PaneOutput {
+                            text: format!("[stderr] {}", line),
+                            is_deadloop: false,
+                        });
                     }
-                });
-
-                for line in reader.lines() {
-                    if shutdown.load(Ordering::SeqCst) {
+                };
+                let session = AgentSession {
+                    id: claude_session_id,
+                    resume,
+                    model: model_override.as_deref(),
+                };
+                match AgentProcess::spawn(backend.clone(), &session, working_dir, on_stderr) {
+                    Ok(process) => {
+                        current_process.set(process.pid());
+                        claude = Some(process);
+                        running_resumed = resume;
+                        resume = true;
+                        running_model = model_override.clone();
+                    }
+                    Err(e) => {
+                        let _ = output_tx.send(PaneOutput {
+                            text: format!("[Error: {}]", e),
+                            is_deadloop: false,
+                        });
                         break;
                     }
+                }
+            }
 
-                    let line = match line {
-                        Ok(l) => l,
-                        Err(_) => break,
-                    };
-
-                    if line.trim().is_empty() {
-                        continue;
+            let process = claude.as_mut().expect("spawned above");
+            match process.send_user_message(&prompt) {
+                Ok(()) => {
+                    last_prompt = Some(prompt.clone());
+                    break;
+                }
+                Err(e) => {
+                    if let Some(process) = claude.take() {
+                        process.finish_stderr(Duration::from_millis(500));
                     }
-
-                    // Parse and process
-                    match serde_json::from_str::<ClaudeStreamMessage>(&line) {
-                        Ok(message) => {
-                            // Display locally
-                            let display_text = format_stream_message(&message);
-                            let _ = output_tx.send(PaneOutput {
-                                text: display_text,
-                                is_deadloop: false,
-                            });
-
-                            // Send to server
-                            let _ = server_tx.blocking_send(CliToServer::StreamMessage {
-                                session_id,
-                                message,
-                                pane_type: Some(PaneType::Interactive),
-                            });
-                        }
-                        Err(_) => {
-                            let _ = output_tx.send(PaneOutput {
-                                text: line,
-                                is_deadloop: false,
-                            });
-                        }
+                    current_process.clear();
+                    let stderr_text = stderr_seen.lock().map(|s| s.clone()).unwrap_or_default();
+                    if running_resumed && backend.is_missing_session(&stderr_text) {
+                        claude_session_id = replace_missing_session(
+                            PaneType::Interactive,
+                            claude_session_id,
+                            working_dir,
+                            session_id,
+                            &output_tx,
+                            &server_tx,
+                        );
+                        resume = false;
+                    }
+                    if attempt == 1 {
+                        let _ = output_tx.send(PaneOutput {
+                            text: format!("[Error sending to Claude: {}]", e),
+                            is_deadloop: false,
+                        });
                     }
                 }
-
-                let _ = child.wait();
-                let _ = stderr_thread.join();
-            }
-            Err(e) => {
-                let _ = output_tx.send(PaneOutput {
-                    text: format!("[Error: {}]", e),
-                    is_deadloop: false,
-                });
             }
         }
     }
+
+    // Dropping the process stops it
+    current_process.clear();
+}
+
+/// Display one stdout line of the interactive process and send it to the server
+fn forward_interactive_line(
+    backend: &dyn AgentBackend,
+    line: String,
+    session_id: Uuid,
+    output_tx: &mpsc::Sender<PaneOutput>,
+    server_tx: &tokio_mpsc::Sender<CliToServer>,
+) {
+    if line.trim().is_empty() {
+        return;
+    }
+
+    match backend.parse_event(&line) {
+        Some(message) => {
+            // Display locally
+            let display_text = format_stream_message(&message);
+            let _ = output_tx.send(PaneOutput {
+                text: display_text,
+                is_deadloop: false,
+            });
+
+            // Send to server
+            let _ = server_tx.blocking_send(CliToServer::StreamMessage {
+                session_id,
+                message,
+                pane_type: Some(PaneType::Interactive),
+            });
+        }
+        None => {
+            let _ = output_tx.send(PaneOutput {
+                text: line,
+                is_deadloop: false,
+            });
+        }
+    }
 }
 
 /// Truncate a string to max_chars characters, respecting UTF-8 boundaries
@@ -828,6 +1477,7 @@ fn format_stream_message(message: &ClaudeStreamMessage) -> String {
 }
 
 /// Run server connection with automatic reconnection
+#[allow(clippy::too_many_arguments)]
 async fn run_server_connection(
     server_url: &str,
     token: &str,
@@ -836,6 +1486,11 @@ async fn run_server_connection(
     mut output_rx: tokio_mpsc::Receiver<CliToServer>,
     shutdown: Arc<AtomicBool>,
     pause_deadloop: Arc<AtomicBool>,
+    pause_reason: Arc<Mutex<Option<String>>>,
+    deadloop_process: CurrentProcess,
+    interactive_process: CurrentProcess,
+    deadloop_model: Arc<Mutex<Option<String>>>,
+    interactive_model: Arc<Mutex<Option<String>>>,
     web_input_tx: mpsc::Sender<String>,
     status_tx: mpsc::Sender<PaneOutput>,
 ) -> Result<()> {
@@ -866,9 +1521,11 @@ async fn run_server_connection(
                 let register_msg = CliToServer::Register {
                     token: token.to_string(),
                     version: Some(env!("APAS_VERSION").to_string()),
+                    compression: Compression::SUPPORTED.to_vec(),
+                    machine: crate::machine::info(),
                 };
                 let msg_text = serde_json::to_string(&register_msg)?;
-                if ws_sender.send(Message::Text(msg_text.into())).await.is_err() {
+                if ws_sender.send(Message::Text(msg_text)).await.is_err() {
                     let _ = status_tx.send(PaneOutput {
                         text: "[Server: Connection lost during registration]".to_string(),
                         is_deadloop: true,
@@ -889,8 +1546,8 @@ async fn run_server_connection(
                                         Err(_) => continue,
                                     };
                                     match response {
-                                        ServerToCli::Registered { cli_id } => {
-                                            return Some(Ok(cli_id));
+                                        ServerToCli::Registered { cli_id, compression } => {
+                                            return Some(Ok((cli_id, compression)));
                                         }
                                         ServerToCli::RegistrationFailed { reason } => {
                                             return Some(Err(reason));
@@ -915,13 +1572,14 @@ async fn run_server_connection(
                     }
                 ).await;
 
-                match registration_timeout {
-                    Ok(Some(Ok(cli_id))) => {
+                let compression = match registration_timeout {
+                    Ok(Some(Ok((cli_id, compression)))) => {
                         let _ = status_tx.send(PaneOutput {
                             text: format!("[Server: Connected ({})]", &cli_id.to_string()[..8]),
                             is_deadloop: true,
                         });
                         // Successfully registered, continue to session start
+                        compression
                     }
                     Ok(Some(Err(reason))) if reason.starts_with("ping:") => {
                         // Got a ping, need to handle it - restart the connection
@@ -947,7 +1605,7 @@ async fn run_server_connection(
                         tokio::time::sleep(reconnect_delay).await;
                         continue;
                     }
-                }
+                };
 
                 // Register session (pane_type in messages will differentiate deadloop vs interactive)
                 let hostname = hostname::get()
@@ -961,7 +1619,7 @@ async fn run_server_connection(
                     pane_type: None, // Single session, pane_type on individual messages
                 };
                 let msg_text = serde_json::to_string(&session_start)?;
-                if ws_sender.send(Message::Text(msg_text.into())).await.is_err() {
+                if ws_sender.send(Message::Text(msg_text)).await.is_err() {
                     let _ = status_tx.send(PaneOutput {
                         text: "[Server: Connection lost during session start]".to_string(),
                         is_deadloop: true,
@@ -980,8 +1638,7 @@ async fn run_server_connection(
                 loop {
                     tokio::select! {
                         Some(msg) = output_rx.recv() => {
-                            let msg_text = serde_json::to_string(&msg)?;
-                            if ws_sender.send(Message::Text(msg_text.into())).await.is_err() {
+                            if send_batch(&mut ws_sender, msg, &mut output_rx, compression).await.is_err() {
                                 let _ = status_tx.send(PaneOutput {
                                     text: "[Server: Connection lost, reconnecting...]".to_string(),
                                     is_deadloop: true,
@@ -1001,8 +1658,38 @@ async fn run_server_connection(
                                             ServerToCli::Heartbeat => {
                                                 // Heartbeat response, nothing to do
                                             }
+                                            ServerToCli::SetModel { pane_type, model, .. } => {
+                                                let (target, is_deadloop, when) = match pane_type {
+                                                    PaneType::Interactive => (&interactive_model, false, "next message"),
+                                                    PaneType::Deadloop => (&deadloop_model, true, "next iteration"),
+                                                };
+                                                let text = match &model {
+                                                    Some(m) => format!("[Model set to {} from the {}]", m, when),
+                                                    None => format!("[Configured model restored from the {}]", when),
+                                                };
+                                                if let Ok(mut guard) = target.lock() {
+                                                    *guard = model;
+                                                }
+                                                let _ = status_tx.send(PaneOutput { text, is_deadloop });
+                                            }
+                                            ServerToCli::Signal { signal: name, pane_type, .. } => {
+                                                let (process, is_deadloop) = match pane_type {
+                                                    Some(PaneType::Deadloop) => (&deadloop_process, true),
+                                                    _ => (&interactive_process, false),
+                                                };
+                                                let text = match Signal::parse(&name).map(|s| process.signal(s)) {
+                                                    None => format!("[Unknown signal from web: {}]", name),
+                                                    Some(Ok(true)) => format!("[{} received from web]", name),
+                                                    Some(Ok(false)) => format!("[{} from web ignored, Claude is not running]", name),
+                                                    Some(Err(e)) => format!("[Failed to deliver {}: {}]", name, e),
+                                                };
+                                                let _ = status_tx.send(PaneOutput { text, is_deadloop });
+                                            }
                                             ServerToCli::PauseDeadloop { .. } => {
                                                 pause_deadloop.store(true, Ordering::SeqCst);
+                                                if let Ok(mut guard) = pause_reason.lock() {
+                                                    *guard = Some(PauseReason::User.description().to_string());
+                                                }
                                                 let _ = status_tx.send(PaneOutput {
                                                     text: "[Pause command received from web]".to_string(),
                                                     is_deadloop: true,
@@ -1011,12 +1698,16 @@ async fn run_server_connection(
                                                 let status_msg = CliToServer::DeadloopStatus {
                                                     session_id,
                                                     is_paused: true,
+                                                    reason: Some(PauseReason::User),
                                                 };
                                                 let msg_text = serde_json::to_string(&status_msg).unwrap_or_default();
-                                                let _ = ws_sender.send(Message::Text(msg_text.into())).await;
+                                                let _ = ws_sender.send(Message::Text(msg_text)).await;
                                             }
                                             ServerToCli::ResumeDeadloop { .. } => {
                                                 pause_deadloop.store(false, Ordering::SeqCst);
+                                                if let Ok(mut guard) = pause_reason.lock() {
+                                                    *guard = None;
+                                                }
                                                 let _ = status_tx.send(PaneOutput {
                                                     text: "[Resume command received from web]".to_string(),
                                                     is_deadloop: true,
@@ -1025,9 +1716,10 @@ async fn run_server_connection(
                                                 let status_msg = CliToServer::DeadloopStatus {
                                                     session_id,
                                                     is_paused: false,
+                                                    reason: None,
                                                 };
                                                 let msg_text = serde_json::to_string(&status_msg).unwrap_or_default();
-                                                let _ = ws_sender.send(Message::Text(msg_text.into())).await;
+                                                let _ = ws_sender.send(Message::Text(msg_text)).await;
                                             }
                                             _ => {}
                                         }
@@ -1035,7 +1727,8 @@ async fn run_server_connection(
                                 }
                                 Some(Ok(Message::Ping(data))) => {
                                     // Respond to server ping with pong
-                                    if ws_sender.send(Message::Pong(data)).await.is_err() {
+                                    let pong_failed = ws_sender.send(Message::Pong(data)).await.is_err();
+                                    if pong_failed {
                                         let _ = status_tx.send(PaneOutput {
                                             text: "[Server: Failed to send pong, reconnecting...]".to_string(),
                                             is_deadloop: true,
@@ -1065,7 +1758,7 @@ async fn run_server_connection(
                         }
                         _ = heartbeat_interval.tick() => {
                             // Send ping to server to keep connection alive
-                            if ws_sender.send(Message::Ping(vec![].into())).await.is_err() {
+                            if ws_sender.send(Message::Ping(vec![])).await.is_err() {
                                 let _ = status_tx.send(PaneOutput {
                                     text: "[Server: Heartbeat failed, reconnecting...]".to_string(),
                                     is_deadloop: true,
@@ -1099,3 +1792,737 @@ async fn run_server_connection(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::ClaudeBackend;
+    use crate::config::ClaudeSettings;
+    use std::path::PathBuf;
+
+    /// `fake-claude` from this workspace, rebuilt once per test run so it
+    /// never lags behind its source
+    fn fake_claude() -> PathBuf {
+        static BUILD: std::sync::Once = std::sync::Once::new();
+        // Test binaries live in target/<profile>/deps
+        let exe = std::env::current_exe().unwrap();
+        let profile_dir = exe.parent().unwrap().parent().unwrap();
+        BUILD.call_once(|| {
+            let mut cargo = std::process::Command::new(env!("CARGO"));
+            cargo.args(["build", "-p", "fake-claude"]);
+            if profile_dir.ends_with("release") {
+                cargo.arg("--release");
+            }
+            assert!(cargo.status().unwrap().success(), "failed to build fake-claude");
+        });
+        profile_dir.join(format!("fake-claude{}", std::env::consts::EXE_SUFFIX))
+    }
+
+    /// Claude backend pointed at the fake, playing `scenario` and logging
+    /// each invocation's arguments
+    struct FakeClaude {
+        inner: ClaudeBackend,
+        scenario: String,
+        log: PathBuf,
+        /// Sessions the fake knows about
+        sessions: PathBuf,
+    }
+
+    impl FakeClaude {
+        /// Play `scenario`, keeping the log and sessions under `dir`
+        fn new(dir: &Path, scenario: &str) -> Self {
+            Self {
+                inner: ClaudeBackend::new(fake_claude().to_str().unwrap(), ClaudeSettings::default()),
+                scenario: scenario.to_string(),
+                log: dir.join("invocations.jsonl"),
+                sessions: dir.join("sessions"),
+            }
+        }
+
+        fn command(&self, mut command: std::process::Command) -> std::process::Command {
+            command
+                .env("FAKE_CLAUDE_SCENARIO", &self.scenario)
+                .env("FAKE_CLAUDE_LOG", &self.log)
+                .env("FAKE_CLAUDE_SESSIONS", &self.sessions);
+            command
+        }
+
+        /// Arguments of every invocation so far
+        fn invocations(&self) -> Vec<Vec<String>> {
+            std::fs::read_to_string(&self.log)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    impl AgentBackend for FakeClaude {
+        fn name(&self) -> &str {
+            "fake-claude"
+        }
+
+        fn iteration_command(&self, session: &AgentSession, prompt: &str) -> std::process::Command {
+            self.command(self.inner.iteration_command(session, prompt))
+        }
+
+        fn conversation_command(&self, session: &AgentSession) -> std::process::Command {
+            self.command(self.inner.conversation_command(session))
+        }
+
+        fn encode_message(&self, text: &str) -> String {
+            self.inner.encode_message(text)
+        }
+
+        fn parse_event(&self, line: &str) -> Option<ClaudeStreamMessage> {
+            self.inner.parse_event(line)
+        }
+
+        fn is_missing_session(&self, stderr: &str) -> bool {
+            self.inner.is_missing_session(stderr)
+        }
+    }
+
+    /// A deadloop running in the background, against the fake unless told
+    /// otherwise
+    struct Deadloop<B = FakeClaude> {
+        backend: Arc<B>,
+        output_rx: mpsc::Receiver<PaneOutput>,
+        server_rx: tokio_mpsc::Receiver<CliToServer>,
+        shutdown: Arc<AtomicBool>,
+        pause: Arc<AtomicBool>,
+        thread: Option<thread::JoinHandle<()>>,
+        dir: PathBuf,
+    }
+
+    impl Deadloop {
+        fn start(scenario: &str, stuck_detection: StuckDetection, iteration_limits: IterationLimits) -> Self {
+            let options = DeadloopOptions { stuck_detection, iteration_limits, ..Default::default() };
+            Self::start_with(scenario, options, Vec::new())
+        }
+
+        fn start_with(scenario: &str, options: DeadloopOptions, stages: Vec<Stage>) -> Self {
+            let dir = std::env::temp_dir().join(format!("apas-deadloop-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Deadloop::run(Arc::new(FakeClaude::new(&dir, scenario)), dir, options, stages)
+        }
+    }
+
+    impl<B: AgentBackend + 'static> Deadloop<B> {
+        /// Run the loop with `backend` in `dir`, which it then owns
+        fn run(backend: Arc<B>, dir: PathBuf, options: DeadloopOptions, stages: Vec<Stage>) -> Self {
+            let (output_tx, output_rx) = mpsc::channel();
+            let (server_tx, server_rx) = tokio_mpsc::channel(256);
+            let shutdown = Arc::new(AtomicBool::new(false));
+            let pause = Arc::new(AtomicBool::new(false));
+
+            let thread = {
+                let backend = backend.clone();
+                let working_dir = dir.to_string_lossy().to_string();
+                let (shutdown, pause) = (shutdown.clone(), pause.clone());
+                thread::spawn(move || {
+                    run_deadloop_session(
+                        backend.as_ref(),
+                        &working_dir,
+                        Uuid::new_v4(),
+                        Uuid::new_v4(),
+                        "work on TODO.md",
+                        stages,
+                        Arc::new(Mutex::new(None)),
+                        output_tx,
+                        server_tx,
+                        shutdown,
+                        pause,
+                        Arc::new(Mutex::new(None)),
+                        Arc::new(Mutex::new(None)),
+                        CurrentProcess::default(),
+                        options,
+                        Arc::new(Mutex::new(None)),
+                    )
+                })
+            };
+
+            Self { backend, output_rx, server_rx, shutdown, pause, thread: Some(thread), dir }
+        }
+
+        /// Wait for a line in the deadloop pane containing `text`
+        fn expect_output(&self, text: &str) {
+            expect_output(&self.output_rx, text);
+        }
+
+        /// Wait for a message to the server matching `check`
+        fn expect_sent(&mut self, check: impl Fn(&CliToServer) -> bool) -> CliToServer {
+            expect_sent(&mut self.server_rx, check)
+        }
+    }
+
+    impl<B> Drop for Deadloop<B> {
+        fn drop(&mut self) {
+            self.shutdown.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// An interactive pane running in the background against the fake, fed
+    /// as if typed in the TUI
+    struct Interactive {
+        backend: Arc<FakeClaude>,
+        input_tx: mpsc::Sender<String>,
+        output_rx: mpsc::Receiver<PaneOutput>,
+        server_rx: tokio_mpsc::Receiver<CliToServer>,
+        shutdown: Arc<AtomicBool>,
+        current_process: CurrentProcess,
+        thread: Option<thread::JoinHandle<()>>,
+        dir: PathBuf,
+    }
+
+    impl Interactive {
+        fn start(scenario: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("apas-interactive-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let backend = Arc::new(FakeClaude::new(&dir, scenario));
+            let (input_tx, input_rx) = mpsc::channel();
+            let (_, web_input_rx) = mpsc::channel();
+            let (output_tx, output_rx) = mpsc::channel();
+            let (server_tx, server_rx) = tokio_mpsc::channel(256);
+            let shutdown = Arc::new(AtomicBool::new(false));
+            let current_process = CurrentProcess::default();
+
+            let thread = {
+                let backend = backend.clone();
+                let working_dir = dir.to_string_lossy().to_string();
+                let (shutdown, current_process) = (shutdown.clone(), current_process.clone());
+                thread::spawn(move || {
+                    run_interactive_session(
+                        backend,
+                        &working_dir,
+                        Uuid::new_v4(),
+                        Uuid::new_v4(),
+                        input_rx,
+                        web_input_rx,
+                        output_tx,
+                        server_tx,
+                        shutdown,
+                        current_process,
+                        Arc::new(Mutex::new(None)),
+                    )
+                })
+            };
+
+            Self { backend, input_tx, output_rx, server_rx, shutdown, current_process, thread: Some(thread), dir }
+        }
+
+        /// Type `text` and wait for Claude's answer to it
+        fn ask(&mut self, text: &str) {
+            self.input_tx.send(text.to_string()).unwrap();
+            self.expect_sent(|m| matches!(m, CliToServer::UserInput { text: sent, .. } if sent == text));
+            self.expect_sent(|m| {
+                matches!(
+                    m,
+                    CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { .. }, pane_type: Some(PaneType::Interactive), .. }
+                )
+            });
+        }
+
+        /// Wait for a line in the interactive pane containing `text`
+        fn expect_output(&self, text: &str) {
+            expect_output(&self.output_rx, text);
+        }
+
+        /// Wait for a message to the server matching `check`
+        fn expect_sent(&mut self, check: impl Fn(&CliToServer) -> bool) -> CliToServer {
+            expect_sent(&mut self.server_rx, check)
+        }
+    }
+
+    impl Drop for Interactive {
+        fn drop(&mut self) {
+            self.shutdown.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Wait for a line in a pane containing `text`
+    fn expect_output(output_rx: &mpsc::Receiver<PaneOutput>, text: &str) {
+        let deadline = Instant::now() + Duration::from_secs(15);
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match output_rx.recv_timeout(left) {
+                Ok(output) if output.text.contains(text) => return,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        panic!("pane never printed {:?}", text);
+    }
+
+    /// Wait for a message to the server matching `check`
+    fn expect_sent(server_rx: &mut tokio_mpsc::Receiver<CliToServer>, check: impl Fn(&CliToServer) -> bool) -> CliToServer {
+        let deadline = Instant::now() + Duration::from_secs(15);
+        while Instant::now() < deadline {
+            match server_rx.try_recv() {
+                Ok(message) if check(&message) => return message,
+                Ok(_) => {}
+                Err(_) => thread::sleep(Duration::from_millis(20)),
+            }
+        }
+        panic!("pane never sent the expected message");
+    }
+
+    fn no_stuck_detection() -> StuckDetection {
+        StuckDetection {
+            max_iterations_without_commit: 0,
+            max_repeated_tool_calls: 0,
+            max_repeated_results: 0,
+            max_consecutive_errors: 0,
+        }
+    }
+
+    /// Options with stuck detection off, so tests can loop freely
+    fn quiet() -> DeadloopOptions {
+        DeadloopOptions { stuck_detection: no_stuck_detection(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_iterations_stream_and_resume_the_session() {
+        let mut deadloop = Deadloop::start("success", no_stuck_detection(), IterationLimits::default());
+        deadloop.expect_sent(|m| matches!(m, CliToServer::UserInput { pane_type: Some(PaneType::Deadloop), .. }));
+        deadloop.expect_sent(|m| {
+            matches!(
+                m,
+                CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { is_error: false, .. }, pane_type: Some(PaneType::Deadloop), .. }
+            )
+        });
+        deadloop.expect_output("=== Iteration 2 ===");
+        deadloop.expect_output("[Claude process exited normally]");
+
+        let invocations = deadloop.backend.invocations();
+        assert!(invocations[0].contains(&"--session-id".to_string()));
+        assert!(invocations[1].contains(&"--resume".to_string()));
+        assert_eq!(invocations[0].last().map(String::as_str), Some("work on TODO.md"));
+    }
+
+    #[test]
+    fn test_workflow_runs_stages_in_order() {
+        use crate::workflow::StageExit;
+
+        let stage = |name: &str, model: Option<&str>, exit| Stage {
+            name: name.to_string(),
+            prompt: format!("{} it", name),
+            model: model.map(str::to_string),
+            exit,
+            max_iterations: None,
+        };
+        let stages = vec![
+            stage("plan", Some("opus"), StageExit::Iteration),
+            stage("test", None, StageExit::ResultContains("Done".to_string())),
+        ];
+        let mut deadloop = Deadloop::start_with("success", quiet(), stages);
+        let stage_index = |m: &CliToServer, want: u32| {
+            matches!(m, CliToServer::WorkflowStage { index, total: 2, .. } if *index == want)
+        };
+        deadloop.expect_sent(|m| stage_index(m, 0));
+        deadloop.expect_sent(|m| stage_index(m, 1));
+        // The fake's result says "Done", so the test stage hands back to planning
+        deadloop.expect_sent(|m| stage_index(m, 0));
+
+        let invocations = deadloop.backend.invocations();
+        assert_eq!(invocations[0].last().map(String::as_str), Some("plan it"));
+        assert!(invocations[0].windows(2).any(|w| w == ["--model", "opus"]));
+        assert_eq!(invocations[1].last().map(String::as_str), Some("test it"));
+        assert!(!invocations[1].contains(&"--model".to_string()));
+    }
+
+    #[test]
+    fn test_prompt_is_rendered_each_iteration() {
+        let stages = vec![Stage {
+            name: "work".to_string(),
+            prompt: "Iteration {{iteration}}{{#if last_result}}, last said {{last_result}}{{/if}}".to_string(),
+            model: None,
+            exit: Default::default(),
+            max_iterations: None,
+        }];
+        let mut deadloop = Deadloop::start_with("success", quiet(), stages);
+        deadloop.expect_sent(|m| matches!(m, CliToServer::UserInput { text, .. } if text.ends_with("\nIteration 1")));
+        deadloop.expect_sent(
+            |m| matches!(m, CliToServer::UserInput { text, .. } if text.ends_with("\nIteration 2, last said Done")),
+        );
+        let invocations = deadloop.backend.invocations();
+        assert_eq!(invocations[1].last().map(String::as_str), Some("Iteration 2, last said Done"));
+    }
+
+    #[test]
+    fn test_failed_verification_is_fed_into_the_next_prompt() {
+        let options = DeadloopOptions {
+            verification: Some(Verification { command: "echo 'test foo ... FAILED'; exit 3".to_string(), timeout_secs: 10 }),
+            ..quiet()
+        };
+        let mut deadloop = Deadloop::start_with("success", options, Vec::new());
+        deadloop.expect_sent(|m| matches!(m, CliToServer::VerificationOutput { line, .. } if line == "test foo ... FAILED"));
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::VerificationFinished { passed: false, exit_code: Some(3), timed_out: false, .. })
+        });
+        // A failed check counts as a failed iteration
+        deadloop.expect_output("[Backing off");
+        let prompt = deadloop.expect_sent(|m| matches!(m, CliToServer::UserInput { text, .. } if text.starts_with("[Iteration 2]")));
+        let CliToServer::UserInput { text, .. } = prompt else { unreachable!() };
+        assert!(text.contains("work on TODO.md\n\nAfter the last iteration, the verification command"));
+        assert!(text.contains("failed with exit code 3"));
+        assert!(text.contains("test foo ... FAILED"));
+    }
+
+    #[test]
+    fn test_skipped_verification_drops_the_old_failure() {
+        let result = |is_error: bool| {
+            serde_json::json!({
+                "type": "result", "subtype": "success", "session_id": "s", "is_error": is_error,
+                "result": "Done", "total_cost_usd": 0.01, "duration_ms": 5,
+            })
+            .to_string()
+        };
+        let scenario = std::env::temp_dir().join(format!("apas-scenario-{}", Uuid::new_v4()));
+        std::fs::write(&scenario, result(false)).unwrap();
+        let options = DeadloopOptions {
+            verification: Some(Verification { command: "exit 3".to_string(), timeout_secs: 10 }),
+            ..quiet()
+        };
+        let mut deadloop = Deadloop::start_with(scenario.to_str().unwrap(), options, Vec::new());
+        deadloop.expect_sent(|m| matches!(m, CliToServer::VerificationFinished { passed: false, .. }));
+        // The next iteration errors, so nothing is verified after it
+        std::fs::write(&scenario, result(true)).unwrap();
+
+        let prompt = |m: &CliToServer, iteration: &str| {
+            matches!(m, CliToServer::UserInput { text, .. } if text.starts_with(iteration))
+        };
+        let CliToServer::UserInput { text, .. } = deadloop.expect_sent(|m| prompt(m, "[Iteration 2]")) else {
+            unreachable!()
+        };
+        assert!(text.contains("failed with exit code 3"));
+        let CliToServer::UserInput { text, .. } = deadloop.expect_sent(|m| prompt(m, "[Iteration 3]")) else {
+            unreachable!()
+        };
+        assert!(!text.contains("verification command"), "stale failure in {:?}", text);
+        let _ = std::fs::remove_file(scenario);
+    }
+
+    #[test]
+    fn test_hooks_run_around_iterations() {
+        let hooks = Hooks {
+            pre_iteration: Some("echo pre $APAS_ITERATION $APAS_STAGE".to_string()),
+            post_iteration: Some("echo post $APAS_IS_ERROR $APAS_RESULT".to_string()),
+            ..Hooks::default()
+        };
+        let mut deadloop = Deadloop::start_with("success", DeadloopOptions { hooks, ..quiet() }, Vec::new());
+        let hook_line = |m: &CliToServer, hook: &str, want: &str| {
+            matches!(m, CliToServer::HookOutput { hook: h, line, .. } if h == hook && line == want)
+        };
+        deadloop.expect_sent(|m| hook_line(m, "pre_iteration", "pre 1 loop"));
+        deadloop.expect_sent(|m| matches!(m, CliToServer::UserInput { .. }));
+        deadloop.expect_sent(|m| hook_line(m, "post_iteration", "post false Done"));
+
+        // A failing pre_iteration hook skips the iteration
+        let hooks = Hooks { pre_iteration: Some("exit 4".to_string()), ..Hooks::default() };
+        let deadloop = Deadloop::start_with("success", DeadloopOptions { hooks, ..quiet() }, Vec::new());
+        deadloop.expect_output("[pre_iteration] [hook failed (exit status: 4)]");
+        deadloop.expect_output("[Backing off for 4s before retry]");
+        assert!(deadloop.backend.invocations().is_empty());
+    }
+
+    #[test]
+    fn test_failing_pre_iteration_hook_fails_the_iteration() {
+        let hooks = Hooks {
+            pre_iteration: Some("exit 4".to_string()),
+            on_error: Some("echo failed $APAS_ITERATION".to_string()),
+            ..Hooks::default()
+        };
+        let options = DeadloopOptions {
+            hooks: hooks.clone(),
+            stuck_detection: StuckDetection { max_consecutive_errors: 2, ..no_stuck_detection() },
+            ..Default::default()
+        };
+        let mut deadloop = Deadloop::start_with("success", options, Vec::new());
+        let on_error = |m: &CliToServer, want: &str| {
+            matches!(m, CliToServer::HookOutput { hook, line, .. } if hook == "on_error" && line == want)
+        };
+        deadloop.expect_sent(|m| on_error(m, "failed 1"));
+        deadloop.expect_sent(|m| on_error(m, "failed 2"));
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::DeadloopStatus { is_paused: true, reason: Some(PauseReason::Error), .. })
+        });
+        assert!(deadloop.pause.load(Ordering::SeqCst));
+
+        // Skipped iterations count towards the stop conditions too
+        let stop = StopConditions { max_iterations: Some(1), ..Default::default() };
+        let mut deadloop = Deadloop::start_with("success", DeadloopOptions { hooks, stop, ..quiet() }, Vec::new());
+        deadloop.expect_sent(|m| matches!(m, CliToServer::SessionEnd { reason, .. } if reason == "reached 1 iterations"));
+        assert!(deadloop.backend.invocations().is_empty());
+    }
+
+    #[test]
+    fn test_stop_condition_ends_the_session() {
+        let stop = StopConditions { max_iterations: Some(2), ..Default::default() };
+        let mut deadloop = Deadloop::start_with("success", DeadloopOptions { stop, ..quiet() }, Vec::new());
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::SessionEnd { reason, pane_type: Some(PaneType::Deadloop), .. } if reason == "reached 2 iterations")
+        });
+        deadloop.expect_output("[Deadloop finished: reached 2 iterations]");
+        assert_eq!(deadloop.backend.invocations().len(), 2);
+    }
+
+    /// A stand-in for bubblewrap running `script`, in place of its sandbox
+    #[cfg(target_os = "linux")]
+    fn fake_bwrap(script: &str) -> Sandbox {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("apas-bwrap-{}", Uuid::new_v4()));
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        Sandbox { enabled: true, bwrap: path.to_string_lossy().to_string(), ..Default::default() }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_deadloop_does_not_run_without_its_sandbox() {
+        let sandbox = fake_bwrap("echo 'bwrap: No permissions to create new namespace' >&2; exit 1");
+        let mut deadloop = Deadloop::start_with("success", DeadloopOptions { sandbox, ..quiet() }, Vec::new());
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::SandboxViolation { kind: SandboxViolation::SetupFailed, detail, .. }
+                if detail.contains("No permissions to create new namespace"))
+        });
+        deadloop.expect_sent(|m| matches!(m, CliToServer::SessionEnd { .. }));
+        assert!(deadloop.backend.invocations().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sandbox_kill_is_an_iteration_outcome() {
+        // Passes the startup check, then exits the way bwrap does when the
+        // kernel kills what it runs at the hard CPU limit
+        let sandbox = Sandbox {
+            cpu_secs: Some(600),
+            ..fake_bwrap("while [ \"$1\" != -- ]; do shift; done; shift\n[ \"$1\" = true ] && exit 0\nexit 137")
+        };
+        let mut deadloop = Deadloop::start_with("success", DeadloopOptions { sandbox, ..quiet() }, Vec::new());
+        deadloop.expect_output("[Sandboxed: writes limited to the project, network on]");
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::SandboxViolation { kind: SandboxViolation::CpuLimit, .. })
+        });
+        deadloop.expect_output("[Sandbox: killed for using more than 600s of CPU time]");
+        deadloop.expect_output("[Backing off");
+    }
+
+    #[test]
+    fn test_context_rotation_hands_off_to_a_new_session() {
+        let options = DeadloopOptions {
+            context_rotation: ContextRotation { every_iterations: Some(1), ..Default::default() },
+            stop: StopConditions { max_iterations: Some(2), ..Default::default() },
+            ..quiet()
+        };
+        let mut deadloop = Deadloop::start_with("success", options, Vec::new());
+        let mut rotations = Vec::new();
+        for _ in 0..2 {
+            let rotated = deadloop.expect_sent(|m| matches!(m, CliToServer::ClaudeSessionRotated { .. }));
+            let CliToServer::ClaudeSessionRotated { previous_claude_session_id, claude_session_id, summary, .. } = rotated
+            else {
+                unreachable!()
+            };
+            assert_eq!(summary.as_deref(), Some("Done"));
+            rotations.push((previous_claude_session_id, claude_session_id));
+        }
+        deadloop.expect_sent(|m| matches!(m, CliToServer::SessionEnd { .. }));
+
+        // The summary comes from the old session; the new one is created
+        // with it and remembered for restarts
+        let invocations = deadloop.backend.invocations();
+        let flag = |args: &[String], flag: &str| args.windows(2).find(|w| w[0] == flag).map(|w| w[1].clone());
+        assert_eq!(flag(&invocations[1], "--resume"), Some(rotations[0].0.to_string()));
+        assert_eq!(flag(&invocations[2], "--session-id"), Some(rotations[0].1.to_string()));
+        assert!(invocations[2].last().unwrap().contains("Handoff notes from the previous session:\n\nDone"));
+        assert_eq!(rotations[1].0, rotations[0].1);
+        let metadata = get_or_create_project(&deadloop.dir).unwrap();
+        assert_eq!(metadata.deadloop_claude_session_id, Some(rotations[1].1));
+    }
+
+    #[test]
+    fn test_missing_session_is_replaced() {
+        let mut deadloop = Deadloop::start("success", no_stuck_detection(), IterationLimits::default());
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { .. }, .. })
+        });
+        // Claude loses the session between iterations
+        std::fs::remove_dir_all(&deadloop.backend.sessions).unwrap();
+
+        let replaced = deadloop.expect_sent(|m| matches!(m, CliToServer::ClaudeSessionRotated { .. }));
+        let CliToServer::ClaudeSessionRotated { previous_claude_session_id, claude_session_id, summary: None, .. } = replaced
+        else {
+            panic!("unexpected {:?}", replaced)
+        };
+        deadloop.expect_output("no longer exists, starting a new one");
+        // Straight on with the new session, no backoff
+        deadloop.expect_output("=== Iteration 3 ===");
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { .. }, .. })
+        });
+
+        let invocations = deadloop.backend.invocations();
+        let flag = |args: &[String], flag: &str| args.windows(2).find(|w| w[0] == flag).map(|w| w[1].clone());
+        assert_eq!(flag(&invocations[1], "--resume"), Some(previous_claude_session_id.to_string()));
+        assert_eq!(flag(&invocations[2], "--session-id"), Some(claude_session_id.to_string()));
+        let metadata = get_or_create_project(&deadloop.dir).unwrap();
+        assert_eq!(metadata.deadloop_claude_session_id, Some(claude_session_id));
+    }
+
+    #[test]
+    fn test_failures_back_off() {
+        let deadloop = Deadloop::start("error", no_stuck_detection(), IterationLimits::default());
+        deadloop.expect_output("[Backing off for 4s before retry]");
+
+        let deadloop = Deadloop::start("crash", no_stuck_detection(), IterationLimits::default());
+        deadloop.expect_output("[Claude process exited with");
+        deadloop.expect_output("[Backing off for 4s before retry]");
+    }
+
+    #[test]
+    fn test_garbage_output_is_forwarded_as_text() {
+        let mut deadloop = Deadloop::start("garbage", no_stuck_detection(), IterationLimits::default());
+        deadloop.expect_sent(|m| matches!(m, CliToServer::Output { data, .. } if data == "not json {"));
+        deadloop.expect_sent(|m| matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { .. }, .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_deadloop_drives_a_command_agent() {
+        use crate::agent::CommandBackend;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("apas-deadloop-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let agent = dir.join("agent.sh");
+        let script = r#"#!/bin/sh
+echo '{"type":"start","model":"script-agent"}'
+echo "{\"type\":\"text\",\"text\":\"resume=${APAS_RESUME:-0}\"}"
+echo 'Compiling the project'
+echo '{"type":"tool_use","id":"1","name":"Bash","input":{"command":"ls"}}'
+echo '{"type":"tool_result","tool_use_id":"1","content":"TODO.md"}'
+echo '{"type":"result","text":"Done","cost_usd":0.01,"duration_ms":5}'
+"#;
+        std::fs::write(&agent, script).unwrap();
+        std::fs::set_permissions(&agent, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let backend = Arc::new(CommandBackend::new(agent.to_str().unwrap(), &[]));
+        let mut deadloop = Deadloop::run(backend, dir, quiet(), Vec::new());
+
+        let text = |m: &CliToServer, want: &str| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::Assistant { message, .. }, pane_type: Some(PaneType::Deadloop), .. }
+                if matches!(message.content.as_slice(), [shared::ClaudeContentBlock::Text { text }] if text == want))
+        };
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::System { model, .. }, .. } if model == "script-agent")
+        });
+        deadloop.expect_sent(|m| text(m, "resume=0"));
+        deadloop.expect_sent(|m| matches!(m, CliToServer::Output { data, .. } if data == "Compiling the project"));
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::User { .. }, .. })
+        });
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { result, is_error: false, .. }, .. } if result == "Done")
+        });
+        // The next iteration continues the same session
+        deadloop.expect_sent(|m| text(m, "resume=1"));
+    }
+
+    #[test]
+    fn test_stuck_loop_pauses_until_resumed() {
+        let stuck_detection = StuckDetection {
+            max_consecutive_errors: 1,
+            ..no_stuck_detection()
+        };
+        let mut deadloop = Deadloop::start("error", stuck_detection, IterationLimits::default());
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::DeadloopStatus { is_paused: true, reason: Some(PauseReason::Error), .. })
+        });
+        deadloop.expect_output("[Deadloop paused - waiting for resume...]");
+        assert!(deadloop.pause.load(Ordering::SeqCst));
+
+        deadloop.pause.store(false, Ordering::SeqCst);
+        deadloop.expect_output("[Deadloop resumed]");
+        deadloop.expect_output("=== Iteration 2 ===");
+    }
+
+    #[test]
+    fn test_spending_limit_pauses_the_loop() {
+        // The fake reports $0.01 per iteration
+        let options = DeadloopOptions { budget: Budget { max_cost_usd: Some(0.02) }, ..quiet() };
+        let mut deadloop = Deadloop::start_with("success", options, Vec::new());
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::DeadloopStatus { is_paused: true, reason: Some(PauseReason::Budget), .. })
+        });
+        deadloop.expect_output("[Deadloop over budget (spent $0.02 of $0.02), pausing]");
+        assert!(deadloop.pause.load(Ordering::SeqCst));
+        assert_eq!(deadloop.backend.invocations().len(), 2);
+    }
+
+    #[test]
+    fn test_hung_iteration_is_stopped() {
+        let limits = IterationLimits {
+            max_duration_secs: 0,
+            max_silence_secs: 1,
+        };
+        let mut deadloop = Deadloop::start("hang", no_stuck_detection(), limits);
+        deadloop.expect_output("[Iteration produced no output for too long, interrupting Claude]");
+        deadloop.expect_sent(|m| {
+            matches!(m, CliToServer::IterationTimedOut { reason: shared::IterationTimeout::Idle, .. })
+        });
+    }
+
+    #[test]
+    fn test_interactive_messages_share_one_process_until_it_dies() {
+        let mut interactive = Interactive::start("success");
+        interactive.ask("first");
+        interactive.ask("second");
+        let invocations = interactive.backend.invocations();
+        assert_eq!(invocations.len(), 1);
+        assert!(invocations[0].windows(2).any(|w| w == ["--input-format", "stream-json"]));
+
+        assert!(interactive.current_process.signal(Signal::Kill).unwrap());
+        interactive.expect_output("[Claude exited; the next message resumes the conversation]");
+        interactive.ask("third");
+
+        let invocations = interactive.backend.invocations();
+        let flag = |args: &[String], flag: &str| args.windows(2).find(|w| w[0] == flag).map(|w| w[1].clone());
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(flag(&invocations[1], "--resume"), flag(&invocations[0], "--session-id"));
+    }
+
+    #[test]
+    fn test_interactive_missing_session_is_replaced() {
+        let mut interactive = Interactive::start("success");
+        interactive.ask("first");
+        assert!(interactive.current_process.signal(Signal::Kill).unwrap());
+        interactive.expect_output("[Claude exited; the next message resumes the conversation]");
+        // Claude loses the session while no process is running
+        std::fs::remove_dir_all(&interactive.backend.sessions).unwrap();
+
+        interactive.input_tx.send("second".to_string()).unwrap();
+        let replaced = interactive.expect_sent(|m| matches!(m, CliToServer::ClaudeSessionRotated { .. }));
+        let CliToServer::ClaudeSessionRotated { previous_claude_session_id, claude_session_id, summary: None, .. } = replaced
+        else {
+            panic!("unexpected {:?}", replaced)
+        };
+        // The message the failed resume swallowed goes to the new session
+        interactive.expect_sent(|m| {
+            matches!(m, CliToServer::StreamMessage { message: ClaudeStreamMessage::Result { .. }, pane_type: Some(PaneType::Interactive), .. })
+        });
+
+        let invocations = interactive.backend.invocations();
+        let flag = |args: &[String], flag: &str| args.windows(2).find(|w| w[0] == flag).map(|w| w[1].clone());
+        assert_eq!(flag(&invocations[1], "--resume"), Some(previous_claude_session_id.to_string()));
+        assert_eq!(flag(&invocations[2], "--session-id"), Some(claude_session_id.to_string()));
+        let metadata = get_or_create_project(&interactive.dir).unwrap();
+        assert_eq!(metadata.interactive_claude_session_id, Some(claude_session_id));
+    }
+}
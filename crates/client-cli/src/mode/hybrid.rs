@@ -5,6 +5,7 @@
 
 use anyhow::Result;
 use futures::{SinkExt, StreamExt};
+use shared::transport::Compression;
 use shared::{CliToServer, ClaudeStreamMessage, ServerToCli};
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -16,14 +17,25 @@ use tokio::sync::mpsc;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 use uuid::Uuid;
 
-use crate::config::Config;
+use crate::config::{ClaudeSettings, Config};
 use crate::project;
+use crate::signal::{self, CurrentProcess, Signal};
+use crate::transport::send_batch;
 
 const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
 const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
 const VERSION: &str = env!("APAS_VERSION");
 
+/// What the web UI can do to the running loop
+#[derive(Clone, Default)]
+struct Controls {
+    /// Process group of the running Claude, for signals
+    process: CurrentProcess,
+    /// Model picked in the web UI, overriding the configured one
+    model: Arc<Mutex<Option<String>>>,
+}
+
 const DEFAULT_PROMPT: &str = r#"Work on tasks defined in TODO.md. Repeat the following steps, don't stop until interrupted. Don't ask me for advice, just pick the best option you think that is honest, complete, and not corner-cutting:
 
 1. Pick a task: First check if there are any repeated task that needs to be run again. If yes this is the task we need to do and go to step 2. If no repeated task needs to run, pick the top undone task with highest priority (high-medium-low), choose its first leaf task. If there are no task at all, (no fit repeated task and no undone TODO items left), sleep a minute, check if TODO.md is updated locally, and git pull to see if TODO.md is updated remotely. Restart step 1 (so this step is a dead loop until you find a todo item).
@@ -44,6 +56,8 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     let session_id = project_meta.id;
     let project_name = project_meta.name.clone();
     let prompt = project_meta.prompt.clone().unwrap_or_else(|| DEFAULT_PROMPT.to_string());
+    // Hybrid mode runs the deadloop's settings; the project's win over this machine's
+    let settings = project_meta.claude.deadloop.or(&config.local.claude.deadloop);
 
     // Channel for sending output to server (buffered to handle reconnections)
     let (server_tx, server_rx) = mpsc::channel::<CliToServer>(256);
@@ -53,6 +67,7 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
 
     // Shared handle to current Claude child process (for cleanup on Ctrl+C)
     let child_process: Arc<Mutex<Option<Child>>> = Arc::new(Mutex::new(None));
+    let controls = Controls::default();
 
     // Set up Ctrl+C handler to kill Claude process on exit
     let shutdown_for_ctrlc = shutdown.clone();
@@ -73,8 +88,9 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     let token_owned = token.to_string();
     let shutdown_clone = shutdown.clone();
     let working_dir_str = working_dir.to_string_lossy().to_string();
+    let controls_for_server = controls.clone();
     let _server_task = tokio::spawn(async move {
-        run_server_connection(&server_url_owned, &token_owned, session_id, &working_dir_str, server_rx, shutdown_clone).await
+        run_server_connection(&server_url_owned, &token_owned, session_id, &working_dir_str, server_rx, shutdown_clone, controls_for_server).await
     });
 
     // Run Claude with stream-json output (blocking I/O in a separate thread)
@@ -82,7 +98,7 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
     let working_dir_owned = working_dir.to_path_buf();
     let shutdown_for_claude = shutdown.clone();
     let result = tokio::task::spawn_blocking(move || {
-        run_dead_loop_session(&claude_path_owned, &working_dir_owned, session_id, project_name, &prompt, server_tx, &shutdown_for_claude, child_process)
+        run_dead_loop_session(&claude_path_owned, &working_dir_owned, session_id, project_name, &prompt, server_tx, &shutdown_for_claude, child_process, &settings, controls)
     }).await?;
 
     // Signal shutdown
@@ -93,6 +109,7 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
 
 /// Run Claude CLI in a dead loop, continuously sending the same prompt
 /// Uses --resume to maintain conversation continuity across invocations
+#[allow(clippy::too_many_arguments)]
 fn run_dead_loop_session(
     claude_path: &str,
     working_dir: &Path,
@@ -102,6 +119,8 @@ fn run_dead_loop_session(
     server_tx: mpsc::Sender<CliToServer>,
     shutdown: &Arc<AtomicBool>,
     child_handle: Arc<Mutex<Option<Child>>>,
+    settings: &ClaudeSettings,
+    controls: Controls,
 ) -> Result<()> {
     let display_name = project_name.as_deref().unwrap_or("unnamed");
     println!("Project: {} (ID: {})", display_name, session_id);
@@ -132,23 +151,26 @@ fn run_dead_loop_session(
         }
 
         // Run Claude with --print for this prompt
-        let args = vec![
-            "--print".to_string(),
+        let model = controls.model.lock().ok().and_then(|m| m.clone());
+        let mut args = vec!["--print".to_string()];
+        args.extend(settings.args(model.as_deref()));
+        args.extend([
             "--output-format".to_string(), "stream-json".to_string(),
             "--verbose".to_string(),
             "--dangerously-skip-permissions".to_string(),
             prompt.to_string(),
-        ];
+        ]);
 
         // Spawn Claude for this prompt
-        let mut child = match Command::new(claude_path)
+        let mut command = Command::new(claude_path);
+        command
             .args(&args)
             .current_dir(working_dir)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-        {
+            .stderr(Stdio::piped());
+        signal::new_process_group(&mut command);
+        let mut child = match command.spawn() {
             Ok(c) => c,
             Err(e) => {
                 eprintln!("Failed to spawn Claude: {}", e);
@@ -163,6 +185,7 @@ fn run_dead_loop_session(
             .ok_or_else(|| anyhow::anyhow!("Failed to capture stderr"))?;
 
         // Store child in shared handle so Ctrl+C handler can kill it
+        controls.process.set(child.id());
         if let Ok(mut guard) = child_handle.lock() {
             *guard = Some(child);
         }
@@ -174,20 +197,18 @@ fn run_dead_loop_session(
         let rate_limit_clone = rate_limit_detected.clone();
         let stderr_thread = std::thread::spawn(move || {
             let reader = BufReader::new(stderr);
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    eprintln!("{}", line);
-                    // Check for rate limit / usage limit keywords
-                    let lower = line.to_lowercase();
-                    if lower.contains("rate limit")
-                        || lower.contains("rate_limit")
-                        || lower.contains("usage limit")
-                        || lower.contains("too many requests")
-                        || lower.contains("quota exceeded")
-                        || lower.contains("capacity")
-                        || lower.contains("overloaded") {
-                        rate_limit_clone.store(true, Ordering::SeqCst);
-                    }
+            for line in reader.lines().map_while(Result::ok) {
+                eprintln!("{}", line);
+                // Check for rate limit / usage limit keywords
+                let lower = line.to_lowercase();
+                if lower.contains("rate limit")
+                    || lower.contains("rate_limit")
+                    || lower.contains("usage limit")
+                    || lower.contains("too many requests")
+                    || lower.contains("quota exceeded")
+                    || lower.contains("capacity")
+                    || lower.contains("overloaded") {
+                    rate_limit_clone.store(true, Ordering::SeqCst);
                 }
             }
         });
@@ -260,12 +281,14 @@ fn run_dead_loop_session(
                 *guard = None; // Clear the handle
                 status
             } else {
-                Err(std::io::Error::new(std::io::ErrorKind::Other, "Child process not found"))
+                Err(std::io::Error::other("Child process not found"))
             }
         } else {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to lock child handle"))
+            Err(std::io::Error::other("Failed to lock child handle"))
         };
 
+        controls.process.clear();
+
         match &status {
             Ok(s) => tracing::debug!("Claude exited with status: {}", s),
             Err(e) => tracing::debug!("Error waiting for Claude: {}", e),
@@ -355,6 +378,7 @@ async fn run_server_connection(
     working_dir: &str,
     mut output_rx: mpsc::Receiver<CliToServer>,
     shutdown: Arc<AtomicBool>,
+    controls: Controls,
 ) {
     let mut reconnect_delay = INITIAL_RECONNECT_DELAY;
 
@@ -363,7 +387,7 @@ async fn run_server_connection(
             break;
         }
 
-        match connect_to_server(server_url, token, session_id, working_dir, &mut output_rx, &shutdown).await {
+        match connect_to_server(server_url, token, session_id, working_dir, &mut output_rx, &shutdown, &controls).await {
             Ok(_) => {
                 reconnect_delay = INITIAL_RECONNECT_DELAY;
             }
@@ -388,6 +412,7 @@ async fn connect_to_server(
     working_dir: &str,
     output_rx: &mut mpsc::Receiver<CliToServer>,
     shutdown: &Arc<AtomicBool>,
+    controls: &Controls,
 ) -> Result<()> {
     let ws_url = format!("{}/ws/cli", server_url);
     tracing::debug!("Connecting to server: {}", ws_url);
@@ -399,19 +424,21 @@ async fn connect_to_server(
     let register_msg = CliToServer::Register {
         token: token.to_string(),
         version: Some(VERSION.to_string()),
+        compression: Compression::SUPPORTED.to_vec(),
+        machine: crate::machine::info(),
     };
     let msg_text = serde_json::to_string(&register_msg)?;
-    ws_sender.send(Message::Text(msg_text.into())).await?;
+    ws_sender.send(Message::Text(msg_text)).await?;
 
     // Wait for registration response
-    loop {
+    let compression = loop {
         match ws_receiver.next().await {
             Some(Ok(Message::Text(text))) => {
                 let response: ServerToCli = serde_json::from_str(&text)?;
                 match response {
-                    ServerToCli::Registered { cli_id } => {
-                        tracing::debug!("Connected to server as CLI {}", cli_id);
-                        break;
+                    ServerToCli::Registered { cli_id, compression } => {
+                        tracing::debug!("Connected to server as CLI {} (compression: {:?})", cli_id, compression);
+                        break compression;
                     }
                     ServerToCli::RegistrationFailed { reason } => {
                         return Err(anyhow::anyhow!("Registration failed: {}", reason));
@@ -434,7 +461,7 @@ async fn connect_to_server(
             None => return Err(anyhow::anyhow!("Connection closed during registration")),
             _ => continue,
         }
-    }
+    };
 
     // Send SessionStart to register our local session with the server
     let hostname = hostname::get()
@@ -447,7 +474,7 @@ async fn connect_to_server(
         pane_type: None,
     };
     let msg_text = serde_json::to_string(&session_start_msg)?;
-    ws_sender.send(Message::Text(msg_text.into())).await?;
+    ws_sender.send(Message::Text(msg_text)).await?;
     tracing::debug!("Registered local session {} with server", session_id);
 
     // Channel for sending to WebSocket
@@ -472,8 +499,7 @@ async fn connect_to_server(
     // Task to send messages to WebSocket
     let send_task = tokio::spawn(async move {
         while let Some(msg) = ws_rx.recv().await {
-            let text = serde_json::to_string(&msg).unwrap();
-            if ws_sender.send(Message::Text(text.into())).await.is_err() {
+            if send_batch(&mut ws_sender, msg, &mut ws_rx, compression).await.is_err() {
                 break;
             }
         }
@@ -494,8 +520,27 @@ async fn connect_to_server(
             }
             msg_result = ws_receiver.next() => {
                 match msg_result {
-                    Some(Ok(Message::Text(_))) => {
-                        // Handle server messages if needed
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str(&text) {
+                            Ok(ServerToCli::Signal { signal: name, .. }) => {
+                                match Signal::parse(&name).map(|s| controls.process.signal(s)) {
+                                    Some(Ok(true)) => println!("\n[{} received from web]", name),
+                                    Some(Ok(false)) => {}
+                                    Some(Err(e)) => eprintln!("\n[Failed to deliver {}: {}]", name, e),
+                                    None => tracing::warn!("Unknown signal from web: {}", name),
+                                }
+                            }
+                            Ok(ServerToCli::SetModel { model, .. }) => {
+                                match &model {
+                                    Some(m) => println!("\n[Model set to {} from the next iteration]", m),
+                                    None => println!("\n[Configured model restored from the next iteration]"),
+                                }
+                                if let Ok(mut guard) = controls.model.lock() {
+                                    *guard = model;
+                                }
+                            }
+                            _ => {}
+                        }
                     }
                     Some(Ok(Message::Close(_))) => break,
                     Some(Err(_)) => break,
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use futures::{SinkExt, StreamExt};
+use shared::transport::Compression;
 use shared::{CliToServer, OutputType, ServerToCli};
 use std::path::Path;
 use std::time::Duration;
@@ -9,12 +10,19 @@ use uuid::Uuid;
 
 use crate::claude::ClaudeProcess;
 use crate::config::Config;
+use crate::transport::send_batch;
 
 const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
 const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
 const VERSION: &str = env!("APAS_VERSION");
 
+/// What the server can ask of a running Claude process
+enum ProcessCommand {
+    Input(String),
+    Signal(String),
+}
+
 /// Run in remote mode - connect to backend server and stream I/O
 /// Automatically reconnects on connection loss with exponential backoff
 pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()> {
@@ -28,12 +36,7 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
         attempt += 1;
 
         match run_connection(server_url, token, working_dir, &claude_path).await {
-            Ok(ConnectionResult::Shutdown) => {
-                // Explicit shutdown requested
-                tracing::info!("Shutting down");
-                break;
-            }
-            Ok(ConnectionResult::Disconnected) => {
+            Ok(()) => {
                 // Server closed connection or we lost connectivity - reconnect
                 // Reset backoff since we had a successful connection
                 reconnect_delay = INITIAL_RECONNECT_DELAY;
@@ -60,24 +63,15 @@ pub async fn run(server_url: &str, token: &str, working_dir: &Path) -> Result<()
 
         tokio::time::sleep(reconnect_delay).await;
     }
-
-    Ok(())
-}
-
-/// Result of a connection attempt
-enum ConnectionResult {
-    /// Connection was gracefully closed by server (reconnect)
-    Disconnected,
-    /// Client received shutdown signal (exit)
-    Shutdown,
 }
 
+/// One connection to the server; Ok once it closes, to reconnect
 async fn run_connection(
     server_url: &str,
     token: &str,
     working_dir: &Path,
     claude_path: &str,
-) -> Result<ConnectionResult> {
+) -> Result<()> {
     // Connect to WebSocket
     let ws_url = format!("{}/ws/cli", server_url);
     tracing::info!("Connecting to {}...", ws_url);
@@ -89,22 +83,24 @@ async fn run_connection(
     let register_msg = CliToServer::Register {
         token: token.to_string(),
         version: Some(VERSION.to_string()),
+        compression: Compression::SUPPORTED.to_vec(),
+        machine: crate::machine::info(),
     };
     let msg_text = serde_json::to_string(&register_msg)?;
-    ws_sender.send(Message::Text(msg_text.into())).await?;
+    ws_sender.send(Message::Text(msg_text)).await?;
 
     // Wait for registration response
     let cli_id: Uuid;
-    loop {
+    let compression = loop {
         match ws_receiver.next().await {
             Some(Ok(Message::Text(text))) => {
                 let response: ServerToCli = serde_json::from_str(&text)?;
                 match response {
-                    ServerToCli::Registered { cli_id: id } => {
+                    ServerToCli::Registered { cli_id: id, compression } => {
                         cli_id = id;
-                        tracing::info!("Connected and registered as CLI {}", cli_id);
+                        tracing::info!("Connected and registered as CLI {} (compression: {:?})", cli_id, compression);
                         println!("Connected to server. CLI ID: {}", cli_id);
-                        break;
+                        break compression;
                     }
                     ServerToCli::RegistrationFailed { reason } => {
                         return Err(anyhow::anyhow!("Registration failed: {}", reason));
@@ -127,21 +123,20 @@ async fn run_connection(
             None => return Err(anyhow::anyhow!("Connection closed during registration")),
             _ => continue,
         }
-    }
+    };
 
     // Channel for sending messages to WebSocket
     let (ws_tx, mut ws_rx) = mpsc::channel::<CliToServer>(32);
 
     // Active Claude processes per session
     let claude_processes: std::sync::Arc<
-        tokio::sync::Mutex<std::collections::HashMap<Uuid, mpsc::Sender<String>>>,
+        tokio::sync::Mutex<std::collections::HashMap<Uuid, mpsc::Sender<ProcessCommand>>>,
     > = std::sync::Arc::new(tokio::sync::Mutex::new(std::collections::HashMap::new()));
 
     // Task to send messages to WebSocket
     let send_task = tokio::spawn(async move {
         while let Some(msg) = ws_rx.recv().await {
-            let text = serde_json::to_string(&msg).unwrap();
-            if ws_sender.send(Message::Text(text.into())).await.is_err() {
+            if send_batch(&mut ws_sender, msg, &mut ws_rx, compression).await.is_err() {
                 break;
             }
         }
@@ -198,16 +193,19 @@ async fn run_connection(
                         // Forward input to the appropriate Claude process
                         let processes = processes.lock().await;
                         if let Some(sender) = processes.get(&session_id) {
-                            let _ = sender.send(data).await;
+                            let _ = sender.send(ProcessCommand::Input(data)).await;
                         }
                     }
-                    Ok(ServerToCli::Signal { session_id, signal }) => {
+                    Ok(ServerToCli::Signal { session_id, signal, .. }) => {
                         tracing::info!(
                             "Received signal {} for session {}",
                             signal,
                             session_id
                         );
-                        // TODO: Forward signal to Claude process
+                        let processes = processes.lock().await;
+                        if let Some(sender) = processes.get(&session_id) {
+                            let _ = sender.send(ProcessCommand::Signal(signal)).await;
+                        }
                     }
                     Ok(ServerToCli::SessionDisconnected { session_id }) => {
                         tracing::info!("Session {} disconnected from web", session_id);
@@ -222,8 +220,9 @@ async fn run_connection(
                         // Already handled during registration
                     }
                     Ok(ServerToCli::PauseDeadloop { .. })
-                    | Ok(ServerToCli::ResumeDeadloop { .. }) => {
-                        // Pause/resume not supported in remote mode
+                    | Ok(ServerToCli::ResumeDeadloop { .. })
+                    | Ok(ServerToCli::SetModel { .. }) => {
+                        // Pause/resume and model changes not supported in remote mode
                     }
                     Err(e) => {
                         tracing::warn!("Failed to parse server message: {}", e);
@@ -249,8 +248,8 @@ async fn run_connection(
     heartbeat_task.abort();
     send_task.abort();
 
-    // Return disconnected to trigger reconnection
-    Ok(ConnectionResult::Disconnected)
+    // Closed; the caller reconnects
+    Ok(())
 }
 
 async fn handle_session(
@@ -259,7 +258,7 @@ async fn handle_session(
     working_dir: &Path,
     ws_tx: mpsc::Sender<CliToServer>,
     processes: std::sync::Arc<
-        tokio::sync::Mutex<std::collections::HashMap<Uuid, mpsc::Sender<String>>>,
+        tokio::sync::Mutex<std::collections::HashMap<Uuid, mpsc::Sender<ProcessCommand>>>,
     >,
 ) -> Result<()> {
     tracing::info!("Starting Claude process for session {}", session_id);
@@ -269,7 +268,7 @@ async fn handle_session(
         ClaudeProcess::spawn(claude_path, working_dir).await?;
 
     // Channel for input to this Claude process
-    let (input_tx, mut input_rx) = mpsc::channel::<String>(32);
+    let (input_tx, mut input_rx) = mpsc::channel::<ProcessCommand>(32);
 
     // Register this process
     {
@@ -307,11 +306,20 @@ async fn handle_session(
         }
     });
 
-    // Task to forward input from server to Claude
+    // Task to forward input and signals from server to Claude
     let input_task = tokio::spawn(async move {
-        while let Some(input) = input_rx.recv().await {
-            if claude.send_input(&input).await.is_err() {
-                break;
+        while let Some(command) = input_rx.recv().await {
+            match command {
+                ProcessCommand::Input(input) => {
+                    if claude.send_input(&input).await.is_err() {
+                        break;
+                    }
+                }
+                ProcessCommand::Signal(signal) => {
+                    if let Err(e) = claude.send_signal(&signal) {
+                        tracing::warn!("Failed to send {} to session {}: {}", signal, session_id, e);
+                    }
+                }
             }
         }
         // Wait for process to exit
@@ -332,6 +340,7 @@ async fn handle_session(
         .send(CliToServer::SessionEnd {
             session_id,
             reason: "Process exited".to_string(),
+            pane_type: None,
         })
         .await;
 
@@ -1,8 +1,21 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use shared::PaneType;
+use std::path::Path;
 use uuid::Uuid;
 
+use crate::agent::AgentConfig;
+use crate::budget::Budget;
+use crate::config::PaneSettings;
+use crate::hooks::Hooks;
+use crate::rotation::ContextRotation;
+use crate::sandbox::Sandbox;
+use crate::stop::StopConditions;
+use crate::stuck::StuckDetection;
+use crate::verify::Verification;
+use crate::watchdog::IterationLimits;
+use crate::workflow::Stage;
+
 const APAS_FILE: &str = ".apas";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,37 +29,48 @@ pub struct ProjectMetadata {
     /// Custom prompt to use (if not set, uses default)
     #[serde(default)]
     pub prompt: Option<String>,
+    /// Named deadloop stages run in order; replaces `prompt` when set
+    #[serde(default)]
+    pub workflow: Vec<Stage>,
     /// Claude session ID for the deadloop pane (persisted for --resume)
     #[serde(default)]
     pub deadloop_claude_session_id: Option<Uuid>,
     /// Claude session ID for the interactive pane (persisted for --resume)
     #[serde(default)]
     pub interactive_claude_session_id: Option<Uuid>,
+    /// When to pause the deadloop because it looks stuck
+    #[serde(default)]
+    pub stuck_detection: StuckDetection,
+    /// When to pause the deadloop because it has spent enough
+    #[serde(default)]
+    pub budget: Budget,
+    /// When to stop a deadloop iteration that hangs
+    #[serde(default)]
+    pub iteration_limits: IterationLimits,
+    /// Command checking each deadloop iteration's work
+    #[serde(default)]
+    pub verify: Option<Verification>,
+    /// Shell commands run around deadloop iterations
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// When the deadloop ends on its own
+    #[serde(default)]
+    pub stop: StopConditions,
+    /// When the deadloop moves to a fresh Claude session
+    #[serde(default)]
+    pub context_rotation: ContextRotation,
+    /// Isolation for deadloop iterations (Linux only)
+    #[serde(default)]
+    pub sandbox: Sandbox,
+    /// How Claude is invoked in each pane; overrides the machine's config
+    #[serde(default)]
+    pub claude: PaneSettings,
+    /// Agent the panes drive; overrides the machine's config
+    #[serde(default)]
+    pub agent: Option<AgentConfig>,
 }
 
 impl ProjectMetadata {
-    pub fn new() -> Self {
-        Self {
-            id: Uuid::new_v4(),
-            name: None,
-            created_at: chrono::Utc::now().to_rfc3339(),
-            prompt: None,
-            deadloop_claude_session_id: None,
-            interactive_claude_session_id: None,
-        }
-    }
-
-    pub fn with_name(name: String) -> Self {
-        Self {
-            id: Uuid::new_v4(),
-            name: Some(name),
-            created_at: chrono::Utc::now().to_rfc3339(),
-            prompt: None,
-            deadloop_claude_session_id: None,
-            interactive_claude_session_id: None,
-        }
-    }
-
     /// Get or create the deadloop Claude session ID
     pub fn get_or_create_deadloop_session_id(&mut self) -> Uuid {
         if let Some(id) = self.deadloop_claude_session_id {
@@ -91,8 +115,19 @@ pub fn get_or_create_project(dir: &Path) -> Result<ProjectMetadata> {
             name,
             created_at: chrono::Utc::now().to_rfc3339(),
             prompt: None,
+            workflow: Vec::new(),
             deadloop_claude_session_id: None,
             interactive_claude_session_id: None,
+            stuck_detection: StuckDetection::default(),
+            budget: Budget::default(),
+            iteration_limits: IterationLimits::default(),
+            verify: None,
+            hooks: Hooks::default(),
+            stop: StopConditions::default(),
+            context_rotation: ContextRotation::default(),
+            sandbox: Sandbox::default(),
+            claude: PaneSettings::default(),
+            agent: None,
         };
 
         // Save to file
@@ -113,12 +148,14 @@ pub fn save_project(dir: &Path, metadata: &ProjectMetadata) -> Result<()> {
     Ok(())
 }
 
-/// Get the .apas file path for a directory
-pub fn get_apas_path(dir: &Path) -> PathBuf {
-    dir.join(APAS_FILE)
-}
-
-/// Check if a directory has been initialized as an apas project
-pub fn is_project(dir: &Path) -> bool {
-    dir.join(APAS_FILE).exists()
+/// Give a pane a new Claude session, saved so restarts resume it
+pub fn replace_claude_session_id(dir: &Path, pane: PaneType) -> Result<Uuid> {
+    let mut metadata = get_or_create_project(dir)?;
+    let id = Uuid::new_v4();
+    match pane {
+        PaneType::Deadloop => metadata.deadloop_claude_session_id = Some(id),
+        PaneType::Interactive => metadata.interactive_claude_session_id = Some(id),
+    }
+    save_project(dir, &metadata)?;
+    Ok(id)
 }
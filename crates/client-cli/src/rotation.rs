@@ -0,0 +1,163 @@
+//! Context rotation for the deadloop
+//!
+//! Resuming one Claude session forever lets its context grow until quality
+//! drops. With a `context_rotation` policy in `.apas` the deadloop
+//! periodically asks the old session for a handoff summary, starts a fresh
+//! session and opens it with that summary.
+
+use serde::{Deserialize, Serialize};
+use shared::ClaudeStreamMessage;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::agent::{self, AgentBackend, AgentSession};
+use crate::signal::{self, Signal};
+
+const DEFAULT_SUMMARY_PROMPT: &str = "This session is about to be replaced by a fresh one with an empty context. \
+Write a handoff for it: what you were working on, what is done, what is left, decisions made and why, \
+and anything that tripped you up. Be concise, don't make changes, just reply with the handoff.";
+
+/// Give up on a handoff summary after this long
+const SUMMARY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextRotation {
+    /// Start a new session after this many iterations in one
+    pub every_iterations: Option<u32>,
+    /// Start a new session once the context reported by Claude reaches this
+    /// many tokens
+    pub max_context_tokens: Option<u64>,
+    /// What to ask the old session for; a generic handoff request by default
+    pub summary_prompt: Option<String>,
+}
+
+impl ContextRotation {
+    pub fn summary_prompt(&self) -> &str {
+        self.summary_prompt.as_deref().unwrap_or(DEFAULT_SUMMARY_PROMPT)
+    }
+}
+
+/// How full the current session is
+pub struct ContextTracker {
+    policy: ContextRotation,
+    iterations: u32,
+    /// Context size of the latest turn
+    tokens: u64,
+}
+
+impl ContextTracker {
+    pub fn new(policy: ContextRotation) -> Self {
+        Self { policy, iterations: 0, tokens: 0 }
+    }
+
+    pub fn policy(&self) -> &ContextRotation {
+        &self.policy
+    }
+
+    /// Feed one stream message from the running iteration
+    pub fn observe(&mut self, message: &ClaudeStreamMessage) {
+        if let ClaudeStreamMessage::Assistant { message, .. } = message {
+            let usage = &message.extra["usage"];
+            let tokens: u64 = ["input_tokens", "cache_creation_input_tokens", "cache_read_input_tokens"]
+                .iter()
+                .filter_map(|field| usage[field].as_u64())
+                .sum();
+            if tokens > 0 {
+                self.tokens = tokens;
+            }
+        }
+    }
+
+    pub fn finish_iteration(&mut self) {
+        self.iterations += 1;
+    }
+
+    /// Why the session should be replaced now, if it should
+    pub fn due(&self) -> Option<String> {
+        if let Some(every) = self.policy.every_iterations.filter(|every| *every > 0 && self.iterations >= *every) {
+            return Some(format!("{} iterations in one session", every));
+        }
+        if self.policy.max_context_tokens.is_some_and(|max| max > 0 && self.tokens >= max) {
+            return Some(format!("context reached {} tokens", self.tokens));
+        }
+        None
+    }
+
+    /// Start counting for a new session
+    pub fn reset(&mut self) {
+        self.iterations = 0;
+        self.tokens = 0;
+    }
+}
+
+/// Ask `session` for a handoff summary. None if the agent failed, timed out
+/// or had nothing to say.
+pub fn ask_for_summary(
+    backend: &dyn AgentBackend,
+    session: &AgentSession,
+    prompt: &str,
+    working_dir: &str,
+    shutdown: &AtomicBool,
+) -> Option<String> {
+    let mut command = backend.iteration_command(session, prompt);
+    agent::prepare(&mut command, working_dir, Stdio::null());
+    let mut child = command.spawn().ok()?;
+
+    let (line_tx, line_rx) = mpsc::channel();
+    let Some(stdout) = child.stdout.take() else {
+        abandon(&mut child);
+        return None;
+    };
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if line_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    // Nobody shows it, but a full pipe would block the agent
+    if let Some(mut stderr) = child.stderr.take() {
+        thread::spawn(move || std::io::copy(&mut stderr, &mut std::io::sink()));
+    }
+
+    let started = Instant::now();
+    let mut summary = None;
+    loop {
+        match line_rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(line) => {
+                if let Some(ClaudeStreamMessage::Result { result, is_error, .. }) = backend.parse_event(&line) {
+                    summary = (!is_error && !result.trim().is_empty()).then(|| result.trim().to_string());
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if shutdown.load(Ordering::SeqCst) || started.elapsed() > SUMMARY_TIMEOUT {
+                    abandon(&mut child);
+                    return None;
+                }
+            }
+        }
+    }
+    let status = child.wait().ok()?;
+    summary.filter(|_| status.success())
+}
+
+/// Kill the agent and everything it started, and reap it
+fn abandon(child: &mut Child) {
+    let _ = signal::signal_group(child.id(), Signal::Kill);
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// A fresh session's first prompt, opening with the old session's handoff
+pub fn seed_prompt(summary: &str, prompt: &str) -> String {
+    format!(
+        "This continues earlier work in a fresh session. Handoff notes from the previous session:\n\n{}\n\n---\n\n{}",
+        summary, prompt
+    )
+}
@@ -0,0 +1,501 @@
+//! Sandbox for deadloop iterations on Linux
+//!
+//! The deadloop runs Claude unattended with permission prompts skipped. With
+//! `sandbox.enabled` in `.apas` every iteration runs under bubblewrap
+//! instead: the filesystem is read-only apart from the project, a scratch
+//! directory mounted at /tmp and a separate HOME, networking can be cut off,
+//! and CPU time is capped with an rlimit, memory and processes with a cgroup.
+
+use serde::{Deserialize, Serialize};
+use shared::{ClaudeContentBlock, ClaudeStreamMessage, SandboxViolation};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use uuid::Uuid;
+
+use crate::agent::{AgentBackend, AgentSession};
+
+/// Tool output that means a write hit the read-only filesystem
+const BLOCKED_WRITE: &[&str] = &["Read-only file system", "EROFS"];
+/// Tool output that means a connection found no network
+const BLOCKED_NETWORK: &[&str] = &[
+    "Could not resolve host",
+    "Temporary failure in name resolution",
+    "Network is unreachable",
+    "getaddrinfo EAI_AGAIN",
+    "getaddrinfo ENOTFOUND",
+];
+/// Output that means a fork or spawn hit the process limit
+const PID_LIMIT: &[&str] = &["fork: Resource temporarily unavailable", "fork: retry", "Cannot fork", "spawn EAGAIN"];
+/// Start of the systemd scope units iterations run in
+const SCOPE_PREFIX: &str = "apas-sandbox-";
+/// Output that means an allocation hit the memory limit
+const OUT_OF_MEMORY: &[&str] = &["heap out of memory", "Cannot allocate memory", "std::bad_alloc", "memory allocation of"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sandbox {
+    pub enabled: bool,
+    /// Allow network access. Claude needs it to reach its API, so turning it
+    /// off only suits agents that talk to a local model.
+    pub network: bool,
+    /// Mounted read-write at /tmp; a directory per session under the system
+    /// temp dir by default
+    pub scratch_dir: Option<PathBuf>,
+    /// HOME inside the sandbox, shared by all projects by default. Claude
+    /// keeps its login there: sign in once with `HOME=<dir> claude`, or set
+    /// ANTHROPIC_API_KEY.
+    pub home: Option<PathBuf>,
+    /// More directories to allow writes to, relative to the project
+    pub writable: Vec<PathBuf>,
+    /// CPU seconds each process may use
+    pub cpu_secs: Option<u64>,
+    /// Memory cap in MB for the whole iteration; needs `cgroup`
+    pub memory_mb: Option<u64>,
+    /// Process cap for the whole iteration; needs `cgroup`
+    pub max_pids: Option<u64>,
+    /// Enforce the memory and process caps with a cgroup, through
+    /// `systemd-run --user --scope`
+    pub cgroup: bool,
+    /// The bubblewrap binary
+    pub bwrap: String,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            network: true,
+            scratch_dir: None,
+            home: None,
+            writable: Vec::new(),
+            cpu_secs: None,
+            memory_mb: None,
+            max_pids: None,
+            cgroup: false,
+            bwrap: "bwrap".to_string(),
+        }
+    }
+}
+
+impl Sandbox {
+    /// Wrap `inner` so its commands run in the sandbox, after checking that
+    /// the sandbox works here
+    pub fn backend<'a>(
+        &self,
+        inner: &'a dyn AgentBackend,
+        working_dir: &Path,
+        session_id: Uuid,
+    ) -> io::Result<SandboxedBackend<'a>> {
+        if !cfg!(target_os = "linux") {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "the sandbox needs Linux"));
+        }
+        // As rlimits these would count reserved address space and every
+        // process the user runs, breaking ordinary iterations
+        if !self.cgroup && (self.memory_mb.is_some() || self.max_pids.is_some()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "memory_mb and max_pids need \"cgroup\": true",
+            ));
+        }
+        let project = working_dir.canonicalize()?;
+        let scratch = match &self.scratch_dir {
+            Some(dir) => project.join(dir),
+            None => std::env::temp_dir().join(format!("apas-sandbox-{}", session_id)),
+        };
+        let home = match &self.home {
+            Some(dir) => project.join(dir),
+            None => directories::ProjectDirs::from("com", "apas", "apas")
+                .map(|dirs| dirs.data_dir().join("sandbox-home"))
+                .unwrap_or_else(|| scratch.join("home")),
+        };
+        for dir in [&scratch, &home] {
+            std::fs::create_dir_all(dir)?;
+        }
+        let backend = SandboxedBackend {
+            inner,
+            sandbox: self.clone(),
+            scratch: scratch.canonicalize()?,
+            home: home.canonicalize()?,
+            project,
+        };
+
+        // Fails fast on a missing bwrap or disabled user namespaces
+        let output = backend
+            .wrap(Command::new("true"))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| io::Error::new(e.kind(), format!("can't run {}: {}", self.bwrap, e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!("{} failed: {}", self.bwrap, stderr.trim())));
+        }
+        Ok(backend)
+    }
+}
+
+/// An agent backend whose commands run in the sandbox
+pub struct SandboxedBackend<'a> {
+    inner: &'a dyn AgentBackend,
+    sandbox: Sandbox,
+    project: PathBuf,
+    scratch: PathBuf,
+    home: PathBuf,
+}
+
+impl SandboxedBackend<'_> {
+    pub fn sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+
+    /// `command`, run by bubblewrap (in a cgroup scope if configured)
+    fn wrap(&self, command: Command) -> Command {
+        let s = &self.sandbox;
+        let mut wrapped = if s.cgroup {
+            let mut systemd_run = Command::new("systemd-run");
+            systemd_run.args(["--user", "--scope", "--quiet", "--collect"]);
+            // Named so the monitor can tell the scope from the one APAS runs in
+            systemd_run.arg(format!("--unit={}{}", SCOPE_PREFIX, Uuid::new_v4()));
+            if let Some(mb) = s.memory_mb {
+                systemd_run.arg(format!("--property=MemoryMax={}M", mb));
+                systemd_run.arg("--property=MemorySwapMax=0");
+            }
+            if let Some(pids) = s.max_pids {
+                systemd_run.arg(format!("--property=TasksMax={}", pids));
+            }
+            systemd_run.arg(&s.bwrap);
+            systemd_run
+        } else {
+            Command::new(&s.bwrap)
+        };
+
+        wrapped.args(["--unshare-all", "--die-with-parent"]);
+        if s.network {
+            wrapped.arg("--share-net");
+        }
+        wrapped.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+        let mut bind = |from: &Path, to: &Path| {
+            wrapped.arg("--bind").arg(from).arg(to);
+        };
+        bind(&self.scratch, Path::new("/tmp"));
+        bind(&self.home, &self.home);
+        bind(&self.project, &self.project);
+        for dir in &s.writable {
+            let dir = self.project.join(dir);
+            bind(&dir, &dir);
+        }
+        wrapped.arg("--setenv").arg("HOME").arg(&self.home);
+        wrapped.args(["--setenv", "TMPDIR", "/tmp"]);
+        wrapped.arg("--chdir").arg(&self.project);
+
+        wrapped.arg("--").arg(command.get_program()).args(command.get_args());
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => wrapped.env(key, value),
+                None => wrapped.env_remove(key),
+            };
+        }
+        set_rlimits(&mut wrapped, s);
+        wrapped
+    }
+}
+
+impl AgentBackend for SandboxedBackend<'_> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn iteration_command(&self, session: &AgentSession, prompt: &str) -> Command {
+        self.wrap(self.inner.iteration_command(session, prompt))
+    }
+
+    fn conversation_command(&self, session: &AgentSession) -> Command {
+        self.wrap(self.inner.conversation_command(session))
+    }
+
+    fn encode_message(&self, text: &str) -> String {
+        self.inner.encode_message(text)
+    }
+
+    fn parse_event(&self, line: &str) -> Option<ClaudeStreamMessage> {
+        self.inner.parse_event(line)
+    }
+
+    fn is_missing_session(&self, stderr: &str) -> bool {
+        self.inner.is_missing_session(stderr)
+    }
+}
+
+/// Apply the CPU time limit, in the child before exec. Memory and processes
+/// are left to the cgroup.
+#[cfg(target_os = "linux")]
+fn set_rlimits(command: &mut Command, sandbox: &Sandbox) {
+    use std::os::unix::process::CommandExt;
+
+    let Some(secs) = sandbox.cpu_secs else {
+        return;
+    };
+    // SAFETY: the closure only calls setrlimit(2), which is async-signal-safe
+    unsafe {
+        command.pre_exec(move || {
+            // SIGXCPU at the soft limit, SIGKILL a little later
+            let limit = libc::rlimit { rlim_cur: secs, rlim_max: secs + 5 };
+            if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_rlimits(_command: &mut Command, _sandbox: &Sandbox) {}
+
+/// Watches one sandboxed iteration for what the sandbox stopped or blocked
+pub struct SandboxMonitor<'a> {
+    sandbox: &'a Sandbox,
+    /// The sandboxed process, to find its cgroup by
+    pid: u32,
+    /// The iteration's cgroup scope, once the process has moved into it
+    cgroup: Option<PathBuf>,
+    /// Processes the kernel killed for going over the cgroup's memory cap
+    oom_kills: u64,
+    blocked_writes: u32,
+    blocked_network: u32,
+    output: String,
+}
+
+impl<'a> SandboxMonitor<'a> {
+    pub fn new(sandbox: &'a Sandbox, pid: u32) -> Self {
+        Self {
+            sandbox,
+            pid,
+            cgroup: None,
+            oom_kills: 0,
+            blocked_writes: 0,
+            blocked_network: 0,
+            output: String::new(),
+        }
+    }
+
+    /// Feed one stream message from the running iteration
+    pub fn observe(&mut self, message: &ClaudeStreamMessage) {
+        // The scope goes away with its last process, so look while it runs
+        self.check_cgroup();
+        let ClaudeStreamMessage::User { message, .. } = message else {
+            return;
+        };
+        for block in &message.content {
+            if let ClaudeContentBlock::ToolResult { content, .. } = block {
+                if mentions(content, BLOCKED_WRITE) {
+                    self.blocked_writes += 1;
+                }
+                if !self.sandbox.network && mentions(content, BLOCKED_NETWORK) {
+                    self.blocked_network += 1;
+                }
+                if mentions(content, PID_LIMIT) || mentions(content, OUT_OF_MEMORY) {
+                    self.output.push_str(content);
+                    self.output.push('\n');
+                }
+            }
+        }
+    }
+
+    /// Count the OOM kills in the iteration's cgroup so far
+    fn check_cgroup(&mut self) {
+        if !self.sandbox.cgroup || self.sandbox.memory_mb.is_none() {
+            return;
+        }
+        if self.cgroup.is_none() {
+            self.cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", self.pid))
+                .ok()
+                .and_then(|cgroups| scope_cgroup(&cgroups));
+        }
+        let events = self.cgroup.as_ref().and_then(|dir| std::fs::read_to_string(dir.join("memory.events")).ok());
+        if let Some(kills) = events.as_deref().and_then(oom_kills) {
+            self.oom_kills = self.oom_kills.max(kills);
+        }
+    }
+
+    /// What happened, once the iteration is over. `killed` is whether APAS
+    /// itself killed the process (watchdog or shutdown).
+    pub fn finish(&mut self, status: Option<ExitStatus>, stderr: &str, killed: bool) -> Vec<(SandboxViolation, String)> {
+        self.check_cgroup();
+        let s = self.sandbox;
+        let mut violations = Vec::new();
+        if let Some(line) = stderr.lines().find(|line| {
+            line.starts_with("bwrap:") || line.contains("Failed to start transient scope unit")
+        }) {
+            violations.push((SandboxViolation::SetupFailed, format!("couldn't start: {}", line.trim())));
+        }
+
+        let (sigkill, sigxcpu) = fatal_signals(status);
+        // Something else killed it: the kernel, for a limit
+        let limit_kill = sigkill && !killed;
+        let output = format!("{}\n{}", stderr, self.output);
+        let cpu_limit = |secs: u64| (SandboxViolation::CpuLimit, format!("killed for using more than {}s of CPU time", secs));
+        match (s.memory_mb, s.cpu_secs) {
+            (Some(mb), _) if self.oom_kills > 0 => {
+                let detail = format!("{} process(es) killed for going over the {} MB memory limit", self.oom_kills, mb);
+                violations.push((SandboxViolation::OutOfMemory, detail));
+            }
+            (_, Some(secs)) if sigxcpu => violations.push(cpu_limit(secs)),
+            (Some(mb), _) if limit_kill => {
+                let detail = format!("killed, most likely for going over the {} MB memory limit", mb);
+                violations.push((SandboxViolation::OutOfMemory, detail));
+            }
+            (None, Some(secs)) if limit_kill => violations.push(cpu_limit(secs)),
+            (Some(mb), _) if mentions(&output, OUT_OF_MEMORY) => {
+                violations.push((SandboxViolation::OutOfMemory, format!("ran out of memory (limit {} MB)", mb)));
+            }
+            _ => {}
+        }
+        if let Some(pids) = s.max_pids.filter(|_| mentions(&output, PID_LIMIT)) {
+            violations.push((SandboxViolation::PidLimit, format!("couldn't start more processes (limit {})", pids)));
+        }
+        if self.blocked_writes > 0 {
+            let detail = format!("blocked {} write(s) outside the project", self.blocked_writes);
+            violations.push((SandboxViolation::BlockedWrite, detail));
+        }
+        if self.blocked_network > 0 {
+            let detail = format!("blocked {} network access attempt(s)", self.blocked_network);
+            violations.push((SandboxViolation::BlockedNetwork, detail));
+        }
+        violations
+    }
+}
+
+fn mentions(text: &str, phrases: &[&str]) -> bool {
+    phrases.iter().any(|phrase| text.contains(phrase))
+}
+
+/// The cgroup directory of an iteration's scope, from `/proc/<pid>/cgroup`.
+/// None on cgroup v1, or before systemd-run has moved the process.
+fn scope_cgroup(cgroups: &str) -> Option<PathBuf> {
+    let path = Path::new(cgroups.lines().find_map(|line| line.strip_prefix("0::"))?);
+    let unit = path.file_name()?.to_str()?;
+    (unit.starts_with(SCOPE_PREFIX) && unit.ends_with(".scope"))
+        .then(|| Path::new("/sys/fs/cgroup").join(path.strip_prefix("/").unwrap_or(path)))
+}
+
+/// The `oom_kill` count in a cgroup's `memory.events`
+fn oom_kills(events: &str) -> Option<u64> {
+    events.lines().find_map(|line| line.strip_prefix("oom_kill ")).and_then(|n| n.trim().parse().ok())
+}
+
+/// Whether the process died of SIGKILL, and whether of SIGXCPU. bwrap
+/// reports its child's death by signal n as exit code 128 + n.
+#[cfg(unix)]
+fn fatal_signals(status: Option<ExitStatus>) -> (bool, bool) {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.and_then(|status| status.signal().or(status.code().filter(|code| *code > 128).map(|code| code - 128)));
+    (signal == Some(libc::SIGKILL), signal == Some(libc::SIGXCPU))
+}
+
+#[cfg(not(unix))]
+fn fatal_signals(_status: Option<ExitStatus>) -> (bool, bool) {
+    (false, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::ClaudeBackend;
+    use crate::config::ClaudeSettings;
+
+    #[test]
+    fn test_wrap_confines_writes_to_the_project() {
+        let inner = ClaudeBackend::new("claude", ClaudeSettings::default());
+        let backend = SandboxedBackend {
+            inner: &inner,
+            sandbox: Sandbox { enabled: true, network: false, writable: vec!["../cache".into()], ..Default::default() },
+            project: PathBuf::from("/work/project"),
+            scratch: PathBuf::from("/tmp/apas-sandbox"),
+            home: PathBuf::from("/home/me/.local/share/apas/sandbox-home"),
+        };
+        let mut command = Command::new("claude");
+        command.arg("-p").arg("hi").env("FOO", "bar");
+        let wrapped = backend.wrap(command);
+
+        assert_eq!(wrapped.get_program(), "bwrap");
+        let args: Vec<_> = wrapped.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        let args = args.join(" ");
+        assert!(!args.contains("--share-net"));
+        assert!(args.contains("--ro-bind / /"));
+        assert!(args.contains("--bind /tmp/apas-sandbox /tmp"));
+        assert!(args.contains("--bind /work/project /work/project"));
+        assert!(args.contains("--bind /work/project/../cache /work/project/../cache"));
+        assert!(args.contains("--setenv HOME /home/me/.local/share/apas/sandbox-home"));
+        assert!(args.ends_with("-- claude -p hi"));
+        assert!(wrapped.get_envs().any(|(k, v)| k == "FOO" && v == Some("bar".as_ref())));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memory_and_pid_caps_need_a_cgroup() {
+        let inner = ClaudeBackend::new("claude", ClaudeSettings::default());
+        for sandbox in [
+            Sandbox { enabled: true, memory_mb: Some(4096), ..Default::default() },
+            Sandbox { enabled: true, max_pids: Some(512), ..Default::default() },
+        ] {
+            let refused = sandbox.backend(&inner, Path::new("."), Uuid::new_v4()).err().unwrap();
+            assert_eq!(refused.kind(), io::ErrorKind::InvalidInput);
+            assert!(refused.to_string().contains("need \"cgroup\": true"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kills_and_blocked_writes_are_reported() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let sandbox = Sandbox { enabled: true, memory_mb: Some(512), ..Default::default() };
+        let mut monitor = SandboxMonitor::new(&sandbox, 0);
+        let result: ClaudeStreamMessage = serde_json::from_value(serde_json::json!({
+            "type": "user",
+            "session_id": "s",
+            "message": { "role": "user", "content": [{
+                "type": "tool_result", "tool_use_id": "t1", "is_error": true,
+                "content": "touch: cannot touch '/etc/x': Read-only file system"
+            }]}
+        }))
+        .unwrap();
+        monitor.observe(&result);
+
+        let kinds = |violations: Vec<(SandboxViolation, String)>| violations.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        let killed = Some(ExitStatus::from_raw(libc::SIGKILL));
+        assert_eq!(
+            kinds(monitor.finish(killed, "", false)),
+            [SandboxViolation::OutOfMemory, SandboxViolation::BlockedWrite]
+        );
+        // The watchdog's own kill isn't the sandbox's doing
+        assert_eq!(kinds(monitor.finish(killed, "", true)), [SandboxViolation::BlockedWrite]);
+        let setup = monitor.finish(Some(ExitStatus::from_raw(1 << 8)), "bwrap: No permissions to create new namespace\n", false);
+        assert_eq!(setup[0].0, SandboxViolation::SetupFailed);
+
+        // bwrap exits with 128 + the signal that killed Claude
+        let relayed = |code: i32| Some(ExitStatus::from_raw(code << 8));
+        assert_eq!(kinds(monitor.finish(relayed(137), "", false)), [SandboxViolation::OutOfMemory, SandboxViolation::BlockedWrite]);
+        let sandbox = Sandbox { enabled: true, cpu_secs: Some(60), ..Default::default() };
+        let mut monitor = SandboxMonitor::new(&sandbox, 0);
+        assert_eq!(kinds(monitor.finish(relayed(152), "", false)), [SandboxViolation::CpuLimit]);
+        assert!(monitor.finish(relayed(1), "", false).is_empty());
+    }
+
+    #[test]
+    fn test_oom_kills_are_read_from_the_scope() {
+        let cgroups = "0::/user.slice/user-1000.slice/user@1000.service/app.slice/apas-sandbox-1.scope\n";
+        assert_eq!(
+            scope_cgroup(cgroups),
+            Some(PathBuf::from("/sys/fs/cgroup/user.slice/user-1000.slice/user@1000.service/app.slice/apas-sandbox-1.scope"))
+        );
+        // Still in the terminal's scope, or cgroup v1
+        assert_eq!(scope_cgroup("0::/user.slice/user-1000.slice/session-2.scope\n"), None);
+        assert_eq!(scope_cgroup("12:memory:/user.slice\n"), None);
+
+        let events = "low 0\nhigh 0\nmax 12\noom 2\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(oom_kills(events), Some(1));
+        assert_eq!(oom_kills("low 0\n"), None);
+    }
+}
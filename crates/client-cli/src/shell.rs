@@ -0,0 +1,185 @@
+//! Project shell commands run by the CLI itself (as opposed to by Claude)
+//!
+//! The command runs under `sh -c` in its own process group, so a timeout or
+//! shutdown kills everything it started. Output from stdout and stderr is
+//! handed over line by line as it arrives.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::signal::{self, Signal};
+
+/// How much of the output to keep for summaries, from the end
+const MAX_KEPT_OUTPUT: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub struct ShellOutcome {
+    /// None if the command was killed before exiting on its own
+    pub status: Option<ExitStatus>,
+    pub timed_out: bool,
+    pub duration: Duration,
+    /// The end of the combined output
+    pub output: String,
+}
+
+impl ShellOutcome {
+    pub fn success(&self) -> bool {
+        !self.timed_out && self.status.is_some_and(|s| s.success())
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.status.and_then(|s| s.code())
+    }
+}
+
+/// Run `command` in `working_dir` with extra `env`, killing it after
+/// `timeout` (None for no limit) or once `shutdown` is set
+pub fn run(
+    command: &str,
+    working_dir: &Path,
+    env: &[(&str, String)],
+    timeout: Option<Duration>,
+    shutdown: &AtomicBool,
+    mut on_line: impl FnMut(&str),
+) -> io::Result<ShellOutcome> {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command])
+        .current_dir(working_dir)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    signal::new_process_group(&mut cmd);
+
+    let started = Instant::now();
+    let mut child = cmd.spawn()?;
+    let (line_tx, line_rx) = mpsc::channel::<String>();
+    for stream in [child.stdout.take().map(|s| Box::new(s) as Box<dyn Read + Send>), child.stderr.take().map(|s| Box::new(s) as _)]
+        .into_iter()
+        .flatten()
+    {
+        let line_tx = line_tx.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if line_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(line_tx);
+
+    let mut output = String::new();
+    let mut timed_out = false;
+    let mut killed_at: Option<Instant> = None;
+    let mut exited: Option<(ExitStatus, Instant)> = None;
+    loop {
+        match line_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => {
+                on_line(&line);
+                output.push_str(&line);
+                output.push('\n');
+                if output.len() > 2 * MAX_KEPT_OUTPUT {
+                    output = tail(&output, MAX_KEPT_OUTPUT).to_string();
+                }
+            }
+            // Both streams closed
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+
+        match (exited, killed_at) {
+            (None, None) => {
+                if let Some(status) = child.try_wait()? {
+                    exited = Some((status, Instant::now()));
+                    continue;
+                }
+                let expired = timeout.is_some_and(|t| started.elapsed() >= t);
+                if expired || shutdown.load(Ordering::SeqCst) {
+                    timed_out = expired;
+                    let _ = signal::signal_group(child.id(), Signal::Kill);
+                    killed_at = Some(Instant::now());
+                }
+            }
+            // The command is done, but something it started in the
+            // background may still hold the pipes open; leave that running
+            (Some((_, at)), _) if at.elapsed() > Duration::from_millis(500) => break,
+            // Something that left the group may still hold the pipes open
+            (None, Some(at)) if at.elapsed() > Duration::from_secs(1) => break,
+            _ => {}
+        }
+    }
+
+    let status = match exited {
+        Some((status, _)) => status,
+        None => child.wait()?,
+    };
+    Ok(ShellOutcome {
+        status: killed_at.is_none().then_some(status),
+        timed_out,
+        duration: started.elapsed(),
+        output: tail(&output, MAX_KEPT_OUTPUT).to_string(),
+    })
+}
+
+/// The last `max_len` bytes of `text`, starting on a character boundary
+pub fn tail(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut start = text.len() - max_len;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_for(command: &str, timeout: Duration) -> ShellOutcome {
+        let mut lines = Vec::new();
+        let outcome = run(command, Path::new("."), &[], Some(timeout), &AtomicBool::new(false), |l| {
+            lines.push(l.to_string())
+        })
+        .unwrap();
+        assert_eq!(outcome.output, lines.iter().map(|l| format!("{}\n", l)).collect::<String>());
+        outcome
+    }
+
+    #[test]
+    fn test_exit_status_and_output() {
+        let outcome = run_for("echo out; echo err >&2; exit 3", Duration::from_secs(10));
+        assert_eq!(outcome.exit_code(), Some(3));
+        assert!(!outcome.timed_out);
+        assert!(outcome.output.contains("out\n") && outcome.output.contains("err\n"));
+    }
+
+    #[test]
+    fn test_timeout_kills_the_command() {
+        let outcome = run_for("echo started; sleep 30", Duration::from_millis(300));
+        assert!(outcome.timed_out && outcome.status.is_none());
+        assert_eq!(outcome.output, "started\n");
+        assert!(outcome.duration < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_background_children_outlive_the_command() {
+        // The sleep keeps stdout open but must neither hold up nor be killed
+        // with the shell that started it
+        let outcome = run_for("sleep 30 & echo $!", Duration::from_secs(20));
+        assert!(outcome.success(), "{:?}", outcome);
+        assert!(outcome.duration < Duration::from_secs(5));
+
+        let pid = outcome.output.trim();
+        let alive = |pid: &str| Command::new("kill").args(["-0", pid]).status().unwrap().success();
+        assert!(alive(pid), "background child was killed");
+        let _ = Command::new("kill").arg(pid).status();
+    }
+}
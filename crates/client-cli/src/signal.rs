@@ -0,0 +1,91 @@
+//! POSIX signals for Claude processes
+//!
+//! Claude is started as the leader of its own process group, so a signal sent
+//! to the group also reaches the tools it is running (a test suite, a
+//! compiler, a shell waiting on a prompt).
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+    Kill,
+}
+
+impl Signal {
+    /// Accepts "SIGINT", "INT" or "sigint" (likewise TERM and KILL)
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "INT" => Some(Signal::Interrupt),
+            "TERM" => Some(Signal::Terminate),
+            "KILL" => Some(Signal::Kill),
+            _ => None,
+        }
+    }
+}
+
+/// Signal every process in the group led by `pid`. A group that has already
+/// exited is not an error.
+#[cfg(unix)]
+pub fn signal_group(pid: u32, signal: Signal) -> io::Result<()> {
+    let signal = match signal {
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: kill(2) takes plain integers and has no memory effects
+    if unsafe { libc::kill(-(pid as libc::pid_t), signal) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn signal_group(pid: u32, signal: Signal) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't send {:?} to process group {} on this platform", signal, pid),
+    ))
+}
+
+/// Make the spawned process lead a new process group
+pub fn new_process_group(command: &mut std::process::Command) {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// The Claude process a pane is currently running, shared between the
+/// thread that runs it and whoever needs to interrupt it
+#[derive(Debug, Clone, Default)]
+pub struct CurrentProcess(Arc<Mutex<Option<u32>>>);
+
+impl CurrentProcess {
+    pub fn set(&self, pid: u32) {
+        if let Ok(mut guard) = self.0.lock() {
+            *guard = Some(pid);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut guard) = self.0.lock() {
+            *guard = None;
+        }
+    }
+
+    /// Returns false if nothing is running
+    pub fn signal(&self, signal: Signal) -> io::Result<bool> {
+        let pid = self.0.lock().ok().and_then(|guard| *guard);
+        match pid {
+            Some(pid) => signal_group(pid, signal).map(|()| true),
+            None => Ok(false),
+        }
+    }
+}
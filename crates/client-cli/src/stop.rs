@@ -0,0 +1,124 @@
+//! When the deadloop is done
+//!
+//! Left alone the deadloop runs until Ctrl+C. The `stop` section of `.apas`
+//! ends it cleanly instead: after a number of iterations, at a deadline, once
+//! TODO.md has no unchecked items, when a sentinel file appears, or after
+//! Claude reports several times in a row that there is nothing left to do.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::template::active_task;
+
+/// Result phrases taken to mean there is nothing to do, unless configured
+const DEFAULT_NOTHING_TO_DO: &[&str] = &["nothing to do", "no undone", "no remaining tasks", "no tasks left"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StopConditions {
+    /// Stop after this many iterations
+    pub max_iterations: Option<u32>,
+    /// Stop once this time has passed (RFC 3339)
+    pub deadline: Option<DateTime<Utc>>,
+    /// Stop once TODO.md has no unchecked `- [ ]` items
+    pub todo_done: bool,
+    /// Stop once this file exists, relative to the project directory
+    pub sentinel_file: Option<String>,
+    /// Stop after this many results in a row saying there is nothing to do
+    pub nothing_to_do: Option<u32>,
+    /// Phrases that mark such a result (case-insensitive)
+    pub nothing_to_do_phrases: Vec<String>,
+}
+
+pub struct Stopper {
+    conditions: StopConditions,
+    iterations: u32,
+    /// Successful iterations in a row that found nothing to do
+    idle_streak: u32,
+}
+
+impl Stopper {
+    pub fn new(conditions: StopConditions) -> Self {
+        Self {
+            conditions,
+            iterations: 0,
+            idle_streak: 0,
+        }
+    }
+
+    /// Count a finished iteration. Returns whether it counted toward the
+    /// nothing-to-do condition, so other checks can leave it to this one.
+    pub fn finish_iteration(&mut self, had_error: bool, result: &str) -> bool {
+        self.iterations += 1;
+        let result = result.to_lowercase();
+        let idle = !had_error
+            && if self.conditions.nothing_to_do_phrases.is_empty() {
+                DEFAULT_NOTHING_TO_DO.iter().any(|p| result.contains(p))
+            } else {
+                self.conditions.nothing_to_do_phrases.iter().any(|p| result.contains(&p.to_lowercase()))
+            };
+        self.idle_streak = if idle { self.idle_streak + 1 } else { 0 };
+        idle && self.conditions.nothing_to_do.is_some_and(|max| max > 0)
+    }
+
+    /// Why the loop should end now, if it should
+    pub fn reason(&self, working_dir: &Path) -> Option<String> {
+        let c = &self.conditions;
+        if let Some(max) = c.max_iterations.filter(|max| self.iterations >= *max) {
+            return Some(format!("reached {} iterations", max));
+        }
+        if let Some(deadline) = c.deadline.filter(|deadline| Utc::now() >= *deadline) {
+            return Some(format!("deadline {} passed", deadline.to_rfc3339()));
+        }
+        if let Some(file) = c.sentinel_file.as_ref().filter(|file| working_dir.join(file).exists()) {
+            return Some(format!("found {}", file));
+        }
+        if let Some(max) = c.nothing_to_do.filter(|max| *max > 0 && self.idle_streak >= *max) {
+            return Some(format!("nothing to do in {} iterations in a row", max));
+        }
+        if c.todo_done && working_dir.join("TODO.md").exists() && active_task(working_dir).is_none() {
+            return Some("TODO.md has no unchecked items".to_string());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nothing_to_do_needs_a_streak() {
+        let dir = std::env::temp_dir();
+        let mut stopper = Stopper::new(StopConditions { nothing_to_do: Some(2), ..Default::default() });
+        assert!(stopper.finish_iteration(false, "No undone TODO items left, exiting"));
+        assert_eq!(stopper.reason(&dir), None);
+        // Failures and real work break the streak
+        assert!(!stopper.finish_iteration(true, "Nothing to do"));
+        stopper.finish_iteration(false, "Nothing to do");
+        assert_eq!(stopper.reason(&dir), None);
+        stopper.finish_iteration(false, "Still nothing to do.");
+        assert_eq!(stopper.reason(&dir).as_deref(), Some("nothing to do in 2 iterations in a row"));
+    }
+
+    #[test]
+    fn test_todo_done_and_sentinel_file() {
+        let dir = std::env::temp_dir().join(format!("apas-stop-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let stopper = Stopper::new(StopConditions {
+            todo_done: true,
+            sentinel_file: Some("STOP".to_string()),
+            ..Default::default()
+        });
+        // No TODO.md is not the same as an empty one
+        assert_eq!(stopper.reason(&dir), None);
+        std::fs::write(dir.join("TODO.md"), "- [ ] one\n").unwrap();
+        assert_eq!(stopper.reason(&dir), None);
+        std::fs::write(dir.join("TODO.md"), "- [x] one\n").unwrap();
+        assert_eq!(stopper.reason(&dir).as_deref(), Some("TODO.md has no unchecked items"));
+        std::fs::write(dir.join("STOP"), "").unwrap();
+        assert_eq!(stopper.reason(&dir).as_deref(), Some("found STOP"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
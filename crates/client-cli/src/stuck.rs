@@ -0,0 +1,342 @@
+//! Stuck-loop detection for the deadloop
+//!
+//! Watches each iteration's stream output and pauses the loop when it stops
+//! making progress: no new commits for a while, the same tool calls or the
+//! same final result over and over, or several failures in a row. Thresholds
+//! come from the `stuck_detection` section of `.apas`; 0 turns a check off.
+//! Iterations that the `stop.nothing_to_do` condition counts are left to it.
+
+use serde::{Deserialize, Serialize};
+use shared::{ClaudeContentBlock, ClaudeStreamMessage, PauseReason};
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StuckDetection {
+    /// Pause after this many iterations without a new commit on HEAD
+    pub max_iterations_without_commit: u32,
+    /// Pause after this many iterations making exactly the same tool calls
+    pub max_repeated_tool_calls: u32,
+    /// Pause after this many iterations ending with the same result text
+    pub max_repeated_results: u32,
+    /// Pause after this many failed iterations in a row
+    pub max_consecutive_errors: u32,
+}
+
+impl Default for StuckDetection {
+    fn default() -> Self {
+        Self {
+            max_iterations_without_commit: 10,
+            max_repeated_tool_calls: 3,
+            max_repeated_results: 3,
+            max_consecutive_errors: 5,
+        }
+    }
+}
+
+/// Counter for how many iterations in a row produced the same value
+#[derive(Debug, Default)]
+struct Repeats {
+    last: Option<String>,
+    count: u32,
+}
+
+impl Repeats {
+    /// Record this iteration's value; empty values break the streak
+    fn record(&mut self, value: String) -> u32 {
+        if value.is_empty() {
+            *self = Self::default();
+        } else if self.last.as_ref() == Some(&value) {
+            self.count += 1;
+        } else {
+            self.last = Some(value);
+            self.count = 1;
+        }
+        self.count
+    }
+}
+
+pub struct StuckDetector {
+    config: StuckDetection,
+    last_head: Option<String>,
+    without_commit: u32,
+    consecutive_errors: u32,
+    tool_calls: Repeats,
+    results: Repeats,
+    /// Tool calls and final result seen so far in the current iteration
+    current_tool_calls: Vec<String>,
+    current_result: String,
+}
+
+impl StuckDetector {
+    pub fn new(config: StuckDetection, working_dir: &Path) -> Self {
+        Self {
+            config,
+            last_head: git_head(working_dir),
+            without_commit: 0,
+            consecutive_errors: 0,
+            tool_calls: Repeats::default(),
+            results: Repeats::default(),
+            current_tool_calls: Vec::new(),
+            current_result: String::new(),
+        }
+    }
+
+    /// Feed one stream message from the running iteration
+    pub fn observe(&mut self, message: &ClaudeStreamMessage) {
+        match message {
+            ClaudeStreamMessage::Assistant { message, .. } => {
+                for block in &message.content {
+                    if let ClaudeContentBlock::ToolUse { name, input, .. } = block {
+                        self.current_tool_calls.push(format!("{}{}", name, input));
+                    }
+                }
+            }
+            ClaudeStreamMessage::Result { result, .. } => {
+                self.current_result = result.trim().to_string();
+            }
+            _ => {}
+        }
+    }
+
+    /// Close out an iteration. Returns why the loop should pause, if it looks
+    /// stuck. An `idle` iteration, one that reported there is nothing left to
+    /// do, doesn't count toward the commit and repetition checks.
+    pub fn finish_iteration(
+        &mut self,
+        had_error: bool,
+        idle: bool,
+        working_dir: &Path,
+    ) -> Option<(PauseReason, String)> {
+        let head = git_head(working_dir);
+        // Outside a git repository there is nothing to compare
+        if head.is_none() || head != self.last_head {
+            self.without_commit = 0;
+        } else if !idle {
+            self.without_commit += 1;
+        }
+        self.last_head = head;
+
+        self.consecutive_errors = if had_error { self.consecutive_errors + 1 } else { 0 };
+        let tool_calls = std::mem::take(&mut self.current_tool_calls).join("\n");
+        let result = std::mem::take(&mut self.current_result);
+        let (tool_repeats, result_repeats) = if idle {
+            (self.tool_calls.count, self.results.count)
+        } else {
+            (self.tool_calls.record(tool_calls), self.results.record(result))
+        };
+
+        let c = &self.config;
+        if tripped(c.max_consecutive_errors, self.consecutive_errors) {
+            Some((PauseReason::Error, format!("{} failed iterations in a row", self.consecutive_errors)))
+        } else if tripped(c.max_repeated_tool_calls, tool_repeats) {
+            Some((PauseReason::RepeatedToolCalls, format!("same tool calls in {} iterations", tool_repeats)))
+        } else if tripped(c.max_repeated_results, result_repeats) {
+            Some((PauseReason::RepeatedResult, format!("same result in {} iterations", result_repeats)))
+        } else if tripped(c.max_iterations_without_commit, self.without_commit) {
+            Some((PauseReason::NoCommits, format!("no new commits in {} iterations", self.without_commit)))
+        } else {
+            None
+        }
+    }
+
+    /// Commit HEAD pointed at when the last iteration finished
+    pub fn head(&self) -> Option<&str> {
+        self.last_head.as_deref()
+    }
+
+    /// Start counting afresh, e.g. after the user resumes a paused loop
+    pub fn reset(&mut self, working_dir: &Path) {
+        *self = Self::new(self.config.clone(), working_dir);
+    }
+}
+
+fn tripped(threshold: u32, count: u32) -> bool {
+    threshold > 0 && count >= threshold
+}
+
+/// Current commit hash, or None outside a git repository
+pub fn git_head(working_dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(working_dir)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Subjects of the commits reachable from HEAD but not from `since`, oldest
+/// first. Without `since` (the repository had no commits) that is all of them.
+pub fn commits_since(working_dir: &Path, since: Option<&str>) -> Vec<String> {
+    let range = match since {
+        Some(since) => format!("{}..HEAD", since),
+        None => "HEAD".to_string(),
+    };
+    let output = Command::new("git")
+        .args(["log", "--reverse", "--format=%s", &range])
+        .current_dir(working_dir)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn only(config: impl FnOnce(&mut StuckDetection)) -> StuckDetection {
+        let mut detection = StuckDetection {
+            max_iterations_without_commit: 0,
+            max_repeated_tool_calls: 0,
+            max_repeated_results: 0,
+            max_consecutive_errors: 0,
+        };
+        config(&mut detection);
+        detection
+    }
+
+    fn tool_call(command: &str) -> ClaudeStreamMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "assistant",
+            "message": { "content": [{ "type": "tool_use", "id": "t", "name": "Bash", "input": { "command": command } }] },
+            "session_id": "s",
+        }))
+        .unwrap()
+    }
+
+    fn result(text: &str) -> ClaudeStreamMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "result",
+            "subtype": "success",
+            "result": text,
+            "session_id": "s",
+        }))
+        .unwrap()
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=apas", "-c", "user.email=apas@example.com", "-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_consecutive_errors() {
+        let dir = std::env::temp_dir();
+        let mut detector = StuckDetector::new(only(|c| c.max_consecutive_errors = 2), &dir);
+        assert_eq!(detector.finish_iteration(true, false, &dir), None);
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        assert_eq!(detector.finish_iteration(true, false, &dir), None);
+        let (reason, detail) = detector.finish_iteration(true, false, &dir).unwrap();
+        assert_eq!(reason, PauseReason::Error);
+        assert_eq!(detail, "2 failed iterations in a row");
+    }
+
+    #[test]
+    fn test_repeated_tool_calls() {
+        let dir = std::env::temp_dir();
+        let mut detector = StuckDetector::new(only(|c| c.max_repeated_tool_calls = 2), &dir);
+        detector.observe(&tool_call("cargo test"));
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        detector.observe(&tool_call("cargo build"));
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        // Iterations without tool calls break the streak
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        detector.observe(&tool_call("cargo build"));
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        detector.observe(&tool_call("cargo build"));
+        let (reason, _) = detector.finish_iteration(false, false, &dir).unwrap();
+        assert_eq!(reason, PauseReason::RepeatedToolCalls);
+    }
+
+    #[test]
+    fn test_repeated_results() {
+        let dir = std::env::temp_dir();
+        let mut detector = StuckDetector::new(only(|c| c.max_repeated_results = 2), &dir);
+        detector.observe(&result("Fixed the parser"));
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        detector.observe(&result("  Fixed the parser\n"));
+        let (reason, detail) = detector.finish_iteration(false, false, &dir).unwrap();
+        assert_eq!(reason, PauseReason::RepeatedResult);
+        assert_eq!(detail, "same result in 2 iterations");
+    }
+
+    #[test]
+    fn test_idle_iterations_are_left_to_the_stop_condition() {
+        let dir = std::env::temp_dir();
+        let mut detector = StuckDetector::new(
+            only(|c| {
+                c.max_repeated_results = 2;
+                c.max_repeated_tool_calls = 2;
+            }),
+            &dir,
+        );
+        for _ in 0..5 {
+            detector.observe(&tool_call("cat TODO.md"));
+            detector.observe(&result("Nothing to do"));
+            assert_eq!(detector.finish_iteration(false, true, &dir), None);
+        }
+    }
+
+    #[test]
+    fn test_iterations_without_commit() {
+        let dir = std::env::temp_dir().join(format!("apas-stuck-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = only(|c| c.max_iterations_without_commit = 2);
+
+        // Outside a git repository the check never trips
+        let mut detector = StuckDetector::new(config.clone(), &dir);
+        for _ in 0..3 {
+            assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        }
+
+        git(&dir, &["init", "-q"]);
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        let mut detector = StuckDetector::new(config, &dir);
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "second"]);
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        assert_eq!(detector.finish_iteration(false, true, &dir), None);
+        assert_eq!(detector.finish_iteration(false, false, &dir), None);
+        let (reason, detail) = detector.finish_iteration(false, false, &dir).unwrap();
+        assert_eq!(reason, PauseReason::NoCommits);
+        assert_eq!(detail, "no new commits in 2 iterations");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commits_since() {
+        let dir = std::env::temp_dir().join(format!("apas-commits-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(commits_since(&dir, None).is_empty(), "not a repository");
+
+        git(&dir, &["init", "-q"]);
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        assert_eq!(commits_since(&dir, None), ["first"]);
+
+        let detector = StuckDetector::new(only(|_| {}), &dir);
+        assert!(commits_since(&dir, detector.head()).is_empty());
+        // A commit that fails doesn't count; an amended one does
+        let failed = Command::new("git").args(["commit", "-q", "-m", "nothing"]).current_dir(&dir).output().unwrap();
+        assert!(!failed.status.success());
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "second\n\nbody"]);
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "third"]);
+        assert_eq!(commits_since(&dir, detector.head()), ["second", "third"]);
+        git(&dir, &["commit", "-q", "--amend", "--allow-empty", "-m", "third, amended"]);
+        assert_eq!(commits_since(&dir, detector.head()), ["second", "third, amended"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
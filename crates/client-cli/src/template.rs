@@ -0,0 +1,212 @@
+//! Prompt templates for the deadloop
+//!
+//! `.apas` prompts are rendered before every iteration, so they can refer to
+//! how the loop is going: `{{iteration}}`, `{{stage}}`, `{{branch}}`,
+//! `{{last_result}}`, `{{last_error}}`, `{{failing_tests}}`, `{{task}}` (the
+//! first unchecked item in TODO.md), `{{verification}}`, `{{date}}` and
+//! `{{time}}`. A failed verification is appended to prompts that don't use
+//! `{{verification}}` themselves.
+//! `{{#if name}}...{{/if}}` keeps its body only when the variable is set and
+//! not "false". Unknown variables are left as they are.
+
+use chrono::Local;
+use shared::{ClaudeContentBlock, ClaudeStreamMessage};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use crate::shell::tail;
+
+/// Keep the end of long test output, where the failures are summarized
+const MAX_FAILING_TESTS_LEN: usize = 4000;
+
+/// What the deadloop knows about its previous iteration
+#[derive(Debug, Default)]
+pub struct PromptContext {
+    pub iteration: u32,
+    pub stage: String,
+    pub last_result: String,
+    pub last_error: bool,
+    pub failing_tests: String,
+    /// Why the verification command failed after the last iteration
+    pub verification: String,
+    /// Test commands run in the current iteration, by tool use id
+    test_commands: HashMap<String, String>,
+    /// Output of the current iteration's last failed test command
+    current_failures: String,
+}
+
+impl PromptContext {
+    /// Feed one stream message from the running iteration
+    pub fn observe(&mut self, message: &ClaudeStreamMessage) {
+        match message {
+            ClaudeStreamMessage::Assistant { message, .. } => {
+                for block in &message.content {
+                    if let ClaudeContentBlock::ToolUse { id, name, input } = block {
+                        let command = input["command"].as_str().unwrap_or_default();
+                        if name == "Bash" && command.contains("test") {
+                            self.test_commands.insert(id.clone(), command.to_string());
+                        }
+                    }
+                }
+            }
+            ClaudeStreamMessage::User { message, .. } => {
+                for block in &message.content {
+                    if let ClaudeContentBlock::ToolResult { tool_use_id, content, .. } = block {
+                        if let Some(command) = self.test_commands.remove(tool_use_id) {
+                            // A later passing run means the failures were fixed
+                            self.current_failures = if is_failure(block) {
+                                format!("$ {}\n{}", command, tail(content, MAX_FAILING_TESTS_LEN))
+                            } else {
+                                String::new()
+                            };
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Close out an iteration, making its outcome the "last" one
+    pub fn finish_iteration(&mut self, had_error: bool, result: &str) {
+        self.last_result = result.trim().to_string();
+        self.last_error = had_error;
+        self.failing_tests = std::mem::take(&mut self.current_failures);
+        self.test_commands.clear();
+    }
+
+    /// Render `template` for the coming iteration
+    pub fn render(&self, template: &str, working_dir: &Path) -> String {
+        let mentions_verification = Cell::new(false);
+        let mut prompt = render(template, &|name| {
+            if name == "verification" {
+                mentions_verification.set(true);
+            }
+            self.variable(name, working_dir)
+        });
+        if !self.verification.is_empty() && !mentions_verification.get() {
+            prompt.push_str("\n\n");
+            prompt.push_str(&self.verification);
+        }
+        prompt
+    }
+
+    fn variable(&self, name: &str, working_dir: &Path) -> Option<String> {
+        let value = match name {
+            "iteration" => self.iteration.to_string(),
+            "stage" => self.stage.clone(),
+            "last_result" => self.last_result.clone(),
+            "last_error" => self.last_error.to_string(),
+            "failing_tests" => self.failing_tests.clone(),
+            "verification" => self.verification.clone(),
+            "branch" => git_branch(working_dir).unwrap_or_default(),
+            "task" => active_task(working_dir).unwrap_or_default(),
+            "date" => Local::now().format("%Y-%m-%d").to_string(),
+            "time" => Local::now().format("%H:%M").to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+fn is_failure(block: &ClaudeContentBlock) -> bool {
+    matches!(block, ClaudeContentBlock::ToolResult { is_error: true, .. })
+}
+
+/// Substitute `{{name}}` tags and `{{#if name}}` sections using `lookup`
+fn render(template: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find("}}").map(|c| open + c) else {
+            rest = &rest[open..];
+            break;
+        };
+        let tag = rest[open + 2..close].trim();
+        let after = &rest[close + 2..];
+
+        if let Some(name) = tag.strip_prefix("#if ") {
+            let Some(end) = after.find("{{/if}}") else {
+                // Unterminated section, leave it alone
+                out.push_str(&rest[open..close + 2]);
+                rest = after;
+                continue;
+            };
+            let set = lookup(name.trim()).is_some_and(|v| !v.is_empty() && v != "false");
+            if set {
+                out.push_str(&render(&after[..end], lookup));
+            }
+            rest = &after[end + "{{/if}}".len()..];
+        } else {
+            match lookup(tag) {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(&rest[open..close + 2]),
+            }
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn git_branch(working_dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .current_dir(working_dir)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The first unchecked `- [ ]` item in TODO.md
+pub fn active_task(working_dir: &Path) -> Option<String> {
+    let todo = std::fs::read_to_string(working_dir.join("TODO.md")).ok()?;
+    todo.lines().find_map(|line| {
+        let line = line.trim_start();
+        let item = line.strip_prefix("- [ ]").or_else(|| line.strip_prefix("* [ ]"))?;
+        Some(item.trim().to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(name: &str) -> Option<String> {
+        match name {
+            "iteration" => Some("3".to_string()),
+            "last_error" => Some("false".to_string()),
+            "failing_tests" => Some("test foo ... FAILED".to_string()),
+            "last_result" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_render_substitutes_variables_and_sections() {
+        let template = "Iteration {{ iteration }}.{{#if last_error}} Last one failed.{{/if}}\
+                        {{#if failing_tests}}\nFix these:\n{{failing_tests}}{{/if}}{{#if last_result}}unused{{/if}}";
+        assert_eq!(render(template, &vars), "Iteration 3.\nFix these:\ntest foo ... FAILED");
+    }
+
+    #[test]
+    fn test_render_leaves_unknown_and_broken_tags() {
+        assert_eq!(render("{{nope}} {x} {{iteration", &vars), "{{nope}} {x} {{iteration");
+        assert_eq!(render("{{#if iteration}}open", &vars), "{{#if iteration}}open");
+    }
+
+    #[test]
+    fn test_active_task_is_first_unchecked_item() {
+        let dir = std::env::temp_dir().join(format!("apas-template-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("TODO.md"), "# TODO\n- [x] done\n  - [ ] Add retries\n- [ ] later\n").unwrap();
+        assert_eq!(active_task(&dir).as_deref(), Some("Add retries"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
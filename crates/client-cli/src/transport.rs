@@ -0,0 +1,66 @@
+//! Outbound framing for the server connection
+//!
+//! Messages queued while the socket is busy are sent together: as a single
+//! compressed binary frame when the server agreed on a codec at registration,
+//! or as consecutive text frames otherwise.
+
+use anyhow::Result;
+use futures::{Sink, SinkExt};
+use shared::transport::{encode_frame, Compression};
+use shared::CliToServer;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Most messages to pack into one frame
+const MAX_BATCH_MESSAGES: usize = 256;
+
+/// Stop growing a batch once its uncompressed JSON reaches this size
+const MAX_BATCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// A lone message smaller than this goes out as a text frame even when
+/// compression is available; it isn't worth the codec overhead
+const COMPRESS_THRESHOLD: usize = 1024;
+
+/// Send `first` plus whatever is already queued behind it in `rx`
+pub async fn send_batch<S>(
+    sink: &mut S,
+    first: CliToServer,
+    rx: &mut mpsc::Receiver<CliToServer>,
+    compression: Option<Compression>,
+) -> Result<()>
+where
+    S: Sink<Message> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let mut batch = vec![serde_json::to_string(&first)?];
+    let mut batch_bytes = batch[0].len();
+
+    // Only coalesce when the result can travel as one frame
+    if compression.is_some() {
+        while batch.len() < MAX_BATCH_MESSAGES && batch_bytes < MAX_BATCH_BYTES {
+            match rx.try_recv() {
+                Ok(msg) => {
+                    let text = serde_json::to_string(&msg)?;
+                    batch_bytes += text.len();
+                    batch.push(text);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    match compression {
+        Some(codec) if batch.len() > 1 || batch_bytes >= COMPRESS_THRESHOLD => {
+            let frame = encode_frame(codec, &batch)?;
+            tracing::trace!("Sending {} messages as {} byte frame ({} bytes raw)", batch.len(), frame.len(), batch_bytes);
+            sink.send(Message::Binary(frame)).await?;
+        }
+        _ => {
+            for text in batch {
+                sink.send(Message::Text(text)).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 //! Main TUI application for dual-pane mode
 
-use std::io::{self, Stdout};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::io;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crossterm::{
@@ -14,6 +15,8 @@ use ratatui::{
     widgets::{Block, Borders, Paragraph, Wrap},
 };
 
+use crate::signal::{CurrentProcess, Signal};
+
 /// Output message for a pane
 #[derive(Debug, Clone)]
 pub struct PaneOutput {
@@ -50,13 +53,25 @@ pub struct App {
     input_tx: Sender<String>,
     /// Channel to receive output
     output_rx: Receiver<PaneOutput>,
+    /// Why the deadloop is paused, if it is
+    pause_reason: Arc<Mutex<Option<String>>>,
+    /// Workflow stage the deadloop is in, e.g. "2/5 plan"
+    stage: Arc<Mutex<Option<String>>>,
+    /// Claude process answering the current interactive turn
+    interactive_process: CurrentProcess,
     /// Whether to quit
     should_quit: bool,
 }
 
 impl App {
     /// Create a new App with channels for I/O
-    pub fn new(input_tx: Sender<String>, output_rx: Receiver<PaneOutput>) -> Self {
+    pub fn new(
+        input_tx: Sender<String>,
+        output_rx: Receiver<PaneOutput>,
+        pause_reason: Arc<Mutex<Option<String>>>,
+        stage: Arc<Mutex<Option<String>>>,
+        interactive_process: CurrentProcess,
+    ) -> Self {
         Self {
             deadloop_output: vec!["[Deadloop - Autonomous Worker]".to_string()],
             interactive_output: vec!["[Interactive - Press Enter to send]".to_string()],
@@ -68,6 +83,9 @@ impl App {
             interactive_auto_scroll: true,
             input_tx,
             output_rx,
+            pause_reason,
+            stage,
+            interactive_process,
             should_quit: false,
         }
     }
@@ -137,6 +155,9 @@ impl App {
                 KeyCode::Char('r') => {
                     self.focus = Focus::Interactive;
                 }
+                KeyCode::Char('x') => {
+                    self.interrupt_interactive();
+                }
                 _ => {}
             }
             return;
@@ -145,12 +166,10 @@ impl App {
         // Input handling (only in interactive focus)
         if self.focus == Focus::Interactive {
             match code {
-                KeyCode::Enter => {
-                    if !self.input.is_empty() {
-                        let input = std::mem::take(&mut self.input);
-                        // Don't display here - run_interactive_session will display it
-                        let _ = self.input_tx.send(input);
-                    }
+                KeyCode::Enter if !self.input.is_empty() => {
+                    let input = std::mem::take(&mut self.input);
+                    // Don't display here - run_interactive_session will display it
+                    let _ = self.input_tx.send(input);
                 }
                 KeyCode::Char(c) => {
                     self.input.push(c);
@@ -211,6 +230,16 @@ impl App {
         }
     }
 
+    /// Interrupt the interactive turn in progress; the deadloop keeps going
+    fn interrupt_interactive(&mut self) {
+        let text = match self.interactive_process.signal(Signal::Interrupt) {
+            Ok(true) => "[Interrupted]".to_string(),
+            Ok(false) => "[Nothing to interrupt]".to_string(),
+            Err(e) => format!("[Failed to interrupt: {}]", e),
+        };
+        self.interactive_output.push(text);
+    }
+
     /// Draw the UI
     fn draw(&mut self, frame: &mut Frame) {
         let area = frame.area();
@@ -261,7 +290,7 @@ impl App {
                     1
                 } else {
                     // Calculate how many visual lines this logical line takes
-                    ((line.chars().count() + viewport_width - 1) / viewport_width).max(1) as u16
+                    line.chars().count().div_ceil(viewport_width).max(1) as u16
                 }
             })
             .sum();
@@ -318,7 +347,7 @@ impl App {
                     1
                 } else {
                     // Calculate how many visual lines this logical line takes
-                    ((line.chars().count() + viewport_width - 1) / viewport_width).max(1) as u16
+                    line.chars().count().div_ceil(viewport_width).max(1) as u16
                 }
             })
             .sum();
@@ -369,30 +398,24 @@ impl App {
             Focus::Interactive => "INTERACTIVE",
         };
 
+        let paused = self.pause_reason.lock().ok().and_then(|r| r.clone());
+        let mut deadloop_text = match &paused {
+            Some(reason) => format!(" | Deadloop PAUSED: {}", reason),
+            None => String::new(),
+        };
+        if let Some(stage) = self.stage.lock().ok().and_then(|s| s.clone()) {
+            deadloop_text.push_str(&format!(" | Stage {}", stage));
+        }
+
         let status = format!(
-            " Focus: {} | Ctrl+L/R: Switch | PgUp/PgDn: Scroll | End: Auto-scroll | Ctrl+C: Quit ",
-            focus_text
+            " Focus: {}{} | Ctrl+L/R: Switch | Ctrl+X: Interrupt | PgUp/PgDn: Scroll | End: Auto-scroll | Ctrl+C: Quit ",
+            focus_text, deadloop_text
         );
 
+        let background = if paused.is_some() { Color::Yellow } else { Color::DarkGray };
+        let foreground = if paused.is_some() { Color::Black } else { Color::White };
         let paragraph = Paragraph::new(status)
-            .style(Style::default().bg(Color::DarkGray).fg(Color::White));
+            .style(Style::default().bg(background).fg(foreground));
         frame.render_widget(paragraph, area);
     }
-
-    /// Add output to deadloop pane
-    pub fn add_deadloop_output(&mut self, text: String) {
-        self.deadloop_output.push(text);
-    }
-
-    /// Add output to interactive pane
-    pub fn add_interactive_output(&mut self, text: String) {
-        self.interactive_output.push(text);
-    }
-}
-
-/// Create channels for TUI communication
-pub fn create_channels() -> (Sender<String>, Receiver<String>, Sender<PaneOutput>, Receiver<PaneOutput>) {
-    let (input_tx, input_rx) = mpsc::channel();
-    let (output_tx, output_rx) = mpsc::channel();
-    (input_tx, input_rx, output_tx, output_rx)
 }
@@ -0,0 +1,55 @@
+//! Verification after each deadloop iteration
+//!
+//! Claude saying the tests pass isn't proof. With a `verify` section in
+//! `.apas` the CLI runs the project's own check (say `cargo test
+//! --workspace`) after every successful iteration. A failure counts as a
+//! failed iteration and its summary goes into the next prompt.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use crate::shell::{self, ShellOutcome};
+
+/// How much of the failing output goes into the next prompt
+const MAX_SUMMARY_OUTPUT: usize = 4000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verification {
+    /// Shell command run in the project directory; exit status 0 passes
+    pub command: String,
+    /// Kill the command after this long; 0 means no limit
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    30 * 60
+}
+
+impl Verification {
+    pub fn run(&self, working_dir: &Path, shutdown: &AtomicBool, on_line: impl FnMut(&str)) -> io::Result<ShellOutcome> {
+        let timeout = (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs));
+        shell::run(&self.command, working_dir, &[], timeout, shutdown, on_line)
+    }
+
+    /// What to tell Claude about a failed run
+    pub fn failure_summary(&self, outcome: &ShellOutcome) -> String {
+        let how = if outcome.timed_out {
+            format!("timed out after {}s", outcome.duration.as_secs())
+        } else {
+            match outcome.exit_code() {
+                Some(code) => format!("failed with exit code {}", code),
+                None => "was killed".to_string(),
+            }
+        };
+        format!(
+            "After the last iteration, the verification command `{}` {}. The end of its output:\n```\n{}\n```\nFix this before anything else.",
+            self.command,
+            how,
+            shell::tail(outcome.output.trim_end(), MAX_SUMMARY_OUTPUT)
+        )
+    }
+}
@@ -0,0 +1,191 @@
+//! Per-iteration watchdog for the deadloop
+//!
+//! A Claude process can hang (a Bash tool waiting on a prompt, a wedged
+//! network call) and would otherwise stall the loop forever. The watchdog
+//! stops an iteration that runs past `max_duration_secs` or produces no
+//! output for `max_silence_secs`: Claude's process group gets SIGINT first
+//! and SIGKILL if it is still around after a grace period.
+
+use serde::{Deserialize, Serialize};
+use shared::IterationTimeout;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::signal::{signal_group, Signal};
+
+/// How long the process group gets to exit after SIGINT
+const KILL_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IterationLimits {
+    /// Longest an iteration may run; 0 for no limit
+    pub max_duration_secs: u64,
+    /// Longest an iteration may go without output; 0 for no limit
+    pub max_silence_secs: u64,
+}
+
+impl Default for IterationLimits {
+    fn default() -> Self {
+        Self {
+            max_duration_secs: 3 * 60 * 60,
+            max_silence_secs: 30 * 60,
+        }
+    }
+}
+
+/// What `Watchdog::check` just did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// A limit was just exceeded; the process group has been sent SIGINT
+    Interrupted(IterationTimeout),
+    /// SIGINT didn't do it; the process group has been sent SIGKILL
+    Killed,
+}
+
+pub struct Watchdog {
+    limits: IterationLimits,
+    started: Instant,
+    last_output: Arc<Mutex<Instant>>,
+    tripped: Option<(IterationTimeout, Instant)>,
+    killed: bool,
+    kill_grace: Duration,
+}
+
+impl Watchdog {
+    /// Start watching an iteration that begins now
+    pub fn new(limits: IterationLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            started: now,
+            last_output: Arc::new(Mutex::new(now)),
+            tripped: None,
+            killed: false,
+            kill_grace: KILL_GRACE,
+        }
+    }
+
+    /// Shared timestamp of the latest output, for reader threads to update
+    pub fn activity(&self) -> Arc<Mutex<Instant>> {
+        self.last_output.clone()
+    }
+
+    /// Note that the process produced output
+    pub fn touch(&self) {
+        touch(&self.last_output);
+    }
+
+    /// Which limit stopped the iteration, if any
+    pub fn timed_out(&self) -> Option<IterationTimeout> {
+        self.tripped.map(|(reason, _)| reason)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Enforce the limits on the process group led by `pid`. Call this
+    /// regularly while the iteration runs.
+    pub fn check(&mut self, pid: u32) -> Option<WatchdogAction> {
+        match self.tripped {
+            None => {
+                let silence = self.last_output.lock().map(|t| t.elapsed()).unwrap_or_default();
+                let reason = if exceeded(self.limits.max_duration_secs, self.started.elapsed()) {
+                    IterationTimeout::Duration
+                } else if exceeded(self.limits.max_silence_secs, silence) {
+                    IterationTimeout::Idle
+                } else {
+                    return None;
+                };
+                self.tripped = Some((reason, Instant::now()));
+                stop(pid, Signal::Interrupt);
+                Some(WatchdogAction::Interrupted(reason))
+            }
+            Some((_, at)) if !self.killed && at.elapsed() >= self.kill_grace => {
+                self.killed = true;
+                stop(pid, Signal::Kill);
+                Some(WatchdogAction::Killed)
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+pub fn touch(last_output: &Mutex<Instant>) {
+    if let Ok(mut t) = last_output.lock() {
+        *t = Instant::now();
+    }
+}
+
+fn exceeded(limit_secs: u64, elapsed: Duration) -> bool {
+    limit_secs > 0 && elapsed >= Duration::from_secs(limit_secs)
+}
+
+fn stop(pid: u32, signal: Signal) {
+    if let Err(e) = signal_group(pid, signal) {
+        tracing::warn!("Failed to send {:?} to process group {}: {}", signal, pid, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Child, Command, Stdio};
+
+    /// Run `script` in its own process group, once it has printed a line
+    fn spawn_group(script: &str) -> Child {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]).stdout(Stdio::piped());
+        crate::signal::new_process_group(&mut command);
+        let mut child = command.spawn().unwrap();
+        let mut ready = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut ready).unwrap();
+        child
+    }
+
+    fn backdate(time: &mut Instant, secs: u64) {
+        *time -= Duration::from_secs(secs);
+    }
+
+    #[test]
+    fn test_silent_iteration_is_interrupted_then_killed() {
+        // Ignores SIGINT, so only SIGKILL gets rid of it
+        let mut child = spawn_group("trap '' INT; echo ready; sleep 30");
+        let limits = IterationLimits { max_duration_secs: 0, max_silence_secs: 60 };
+        let mut watchdog = Watchdog { kill_grace: Duration::ZERO, ..Watchdog::new(limits) };
+        assert_eq!(watchdog.check(child.id()), None);
+
+        backdate(&mut watchdog.last_output.lock().unwrap(), 60);
+        assert_eq!(watchdog.check(child.id()), Some(WatchdogAction::Interrupted(IterationTimeout::Idle)));
+        assert_eq!(watchdog.timed_out(), Some(IterationTimeout::Idle));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(child.try_wait().unwrap().is_none());
+
+        assert_eq!(watchdog.check(child.id()), Some(WatchdogAction::Killed));
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+        assert_eq!(watchdog.check(child.id()), None);
+    }
+
+    #[test]
+    fn test_output_keeps_the_iteration_alive() {
+        let mut child = spawn_group("echo ready; sleep 30");
+        let limits = IterationLimits { max_duration_secs: 600, max_silence_secs: 60 };
+        let mut watchdog = Watchdog::new(limits);
+        let activity = watchdog.activity();
+        backdate(&mut activity.lock().unwrap(), 59);
+        assert_eq!(watchdog.check(child.id()), None);
+        backdate(&mut activity.lock().unwrap(), 1);
+        // A reader thread saw output just in time
+        touch(&activity);
+        assert_eq!(watchdog.check(child.id()), None);
+
+        // Output doesn't extend the total duration
+        backdate(&mut watchdog.started, 600);
+        assert_eq!(watchdog.check(child.id()), Some(WatchdogAction::Interrupted(IterationTimeout::Duration)));
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGINT));
+        assert_eq!(watchdog.check(child.id()), None, "still within the grace period");
+    }
+}
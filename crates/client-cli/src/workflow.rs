@@ -0,0 +1,163 @@
+//! Multi-stage deadloop workflows
+//!
+//! Instead of replaying one prompt, `.apas` can list named stages (say
+//! pick-task, plan, implement, test, commit). The deadloop runs the current
+//! stage's prompt until its exit condition holds, then moves on to the next
+//! one, starting over after the last.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use crate::shell;
+use crate::stuck::git_head;
+
+/// How long a `command_succeeds` check may run before the stage counts as
+/// not finished
+const EXIT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stage {
+    pub name: String,
+    pub prompt: String,
+    /// Overrides the deadloop's configured model for this stage
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub exit: StageExit,
+    /// Move on after this many iterations even if `exit` never held
+    #[serde(default)]
+    pub max_iterations: Option<u32>,
+}
+
+/// When a stage is done. Failed iterations never finish a stage.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageExit {
+    /// After one iteration
+    #[default]
+    Iteration,
+    /// Once an iteration's final result contains this text
+    ResultContains(String),
+    /// Once this shell command, run in the project directory, succeeds
+    /// (within ten minutes)
+    CommandSucceeds(String),
+    /// Once HEAD moves to a new commit
+    NewCommit,
+}
+
+/// Where the deadloop is in its workflow
+pub struct Workflow {
+    stages: Vec<Stage>,
+    current: usize,
+    /// Iterations run in the current stage
+    iterations: u32,
+    /// HEAD when the current stage started
+    stage_head: Option<String>,
+    /// Whether `.apas` defined the stages
+    staged: bool,
+}
+
+impl Workflow {
+    /// The project's stages, or a single stage running `prompt` if it has none
+    pub fn new(stages: Vec<Stage>, prompt: &str, working_dir: &Path) -> Self {
+        let staged = !stages.is_empty();
+        let stages = if stages.is_empty() {
+            vec![Stage {
+                name: "loop".to_string(),
+                prompt: prompt.to_string(),
+                model: None,
+                exit: StageExit::Iteration,
+                max_iterations: None,
+            }]
+        } else {
+            stages
+        };
+        Self {
+            stages,
+            current: 0,
+            iterations: 0,
+            stage_head: git_head(working_dir),
+            staged,
+        }
+    }
+
+    pub fn stage(&self) -> &Stage {
+        &self.stages[self.current]
+    }
+
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Whether `.apas` defined the stages, so they are worth reporting
+    pub fn is_staged(&self) -> bool {
+        self.staged
+    }
+
+    /// Close out an iteration of the current stage. Returns true if the
+    /// workflow moved on to another stage.
+    pub fn finish_iteration(&mut self, had_error: bool, result: &str, working_dir: &Path, shutdown: &AtomicBool) -> bool {
+        self.iterations += 1;
+        let stage = self.stage();
+        let exited = !had_error
+            && match &stage.exit {
+                StageExit::Iteration => true,
+                StageExit::ResultContains(text) => result.contains(text.as_str()),
+                StageExit::CommandSucceeds(command) => {
+                    command_succeeds(command, working_dir, EXIT_COMMAND_TIMEOUT, shutdown)
+                }
+                StageExit::NewCommit => git_head(working_dir).is_some_and(|head| Some(&head) != self.stage_head.as_ref()),
+            };
+        let exhausted = stage.max_iterations.is_some_and(|max| self.iterations >= max);
+        if !exited && !exhausted {
+            return false;
+        }
+
+        self.current = (self.current + 1) % self.stages.len();
+        self.iterations = 0;
+        self.stage_head = git_head(working_dir);
+        self.stages.len() > 1
+    }
+}
+
+/// Whether `command` exits 0; a check that times out or is stopped by
+/// `shutdown` hasn't succeeded
+fn command_succeeds(command: &str, working_dir: &Path, timeout: Duration, shutdown: &AtomicBool) -> bool {
+    match shell::run(command, working_dir, &[], Some(timeout), shutdown, |_| {}) {
+        Ok(outcome) => {
+            if outcome.timed_out {
+                tracing::warn!("Stage exit command {:?} timed out after {}s", command, timeout.as_secs());
+            }
+            outcome.success()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to run stage exit command {:?}: {}", command, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_hung_exit_command_does_not_finish_the_stage() {
+        let dir = Path::new(".");
+        let running = AtomicBool::new(false);
+        assert!(command_succeeds("true", dir, Duration::from_secs(10), &running));
+        assert!(!command_succeeds("exit 1", dir, Duration::from_secs(10), &running));
+
+        let started = Instant::now();
+        assert!(!command_succeeds("sleep 30; true", dir, Duration::from_millis(300), &running));
+        assert!(!command_succeeds("sleep 30; true", dir, Duration::from_secs(30), &AtomicBool::new(true)));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,146 @@
+//! Stand-in for the `claude` CLI in tests
+//!
+//! Accepts whatever flags APAS passes to Claude and replays a canned
+//! stream-json scenario on stdout. With `--input-format stream-json` it
+//! plays the scenario once per user message on stdin, like a long-lived
+//! interactive process.
+//!
+//! - `FAKE_CLAUDE_SCENARIO`: `success` (default), `error`, `crash`, `hang`,
+//!   `garbage`, or the path to a script file. A script is replayed line by
+//!   line; `#sleep <ms>`, `#exit <code>` and `#hang` are directives, every
+//!   other line is printed as-is.
+//! - `FAKE_CLAUDE_LOG`: file to append each invocation's arguments to, one
+//!   JSON array per line.
+//! - `FAKE_CLAUDE_SESSIONS`: directory of known sessions. `--session-id`
+//!   adds one, and `--resume` of one that isn't there fails the way Claude
+//!   does.
+
+use serde_json::json;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Ok(path) = std::env::var("FAKE_CLAUDE_LOG") {
+        log_invocation(&path, &args);
+    }
+
+    if let Ok(dir) = std::env::var("FAKE_CLAUDE_SESSIONS") {
+        check_session(&dir, &args);
+    }
+
+    let session_id = flag_value(&args, "--session-id")
+        .or_else(|| flag_value(&args, "--resume"))
+        .unwrap_or("fake-session")
+        .to_string();
+    let scenario = std::env::var("FAKE_CLAUDE_SCENARIO").unwrap_or_else(|_| "success".to_string());
+    let script = script(&scenario, &session_id);
+
+    if flag_value(&args, "--input-format") == Some("stream-json") {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if !line.trim().is_empty() {
+                play(&script);
+            }
+        }
+    } else {
+        play(&script);
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let at = args.iter().position(|a| a == flag)?;
+    args.get(at + 1).map(String::as_str)
+}
+
+fn log_invocation(path: &str, args: &[String]) {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .expect("open FAKE_CLAUDE_LOG");
+    writeln!(file, "{}", json!(args)).expect("write FAKE_CLAUDE_LOG");
+}
+
+fn check_session(dir: &str, args: &[String]) {
+    let dir = std::path::Path::new(dir);
+    if let Some(id) = flag_value(args, "--session-id") {
+        std::fs::create_dir_all(dir).expect("create FAKE_CLAUDE_SESSIONS");
+        std::fs::write(dir.join(id), "").expect("write FAKE_CLAUDE_SESSIONS");
+    } else if let Some(id) = flag_value(args, "--resume") {
+        if !dir.join(id).exists() {
+            eprintln!("No conversation found with session ID: {}", id);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The lines of a built-in scenario, or of the script file `scenario` names
+fn script(scenario: &str, session_id: &str) -> Vec<String> {
+    let system = json!({
+        "type": "system", "subtype": "init", "session_id": session_id,
+        "tools": ["Bash", "Read", "Edit"], "model": "fake-claude",
+    });
+    let text = |text: &str| {
+        json!({
+            "type": "assistant", "session_id": session_id,
+            "message": { "model": "fake-claude", "content": [{ "type": "text", "text": text }] },
+        })
+    };
+    let result = |is_error: bool, text: &str| {
+        json!({
+            "type": "result", "subtype": if is_error { "error_during_execution" } else { "success" },
+            "session_id": session_id, "is_error": is_error, "result": text,
+            "total_cost_usd": 0.01, "duration_ms": 5,
+        })
+    };
+
+    let lines = match scenario {
+        "success" => vec![
+            system,
+            text("Looking at TODO.md"),
+            json!({
+                "type": "assistant", "session_id": session_id,
+                "message": { "model": "fake-claude", "content": [
+                    { "type": "tool_use", "id": "tool-1", "name": "Bash", "input": { "command": "ls" } },
+                ] },
+            }),
+            json!({
+                "type": "user", "session_id": session_id,
+                "message": { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "tool-1", "content": "TODO.md", "is_error": false },
+                ] },
+            }),
+            result(false, "Done"),
+        ],
+        "error" => vec![system, text("Trying something"), result(true, "It failed")],
+        "crash" => return vec![system.to_string(), text("About to crash").to_string(), "#exit 2".into()],
+        "hang" => return vec![system.to_string(), "#hang".into()],
+        "garbage" => return vec!["not json {".into(), "\u{1b}[31mcolored noise".into(), result(false, "Done").to_string()],
+        path => {
+            let content = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("unknown scenario {path}: {e}"));
+            return content.lines().map(str::to_string).collect();
+        }
+    };
+    lines.into_iter().map(|line| line.to_string()).collect()
+}
+
+fn play(script: &[String]) {
+    let mut stdout = std::io::stdout().lock();
+    for line in script {
+        let directive = line.strip_prefix('#').map(|d| d.split_once(' ').unwrap_or((d, "")));
+        match directive {
+            Some(("sleep", ms)) => std::thread::sleep(Duration::from_millis(ms.trim().parse().unwrap_or(0))),
+            Some(("exit", code)) => {
+                eprintln!("fake-claude: exiting with {}", code.trim());
+                std::process::exit(code.trim().parse().unwrap_or(1));
+            }
+            Some(("hang", _)) => loop {
+                std::thread::sleep(Duration::from_secs(60));
+            },
+            _ => {
+                writeln!(stdout, "{}", line).expect("write stdout");
+                stdout.flush().expect("flush stdout");
+            }
+        }
+    }
+}
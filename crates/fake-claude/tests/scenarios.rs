@@ -0,0 +1,67 @@
+//! The fake has to speak the same stream-json the real CLI does, or the
+//! tests built on it prove nothing
+
+use shared::ClaudeStreamMessage;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(scenario: &str, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fake-claude"))
+        .args(args)
+        .env("FAKE_CLAUDE_SCENARIO", scenario)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn parse(output: &Output) -> Vec<ClaudeStreamMessage> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{line}: {e}")))
+        .collect()
+}
+
+#[test]
+fn test_scenarios_speak_stream_json() {
+    let output = run("success", &["--print", "--session-id", "abc", "go"], "");
+    assert!(output.status.success());
+    let messages = parse(&output);
+    assert_eq!(messages.len(), 5);
+    assert!(matches!(&messages[0], ClaudeStreamMessage::System { session_id, .. } if session_id == "abc"));
+    assert!(matches!(&messages[4], ClaudeStreamMessage::Result { is_error: false, .. }));
+
+    let output = run("error", &["--print", "go"], "");
+    assert!(matches!(parse(&output).last(), Some(ClaudeStreamMessage::Result { is_error: true, .. })));
+
+    let output = run("crash", &["--print", "go"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(parse(&output).len(), 2);
+
+    let output = run("garbage", &["--print", "go"], "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().filter(|l| serde_json::from_str::<ClaudeStreamMessage>(l).is_err()).count(), 2);
+}
+
+#[test]
+fn test_stream_json_input_plays_once_per_message() {
+    let output = run("error", &["--print", "--input-format", "stream-json", "--resume", "abc"], "{}\n{}\n");
+    let results = parse(&output)
+        .into_iter()
+        .filter(|m| matches!(m, ClaudeStreamMessage::Result { .. }))
+        .count();
+    assert_eq!(results, 2);
+}
+
+#[test]
+fn test_script_file() {
+    let path = std::env::temp_dir().join(format!("fake-claude-{}.jsonl", std::process::id()));
+    std::fs::write(&path, "plain line\n#sleep 10\n#exit 7\nnever printed\n").unwrap();
+    let output = run(path.to_str().unwrap(), &[], "");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "plain line\n");
+}
@@ -0,0 +1,6 @@
+-- CLI clients are keyed by the machine id the CLI persists, so one row per
+-- computer accumulates history instead of one row per connection (Postgres).
+
+ALTER TABLE cli_clients ADD COLUMN hostname TEXT;
+ALTER TABLE cli_clients ADD COLUMN os TEXT;
+ALTER TABLE cli_clients ADD COLUMN claude_version TEXT;
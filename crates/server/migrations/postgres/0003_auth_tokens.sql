@@ -0,0 +1,16 @@
+-- Short-lived auth state that used to live in memory, so a restart doesn't
+-- break in-flight logins or reset links (Postgres). Expiry is a Unix timestamp.
+
+CREATE TABLE IF NOT EXISTS device_codes (
+    code TEXT PRIMARY KEY,
+    user_id TEXT,
+    expires_at BIGINT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    token TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- Iterations the CLI's watchdog stopped before Claude produced a result
+-- (Postgres). They are also counted as errors.
+
+ALTER TABLE iterations ADD COLUMN timed_out BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- What the deadloop sandbox stopped or blocked, for webhooks and the daily
+-- digest (Postgres). Times are Unix timestamps.
+
+CREATE TABLE IF NOT EXISTS sandbox_violations (
+    id BIGSERIAL PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sandbox_violations_session ON sandbox_violations(session_id, created_at);
@@ -0,0 +1,34 @@
+-- Email notification preferences and the activity the daily digest
+-- summarizes (SQLite). Times are Unix timestamps.
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id TEXT PRIMARY KEY REFERENCES users(id),
+    pause_alerts BOOLEAN NOT NULL DEFAULT TRUE,
+    -- NULL turns offline alerts off
+    offline_alert_minutes BIGINT DEFAULT 30,
+    daily_digest BOOLEAN NOT NULL DEFAULT FALSE,
+    last_digest_at BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS iterations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    is_error BOOLEAN NOT NULL,
+    cost_usd REAL NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    finished_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS commits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_iterations_session ON iterations(session_id, finished_at);
+CREATE INDEX IF NOT EXISTS idx_commits_session ON commits(session_id, created_at);
+
+-- When a machine went offline, and whether its owner was told
+ALTER TABLE cli_clients ADD COLUMN offline_since BIGINT;
+ALTER TABLE cli_clients ADD COLUMN offline_alerted BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- Store reset tokens as SHA-256 hashes so a leaked database doesn't contain
+-- working reset links (SQLite). Outstanding plaintext tokens are dropped;
+-- their owners can request a new link.
+
+DROP TABLE IF EXISTS password_reset_tokens;
+
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,124 @@
+//! Cross-instance message backplane
+//!
+//! Every server instance keeps its own websocket connections in
+//! `SessionManager`. When a message is for a CLI or web client connected to a
+//! different instance, it is published on the backplane and delivered by
+//! whichever instance holds that connection. Instances also publish the CLIs
+//! they hold (presence) so any instance can list and route to them.
+
+mod redis;
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use shared::{CliClientInfo, ServerToCli, ServerToWeb};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use crate::config::{BackplaneConfig, BackplaneKind};
+
+pub use self::redis::RedisBackplane;
+#[cfg(test)]
+pub(crate) use self::redis::tests::stand_in as redis_stand_in;
+
+/// A message between instances; `origin` lets an instance skip its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub origin: Uuid,
+    pub event: BackplaneEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackplaneEvent {
+    /// Deliver to a specific CLI connection
+    ToCli { cli_id: Uuid, msg: ServerToCli },
+    /// Deliver to the CLI that owns the session
+    SessionToCli { session_id: Uuid, msg: ServerToCli },
+    /// Deliver to the web client attached to the session
+    SessionToWeb { session_id: Uuid, msg: ServerToWeb },
+    /// Every CLI currently connected to the origin instance
+    Presence { clients: Vec<RemoteCli> },
+}
+
+/// A CLI connected to another instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCli {
+    pub user_id: Uuid,
+    pub info: CliClientInfo,
+}
+
+#[async_trait]
+pub trait Backplane: Send + Sync {
+    /// Send an envelope to every instance (including, possibly, this one)
+    async fn publish(&self, envelope: Envelope) -> Result<()>;
+
+    /// Start receiving envelopes published by any instance
+    fn subscribe(&self) -> mpsc::Receiver<Envelope>;
+}
+
+/// In-process backplane. A single server uses it as a no-op; several
+/// `SessionManager`s sharing one behave like separate instances, which is how
+/// the cross-instance routing is tested.
+pub struct LocalBackplane {
+    tx: broadcast::Sender<Envelope>,
+}
+
+impl LocalBackplane {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(1024);
+        Self { tx }
+    }
+}
+
+impl Default for LocalBackplane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Backplane for LocalBackplane {
+    async fn publish(&self, envelope: Envelope) -> Result<()> {
+        // No receivers just means there is nobody else to tell
+        let _ = self.tx.send(envelope);
+        Ok(())
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<Envelope> {
+        let mut rx = self.tx.subscribe();
+        let (tx, out) = mpsc::channel(1024);
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(envelope) => {
+                        if tx.send(envelope).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!("Local backplane subscriber lagged, dropped {} messages", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        out
+    }
+}
+
+/// Build the backplane selected in the config
+pub fn from_config(config: &BackplaneConfig) -> Result<Arc<dyn Backplane>> {
+    Ok(match config.kind {
+        BackplaneKind::Local => Arc::new(LocalBackplane::new()),
+        BackplaneKind::Redis => {
+            let url = config
+                .url
+                .as_deref()
+                .context("backplane.url is required when backplane.kind is \"redis\"")?;
+            Arc::new(RedisBackplane::new(url, &config.channel)?)
+        }
+    })
+}
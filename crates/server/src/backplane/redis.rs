@@ -0,0 +1,400 @@
+//! Redis pub/sub backplane
+//!
+//! All instances publish to and subscribe on one channel. Publishes share one
+//! multiplexed connection; the subscriber has its own and PINGs it
+//! periodically, reconnecting with backoff if Redis goes away or stops
+//! answering. Messages published while an instance is disconnected are lost,
+//! as with any Redis pub/sub.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::aio::MultiplexedConnection;
+use tokio::sync::{mpsc, Mutex};
+
+use super::{Backplane, Envelope};
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// Limit on connecting, on a PUBLISH and on the subscriber's PING replies
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the subscriber checks that its connection still works
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+pub struct RedisBackplane {
+    client: redis::Client,
+    channel: String,
+    /// Connection used for PUBLISH, opened on first use
+    publisher: Mutex<Option<MultiplexedConnection>>,
+    timeout: Duration,
+    ping_interval: Duration,
+}
+
+impl RedisBackplane {
+    /// `url` is `redis://[:password@]host[:port][/db]`
+    pub fn new(url: &str, channel: &str) -> Result<Self> {
+        if !url.starts_with("redis://") {
+            bail!("Unsupported backplane URL {:?}; expected redis://", url);
+        }
+        let client = redis::Client::open(url).with_context(|| format!("Invalid backplane URL {:?}", url))?;
+
+        Ok(Self {
+            client,
+            channel: channel.to_string(),
+            publisher: Mutex::new(None),
+            timeout: REPLY_TIMEOUT,
+            ping_interval: PING_INTERVAL,
+        })
+    }
+
+    async fn subscribe_loop(
+        client: redis::Client,
+        channel: String,
+        timeout: Duration,
+        ping_interval: Duration,
+        tx: mpsc::Sender<Envelope>,
+    ) {
+        let addr = client.get_connection_info().addr.to_string();
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match Self::receive(&client, &channel, timeout, ping_interval, &tx).await {
+                Ok(()) => return, // nobody is listening any more
+                Err(e) => {
+                    tracing::warn!("Redis backplane subscription to {} failed: {}; retrying in {:?}", addr, e, delay);
+                }
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Subscribe and forward messages until the connection fails or stops
+    /// answering PINGs (Err), or the receiver is dropped (Ok)
+    async fn receive(
+        client: &redis::Client,
+        channel: &str,
+        timeout: Duration,
+        ping_interval: Duration,
+        tx: &mpsc::Sender<Envelope>,
+    ) -> Result<()> {
+        let (mut sink, mut stream) = tokio::time::timeout(timeout, async {
+            let mut pubsub = client.get_async_pubsub().await?;
+            pubsub.subscribe(channel).await?;
+            Ok::<_, redis::RedisError>(pubsub.split())
+        })
+        .await
+        .context("Timed out subscribing")??;
+        tracing::info!("Subscribed to Redis backplane channel {} at {}", channel, client.get_connection_info().addr);
+
+        let mut ping = tokio::time::interval(ping_interval);
+        ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ping.tick().await;
+        loop {
+            tokio::select! {
+                msg = stream.next() => {
+                    let Some(msg) = msg else { bail!("connection closed") };
+                    match serde_json::from_slice::<Envelope>(msg.get_payload_bytes()) {
+                        Ok(envelope) => {
+                            if tx.send(envelope).await.is_err() {
+                                return Ok(());
+                            }
+                        }
+                        Err(e) => tracing::warn!("Ignoring malformed backplane message: {}", e),
+                    }
+                }
+                _ = ping.tick() => {
+                    // A silently dropped connection never errors on its own
+                    tokio::time::timeout(timeout, sink.ping::<redis::Value>())
+                        .await
+                        .context("Timed out waiting for PING reply")??;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Backplane for RedisBackplane {
+    async fn publish(&self, envelope: Envelope) -> Result<()> {
+        let payload = serde_json::to_vec(&envelope)?;
+
+        // The connection pipelines commands, so publishers only share the
+        // lock while connecting
+        let mut conn = {
+            let mut publisher = self.publisher.lock().await;
+            if publisher.is_none() {
+                let conn = tokio::time::timeout(self.timeout, self.client.get_multiplexed_async_connection())
+                    .await
+                    .with_context(|| format!("Timed out connecting to Redis at {}", self.client.get_connection_info().addr))??;
+                *publisher = Some(conn);
+            }
+            publisher.clone().expect("connected above")
+        };
+
+        let published = tokio::time::timeout(
+            self.timeout,
+            redis::cmd("PUBLISH").arg(&self.channel).arg(payload).query_async::<i64>(&mut conn),
+        )
+        .await;
+        let reconnect = match &published {
+            Ok(Ok(_)) => false,
+            Ok(Err(e)) => e.is_unrecoverable_error(),
+            Err(_) => true,
+        };
+        if reconnect {
+            // Open a fresh connection on the next publish
+            *self.publisher.lock().await = None;
+        }
+        match published {
+            Ok(result) => result.map(|_| ()).context("Redis PUBLISH failed"),
+            Err(_) => bail!("Timed out waiting for Redis PUBLISH reply"),
+        }
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<Envelope> {
+        let (tx, rx) = mpsc::channel(1024);
+        tokio::spawn(Self::subscribe_loop(
+            self.client.clone(),
+            self.channel.clone(),
+            self.timeout,
+            self.ping_interval,
+            tx,
+        ));
+        rx
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::backplane::BackplaneEvent;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    type Subscribers = Arc<std::sync::Mutex<Vec<(Vec<u8>, mpsc::UnboundedSender<Vec<u8>>)>>>;
+
+    /// Read one command (an array of bulk strings)
+    async fn read_command<R: AsyncBufRead + Unpin>(reader: &mut R) -> Option<Vec<Vec<u8>>> {
+        async fn header<R: AsyncBufRead + Unpin>(reader: &mut R, kind: u8) -> Option<usize> {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.ok()?;
+            line.strip_prefix(kind as char)?.trim_end().parse().ok()
+        }
+        let count = header(reader, b'*').await?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut arg = vec![0; header(reader, b'$').await? + 2];
+            reader.read_exact(&mut arg).await.ok()?;
+            arg.truncate(arg.len() - 2);
+            args.push(arg);
+        }
+        Some(args)
+    }
+
+    fn bulk(item: &[u8]) -> Vec<u8> {
+        let mut out = format!("${}\r\n", item.len()).into_bytes();
+        out.extend_from_slice(item);
+        out.extend_from_slice(b"\r\n");
+        out
+    }
+
+    fn bulk_array(items: &[&[u8]]) -> Vec<u8> {
+        let mut out = format!("*{}\r\n", items.len()).into_bytes();
+        for item in items {
+            out.extend(bulk(item));
+        }
+        out
+    }
+
+    /// Minimal stand-in for a Redis server: PING, AUTH, SUBSCRIBE and PUBLISH
+    pub(crate) async fn stand_in() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let subscribers: Subscribers = Default::default();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let subscribers = subscribers.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = socket.into_split();
+                    let mut reader = BufReader::new(read);
+                    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+                    tokio::spawn(async move {
+                        while let Some(bytes) = out_rx.recv().await {
+                            if write.write_all(&bytes).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+
+                    let mut subscribed = false;
+                    while let Some(args) = read_command(&mut reader).await {
+                        let reply = match args[0].to_ascii_uppercase().as_slice() {
+                            b"PING" if subscribed => bulk_array(&[b"pong", b""]),
+                            b"PING" => b"+PONG\r\n".to_vec(),
+                            b"AUTH" => b"+OK\r\n".to_vec(),
+                            b"SUBSCRIBE" => {
+                                subscribed = true;
+                                subscribers.lock().unwrap().push((args[1].clone(), out_tx.clone()));
+                                let mut ack = b"*3\r\n".to_vec();
+                                ack.extend(bulk(b"subscribe"));
+                                ack.extend(bulk(&args[1]));
+                                ack.extend_from_slice(b":1\r\n");
+                                ack
+                            }
+                            b"PUBLISH" => {
+                                let frame = bulk_array(&[b"message", &args[1], &args[2]]);
+                                let mut delivered = 0;
+                                for (channel, tx) in subscribers.lock().unwrap().iter() {
+                                    if *channel == args[1] && tx.send(frame.clone()).is_ok() {
+                                        delivered += 1;
+                                    }
+                                }
+                                format!(":{}\r\n", delivered).into_bytes()
+                            }
+                            _ => b"-ERR unknown command\r\n".to_vec(),
+                        };
+                        let _ = out_tx.send(reply);
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    /// Forward connections to `target` until `cut` is set; after that the
+    /// existing connections stay open but nothing gets through, like a
+    /// connection dropped somewhere along the way
+    async fn cuttable_proxy(target: String, cut: Arc<AtomicBool>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                if cut.load(Ordering::SeqCst) {
+                    // New connections go straight through
+                    let server = tokio::net::TcpStream::connect(&target).await.unwrap();
+                    let (mut client, mut server) = (client, server);
+                    tokio::spawn(async move {
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                    });
+                    continue;
+                }
+                let server = tokio::net::TcpStream::connect(&target).await.unwrap();
+                let (client_read, client_write) = client.into_split();
+                let (server_read, server_write) = server.into_split();
+                for (mut from, mut to) in [(client_read, server_write), (server_read, client_write)] {
+                    let cut = cut.clone();
+                    tokio::spawn(async move {
+                        let mut buf = [0; 4096];
+                        while let Ok(n) = from.read(&mut buf).await {
+                            if n == 0 || cut.load(Ordering::SeqCst) {
+                                // Hold both ends open without forwarding
+                                std::future::pending::<()>().await;
+                            }
+                            if to.write_all(&buf[..n]).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+            }
+        });
+        addr
+    }
+
+    fn presence(origin: Uuid) -> Envelope {
+        Envelope { origin, event: BackplaneEvent::Presence { clients: vec![] } }
+    }
+
+    /// Publish from `a` until `rx` receives it; pub/sub doesn't buffer, so
+    /// the subscription may not be registered yet
+    async fn publish_until_received(a: &RedisBackplane, rx: &mut mpsc::Receiver<Envelope>) -> Envelope {
+        let origin = Uuid::new_v4();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                a.publish(presence(origin)).await.unwrap();
+                if let Ok(Some(envelope)) = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await {
+                    if envelope.origin == origin {
+                        return envelope;
+                    }
+                }
+            }
+        })
+        .await
+        .expect("message not delivered in time")
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let plain = RedisBackplane::new("redis://cache.internal", "apas").unwrap();
+        assert_eq!(plain.client.get_connection_info().addr.to_string(), "cache.internal:6379");
+        assert_eq!(plain.client.get_connection_info().redis.password, None);
+
+        let auth = RedisBackplane::new("redis://:s3cret@10.0.0.5:6380/", "apas").unwrap();
+        assert_eq!(auth.client.get_connection_info().addr.to_string(), "10.0.0.5:6380");
+        assert_eq!(auth.client.get_connection_info().redis.password.as_deref(), Some("s3cret"));
+
+        assert!(RedisBackplane::new("nats://localhost", "apas").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publish_gives_up_on_a_stalled_server() {
+        // Accepts connections and reads commands but never replies
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut sink = Vec::new();
+                    let _ = socket.read_to_end(&mut sink).await;
+                });
+            }
+        });
+
+        let mut backplane = RedisBackplane::new(&format!("redis://{}", addr), "apas:test").unwrap();
+        backplane.timeout = Duration::from_millis(200);
+
+        // Concurrent publishes wait for their replies side by side, not one after another
+        let started = tokio::time::Instant::now();
+        let (first, second) = tokio::join!(
+            backplane.publish(presence(Uuid::new_v4())),
+            backplane.publish(presence(Uuid::new_v4()))
+        );
+        assert!(first.is_err() && second.is_err());
+        assert!(started.elapsed() < Duration::from_millis(350), "took {:?}", started.elapsed());
+        assert!(backplane.publisher.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_publish_reaches_subscribers() {
+        let addr = stand_in().await;
+        let a = RedisBackplane::new(&format!("redis://:pw@{}", addr), "apas:test").unwrap();
+        let b = RedisBackplane::new(&format!("redis://{}", addr), "apas:test").unwrap();
+        let mut rx = b.subscribe();
+
+        let received = publish_until_received(&a, &mut rx).await;
+        assert!(matches!(received.event, BackplaneEvent::Presence { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_reconnects_when_pings_go_unanswered() {
+        let addr = stand_in().await;
+        let cut = Arc::new(AtomicBool::new(false));
+        let proxied = cuttable_proxy(addr.clone(), cut.clone()).await;
+        let a = RedisBackplane::new(&format!("redis://{}", addr), "apas:test").unwrap();
+        let mut b = RedisBackplane::new(&format!("redis://{}", proxied), "apas:test").unwrap();
+        b.timeout = Duration::from_millis(200);
+        b.ping_interval = Duration::from_millis(100);
+        let mut rx = b.subscribe();
+        publish_until_received(&a, &mut rx).await;
+
+        // The old connection stays open but goes quiet; only the PING notices
+        cut.store(true, Ordering::SeqCst);
+        publish_until_received(&a, &mut rx).await;
+    }
+}
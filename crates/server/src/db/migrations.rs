@@ -0,0 +1,352 @@
+//! Versioned schema migrations
+//!
+//! Migrations are SQL scripts in `crates/server/migrations/<backend>`, applied
+//! in version order. Every migration has a SQLite and a Postgres script with
+//! the same version and name. Each one runs in its own transaction together with the
+//! `schema_version` row that records it, so a failing migration leaves the
+//! database at the previous version instead of half-applied. The recorded
+//! checksum catches scripts that were edited after being deployed.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, SqlitePool};
+
+use super::{placeholders, Pool};
+use crate::config::DatabaseBackend;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sqlite: &'static str,
+    pub postgres: &'static str,
+}
+
+impl Migration {
+    fn sql(&self, backend: DatabaseBackend) -> &'static str {
+        match backend {
+            DatabaseBackend::Sqlite => self.sqlite,
+            DatabaseBackend::Postgres => self.postgres,
+        }
+    }
+}
+
+/// All migrations, in the order they must be applied
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sqlite: include_str!("../../migrations/sqlite/0001_initial.sql"),
+        postgres: include_str!("../../migrations/postgres/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "cli_machine_identity",
+        sqlite: include_str!("../../migrations/sqlite/0002_cli_machine_identity.sql"),
+        postgres: include_str!("../../migrations/postgres/0002_cli_machine_identity.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "auth_tokens",
+        sqlite: include_str!("../../migrations/sqlite/0003_auth_tokens.sql"),
+        postgres: include_str!("../../migrations/postgres/0003_auth_tokens.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "webhooks",
+        sqlite: include_str!("../../migrations/sqlite/0004_webhooks.sql"),
+        postgres: include_str!("../../migrations/postgres/0004_webhooks.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "notifications",
+        sqlite: include_str!("../../migrations/sqlite/0005_notifications.sql"),
+        postgres: include_str!("../../migrations/postgres/0005_notifications.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "iteration_timeouts",
+        sqlite: include_str!("../../migrations/sqlite/0006_iteration_timeouts.sql"),
+        postgres: include_str!("../../migrations/postgres/0006_iteration_timeouts.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "hashed_reset_tokens",
+        sqlite: include_str!("../../migrations/sqlite/0007_hashed_reset_tokens.sql"),
+        postgres: include_str!("../../migrations/postgres/0007_hashed_reset_tokens.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "sandbox_violations",
+        sqlite: include_str!("../../migrations/sqlite/0008_sandbox_violations.sql"),
+        postgres: include_str!("../../migrations/postgres/0008_sandbox_violations.sql"),
+    },
+];
+
+/// State of one known migration in a particular database
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    /// None if the migration is still pending
+    pub applied_at: Option<String>,
+}
+
+fn checksum(sql: &str) -> String {
+    hex::encode(Sha256::digest(sql.as_bytes()))
+}
+
+async fn table_exists(pool: &Pool, table: &str) -> Result<bool> {
+    let count: i64 = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+                .bind(table)
+                .fetch_one(pool)
+                .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = $1",
+            )
+            .bind(table)
+            .fetch_one(pool)
+            .await?
+        }
+    };
+    Ok(count > 0)
+}
+
+/// Compare the recorded versions against `migrations` without changing anything.
+/// Fails if the database was migrated by a newer server, if a recorded script
+/// has changed since, or if a pending migration sorts before an applied one.
+pub(super) async fn status(pool: &Pool, migrations: &[Migration]) -> Result<Vec<MigrationStatus>> {
+    if migrations.windows(2).any(|w| w[0].version >= w[1].version) {
+        bail!("Migrations are not in strictly increasing version order");
+    }
+
+    let applied: Vec<(i64, String, Option<String>)> = if table_exists(pool, "schema_version").await? {
+        dispatch!(pool, pool => {
+            sqlx::query_as("SELECT version, checksum, applied_at FROM schema_version ORDER BY version")
+                .fetch_all(pool)
+                .await
+        })?
+    } else {
+        Vec::new()
+    };
+
+    let mut result: Vec<MigrationStatus> = migrations
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name,
+            applied_at: None,
+        })
+        .collect();
+
+    for (version, recorded_checksum, applied_at) in applied {
+        let idx = migrations
+            .iter()
+            .position(|m| m.version == version)
+            .with_context(|| format!("Database is at schema version {}, which this server doesn't know; is it newer?", version))?;
+
+        if checksum(migrations[idx].sql(pool.backend())) != recorded_checksum {
+            bail!(
+                "Migration {} ({}) has changed since it was applied; add a new migration instead of editing it",
+                version,
+                migrations[idx].name
+            );
+        }
+        result[idx].applied_at = Some(applied_at.unwrap_or_default());
+    }
+
+    if let Some(last_applied) = result.iter().rposition(|s| s.applied_at.is_some()) {
+        if let Some(gap) = result[..last_applied].iter().find(|s| s.applied_at.is_none()) {
+            bail!(
+                "Migration {} ({}) is pending but later migrations are already applied",
+                gap.version,
+                gap.name
+            );
+        }
+    }
+
+    Ok(result)
+}
+
+/// Apply all pending migrations, returning the versions that were applied
+pub(super) async fn apply(pool: &Pool, migrations: &[Migration]) -> Result<Vec<i64>> {
+    let fresh_version_table = !table_exists(pool, "schema_version").await?;
+    dispatch!(pool, pool => {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS schema_version (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TEXT DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(pool)
+        .await
+        .map(|done| done.rows_affected())
+    })?;
+
+    if let (true, Pool::Sqlite(pool)) = (fresh_version_table, pool) {
+        adopt_legacy_schema(pool).await?;
+    }
+
+    let insert_version = placeholders(
+        pool.backend(),
+        "INSERT INTO schema_version (version, name, checksum) VALUES (?, ?, ?)",
+    );
+    let mut applied = Vec::new();
+    for status in status(pool, migrations).await? {
+        if status.applied_at.is_some() {
+            continue;
+        }
+        let migration = migrations
+            .iter()
+            .find(|m| m.version == status.version)
+            .expect("status only lists known migrations");
+
+        let sql = migration.sql(pool.backend());
+        dispatch!(pool, pool => {
+            let mut tx = pool.begin().await?;
+            // A plain string runs as a simple query, so multi-statement scripts work
+            tx.execute(sql).await.with_context(|| {
+                format!(
+                    "Migration {} ({}) failed; the database was left at the previous version",
+                    migration.version, migration.name
+                )
+            })?;
+            sqlx::query(&insert_version)
+                .bind(migration.version)
+                .bind(migration.name)
+                .bind(checksum(sql))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        });
+
+        tracing::info!("Applied migration {} ({})", migration.version, migration.name);
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+/// Databases created before versioned migrations may have an older `sessions`
+/// table that predates the working_dir/hostname columns. Bring it up to the
+/// baseline so migration 1 can be recorded as applied.
+async fn adopt_legacy_schema(pool: &SqlitePool) -> Result<()> {
+    let has_sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'sessions'")
+        .fetch_one(pool)
+        .await?;
+    if has_sessions == 0 {
+        return Ok(());
+    }
+
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('sessions')")
+        .fetch_all(pool)
+        .await?;
+    for column in ["working_dir", "hostname"] {
+        if !columns.iter().any(|c| c == column) {
+            tracing::info!("Adding missing sessions.{} column to legacy database", column);
+            sqlx::query(&format!("ALTER TABLE sessions ADD COLUMN {} TEXT", column))
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> Pool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        Pool::Sqlite(pool)
+    }
+
+    #[tokio::test]
+    async fn test_apply_is_idempotent() {
+        let pool = memory_pool().await;
+
+        let before = status(&pool, MIGRATIONS).await.unwrap();
+        assert!(before.iter().all(|s| s.applied_at.is_none()));
+
+        let applied = apply(&pool, MIGRATIONS).await.unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert!(apply(&pool, MIGRATIONS).await.unwrap().is_empty());
+
+        let after = status(&pool, MIGRATIONS).await.unwrap();
+        assert!(after.iter().all(|s| s.applied_at.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_rolled_back() {
+        let pool = memory_pool().await;
+        let migrations = [
+            Migration { version: 1, name: "one", sqlite: "CREATE TABLE a (id INTEGER);", postgres: "" },
+            Migration {
+                version: 2,
+                name: "broken",
+                sqlite: "CREATE TABLE b (id INTEGER); INSERT INTO missing VALUES (1);",
+                postgres: "",
+            },
+        ];
+
+        assert!(apply(&pool, &migrations).await.is_err());
+
+        let state = status(&pool, &migrations).await.unwrap();
+        assert!(state[0].applied_at.is_some());
+        assert!(state[1].applied_at.is_none());
+        assert!(table_exists(&pool, "a").await.unwrap());
+        assert!(!table_exists(&pool, "b").await.unwrap(), "partial migration must not persist");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_edited_and_unknown_migrations() {
+        let pool = memory_pool().await;
+        let original = [Migration { version: 1, name: "one", sqlite: "CREATE TABLE a (id INTEGER);", postgres: "" }];
+        apply(&pool, &original).await.unwrap();
+
+        let edited = [Migration { version: 1, name: "one", sqlite: "CREATE TABLE a (id TEXT);", postgres: "" }];
+        assert!(status(&pool, &edited).await.is_err());
+
+        assert!(status(&pool, &[]).await.is_err(), "database newer than the server");
+    }
+
+    #[test]
+    fn test_baseline_checksum_is_unchanged() {
+        // Recorded by every SQLite database migrated before the Postgres backend
+        assert_eq!(
+            checksum(MIGRATIONS[0].sqlite),
+            "fab1710acae84437cbb6d569fdda4ee3be1ed3b48d8bb4d41c3f5fa7dcde19f8"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_adopts_legacy_database() {
+        let db_pool = memory_pool().await;
+        let Pool::Sqlite(pool) = &db_pool else { unreachable!() };
+        sqlx::query("CREATE TABLE sessions (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, status TEXT)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        apply(&db_pool, MIGRATIONS).await.unwrap();
+
+        let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('sessions')")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        assert!(columns.contains(&"working_dir".to_string()));
+        assert!(columns.contains(&"hostname".to_string()));
+    }
+}
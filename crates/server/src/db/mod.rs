@@ -1,227 +1,286 @@
-use anyhow::Result;
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgPoolOptions, sqlite::SqlitePoolOptions, PgPool, SqlitePool};
+use std::borrow::Cow;
 use std::path::Path;
 
+use crate::config::{DatabaseBackend, DatabaseConfig};
+
+/// Run `$body` against whichever pool is configured, with `$pool` bound to the
+/// concrete sqlx pool. The body is compiled once per backend, so queries stay
+/// statically typed without going through sqlx's `Any` driver.
+macro_rules! dispatch {
+    ($db_pool:expr, $pool:ident => $body:expr) => {
+        match $db_pool {
+            Pool::Sqlite($pool) => $body,
+            Pool::Postgres($pool) => $body,
+        }
+    };
+}
+
+mod migrations;
 mod models;
 
+pub use migrations::MigrationStatus;
 pub use models::*;
 
+#[derive(Clone)]
+enum Pool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+impl Pool {
+    fn backend(&self) -> DatabaseBackend {
+        match self {
+            Pool::Sqlite(_) => DatabaseBackend::Sqlite,
+            Pool::Postgres(_) => DatabaseBackend::Postgres,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Database {
-    pool: SqlitePool,
+    pool: Pool,
 }
 
-impl Database {
-    pub async fn new(path: &str) -> Result<Self> {
-        // Ensure the directory exists
-        if let Some(parent) = Path::new(path).parent() {
-            std::fs::create_dir_all(parent)?;
+/// Rewrite `?` placeholders as `$1, $2, ...` for Postgres. Queries are written
+/// in the SQLite style; question marks inside string literals are left alone.
+fn placeholders(backend: DatabaseBackend, query: &str) -> Cow<'_, str> {
+    if backend == DatabaseBackend::Sqlite || !query.contains('?') {
+        return Cow::Borrowed(query);
+    }
+
+    let mut out = String::with_capacity(query.len() + 8);
+    let mut in_literal = false;
+    let mut n = 0;
+    for c in query.chars() {
+        match c {
+            '\'' => {
+                in_literal = !in_literal;
+                out.push(c);
+            }
+            '?' if !in_literal => {
+                n += 1;
+                out.push('$');
+                out.push_str(&n.to_string());
+            }
+            _ => out.push(c),
         }
+    }
+    Cow::Owned(out)
+}
 
-        let database_url = format!("sqlite:{}?mode=rwc", path);
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&database_url)
-            .await?;
+/// Password reset tokens are stored hashed, so the table alone can't reset a password
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl Database {
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self> {
+        let pool = match config.backend {
+            DatabaseBackend::Sqlite => {
+                // Ensure the directory exists
+                if let Some(parent) = Path::new(&config.path).parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                let database_url = format!("sqlite:{}?mode=rwc", config.path);
+                let pool = SqlitePoolOptions::new()
+                    .max_connections(config.max_connections)
+                    .connect(&database_url)
+                    .await?;
+                Pool::Sqlite(pool)
+            }
+            DatabaseBackend::Postgres => {
+                let database_url = config
+                    .url
+                    .as_deref()
+                    .context("database.url is required when database.backend is \"postgres\"")?;
+                let pool = PgPoolOptions::new()
+                    .max_connections(config.max_connections)
+                    .connect(database_url)
+                    .await
+                    .context("Failed to connect to Postgres")?;
+                Pool::Postgres(pool)
+            }
+        };
 
         Ok(Self { pool })
     }
 
-    pub async fn run_migrations(&self) -> Result<()> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS users (
-                id TEXT PRIMARY KEY,
-                email TEXT UNIQUE NOT NULL,
-                password_hash TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS cli_clients (
-                id TEXT PRIMARY KEY,
-                user_id TEXT NOT NULL REFERENCES users(id),
-                name TEXT,
-                last_seen DATETIME,
-                status TEXT DEFAULT 'offline',
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                user_id TEXT NOT NULL,
-                cli_client_id TEXT,
-                working_dir TEXT,
-                hostname TEXT,
-                status TEXT DEFAULT 'pending',
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        // Add columns if they don't exist (migration for existing DBs)
-        let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN working_dir TEXT")
-            .execute(&self.pool)
-            .await;
-        let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN hostname TEXT")
-            .execute(&self.pool)
-            .await;
-
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                session_id TEXT NOT NULL REFERENCES sessions(id),
-                role TEXT NOT NULL,
-                content TEXT NOT NULL,
-                message_type TEXT DEFAULT 'text',
-                metadata TEXT,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        // Session sharing tables
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS session_shares (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
-                user_id TEXT NOT NULL REFERENCES users(id),
-                invited_by TEXT NOT NULL REFERENCES users(id),
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                UNIQUE(session_id, user_id)
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS invitation_codes (
-                code TEXT PRIMARY KEY,
-                session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
-                created_by TEXT NOT NULL REFERENCES users(id),
-                expires_at DATETIME NOT NULL,
-                redeemed_by TEXT REFERENCES users(id),
-                redeemed_at DATETIME,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+    fn sql<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        placeholders(self.pool.backend(), query)
+    }
 
-        tracing::info!("Database migrations completed");
+    /// Apply any pending schema migrations
+    pub async fn run_migrations(&self) -> Result<()> {
+        let applied = migrations::apply(&self.pool, migrations::MIGRATIONS).await?;
+        if applied.is_empty() {
+            tracing::info!("Database schema is up to date");
+        } else {
+            tracing::info!("Database migrations completed ({} applied)", applied.len());
+        }
         Ok(())
     }
 
+    /// Applied/pending state of every known migration, without changing the database
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        migrations::status(&self.pool, migrations::MIGRATIONS).await
+    }
+
     // User operations
     pub async fn create_user(&self, user: &User) -> Result<()> {
-        sqlx::query(
-            "INSERT INTO users (id, email, password_hash) VALUES (?, ?, ?)",
-        )
-        .bind(&user.id)
-        .bind(&user.email)
-        .bind(&user.password_hash)
-        .execute(&self.pool)
-        .await?;
+        dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql("INSERT INTO users (id, email, password_hash) VALUES (?, ?, ?)"),
+            )
+            .bind(&user.id)
+            .bind(&user.email)
+            .bind(&user.password_hash)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
     pub async fn get_all_users(&self) -> Result<Vec<User>> {
-        let users = sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY email")
-            .fetch_all(&self.pool)
-            .await?;
+        let users = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(&self.sql("SELECT * FROM users ORDER BY email"))
+                .fetch_all(pool)
+                .await
+        })?;
         Ok(users)
     }
 
     pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
-        let user = sqlx::query_as::<_, User>(
-            "SELECT id, email, password_hash, created_at FROM users WHERE email = ?",
-        )
-        .bind(email)
-        .fetch_optional(&self.pool)
-        .await?;
+        let user = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(
+                &self.sql("SELECT id, email, password_hash FROM users WHERE email = ?"),
+            )
+            .bind(email)
+            .fetch_optional(pool)
+            .await
+        })?;
         Ok(user)
     }
 
     pub async fn get_user_by_id(&self, id: &str) -> Result<Option<User>> {
-        let user = sqlx::query_as::<_, User>(
-            "SELECT id, email, password_hash, created_at FROM users WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let user = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(
+                &self.sql("SELECT id, email, password_hash FROM users WHERE id = ?"),
+            )
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+        })?;
         Ok(user)
     }
 
     pub async fn update_user_password(&self, email: &str, password_hash: &str) -> Result<bool> {
-        let result = sqlx::query("UPDATE users SET password_hash = ? WHERE email = ?")
-            .bind(password_hash)
-            .bind(email)
-            .execute(&self.pool)
-            .await?;
-        Ok(result.rows_affected() > 0)
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("UPDATE users SET password_hash = ? WHERE email = ?"))
+                .bind(password_hash)
+                .bind(email)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
     }
 
     // CLI client operations
-    pub async fn upsert_cli_client(&self, client: &CliClient) -> Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO cli_clients (id, user_id, name, last_seen, status)
-            VALUES (?, ?, ?, ?, ?)
-            ON CONFLICT(id) DO UPDATE SET
-                last_seen = excluded.last_seen,
-                status = excluded.status
-            "#,
-        )
-        .bind(&client.id)
-        .bind(&client.user_id)
-        .bind(&client.name)
-        .bind(&client.last_seen)
-        .bind(&client.status)
-        .execute(&self.pool)
-        .await?;
-        Ok(())
+
+    /// Insert or refresh a CLI client row. Returns false, leaving the row
+    /// alone, if the id is already registered to a different user.
+    pub async fn upsert_cli_client(&self, client: &CliClient) -> Result<bool> {
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql(r#"
+                INSERT INTO cli_clients (id, user_id, name, hostname, os, claude_version, last_seen, status)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(id) DO UPDATE SET
+                    user_id = excluded.user_id,
+                    name = excluded.name,
+                    hostname = excluded.hostname,
+                    os = excluded.os,
+                    claude_version = excluded.claude_version,
+                    last_seen = excluded.last_seen,
+                    status = excluded.status,
+                    offline_since = NULL,
+                    offline_alerted = FALSE
+                WHERE cli_clients.user_id = excluded.user_id
+                "#),
+            )
+            .bind(&client.id)
+            .bind(&client.user_id)
+            .bind(&client.name)
+            .bind(&client.hostname)
+            .bind(&client.os)
+            .bind(&client.claude_version)
+            .bind(&client.last_seen)
+            .bind(&client.status)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
     }
 
     pub async fn get_cli_clients_for_user(&self, user_id: &str) -> Result<Vec<CliClient>> {
-        let clients = sqlx::query_as::<_, CliClient>(
-            "SELECT id, user_id, name, last_seen, status, created_at FROM cli_clients WHERE user_id = ?",
-        )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await?;
+        let clients = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, CliClient>(
+                &self.sql("SELECT id, user_id, name, hostname, os, claude_version, last_seen, status FROM cli_clients WHERE user_id = ?"),
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
         Ok(clients)
     }
 
-    pub async fn update_cli_client_status(&self, id: &str, status: &str) -> Result<()> {
-        sqlx::query("UPDATE cli_clients SET status = ?, last_seen = CURRENT_TIMESTAMP WHERE id = ?")
-            .bind(status)
+    pub async fn mark_cli_client_offline(&self, id: &str, now: i64) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "UPDATE cli_clients SET status = 'offline', last_seen = CURRENT_TIMESTAMP, offline_since = ?, offline_alerted = FALSE WHERE id = ?",
+            ))
+            .bind(now)
             .bind(id)
-            .execute(&self.pool)
-            .await?;
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
+    /// Offline machines whose owners haven't been alerted yet
+    pub async fn get_unalerted_offline_clients(&self) -> Result<Vec<OfflineCliClient>> {
+        let clients = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, OfflineCliClient>(&self.sql(
+                "SELECT id, user_id, name, hostname, offline_since FROM cli_clients WHERE status = 'offline' AND offline_alerted = FALSE AND offline_since IS NOT NULL",
+            ))
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(clients)
+    }
+
+    /// Record that the owner is being alerted; false if the machine came back
+    /// or another instance already claimed the alert
+    pub async fn claim_offline_alert(&self, id: &str) -> Result<bool> {
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "UPDATE cli_clients SET offline_alerted = TRUE WHERE id = ? AND status = 'offline' AND offline_alerted = FALSE",
+            ))
+            .bind(id)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
+    }
+
     // Session operations
     pub async fn create_session(&self, session: &Session) -> Result<()> {
         // Use UPSERT (ON CONFLICT DO UPDATE) instead of INSERT OR REPLACE
@@ -229,271 +288,766 @@ impl Database {
         //
         // Also update user_id if the existing session owner is a dev/placeholder user
         // (email like 'dev-*@local'). This migrates sessions from temp users to real users.
-        sqlx::query(
-            r#"
-            INSERT INTO sessions (id, user_id, cli_client_id, working_dir, hostname, status)
-            VALUES (?, ?, ?, ?, ?, ?)
-            ON CONFLICT(id) DO UPDATE SET
-                cli_client_id = excluded.cli_client_id,
-                working_dir = excluded.working_dir,
-                hostname = excluded.hostname,
-                status = excluded.status,
-                updated_at = CURRENT_TIMESTAMP,
-                user_id = CASE
-                    WHEN (SELECT email FROM users WHERE id = sessions.user_id) LIKE 'dev-%@local'
-                    THEN excluded.user_id
-                    ELSE sessions.user_id
-                END
-            "#,
-        )
-        .bind(&session.id)
-        .bind(&session.user_id)
-        .bind(&session.cli_client_id)
-        .bind(&session.working_dir)
-        .bind(&session.hostname)
-        .bind(&session.status)
-        .execute(&self.pool)
-        .await?;
+        dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql(r#"
+                INSERT INTO sessions (id, user_id, cli_client_id, working_dir, hostname, status)
+                VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT(id) DO UPDATE SET
+                    cli_client_id = excluded.cli_client_id,
+                    working_dir = excluded.working_dir,
+                    hostname = excluded.hostname,
+                    status = excluded.status,
+                    updated_at = CURRENT_TIMESTAMP,
+                    user_id = CASE
+                        WHEN (SELECT email FROM users WHERE id = sessions.user_id) LIKE 'dev-%@local'
+                        THEN excluded.user_id
+                        ELSE sessions.user_id
+                    END
+                "#),
+            )
+            .bind(&session.id)
+            .bind(&session.user_id)
+            .bind(&session.cli_client_id)
+            .bind(&session.working_dir)
+            .bind(&session.hostname)
+            .bind(&session.status)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
     pub async fn update_session_status(&self, id: &str, status: &str) -> Result<()> {
-        sqlx::query(
-            "UPDATE sessions SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
-        )
-        .bind(status)
-        .bind(id)
-        .execute(&self.pool)
-        .await?;
+        dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql("UPDATE sessions SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?"),
+            )
+            .bind(status)
+            .bind(id)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
     pub async fn get_session(&self, id: &str) -> Result<Option<Session>> {
-        let session = sqlx::query_as::<_, Session>(
-            "SELECT id, user_id, cli_client_id, working_dir, hostname, status, created_at, updated_at FROM sessions WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let session = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, Session>(
+                &self.sql("SELECT id, user_id, cli_client_id, working_dir, hostname, status, created_at FROM sessions WHERE id = ?"),
+            )
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+        })?;
         Ok(session)
     }
 
-    pub async fn get_all_sessions(&self) -> Result<Vec<Session>> {
-        let sessions = sqlx::query_as::<_, Session>(
-            "SELECT id, user_id, cli_client_id, working_dir, hostname, status, created_at, updated_at FROM sessions ORDER BY created_at DESC LIMIT 50",
-        )
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(sessions)
-    }
-
     pub async fn get_sessions_for_user(&self, user_id: &str) -> Result<Vec<Session>> {
-        let sessions = sqlx::query_as::<_, Session>(
-            "SELECT id, user_id, cli_client_id, working_dir, hostname, status, created_at, updated_at FROM sessions WHERE user_id = ? ORDER BY created_at DESC LIMIT 50",
-        )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await?;
+        let sessions = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, Session>(
+                &self.sql("SELECT id, user_id, cli_client_id, working_dir, hostname, status, created_at FROM sessions WHERE user_id = ? ORDER BY created_at DESC LIMIT 50"),
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
         Ok(sessions)
     }
 
-    // Message operations
-    pub async fn save_message(&self, message: &Message) -> Result<()> {
-        sqlx::query(
-            "INSERT INTO messages (id, session_id, role, content, message_type, metadata) VALUES (?, ?, ?, ?, ?, ?)",
-        )
-        .bind(&message.id)
-        .bind(&message.session_id)
-        .bind(&message.role)
-        .bind(&message.content)
-        .bind(&message.message_type)
-        .bind(&message.metadata)
-        .execute(&self.pool)
-        .await?;
-        Ok(())
-    }
-
-    pub async fn get_messages_for_session(&self, session_id: &str) -> Result<Vec<Message>> {
-        let messages = sqlx::query_as::<_, Message>(
-            "SELECT id, session_id, role, content, message_type, metadata, created_at FROM messages WHERE session_id = ? ORDER BY created_at ASC",
-        )
-        .bind(session_id)
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(messages)
-    }
-
     // Invitation code operations
     pub async fn create_invitation_code(&self, code: &InvitationCode) -> Result<()> {
-        sqlx::query(
-            "INSERT INTO invitation_codes (code, session_id, created_by, expires_at) VALUES (?, ?, ?, ?)",
-        )
-        .bind(&code.code)
-        .bind(&code.session_id)
-        .bind(&code.created_by)
-        .bind(&code.expires_at)
-        .execute(&self.pool)
-        .await?;
+        dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql("INSERT INTO invitation_codes (code, session_id, created_by, expires_at) VALUES (?, ?, ?, ?)"),
+            )
+            .bind(&code.code)
+            .bind(&code.session_id)
+            .bind(&code.created_by)
+            .bind(&code.expires_at)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
     pub async fn get_invitation_code(&self, code: &str) -> Result<Option<InvitationCode>> {
-        let invitation = sqlx::query_as::<_, InvitationCode>(
-            "SELECT code, session_id, created_by, expires_at, redeemed_by, redeemed_at, created_at FROM invitation_codes WHERE code = ?",
-        )
-        .bind(code)
-        .fetch_optional(&self.pool)
-        .await?;
+        let invitation = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, InvitationCode>(
+                &self.sql("SELECT code, session_id, created_by, expires_at, redeemed_by FROM invitation_codes WHERE code = ?"),
+            )
+            .bind(code)
+            .fetch_optional(pool)
+            .await
+        })?;
         Ok(invitation)
     }
 
-    pub async fn redeem_invitation_code(&self, code: &str, user_id: &str) -> Result<bool> {
-        let result = sqlx::query(
-            "UPDATE invitation_codes SET redeemed_by = ?, redeemed_at = CURRENT_TIMESTAMP WHERE code = ? AND redeemed_by IS NULL",
-        )
-        .bind(user_id)
-        .bind(code)
-        .execute(&self.pool)
-        .await?;
-        Ok(result.rows_affected() > 0)
-    }
-
     pub async fn delete_invitation_code(&self, code: &str) -> Result<()> {
-        sqlx::query("DELETE FROM invitation_codes WHERE code = ?")
-            .bind(code)
-            .execute(&self.pool)
-            .await?;
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("DELETE FROM invitation_codes WHERE code = ?"))
+                .bind(code)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
     // Session share operations
     pub async fn create_session_share(&self, session_id: &str, user_id: &str, invited_by: &str) -> Result<()> {
-        sqlx::query(
-            "INSERT OR IGNORE INTO session_shares (session_id, user_id, invited_by) VALUES (?, ?, ?)",
-        )
-        .bind(session_id)
-        .bind(user_id)
-        .bind(invited_by)
-        .execute(&self.pool)
-        .await?;
+        dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql("INSERT INTO session_shares (session_id, user_id, invited_by) VALUES (?, ?, ?) ON CONFLICT (session_id, user_id) DO NOTHING"),
+            )
+            .bind(session_id)
+            .bind(user_id)
+            .bind(invited_by)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
         Ok(())
     }
 
     pub async fn get_shared_sessions_for_user(&self, user_id: &str) -> Result<Vec<(Session, String)>> {
         // Returns sessions shared with this user along with the owner's email
-        let rows = sqlx::query(
-            r#"
-            SELECT s.id, s.user_id, s.cli_client_id, s.working_dir, s.hostname, s.status, s.created_at, s.updated_at, u.email
-            FROM sessions s
-            INNER JOIN session_shares ss ON s.id = ss.session_id
-            INNER JOIN users u ON s.user_id = u.id
-            WHERE ss.user_id = ?
-            ORDER BY s.created_at DESC
-            LIMIT 50
-            "#,
-        )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await?;
-
-        let mut results = Vec::new();
-        for row in rows {
-            use sqlx::Row;
-            let session = Session {
-                id: row.get("id"),
-                user_id: row.get("user_id"),
-                cli_client_id: row.get("cli_client_id"),
-                working_dir: row.get("working_dir"),
-                hostname: row.get("hostname"),
-                status: row.get("status"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            };
-            let email: String = row.get("email");
-            results.push((session, email));
-        }
-        Ok(results)
+        let rows = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, SharedSession>(
+                &self.sql(r#"
+                SELECT s.id, s.user_id, s.cli_client_id, s.working_dir, s.hostname, s.status, s.created_at, u.email
+                FROM sessions s
+                INNER JOIN session_shares ss ON s.id = ss.session_id
+                INNER JOIN users u ON s.user_id = u.id
+                WHERE ss.user_id = ?
+                ORDER BY s.created_at DESC
+                LIMIT 50
+                "#),
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(rows.into_iter().map(|row| (row.session, row.email)).collect())
     }
 
     pub async fn check_session_access(&self, session_id: &str, user_id: &str) -> Result<bool> {
         // Check if user owns the session or has shared access
-        let result = sqlx::query_scalar::<_, i64>(
-            r#"
-            SELECT COUNT(*) FROM (
-                SELECT 1 FROM sessions WHERE id = ? AND user_id = ?
-                UNION ALL
-                SELECT 1 FROM session_shares WHERE session_id = ? AND user_id = ?
+        let result = dispatch!(&self.pool, pool => {
+            sqlx::query_scalar::<_, i64>(
+                &self.sql(r#"
+                SELECT COUNT(*) FROM (
+                    SELECT 1 FROM sessions WHERE id = ? AND user_id = ?
+                    UNION ALL
+                    SELECT 1 FROM session_shares WHERE session_id = ? AND user_id = ?
+                ) AS access
+                "#),
             )
-            "#,
-        )
-        .bind(session_id)
-        .bind(user_id)
-        .bind(session_id)
-        .bind(user_id)
-        .fetch_one(&self.pool)
-        .await?;
+            .bind(session_id)
+            .bind(user_id)
+            .bind(session_id)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+        })?;
         Ok(result > 0)
     }
 
     pub async fn delete_session_share(&self, session_id: &str, user_id: &str) -> Result<bool> {
-        let result = sqlx::query(
-            "DELETE FROM session_shares WHERE session_id = ? AND user_id = ?",
-        )
-        .bind(session_id)
-        .bind(user_id)
-        .execute(&self.pool)
-        .await?;
-        Ok(result.rows_affected() > 0)
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql("DELETE FROM session_shares WHERE session_id = ? AND user_id = ?"),
+            )
+            .bind(session_id)
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
     }
 
     pub async fn get_session_owner(&self, session_id: &str) -> Result<Option<String>> {
-        let owner = sqlx::query_scalar::<_, String>(
-            "SELECT user_id FROM sessions WHERE id = ?",
-        )
-        .bind(session_id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let owner = dispatch!(&self.pool, pool => {
+            sqlx::query_scalar::<_, String>(
+                &self.sql("SELECT user_id FROM sessions WHERE id = ?"),
+            )
+            .bind(session_id)
+            .fetch_optional(pool)
+            .await
+        })?;
         Ok(owner)
     }
 
     /// Get session owner info (user_id and email)
     pub async fn get_session_owner_info(&self, session_id: &str) -> Result<Option<(String, String)>> {
-        let row = sqlx::query(
-            r#"
-            SELECT u.id, u.email
-            FROM sessions s
-            INNER JOIN users u ON s.user_id = u.id
-            WHERE s.id = ?
-            "#,
-        )
-        .bind(session_id)
-        .fetch_optional(&self.pool)
-        .await?;
-
-        Ok(row.map(|r| {
-            use sqlx::Row;
-            (r.get("id"), r.get("email"))
-        }))
+        let owner = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, (String, String)>(
+                &self.sql(r#"
+                SELECT u.id, u.email
+                FROM sessions s
+                INNER JOIN users u ON s.user_id = u.id
+                WHERE s.id = ?
+                "#),
+            )
+            .bind(session_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+        Ok(owner)
     }
 
     /// Get all users who have shared access to a session (with their emails)
     pub async fn get_session_shares_with_emails(&self, session_id: &str) -> Result<Vec<(String, String, Option<String>)>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT u.id, u.email, ss.created_at
-            FROM session_shares ss
-            INNER JOIN users u ON ss.user_id = u.id
-            WHERE ss.session_id = ?
-            ORDER BY ss.created_at DESC
-            "#,
-        )
-        .bind(session_id)
-        .fetch_all(&self.pool)
-        .await?;
-
-        Ok(rows.iter().map(|r| {
-            use sqlx::Row;
-            (r.get("id"), r.get("email"), r.get("created_at"))
-        }).collect())
+        let shares = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, (String, String, Option<String>)>(
+                &self.sql(r#"
+                SELECT u.id, u.email, ss.created_at
+                FROM session_shares ss
+                INNER JOIN users u ON ss.user_id = u.id
+                WHERE ss.session_id = ?
+                ORDER BY ss.created_at DESC
+                "#),
+            )
+            .bind(session_id)
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(shares)
+    }
+
+    // Device code operations (CLI login)
+    pub async fn create_device_code(&self, code: &str, expires_at: i64) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("INSERT INTO device_codes (code, expires_at) VALUES (?, ?)"))
+                .bind(code)
+                .bind(expires_at)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    pub async fn get_device_code(&self, code: &str) -> Result<Option<DeviceCode>> {
+        let device_code = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, DeviceCode>(&self.sql("SELECT code, user_id, expires_at FROM device_codes WHERE code = ?"))
+                .bind(code)
+                .fetch_optional(pool)
+                .await
+        })?;
+        Ok(device_code)
+    }
+
+    /// Link an unexpired device code to the user who approved it
+    pub async fn complete_device_code(&self, code: &str, user_id: &str, now: i64) -> Result<bool> {
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("UPDATE device_codes SET user_id = ? WHERE code = ? AND expires_at > ?"))
+                .bind(user_id)
+                .bind(code)
+                .bind(now)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
+    }
+
+    pub async fn delete_device_code(&self, code: &str) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("DELETE FROM device_codes WHERE code = ?"))
+                .bind(code)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    // Password reset token operations. Only a hash of each token is stored.
+    pub async fn create_password_reset_token(&self, token: &str, email: &str, expires_at: i64) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("INSERT INTO password_reset_tokens (token_hash, email, expires_at) VALUES (?, ?, ?)"))
+                .bind(token_hash(token))
+                .bind(email)
+                .bind(expires_at)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    pub async fn get_password_reset_token(&self, token: &str) -> Result<Option<PasswordResetToken>> {
+        let reset_token = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, PasswordResetToken>(
+                &self.sql("SELECT email, expires_at FROM password_reset_tokens WHERE token_hash = ?"),
+            )
+            .bind(token_hash(token))
+            .fetch_optional(pool)
+            .await
+        })?;
+        Ok(reset_token)
+    }
+
+    pub async fn delete_password_reset_token(&self, token: &str) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("DELETE FROM password_reset_tokens WHERE token_hash = ?"))
+                .bind(token_hash(token))
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    // Webhook operations
+    pub async fn create_webhook(&self, webhook: &Webhook) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("INSERT INTO webhooks (id, user_id, url, secret, events) VALUES (?, ?, ?, ?, ?)"))
+                .bind(&webhook.id)
+                .bind(&webhook.user_id)
+                .bind(&webhook.url)
+                .bind(&webhook.secret)
+                .bind(&webhook.events)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    pub async fn get_webhooks_for_user(&self, user_id: &str) -> Result<Vec<Webhook>> {
+        let webhooks = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, Webhook>(
+                &self.sql("SELECT id, user_id, url, secret, events, created_at FROM webhooks WHERE user_id = ? ORDER BY created_at"),
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(webhooks)
+    }
+
+    /// Delete a webhook if it belongs to the user
+    pub async fn delete_webhook(&self, id: &str, user_id: &str) -> Result<bool> {
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("DELETE FROM webhooks WHERE id = ? AND user_id = ?"))
+                .bind(id)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
+    }
+
+    // Notification operations
+    pub async fn get_notification_preferences(&self, user_id: &str) -> Result<Option<NotificationPreferences>> {
+        let prefs = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, NotificationPreferences>(&self.sql(
+                "SELECT user_id, pause_alerts, offline_alert_minutes, daily_digest, last_digest_at FROM notification_preferences WHERE user_id = ?",
+            ))
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+        Ok(prefs)
+    }
+
+    pub async fn upsert_notification_preferences(&self, prefs: &NotificationPreferences) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(
+                &self.sql(r#"
+                INSERT INTO notification_preferences (user_id, pause_alerts, offline_alert_minutes, daily_digest, last_digest_at)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(user_id) DO UPDATE SET
+                    pause_alerts = excluded.pause_alerts,
+                    offline_alert_minutes = excluded.offline_alert_minutes,
+                    daily_digest = excluded.daily_digest,
+                    last_digest_at = excluded.last_digest_at
+                "#),
+            )
+            .bind(&prefs.user_id)
+            .bind(prefs.pause_alerts)
+            .bind(prefs.offline_alert_minutes)
+            .bind(prefs.daily_digest)
+            .bind(prefs.last_digest_at)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    pub async fn get_digest_subscribers(&self) -> Result<Vec<NotificationPreferences>> {
+        let prefs = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, NotificationPreferences>(&self.sql(
+                "SELECT user_id, pause_alerts, offline_alert_minutes, daily_digest, last_digest_at FROM notification_preferences WHERE daily_digest = TRUE",
+            ))
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(prefs)
+    }
+
+    /// Move the user's digest window forward to `now` if the last digest was
+    /// at or before `due`; false if it isn't due or another instance got it
+    pub async fn claim_digest(&self, user_id: &str, now: i64, due: i64) -> Result<bool> {
+        let rows_affected = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "UPDATE notification_preferences SET last_digest_at = ? WHERE user_id = ? AND daily_digest = TRUE AND (last_digest_at IS NULL OR last_digest_at <= ?)",
+            ))
+            .bind(now)
+            .bind(user_id)
+            .bind(due)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(rows_affected > 0)
+    }
+
+    // Deadloop activity (for digests)
+    pub async fn record_iteration(
+        &self,
+        session_id: &str,
+        is_error: bool,
+        cost_usd: f64,
+        duration_ms: i64,
+        finished_at: i64,
+    ) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "INSERT INTO iterations (session_id, is_error, cost_usd, duration_ms, finished_at) VALUES (?, ?, ?, ?, ?)",
+            ))
+            .bind(session_id)
+            .bind(is_error)
+            .bind(cost_usd)
+            .bind(duration_ms)
+            .bind(finished_at)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    /// Record an iteration the CLI stopped before it produced a result
+    pub async fn record_timed_out_iteration(&self, session_id: &str, duration_ms: i64, finished_at: i64) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "INSERT INTO iterations (session_id, is_error, cost_usd, duration_ms, finished_at, timed_out) VALUES (?, TRUE, 0, ?, ?, TRUE)",
+            ))
+            .bind(session_id)
+            .bind(duration_ms)
+            .bind(finished_at)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    pub async fn record_sandbox_violation(&self, session_id: &str, kind: &str, detail: &str, created_at: i64) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "INSERT INTO sandbox_violations (session_id, kind, detail, created_at) VALUES (?, ?, ?, ?)",
+            ))
+            .bind(session_id)
+            .bind(kind)
+            .bind(detail)
+            .bind(created_at)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    pub async fn record_commit(&self, session_id: &str, summary: &str, created_at: i64) -> Result<()> {
+        dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql("INSERT INTO commits (session_id, summary, created_at) VALUES (?, ?, ?)"))
+                .bind(session_id)
+                .bind(summary)
+                .bind(created_at)
+                .execute(pool)
+                .await
+                .map(|done| done.rows_affected())
+        })?;
+        Ok(())
+    }
+
+    /// Per-session iteration totals for the user's sessions in (since, until]
+    pub async fn get_session_activity(&self, user_id: &str, since: i64, until: i64) -> Result<Vec<SessionActivity>> {
+        let activity = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, SessionActivity>(
+                &self.sql(r#"
+                SELECT i.session_id, s.working_dir,
+                    COUNT(*) AS iterations,
+                    SUM(CASE WHEN i.is_error THEN 1 ELSE 0 END) AS errors,
+                    SUM(CASE WHEN i.timed_out THEN 1 ELSE 0 END) AS timeouts,
+                    SUM(i.cost_usd) AS cost_usd,
+                    CAST(SUM(i.duration_ms) AS BIGINT) AS duration_ms
+                FROM iterations i
+                JOIN sessions s ON s.id = i.session_id
+                WHERE s.user_id = ? AND i.finished_at > ? AND i.finished_at <= ?
+                GROUP BY i.session_id, s.working_dir
+                ORDER BY i.session_id
+                "#),
+            )
+            .bind(user_id)
+            .bind(since)
+            .bind(until)
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(activity)
+    }
+
+    /// Commits in the user's sessions in (since, until], oldest first
+    pub async fn get_commits(&self, user_id: &str, since: i64, until: i64) -> Result<Vec<CommitRecord>> {
+        let commits = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, CommitRecord>(&self.sql(
+                "SELECT c.session_id, c.summary FROM commits c JOIN sessions s ON s.id = c.session_id WHERE s.user_id = ? AND c.created_at > ? AND c.created_at <= ? ORDER BY c.created_at, c.id",
+            ))
+            .bind(user_id)
+            .bind(since)
+            .bind(until)
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(commits)
+    }
+
+    /// Sandbox violations in the user's sessions in (since, until], oldest first
+    pub async fn get_sandbox_violations(&self, user_id: &str, since: i64, until: i64) -> Result<Vec<SandboxViolationRecord>> {
+        let violations = dispatch!(&self.pool, pool => {
+            sqlx::query_as::<_, SandboxViolationRecord>(&self.sql(
+                "SELECT v.session_id, v.kind FROM sandbox_violations v JOIN sessions s ON s.id = v.session_id WHERE s.user_id = ? AND v.created_at > ? AND v.created_at <= ? ORDER BY v.created_at, v.id",
+            ))
+            .bind(user_id)
+            .bind(since)
+            .bind(until)
+            .fetch_all(pool)
+            .await
+        })?;
+        Ok(violations)
+    }
+
+    /// Remove device codes and reset tokens that expired at or before `now`
+    pub async fn delete_expired_auth_tokens(&self, now: i64) -> Result<u64> {
+        let mut removed = 0;
+        for table in ["device_codes", "password_reset_tokens"] {
+            let query = format!("DELETE FROM {} WHERE expires_at <= ?", table);
+            removed += dispatch!(&self.pool, pool => {
+                sqlx::query(&self.sql(&query))
+                    .bind(now)
+                    .execute(pool)
+                    .await
+                    .map(|done| done.rows_affected())
+            })?;
+        }
+        Ok(removed)
+    }
+
+    /// No CLI is connected right after startup, so any session or client still
+    /// marked live in the database was orphaned by the previous process.
+    /// Returns the number of (sessions, cli_clients) rows reset.
+    pub async fn reconcile_after_restart(&self, now: i64) -> Result<(u64, u64)> {
+        let sessions = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "UPDATE sessions SET status = 'inactive', updated_at = CURRENT_TIMESTAMP WHERE status = 'active'",
+            ))
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        let clients = dispatch!(&self.pool, pool => {
+            sqlx::query(&self.sql(
+                "UPDATE cli_clients SET status = 'offline', offline_since = COALESCE(offline_since, ?) WHERE status <> 'offline'",
+            ))
+            .bind(now)
+            .execute(pool)
+            .await
+            .map(|done| done.rows_affected())
+        })?;
+        Ok((sessions, clients))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// Round-trip the queries whose SQL differs most between dialects
+    async fn exercise(db: &Database) {
+        db.run_migrations().await.unwrap();
+
+        let suffix = uuid::Uuid::new_v4().to_string();
+        let owner = User {
+            id: format!("owner-{suffix}"),
+            email: format!("owner-{suffix}@example.com"),
+            password_hash: "x".into(),
+        };
+        let guest = User {
+            id: format!("guest-{suffix}"),
+            email: format!("guest-{suffix}@example.com"),
+            password_hash: "x".into(),
+        };
+        db.create_user(&owner).await.unwrap();
+        db.create_user(&guest).await.unwrap();
+
+        // Reconnecting from the same machine updates its row instead of adding one
+        let mut client = CliClient {
+            id: format!("machine-{suffix}"),
+            user_id: owner.id.clone(),
+            name: None,
+            hostname: Some("devbox".into()),
+            os: Some("linux-x86_64".into()),
+            claude_version: None,
+            last_seen: None,
+            status: "online".into(),
+        };
+        assert!(db.upsert_cli_client(&client).await.unwrap());
+        client.name = Some("work laptop".into());
+        assert!(db.upsert_cli_client(&client).await.unwrap());
+        let clients = db.get_cli_clients_for_user(&owner.id).await.unwrap();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].name.as_deref(), Some("work laptop"));
+
+        // Another user presenting the same machine id can't take the row over
+        let stolen = CliClient { user_id: guest.id.clone(), name: Some("mine now".into()), ..client.clone() };
+        assert!(!db.upsert_cli_client(&stolen).await.unwrap());
+        assert!(db.get_cli_clients_for_user(&guest.id).await.unwrap().is_empty());
+        let clients = db.get_cli_clients_for_user(&owner.id).await.unwrap();
+        assert_eq!(clients[0].name.as_deref(), Some("work laptop"));
+
+        let session = Session {
+            id: format!("session-{suffix}"),
+            user_id: owner.id.clone(),
+            cli_client_id: None,
+            working_dir: Some("/tmp/what?".into()),
+            hostname: None,
+            status: "active".into(),
+            created_at: None,
+        };
+        db.create_session(&session).await.unwrap();
+        db.create_session(&session).await.unwrap();
+        assert_eq!(db.get_session(&session.id).await.unwrap().unwrap().working_dir.as_deref(), Some("/tmp/what?"));
+
+        assert!(!db.check_session_access(&session.id, &guest.id).await.unwrap());
+        db.create_session_share(&session.id, &guest.id, &owner.id).await.unwrap();
+        db.create_session_share(&session.id, &guest.id, &owner.id).await.unwrap();
+        assert!(db.check_session_access(&session.id, &guest.id).await.unwrap());
+        assert_eq!(db.get_shared_sessions_for_user(&guest.id).await.unwrap().len(), 1);
+        assert!(db.delete_session_share(&session.id, &guest.id).await.unwrap());
+
+        db.record_iteration(&session.id, true, 0.5, 90_000, 100).await.unwrap();
+        db.record_timed_out_iteration(&session.id, 30_000, 110).await.unwrap();
+        db.record_commit(&session.id, "Fix it", 100).await.unwrap();
+        let activity = db.get_session_activity(&owner.id, 0, 200).await.unwrap();
+        assert_eq!(
+            (activity[0].iterations, activity[0].errors, activity[0].timeouts, activity[0].duration_ms),
+            (2, 2, 1, 120_000)
+        );
+        assert_eq!(db.get_commits(&owner.id, 0, 200).await.unwrap()[0].summary, "Fix it");
+
+        let mut prefs = NotificationPreferences::defaults(&owner.id);
+        prefs.daily_digest = true;
+        db.upsert_notification_preferences(&prefs).await.unwrap();
+        assert!(db.claim_digest(&owner.id, 100, 0).await.unwrap());
+        assert!(!db.claim_digest(&owner.id, 150, 50).await.unwrap(), "already sent at 100");
+
+        let webhook = Webhook {
+            id: format!("hook-{suffix}"),
+            user_id: owner.id.clone(),
+            url: "https://example.com/hook".into(),
+            secret: "s".into(),
+            events: "session_end,result".into(),
+            created_at: None,
+        };
+        db.create_webhook(&webhook).await.unwrap();
+        let hooks = db.get_webhooks_for_user(&owner.id).await.unwrap();
+        assert_eq!(hooks.len(), 1);
+        assert!(hooks[0].subscribes_to("result") && !hooks[0].subscribes_to("session_start"));
+        assert!(!db.delete_webhook(&webhook.id, &guest.id).await.unwrap(), "only the owner can delete");
+        assert!(db.delete_webhook(&webhook.id, &owner.id).await.unwrap());
+
+        db.update_session_status(&session.id, "active").await.unwrap();
+        let (sessions, _) = db.reconcile_after_restart(0).await.unwrap();
+        assert!(sessions >= 1);
+        assert_eq!(db.get_session(&session.id).await.unwrap().unwrap().status, "inactive");
+    }
+
+    async fn exercise_auth_tokens(db: &Database) {
+        let suffix = uuid::Uuid::new_v4().to_string();
+        let (live, stale) = (format!("live-{suffix}"), format!("stale-{suffix}"));
+        db.create_device_code(&live, 2_000).await.unwrap();
+        db.create_device_code(&stale, 500).await.unwrap();
+        db.create_password_reset_token(&stale, "a@example.com", 500).await.unwrap();
+        db.create_password_reset_token(&live, "a@example.com", 2_000).await.unwrap();
+        let stored: Vec<String> = dispatch!(&db.pool, pool => {
+            sqlx::query_scalar(&db.sql("SELECT token_hash FROM password_reset_tokens WHERE email = 'a@example.com'"))
+                .fetch_all(pool)
+                .await
+        })
+        .unwrap();
+        assert!(!stored.contains(&live) && stored.contains(&token_hash(&live)), "only the hash is stored");
+
+        assert!(db.complete_device_code(&live, "user-1", 1_000).await.unwrap());
+        assert!(!db.complete_device_code(&stale, "user-1", 1_000).await.unwrap(), "expired codes can't be approved");
+        assert_eq!(db.get_device_code(&live).await.unwrap().unwrap().user_id.as_deref(), Some("user-1"));
+
+        assert!(db.delete_expired_auth_tokens(1_000).await.unwrap() >= 2);
+        assert!(db.get_device_code(&stale).await.unwrap().is_none());
+        assert!(db.get_password_reset_token(&stale).await.unwrap().is_none());
+        assert!(db.get_device_code(&live).await.unwrap().is_some());
+        assert_eq!(db.get_password_reset_token(&live).await.unwrap().unwrap().email, "a@example.com");
+        db.delete_password_reset_token(&live).await.unwrap();
+        assert!(db.get_password_reset_token(&live).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let query = "SELECT * FROM t WHERE a = ? AND b = '?' AND c = ?";
+        assert_eq!(placeholders(DatabaseBackend::Sqlite, query), query);
+        assert_eq!(
+            placeholders(DatabaseBackend::Postgres, query),
+            "SELECT * FROM t WHERE a = $1 AND b = '?' AND c = $2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queries_on_sqlite() {
+        let dir = std::env::temp_dir().join(format!("apas-db-{}", uuid::Uuid::new_v4()));
+        let config = DatabaseConfig {
+            path: dir.join("apas.db").to_string_lossy().into_owned(),
+            ..Config::default().database
+        };
+        let db = Database::connect(&config).await.unwrap();
+        exercise(&db).await;
+        exercise_auth_tokens(&db).await;
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Needs a scratch database: run with `--ignored` and APAS_TEST_POSTGRES_URL
+    #[tokio::test]
+    #[ignore = "needs APAS_TEST_POSTGRES_URL"]
+    async fn test_queries_on_postgres() {
+        let url = std::env::var("APAS_TEST_POSTGRES_URL").expect("APAS_TEST_POSTGRES_URL is not set");
+        let config = DatabaseConfig {
+            backend: DatabaseBackend::Postgres,
+            url: Some(url),
+            ..Config::default().database
+        };
+        let db = Database::connect(&config).await.unwrap();
+        exercise(&db).await;
+        exercise_auth_tokens(&db).await;
     }
 }
@@ -5,17 +5,19 @@ pub struct User {
     pub id: String,
     pub email: String,
     pub password_hash: String,
-    pub created_at: Option<String>,
 }
 
 #[derive(Debug, Clone, FromRow)]
 pub struct CliClient {
+    /// Machine id reported by the CLI, or the connection id for older CLIs
     pub id: String,
     pub user_id: String,
     pub name: Option<String>,
+    pub hostname: Option<String>,
+    pub os: Option<String>,
+    pub claude_version: Option<String>,
     pub last_seen: Option<String>,
     pub status: String,
-    pub created_at: Option<String>,
 }
 
 #[derive(Debug, Clone, FromRow)]
@@ -27,36 +29,126 @@ pub struct Session {
     pub hostname: Option<String>,
     pub status: String,
     pub created_at: Option<String>,
-    pub updated_at: Option<String>,
 }
 
 #[derive(Debug, Clone, FromRow)]
-pub struct Message {
-    pub id: String,
+pub struct InvitationCode {
+    pub code: String,
     pub session_id: String,
-    pub role: String,
-    pub content: String,
-    pub message_type: String,
-    pub metadata: Option<String>,
-    pub created_at: Option<String>,
+    pub created_by: String,
+    pub expires_at: String,
+    pub redeemed_by: Option<String>,
 }
 
+/// A session shared with someone, joined with the owner's email
 #[derive(Debug, Clone, FromRow)]
-pub struct SessionShare {
-    pub id: i64,
-    pub session_id: String,
+pub struct SharedSession {
+    #[sqlx(flatten)]
+    pub session: Session,
+    pub email: String,
+}
+
+/// Pending CLI login; user_id is set once the user approves it in the web UI
+#[derive(Debug, Clone, FromRow)]
+pub struct DeviceCode {
+    pub code: String,
+    pub user_id: Option<String>,
+    /// Unix timestamp (seconds)
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct PasswordResetToken {
+    pub email: String,
+    /// Unix timestamp (seconds)
+    pub expires_at: i64,
+}
+
+/// Outbound webhook subscription
+#[derive(Debug, Clone, FromRow)]
+pub struct Webhook {
+    pub id: String,
     pub user_id: String,
-    pub invited_by: String,
+    pub url: String,
+    /// Key for the HMAC signature on each delivery
+    pub secret: String,
+    /// Comma-separated event names; empty means all events
+    pub events: String,
     pub created_at: Option<String>,
 }
 
+impl Webhook {
+    pub fn event_list(&self) -> Vec<&str> {
+        self.events.split(',').map(str::trim).filter(|e| !e.is_empty()).collect()
+    }
+
+    pub fn subscribes_to(&self, event: &str) -> bool {
+        let events = self.event_list();
+        events.is_empty() || events.contains(&event)
+    }
+}
+
+/// Per-user email notification settings
 #[derive(Debug, Clone, FromRow)]
-pub struct InvitationCode {
-    pub code: String,
+pub struct NotificationPreferences {
+    pub user_id: String,
+    /// Email when a deadloop pauses itself (budget, errors)
+    pub pause_alerts: bool,
+    /// Email once a machine has been offline this long; None turns it off
+    pub offline_alert_minutes: Option<i64>,
+    pub daily_digest: bool,
+    /// Unix timestamp of the last digest (or of turning digests on)
+    pub last_digest_at: Option<i64>,
+}
+
+impl NotificationPreferences {
+    /// Settings for users who never changed them
+    pub fn defaults(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            pause_alerts: true,
+            offline_alert_minutes: Some(30),
+            daily_digest: false,
+            last_digest_at: None,
+        }
+    }
+}
+
+/// A machine that went offline and whose owner hasn't been alerted yet
+#[derive(Debug, Clone, FromRow)]
+pub struct OfflineCliClient {
+    pub id: String,
+    pub user_id: String,
+    pub name: Option<String>,
+    pub hostname: Option<String>,
+    /// Unix timestamp
+    pub offline_since: i64,
+}
+
+/// One session's deadloop iterations over a period
+#[derive(Debug, Clone, FromRow)]
+pub struct SessionActivity {
     pub session_id: String,
-    pub created_by: String,
-    pub expires_at: String,
-    pub redeemed_by: Option<String>,
-    pub redeemed_at: Option<String>,
-    pub created_at: Option<String>,
+    pub working_dir: Option<String>,
+    pub iterations: i64,
+    pub errors: i64,
+    /// Iterations stopped by the CLI's watchdog (also counted in `errors`)
+    pub timeouts: i64,
+    pub cost_usd: f64,
+    pub duration_ms: i64,
+}
+
+/// Something the deadloop sandbox stopped or blocked
+#[derive(Debug, Clone, FromRow)]
+pub struct SandboxViolationRecord {
+    pub session_id: String,
+    /// `SandboxViolation` in snake_case, e.g. "out_of_memory"
+    pub kind: String,
+}
+
+/// A `git commit` made by Claude during a session
+#[derive(Debug, Clone, FromRow)]
+pub struct CommitRecord {
+    pub session_id: String,
+    pub summary: String,
 }
@@ -0,0 +1,362 @@
+//! End-to-end tests: the real router on a local port, with a scripted CLI
+//! and web client talking to it over websockets the way `apas` and the web
+//! UI do
+
+use futures::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use shared::{CliToServer, ClaudeStreamMessage, PaneType, PauseReason, ServerToCli, ServerToWeb, WebToServer};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use crate::backplane::LocalBackplane;
+use crate::config::Config;
+use crate::db::Database;
+use crate::state::AppState;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct TestServer {
+    addr: SocketAddr,
+    state: AppState,
+    dir: PathBuf,
+}
+
+impl TestServer {
+    /// Serve the router on a free port, backed by a fresh SQLite database
+    async fn start() -> Self {
+        let dir = std::env::temp_dir().join(format!("apas-e2e-{}", Uuid::new_v4()));
+        let mut config = Config::default();
+        config.database.path = dir.join("apas.db").to_string_lossy().to_string();
+        let db = Database::connect(&config.database).await.unwrap();
+        db.run_migrations().await.unwrap();
+
+        let state = AppState::new(db, config, Arc::new(LocalBackplane::new()));
+        state.sessions.start();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::routes::create_router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Self { addr, state, dir }
+    }
+
+    /// Sign up a new user and return their token
+    async fn register(&self) -> String {
+        let response: serde_json::Value = reqwest::Client::new()
+            .post(format!("http://{}/auth/register", self.addr))
+            .json(&serde_json::json!({ "email": format!("{}@example.com", Uuid::new_v4()), "password": "hunter22" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        response["token"].as_str().unwrap().to_string()
+    }
+
+    /// Connect and register a CLI, as `apas` does on startup
+    async fn cli(&self, token: &str) -> Client<CliToServer, ServerToCli> {
+        let mut cli = Client::connect(self.addr, "/ws/cli").await;
+        cli.send(&CliToServer::Register {
+            token: token.to_string(),
+            version: None,
+            compression: Vec::new(),
+            machine: None,
+        })
+        .await;
+        cli.expect(|m| matches!(m, ServerToCli::Registered { .. })).await;
+        cli
+    }
+
+    /// Connect and authenticate a web client
+    async fn web(&self, token: &str) -> Client<WebToServer, ServerToWeb> {
+        let mut web = Client::connect(self.addr, "/ws/web").await;
+        web.send(&WebToServer::Authenticate { token: token.to_string() }).await;
+        web.expect(|m| matches!(m, ServerToWeb::Authenticated { .. })).await;
+        web
+    }
+
+    /// Wait until the session's row has `status`; CLI messages have no replies
+    /// to wait on
+    async fn expect_session_status(&self, session_id: Uuid, status: &str) {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let session = self.state.db.get_session(&session_id.to_string()).await.unwrap();
+                if session.is_some_and(|s| s.status == status) {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("session {} never became {}", session_id, status));
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// One websocket connection sending `Out` and receiving `In`
+struct Client<Out, In> {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    _messages: std::marker::PhantomData<(Out, In)>,
+}
+
+impl<Out: Serialize, In: DeserializeOwned + std::fmt::Debug> Client<Out, In> {
+    async fn connect(addr: SocketAddr, path: &str) -> Self {
+        let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{}{}", addr, path)).await.unwrap();
+        Self { ws, _messages: std::marker::PhantomData }
+    }
+
+    async fn send(&mut self, message: &Out) {
+        let text = serde_json::to_string(message).unwrap();
+        self.ws.send(Message::Text(text)).await.unwrap();
+    }
+
+    /// Skip messages until one matches `check`
+    async fn expect(&mut self, check: impl Fn(&In) -> bool) -> In {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                match self.ws.next().await {
+                    Some(Ok(Message::Text(text))) => {
+                        let message: In = serde_json::from_str(&text).unwrap();
+                        if check(&message) {
+                            return message;
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    other => panic!("connection ended: {:?}", other),
+                }
+            }
+        })
+        .await
+        .expect("expected message never arrived")
+    }
+
+    async fn close(mut self) {
+        let _ = self.ws.close(None).await;
+    }
+}
+
+fn stream_message(json: serde_json::Value) -> ClaudeStreamMessage {
+    serde_json::from_value(json).unwrap()
+}
+
+/// A CLI with a running deadloop session and a web client attached to it
+async fn attached_session(
+    server: &TestServer,
+) -> (Uuid, Client<CliToServer, ServerToCli>, Client<WebToServer, ServerToWeb>, String) {
+    let token = server.register().await;
+    let mut cli = server.cli(&token).await;
+    let session_id = Uuid::new_v4();
+    cli.send(&CliToServer::SessionStart {
+        session_id,
+        working_dir: Some("/work".into()),
+        hostname: Some("devbox".into()),
+        pane_type: Some(PaneType::Deadloop),
+    })
+    .await;
+    server.expect_session_status(session_id, "active").await;
+
+    let mut web = server.web(&token).await;
+    web.send(&WebToServer::AttachSession { session_id }).await;
+    let history = web.expect(|m| matches!(m, ServerToWeb::SessionMessages { .. })).await;
+    assert!(matches!(history, ServerToWeb::SessionMessages { messages, .. } if messages.is_empty()));
+    (session_id, cli, web, token)
+}
+
+#[tokio::test]
+async fn test_cli_output_reaches_web_and_is_persisted() {
+    let server = TestServer::start().await;
+    let (session_id, mut cli, mut web, token) = attached_session(&server).await;
+
+    let pane_type = Some(PaneType::Deadloop);
+    cli.send(&CliToServer::UserInput { session_id, text: "[Iteration 1]".into(), pane_type }).await;
+    let text = stream_message(serde_json::json!({
+        "type": "assistant", "session_id": "s",
+        "message": { "content": [{ "type": "text", "text": "Fixed the build" }] },
+    }));
+    cli.send(&CliToServer::StreamMessage { session_id, message: text, pane_type }).await;
+    let result = stream_message(serde_json::json!({
+        "type": "result", "subtype": "success", "session_id": "s",
+        "result": "Done", "total_cost_usd": 0.25, "duration_ms": 1000,
+    }));
+    cli.send(&CliToServer::StreamMessage { session_id, message: result, pane_type }).await;
+
+    web.expect(|m| matches!(m, ServerToWeb::UserInput { .. })).await;
+    web.expect(|m| {
+        matches!(m, ServerToWeb::StreamMessage { message: ClaudeStreamMessage::Result { .. }, pane_type: Some(PaneType::Deadloop), .. })
+    })
+    .await;
+
+    // A client attaching later gets the conversation from storage
+    let mut later = server.web(&token).await;
+    later.send(&WebToServer::AttachSession { session_id }).await;
+    let ServerToWeb::SessionMessages { messages, .. } =
+        later.expect(|m| matches!(m, ServerToWeb::SessionMessages { .. })).await
+    else {
+        unreachable!()
+    };
+    let kinds: Vec<_> = messages.iter().map(|m| (m.role.as_str(), m.message_type.as_str())).collect();
+    assert_eq!(kinds, [("user", "text"), ("assistant", "text"), ("system", "result")]);
+    assert_eq!(messages[1].content, "Fixed the build");
+    assert!(messages.iter().all(|m| m.pane_type.as_deref() == Some("deadloop")));
+
+    // The result counted as one iteration
+    let owner = server.state.db.get_session(&session_id.to_string()).await.unwrap().unwrap().user_id;
+    let activity = server.state.db.get_session_activity(&owner, 0, i64::MAX).await.unwrap();
+    assert_eq!((activity[0].iterations, activity[0].errors), (1, 0));
+}
+
+#[tokio::test]
+async fn test_only_deadloop_results_and_reported_commits_reach_the_digest() {
+    let server = TestServer::start().await;
+    let (session_id, mut cli, mut web, _) = attached_session(&server).await;
+
+    // Messages are handled in order, so once the web sees the last result the
+    // commits and the chat turn have been recorded too
+    cli.send(&CliToServer::CommitsMade { session_id, summaries: vec!["Fix the build".into(), "Add a test".into()] })
+        .await;
+    for pane_type in [PaneType::Interactive, PaneType::Deadloop] {
+        let result = stream_message(serde_json::json!({
+            "type": "result", "subtype": "success", "session_id": "s",
+            "result": "Done", "total_cost_usd": 0.25, "duration_ms": 1000,
+        }));
+        cli.send(&CliToServer::StreamMessage { session_id, message: result, pane_type: Some(pane_type) }).await;
+    }
+    web.expect(|m| {
+        matches!(m, ServerToWeb::StreamMessage { message: ClaudeStreamMessage::Result { .. }, pane_type: Some(PaneType::Deadloop), .. })
+    })
+    .await;
+
+    let owner = server.state.db.get_session(&session_id.to_string()).await.unwrap().unwrap().user_id;
+    let activity = server.state.db.get_session_activity(&owner, 0, i64::MAX).await.unwrap();
+    assert_eq!(activity[0].iterations, 1);
+    let commits = server.state.db.get_commits(&owner, 0, i64::MAX).await.unwrap();
+    let summaries: Vec<_> = commits.iter().map(|c| c.summary.as_str()).collect();
+    assert_eq!(summaries, ["Fix the build", "Add a test"]);
+}
+
+#[tokio::test]
+async fn test_a_cli_cannot_add_to_another_users_session() {
+    let server = TestServer::start().await;
+    let (session_id, _cli, _web, _) = attached_session(&server).await;
+    let mut intruder = server.cli(&server.register().await).await;
+
+    intruder.send(&CliToServer::CommitsMade { session_id, summaries: vec!["Fix the build".into()] }).await;
+    let result = stream_message(serde_json::json!({
+        "type": "result", "subtype": "success", "session_id": "s",
+        "result": "Done", "total_cost_usd": 0.25, "duration_ms": 1000,
+    }));
+    intruder.send(&CliToServer::StreamMessage { session_id, message: result, pane_type: Some(PaneType::Deadloop) }).await;
+    intruder
+        .send(&CliToServer::SandboxViolation {
+            session_id,
+            kind: shared::SandboxViolation::OutOfMemory,
+            detail: "killed".into(),
+        })
+        .await;
+    // Messages are handled in order, so the reply means the rest were too
+    intruder.send(&CliToServer::Heartbeat).await;
+    intruder.expect(|m| matches!(m, ServerToCli::Heartbeat)).await;
+
+    let owner = server.state.db.get_session(&session_id.to_string()).await.unwrap().unwrap().user_id;
+    let activity = server.state.db.get_session_activity(&owner, 0, i64::MAX).await.unwrap();
+    assert!(activity.iter().all(|a| a.iterations == 0), "{:?}", activity);
+    assert!(server.state.db.get_commits(&owner, 0, i64::MAX).await.unwrap().is_empty());
+    assert!(server.state.db.get_sandbox_violations(&owner, 0, i64::MAX).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_a_finished_pane_leaves_the_session_running() {
+    let server = TestServer::start().await;
+    let (session_id, mut cli, mut web, _) = attached_session(&server).await;
+
+    let reason = "reached 2 iterations".to_string();
+    cli.send(&CliToServer::SessionEnd { session_id, reason: reason.clone(), pane_type: Some(PaneType::Deadloop) })
+        .await;
+    web.expect(|m| {
+        matches!(m, ServerToWeb::Output { content, pane_type: Some(PaneType::Deadloop), .. } if content == "[Pane ended: reached 2 iterations]")
+    })
+    .await;
+    server.expect_session_status(session_id, "active").await;
+
+    cli.send(&CliToServer::SessionEnd { session_id, reason, pane_type: None }).await;
+    web.expect(|m| matches!(m, ServerToWeb::SessionStatus { status: shared::SessionStatus::Ended })).await;
+    server.expect_session_status(session_id, "ended").await;
+}
+
+#[tokio::test]
+async fn test_sandbox_violations_are_recorded() {
+    let server = TestServer::start().await;
+    let (session_id, mut cli, mut web, _) = attached_session(&server).await;
+
+    cli.send(&CliToServer::SandboxViolation {
+        session_id,
+        kind: shared::SandboxViolation::OutOfMemory,
+        detail: "killed, most likely for going over the 4096 MB memory limit".into(),
+    })
+    .await;
+    web.expect(|m| matches!(m, ServerToWeb::Output { content, .. } if content.starts_with("[Sandbox: killed"))).await;
+
+    let owner = server.state.db.get_session(&session_id.to_string()).await.unwrap().unwrap().user_id;
+    let violations = server.state.db.get_sandbox_violations(&owner, 0, i64::MAX).await.unwrap();
+    let kinds: Vec<_> = violations.iter().map(|v| v.kind.as_str()).collect();
+    assert_eq!(kinds, ["out_of_memory"]);
+}
+
+#[tokio::test]
+async fn test_web_controls_reach_the_cli() {
+    let server = TestServer::start().await;
+    let (session_id, mut cli, mut web, _) = attached_session(&server).await;
+
+    web.send(&WebToServer::PauseDeadloop).await;
+    cli.expect(|m| matches!(m, ServerToCli::PauseDeadloop { session_id: s } if *s == session_id)).await;
+    cli.send(&CliToServer::DeadloopStatus { session_id, is_paused: true, reason: Some(PauseReason::User) }).await;
+    web.expect(|m| matches!(m, ServerToWeb::DeadloopStatus { is_paused: true, .. })).await;
+
+    web.send(&WebToServer::ResumeDeadloop).await;
+    cli.expect(|m| matches!(m, ServerToCli::ResumeDeadloop { .. })).await;
+
+    web.send(&WebToServer::Input { text: "run the tests".into(), pane_type: Some(PaneType::Interactive) }).await;
+    cli.expect(|m| matches!(m, ServerToCli::Input { data, .. } if data == "run the tests")).await;
+    // Echoed back so the web shows it right away
+    web.expect(|m| matches!(m, ServerToWeb::UserInput { text, .. } if text == "run the tests")).await;
+
+    web.send(&WebToServer::Signal { signal: "SIGINT".into(), pane_type: Some(PaneType::Deadloop) }).await;
+    cli.expect(|m| matches!(m, ServerToCli::Signal { pane_type: Some(PaneType::Deadloop), .. })).await;
+}
+
+#[tokio::test]
+async fn test_reconnecting_cli_takes_the_session_back() {
+    let server = TestServer::start().await;
+    let (session_id, cli, mut web, token) = attached_session(&server).await;
+
+    cli.close().await;
+    server.expect_session_status(session_id, "inactive").await;
+
+    // The web client stays attached while the CLI comes back
+    let mut cli = server.cli(&token).await;
+    cli.send(&CliToServer::SessionStart {
+        session_id,
+        working_dir: Some("/work".into()),
+        hostname: Some("devbox".into()),
+        pane_type: Some(PaneType::Deadloop),
+    })
+    .await;
+    server.expect_session_status(session_id, "active").await;
+
+    web.send(&WebToServer::Input { text: "still there?".into(), pane_type: Some(PaneType::Interactive) }).await;
+    cli.expect(|m| matches!(m, ServerToCli::Input { data, .. } if data == "still there?")).await;
+    cli.send(&CliToServer::Output { session_id, data: "yes".into(), output_type: shared::OutputType::Text }).await;
+    web.expect(|m| matches!(m, ServerToWeb::Output { content, .. } if content == "yes")).await;
+}
@@ -0,0 +1,120 @@
+//! Outgoing email, via the local sendmail binary or an SMTP server as
+//! configured in `SmtpConfig`
+
+use anyhow::Result;
+use lettre::{
+    message::header::ContentType,
+    transport::smtp::authentication::Credentials,
+    AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::config::SmtpConfig;
+
+/// Send one HTML email
+pub async fn send(smtp: &SmtpConfig, to: &str, subject: &str, html: String) -> Result<()> {
+    let email = Message::builder()
+        .from(format!("{} <{}>", smtp.from_name, smtp.from_email).parse()?)
+        .to(to.parse()?)
+        .subject(subject)
+        .header(ContentType::TEXT_HTML)
+        .body(html)?;
+
+    if smtp.use_sendmail {
+        let mailer = AsyncSendmailTransport::<Tokio1Executor>::new();
+        mailer.send(email).await?;
+        return Ok(());
+    }
+
+    let mut builder = if smtp.tls {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host)
+    };
+    if !smtp.username.is_empty() {
+        builder = builder.credentials(Credentials::new(smtp.username.clone(), smtp.password.clone()));
+    }
+    builder.port(smtp.port).build().send(email).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Messages accepted by the sink: (recipients, raw message)
+    pub(crate) type Inbox = Arc<Mutex<Vec<(Vec<String>, String)>>>;
+
+    /// Plain-text SMTP server that accepts everything and keeps it in memory.
+    /// Returns an `SmtpConfig` pointing at it.
+    pub(crate) async fn smtp_sink() -> (SmtpConfig, Inbox) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let inbox: Inbox = Default::default();
+
+        let sink_inbox = inbox.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let inbox = sink_inbox.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = socket.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    let _ = write.write_all(b"220 sink ESMTP\r\n").await;
+                    let mut recipients = Vec::new();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let command = line.to_ascii_uppercase();
+                        let reply: &[u8] = if command.starts_with("RCPT TO:") {
+                            recipients.push(line[8..].trim().trim_matches(|c| c == '<' || c == '>').to_string());
+                            b"250 OK\r\n"
+                        } else if command == "DATA" {
+                            let _ = write.write_all(b"354 End with .\r\n").await;
+                            let mut data = String::new();
+                            while let Ok(Some(line)) = lines.next_line().await {
+                                if line == "." {
+                                    break;
+                                }
+                                data.push_str(&line);
+                                data.push('\n');
+                            }
+                            inbox.lock().unwrap().push((std::mem::take(&mut recipients), data));
+                            b"250 Queued\r\n"
+                        } else if command == "QUIT" {
+                            let _ = write.write_all(b"221 Bye\r\n").await;
+                            break;
+                        } else {
+                            // EHLO, MAIL FROM, RSET, NOOP
+                            b"250 OK\r\n"
+                        };
+                        let _ = write.write_all(reply).await;
+                    }
+                });
+            }
+        });
+
+        let config = SmtpConfig {
+            enabled: true,
+            use_sendmail: false,
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+            ..SmtpConfig::default()
+        };
+        (config, inbox)
+    }
+
+    #[tokio::test]
+    async fn test_send_over_smtp() {
+        let (config, inbox) = smtp_sink().await;
+        send(&config, "someone@example.com", "Hello", "<p>Hi there</p>".to_string())
+            .await
+            .unwrap();
+
+        let inbox = inbox.lock().unwrap();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].0, vec!["someone@example.com"]);
+        assert!(inbox[0].1.contains("Subject: Hello"));
+        assert!(inbox[0].1.contains("Hi there"));
+    }
+}
@@ -1,18 +1,57 @@
 use anyhow::Result;
+use clap::{Parser, Subcommand};
+use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod backplane;
 mod config;
 mod db;
+#[cfg(test)]
+mod e2e;
 mod error;
+mod mailer;
+mod notifications;
 mod routes;
 mod session;
 mod state;
 mod storage;
+mod webhooks;
 
 use state::AppState;
 
+#[derive(Parser)]
+#[command(name = "apas-server")]
+#[command(about = "APAS server - relays Claude sessions between CLI clients and the web UI")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Inspect or apply database schema migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// Show applied and pending migrations
+    Status,
+    /// Apply pending migrations
+    Up {
+        /// List what would be applied without changing the database
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     // Initialize tracing
     tracing_subscriber::registry()
         .with(
@@ -27,11 +66,32 @@ async fn main() -> Result<()> {
     tracing::info!("Starting APAS server on {}:{}", config.server.host, config.server.port);
 
     // Initialize database
-    let db = db::Database::new(&config.database.path).await?;
+    let db = db::Database::connect(&config.database).await?;
+
+    if let Some(Commands::Migrate { action }) = cli.command {
+        return migrate(&db, action).await;
+    }
+
     db.run_migrations().await?;
 
+    // Nothing is connected yet, so whatever the database thinks is live is
+    // stale. With a shared backplane other instances still hold their
+    // connections, so leave their rows alone.
+    if config.backplane.kind == config::BackplaneKind::Local {
+        let (sessions, clients) = db.reconcile_after_restart(chrono::Utc::now().timestamp()).await?;
+        if sessions > 0 || clients > 0 {
+            tracing::info!("Marked {} orphaned sessions inactive and {} CLI clients offline", sessions, clients);
+        }
+    }
+    tokio::spawn(sweep_expired_auth_tokens(db.clone()));
+
     // Create app state
-    let state = AppState::new(db, config.clone());
+    let backplane = backplane::from_config(&config.backplane)?;
+    let state = AppState::new(db, config.clone(), backplane);
+    state.sessions.start();
+    if config.smtp.enabled {
+        tokio::spawn(state.notifier.clone().run());
+    }
 
     // Build router
     let app = routes::create_router(state);
@@ -45,3 +105,49 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// How often expired device codes and reset tokens are deleted
+const AUTH_TOKEN_SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+async fn sweep_expired_auth_tokens(db: db::Database) {
+    let mut interval = tokio::time::interval(AUTH_TOKEN_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match db.delete_expired_auth_tokens(chrono::Utc::now().timestamp()).await {
+            Ok(0) => {}
+            Ok(removed) => tracing::debug!("Swept {} expired auth tokens", removed),
+            Err(e) => tracing::warn!("Failed to sweep expired auth tokens: {}", e),
+        }
+    }
+}
+
+async fn migrate(db: &db::Database, action: MigrateAction) -> Result<()> {
+    let status = db.migration_status().await?;
+    let pending: Vec<_> = status.iter().filter(|s| s.applied_at.is_none()).collect();
+
+    match action {
+        MigrateAction::Status => {
+            for s in &status {
+                match &s.applied_at {
+                    Some(at) => println!("{:04}  {:<30} applied {}", s.version, s.name, at),
+                    None => println!("{:04}  {:<30} pending", s.version, s.name),
+                }
+            }
+            println!("{} applied, {} pending", status.len() - pending.len(), pending.len());
+        }
+        MigrateAction::Up { dry_run: true } => {
+            if pending.is_empty() {
+                println!("Nothing to apply; schema is up to date");
+            }
+            for s in &pending {
+                println!("Would apply {:04} {}", s.version, s.name);
+            }
+        }
+        MigrateAction::Up { dry_run: false } => {
+            db.run_migrations().await?;
+            println!("Applied {} migration(s)", pending.len());
+        }
+    }
+
+    Ok(())
+}
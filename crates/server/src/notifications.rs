@@ -0,0 +1,331 @@
+//! Email notifications
+//!
+//! Users are alerted when a deadloop pauses itself (budget, stuck loops)
+//! and when one of their machines stays offline longer than they allow. Those
+//! who opt in also get a daily digest of iterations, cost, commits and
+//! sandbox violations per session. Offline alerts and digests are claimed in the database before
+//! they are sent, so several server instances don't send duplicates.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use shared::PauseReason;
+use uuid::Uuid;
+
+use crate::config::SmtpConfig;
+use crate::db::{CommitRecord, Database, NotificationPreferences, OfflineCliClient, SandboxViolationRecord, SessionActivity};
+use crate::mailer;
+
+/// How often to look for offline machines and due digests
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const DIGEST_PERIOD_SECS: i64 = 24 * 60 * 60;
+
+/// Longest commit subject kept for digests
+pub const MAX_SUMMARY_CHARS: usize = 120;
+
+#[derive(Clone)]
+pub struct Notifier {
+    db: Database,
+    smtp: SmtpConfig,
+}
+
+impl Notifier {
+    pub fn new(db: Database, smtp: SmtpConfig) -> Self {
+        Self { db, smtp }
+    }
+
+    /// The user's settings, or the defaults if they never changed them
+    pub async fn preferences(&self, user_id: &str) -> Result<NotificationPreferences> {
+        Ok(self
+            .db
+            .get_notification_preferences(user_id)
+            .await?
+            .unwrap_or_else(|| NotificationPreferences::defaults(user_id)))
+    }
+
+    async fn email_user(&self, user_id: &str, subject: &str, html: String) -> Result<()> {
+        let Some(user) = self.db.get_user_by_id(user_id).await? else {
+            return Ok(());
+        };
+        mailer::send(&self.smtp, &user.email, subject, html).await
+    }
+
+    /// Alert the owner that a deadloop paused itself. Pauses from the web UI
+    /// are the user's own doing and don't send anything.
+    pub fn deadloop_paused(&self, user_id: Uuid, session_id: Uuid, reason: PauseReason) {
+        if !self.smtp.enabled || reason == PauseReason::User {
+            return;
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            let user_id = user_id.to_string();
+            let result = async {
+                if !this.preferences(&user_id).await?.pause_alerts {
+                    return Ok(());
+                }
+                let session = this.db.get_session(&session_id.to_string()).await?;
+                let working_dir = session.and_then(|s| s.working_dir);
+                let html = render_pause_alert(session_id, working_dir.as_deref(), reason);
+                this.email_user(&user_id, "APAS - Deadloop paused", html).await
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::error!("Failed to send pause alert for session {}: {}", session_id, e);
+            }
+        });
+    }
+
+    /// Send offline alerts and digests as they come due, forever
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let now = chrono::Utc::now().timestamp();
+            if let Err(e) = self.alert_offline_clients(now).await {
+                tracing::error!("Failed to check for offline machines: {}", e);
+            }
+            if let Err(e) = self.send_digests(now).await {
+                tracing::error!("Failed to send digests: {}", e);
+            }
+        }
+    }
+
+    /// Returns the number of alerts sent
+    async fn alert_offline_clients(&self, now: i64) -> Result<usize> {
+        let mut sent = 0;
+        for client in self.db.get_unalerted_offline_clients().await? {
+            let Some(minutes) = self.preferences(&client.user_id).await?.offline_alert_minutes else {
+                continue;
+            };
+            if now - client.offline_since < minutes * 60 || !self.db.claim_offline_alert(&client.id).await? {
+                continue;
+            }
+            let html = render_offline_alert(&client, now);
+            match self.email_user(&client.user_id, "APAS - Machine offline", html).await {
+                Ok(()) => sent += 1,
+                Err(e) => tracing::error!("Failed to send offline alert for {}: {}", client.id, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Returns the number of digests sent
+    async fn send_digests(&self, now: i64) -> Result<usize> {
+        let mut sent = 0;
+        for prefs in self.db.get_digest_subscribers().await? {
+            let since = prefs.last_digest_at.unwrap_or(now - DIGEST_PERIOD_SECS);
+            if !self.db.claim_digest(&prefs.user_id, now, now - DIGEST_PERIOD_SECS).await? {
+                continue;
+            }
+            let activity = self.db.get_session_activity(&prefs.user_id, since, now).await?;
+            let commits = self.db.get_commits(&prefs.user_id, since, now).await?;
+            let violations = self.db.get_sandbox_violations(&prefs.user_id, since, now).await?;
+            if activity.is_empty() && commits.is_empty() && violations.is_empty() {
+                continue;
+            }
+            let html = render_digest(&activity, &commits, &violations);
+            match self.email_user(&prefs.user_id, "APAS - Daily digest", html).await {
+                Ok(()) => sent += 1,
+                Err(e) => tracing::error!("Failed to send digest to {}: {}", prefs.user_id, e),
+            }
+        }
+        Ok(sent)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <title>{title}</title>
+</head>
+<body style="font-family: Arial, sans-serif; line-height: 1.6; color: #333; max-width: 600px; margin: 0 auto; padding: 20px;">
+    <h2 style="color: #0891b2;">{title}</h2>
+{body}
+</body>
+</html>"#
+    )
+}
+
+fn render_pause_alert(session_id: Uuid, working_dir: Option<&str>, reason: PauseReason) -> String {
+    page(
+        "Deadloop paused",
+        &format!(
+            "    <p>The deadloop in <b>{}</b> (session {}) paused itself: {}.</p>\n    <p>Resume it from the web UI once you've had a look.</p>",
+            escape(working_dir.unwrap_or("an unknown directory")),
+            session_id,
+            reason.description()
+        ),
+    )
+}
+
+fn render_offline_alert(client: &OfflineCliClient, now: i64) -> String {
+    let name = client.name.as_deref().or(client.hostname.as_deref()).unwrap_or(&client.id);
+    page(
+        "Machine offline",
+        &format!(
+            "    <p><b>{}</b> has been offline for {} minutes. Any loops running there have stopped reporting.</p>",
+            escape(name),
+            (now - client.offline_since) / 60
+        ),
+    )
+}
+
+fn render_digest(activity: &[SessionActivity], commits: &[CommitRecord], violations: &[SandboxViolationRecord]) -> String {
+    let mut by_session: HashMap<&str, Vec<&CommitRecord>> = HashMap::new();
+    for commit in commits {
+        by_session.entry(&commit.session_id).or_default().push(commit);
+    }
+    let mut violations_by_session: HashMap<&str, Vec<&str>> = HashMap::new();
+    for violation in violations {
+        violations_by_session.entry(&violation.session_id).or_default().push(&violation.kind);
+    }
+
+    // Sessions that only committed or hit the sandbox (no finished
+    // iteration) still get a section
+    let mut sessions: Vec<(&str, Option<&SessionActivity>)> =
+        activity.iter().map(|a| (a.session_id.as_str(), Some(a))).collect();
+    let others = commits.iter().map(|c| c.session_id.as_str()).chain(violations.iter().map(|v| v.session_id.as_str()));
+    for session_id in others {
+        if !sessions.iter().any(|(id, _)| *id == session_id) {
+            sessions.push((session_id, None));
+        }
+    }
+
+    let mut body = String::new();
+    let total_cost: f64 = activity.iter().map(|a| a.cost_usd).sum();
+    let total_iterations: i64 = activity.iter().map(|a| a.iterations).sum();
+    body.push_str(&format!(
+        "    <p>{} iterations, ${:.2} spent, {} commits in the last day.</p>\n",
+        total_iterations,
+        total_cost,
+        commits.len()
+    ));
+
+    for (session_id, activity) in sessions {
+        let title = activity.and_then(|a| a.working_dir.as_deref()).unwrap_or(session_id);
+        body.push_str(&format!("    <h3>{}</h3>\n", escape(title)));
+        if let Some(a) = activity {
+            let timeouts = match a.timeouts {
+                0 => String::new(),
+                n => format!(", {} timed out", n),
+            };
+            body.push_str(&format!(
+                "    <p>{} iterations ({} failed{}), ${:.2}, {} minutes of work</p>\n",
+                a.iterations,
+                a.errors,
+                timeouts,
+                a.cost_usd,
+                a.duration_ms / 60_000
+            ));
+        }
+        if let Some(kinds) = violations_by_session.get(session_id) {
+            let mut distinct = kinds.clone();
+            distinct.sort_unstable();
+            distinct.dedup();
+            body.push_str(&format!(
+                "    <p>{} sandbox violations ({})</p>\n",
+                kinds.len(),
+                escape(&distinct.join(", ").replace('_', " "))
+            ));
+        }
+        if let Some(commits) = by_session.get(session_id) {
+            body.push_str("    <ul>\n");
+            for commit in commits {
+                body.push_str(&format!("        <li>{}</li>\n", escape(&commit.summary)));
+            }
+            body.push_str("    </ul>\n");
+        }
+    }
+
+    page("Daily digest", body.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{CliClient, Session, User};
+    use crate::mailer::tests::smtp_sink;
+
+    #[tokio::test]
+    async fn test_offline_alerts_and_digests() {
+        let dir = std::env::temp_dir().join(format!("apas-notify-{}", Uuid::new_v4()));
+        let config = crate::config::DatabaseConfig {
+            path: dir.join("apas.db").to_string_lossy().into_owned(),
+            ..crate::config::Config::default().database
+        };
+        let db = Database::connect(&config).await.unwrap();
+        db.run_migrations().await.unwrap();
+        let (smtp, inbox) = smtp_sink().await;
+        let notifier = Notifier::new(db.clone(), smtp);
+
+        let user = User {
+            id: "user-1".into(),
+            email: "owner@example.com".into(),
+            password_hash: "x".into(),
+        };
+        db.create_user(&user).await.unwrap();
+        let client = CliClient {
+            id: "machine-1".into(),
+            user_id: user.id.clone(),
+            name: Some("build box".into()),
+            hostname: None,
+            os: None,
+            claude_version: None,
+            last_seen: None,
+            status: "online".into(),
+        };
+        db.upsert_cli_client(&client).await.unwrap();
+
+        // Default threshold is 30 minutes; alert once, not every check
+        let now = 1_000_000;
+        db.mark_cli_client_offline(&client.id, now - 10 * 60).await.unwrap();
+        assert_eq!(notifier.alert_offline_clients(now).await.unwrap(), 0);
+        assert_eq!(notifier.alert_offline_clients(now + 30 * 60).await.unwrap(), 1);
+        assert_eq!(notifier.alert_offline_clients(now + 60 * 60).await.unwrap(), 0);
+
+        let session = Session {
+            id: "session-1".into(),
+            user_id: user.id.clone(),
+            cli_client_id: None,
+            working_dir: Some("/src/app".into()),
+            hostname: None,
+            status: "active".into(),
+            created_at: None,
+        };
+        db.create_session(&session).await.unwrap();
+        db.record_iteration(&session.id, false, 0.25, 120_000, now - 100).await.unwrap();
+        db.record_iteration(&session.id, true, 0.5, 60_000, now - 50).await.unwrap();
+        db.record_commit(&session.id, "Add <retries>", now - 40).await.unwrap();
+        for kind in ["blocked_write", "out_of_memory", "blocked_write"] {
+            db.record_sandbox_violation(&session.id, kind, "details", now - 30).await.unwrap();
+        }
+
+        let mut prefs = NotificationPreferences::defaults(&user.id);
+        prefs.daily_digest = true;
+        prefs.last_digest_at = Some(now - DIGEST_PERIOD_SECS);
+        db.upsert_notification_preferences(&prefs).await.unwrap();
+        assert_eq!(notifier.send_digests(now).await.unwrap(), 1);
+        assert_eq!(notifier.send_digests(now + 60).await.unwrap(), 0, "not due again for a day");
+
+        let inbox = inbox.lock().unwrap();
+        assert_eq!(inbox.len(), 2);
+        assert!(inbox.iter().all(|(to, _)| to == &vec!["owner@example.com".to_string()]));
+        assert!(inbox[0].1.contains("build box"));
+        let digest = &inbox[1].1;
+        assert!(digest.contains("/src/app"));
+        assert!(digest.contains("2 iterations (1 failed)"));
+        assert!(digest.contains("Add &lt;retries&gt;"));
+        assert!(digest.contains("3 sandbox violations (blocked write, out of memory)"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -9,13 +9,7 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{db::User, error::AppError, state::{AppState, DeviceCodeState, PasswordResetState}};
-use lettre::{
-    message::header::ContentType,
-    transport::smtp::authentication::Credentials,
-    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
-    AsyncSendmailTransport,
-};
+use crate::{db::User, error::AppError, state::AppState};
 
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
@@ -64,7 +58,6 @@ pub async fn register(
         id: user_id.clone(),
         email: req.email,
         password_hash,
-        created_at: None,
     };
     state.db.create_user(&user).await?;
 
@@ -167,7 +160,7 @@ pub struct DeviceCompleteRequest {
 
 /// Generate a device code for CLI login
 /// POST /auth/device-code
-pub async fn device_code(State(state): State<AppState>) -> Json<DeviceCodeResponse> {
+pub async fn device_code(State(state): State<AppState>) -> Result<Json<DeviceCodeResponse>, AppError> {
     // Generate random 8-character code
     let code: String = rand::thread_rng()
         .sample_iter(&rand::distributions::Alphanumeric)
@@ -178,22 +171,16 @@ pub async fn device_code(State(state): State<AppState>) -> Json<DeviceCodeRespon
 
     let expires_at = Utc::now() + Duration::minutes(10);
 
-    // Store the device code
-    state.device_codes.insert(
-        code.clone(),
-        DeviceCodeState {
-            expires_at,
-            user_id: None,
-        },
-    );
+    // Store the device code (in the database, so a restart doesn't break the login)
+    state.db.create_device_code(&code, expires_at.timestamp()).await?;
 
     tracing::info!("Generated device code: {}", code);
 
-    Json(DeviceCodeResponse {
+    Ok(Json(DeviceCodeResponse {
         url: format!("{}/login?code={}", WEB_UI_URL, code),
         code,
         expires_in: 600,
-    })
+    }))
 }
 
 /// Poll for device code completion
@@ -204,34 +191,23 @@ pub async fn device_poll(
 ) -> Result<Json<DevicePollResponse>, AppError> {
     tracing::info!("Device poll request for code: {}", req.code);
 
-    // Clean up expired codes first
-    state.device_codes.retain(|_, v| v.expires_at > Utc::now());
-
-    // Remove and check in one operation to avoid any lock issues
-    // We'll re-insert if the user hasn't completed login yet
-    let removed = state.device_codes.remove(&req.code);
+    match state.db.get_device_code(&req.code).await? {
+        Some(device_code) => {
+            tracing::info!("Found device code {}, user_id: {:?}, expires_at: {}",
+                device_code.code, device_code.user_id, device_code.expires_at);
 
-    match removed {
-        Some((code, code_state)) => {
-            tracing::info!("Found device code {}, user_id: {:?}, expires_at: {:?}",
-                code, code_state.user_id, code_state.expires_at);
-
-            if code_state.expires_at <= Utc::now() {
-                tracing::info!("Device code {} expired", code);
+            if device_code.expires_at <= Utc::now().timestamp() {
+                tracing::info!("Device code {} expired", device_code.code);
+                state.db.delete_device_code(&device_code.code).await?;
                 Ok(Json(DevicePollResponse::Expired))
-            } else if let Some(user_id) = code_state.user_id {
-                // User has completed login - generate token
-                tracing::info!("Device code {} - user_id is Some, generating token", code);
-                let token = generate_token(&user_id.to_string(), &state.config.auth)?;
-                tracing::info!("Device code {} completed for user {}", code, user_id);
-                Ok(Json(DevicePollResponse::Success {
-                    token,
-                    user_id: user_id.to_string(),
-                }))
+            } else if let Some(user_id) = device_code.user_id {
+                // User has completed login - generate token; the code is single use
+                let token = generate_token(&user_id, &state.config.auth)?;
+                state.db.delete_device_code(&device_code.code).await?;
+                tracing::info!("Device code {} completed for user {}", device_code.code, user_id);
+                Ok(Json(DevicePollResponse::Success { token, user_id }))
             } else {
-                // Still waiting - put it back
-                tracing::info!("Device code {} still pending, re-inserting", code);
-                state.device_codes.insert(code, code_state);
+                tracing::info!("Device code {} still pending", device_code.code);
                 Ok(Json(DevicePollResponse::Pending))
             }
         }
@@ -251,18 +227,15 @@ pub async fn device_complete(
     let user_id = Uuid::parse_str(&req.user_id)
         .map_err(|_| AppError::BadRequest("Invalid user_id".to_string()))?;
 
-    match state.device_codes.get_mut(&req.code) {
-        Some(mut code_state) => {
-            if code_state.expires_at <= Utc::now() {
-                state.device_codes.remove(&req.code);
-                return Err(AppError::BadRequest("Device code expired".to_string()));
-            }
-            code_state.user_id = Some(user_id);
-            tracing::info!("Device code {} linked to user {}", req.code, user_id);
-            Ok(Json(serde_json::json!({ "success": true })))
-        }
-        None => Err(AppError::BadRequest("Invalid device code".to_string())),
+    let device_code = state.db.get_device_code(&req.code).await?
+        .ok_or_else(|| AppError::BadRequest("Invalid device code".to_string()))?;
+
+    if !state.db.complete_device_code(&device_code.code, &user_id.to_string(), Utc::now().timestamp()).await? {
+        state.db.delete_device_code(&device_code.code).await?;
+        return Err(AppError::BadRequest("Device code expired".to_string()));
     }
+    tracing::info!("Device code {} linked to user {}", req.code, user_id);
+    Ok(Json(serde_json::json!({ "success": true })))
 }
 
 // ============================================================================
@@ -308,13 +281,7 @@ pub async fn forgot_password(
     let expires_at = Utc::now() + Duration::hours(1);
 
     // Store the reset token
-    state.password_reset_tokens.insert(
-        token.clone(),
-        PasswordResetState {
-            email: req.email.clone(),
-            expires_at,
-        },
-    );
+    state.db.create_password_reset_token(&token, &req.email, expires_at.timestamp()).await?;
 
     // Send reset email
     if state.config.smtp.enabled {
@@ -342,20 +309,16 @@ pub async fn reset_password(
     State(state): State<AppState>,
     Json(req): Json<ResetPasswordRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    // Clean up expired tokens
-    state.password_reset_tokens.retain(|_, v| v.expires_at > Utc::now());
-
     // Validate token
-    let reset_state = state.password_reset_tokens.get(&req.token)
+    let reset_token = state.db.get_password_reset_token(&req.token).await?
         .ok_or_else(|| AppError::BadRequest("Invalid or expired reset token".to_string()))?;
 
-    if reset_state.expires_at <= Utc::now() {
-        state.password_reset_tokens.remove(&req.token);
+    if reset_token.expires_at <= Utc::now().timestamp() {
+        state.db.delete_password_reset_token(&req.token).await?;
         return Err(AppError::BadRequest("Reset token has expired".to_string()));
     }
 
-    let email = reset_state.email.clone();
-    drop(reset_state); // Release the lock before making DB calls
+    let email = reset_token.email;
 
     // Validate password length
     if req.password.len() < 6 {
@@ -378,7 +341,7 @@ pub async fn reset_password(
     }
 
     // Remove the used token
-    state.password_reset_tokens.remove(&req.token);
+    state.db.delete_password_reset_token(&req.token).await?;
 
     tracing::info!("Password reset completed for {}", email);
 
@@ -388,19 +351,14 @@ pub async fn reset_password(
     })))
 }
 
-/// Send password reset email via sendmail or SMTP
+/// Send password reset email
 async fn send_password_reset_email(
     smtp_config: &crate::config::SmtpConfig,
     to_email: &str,
     reset_url: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let email = Message::builder()
-        .from(format!("{} <{}>", smtp_config.from_name, smtp_config.from_email).parse()?)
-        .to(to_email.parse()?)
-        .subject("APAS - Password Reset Request")
-        .header(ContentType::TEXT_HTML)
-        .body(format!(
-            r#"<!DOCTYPE html>
+) -> anyhow::Result<()> {
+    let html = format!(
+        r#"<!DOCTYPE html>
 <html>
 <head>
     <meta charset="UTF-8">
@@ -419,29 +377,9 @@ async fn send_password_reset_email(
     <p style="color: #666; font-size: 14px;">If you didn't request this, you can safely ignore this email.</p>
 </body>
 </html>"#,
-            reset_url, reset_url
-        ))?;
-
-    if smtp_config.use_sendmail {
-        // Use local sendmail binary
-        let mailer = AsyncSendmailTransport::<Tokio1Executor>::new();
-        mailer.send(email).await?;
-    } else {
-        // Use SMTP server
-        let creds = Credentials::new(
-            smtp_config.username.clone(),
-            smtp_config.password.clone(),
-        );
-
-        let mailer: AsyncSmtpTransport<Tokio1Executor> = AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp_config.host)?
-            .credentials(creds)
-            .port(smtp_config.port)
-            .build();
-
-        mailer.send(email).await?;
-    }
-
-    Ok(())
+        reset_url, reset_url
+    );
+    crate::mailer::send(smtp_config, to_email, "APAS - Password Reset Request", html).await
 }
 
 // Admin impersonate endpoint for debugging
@@ -0,0 +1,43 @@
+//! Blob store endpoints
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+
+use crate::{error::AppError, routes::share::extract_user_id, state::AppState};
+
+/// Fetch the full content of an offloaded tool output
+/// GET /blobs/:session_id/:hash
+pub async fn get_blob(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Path((session_id, hash)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let user_id = extract_user_id(&state, auth_header).await?;
+
+    if !state.db.check_session_access(&session_id, &user_id).await? {
+        return Err(AppError::AuthError("You don't have access to this session".to_string()));
+    }
+
+    // Blobs are shared between sessions on disk; only serve this session's
+    let session = uuid::Uuid::parse_str(&session_id).map_err(|_| AppError::NotFound(format!("Blob {}", hash)))?;
+    let content = state
+        .storage
+        .get_blob(&session, &hash)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Blob {}", hash)))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            // Content-addressed, so it never changes
+            (header::CACHE_CONTROL, "private, max-age=31536000, immutable"),
+        ],
+        content,
+    ))
+}
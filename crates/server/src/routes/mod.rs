@@ -8,8 +8,11 @@ use tower_http::trace::TraceLayer;
 use crate::state::AppState;
 
 pub mod auth;
+mod blobs;
 mod health;
+mod notifications;
 mod share;
+mod webhooks;
 mod ws_cli;
 mod ws_web;
 
@@ -40,6 +43,16 @@ pub fn create_router(state: AppState) -> Router {
         .route("/share/redeem", post(share::redeem_code))
         .route("/share/list/:session_id", get(share::list_shares))
         .route("/share/:session_id/:user_id", delete(share::revoke_access))
+        // Email notifications
+        .route(
+            "/notifications/preferences",
+            get(notifications::get_preferences).put(notifications::update_preferences),
+        )
+        // Outbound webhooks
+        .route("/webhooks", get(webhooks::list_webhooks).post(webhooks::create_webhook))
+        .route("/webhooks/:id", delete(webhooks::delete_webhook))
+        // Large tool outputs
+        .route("/blobs/:session_id/:hash", get(blobs::get_blob))
         // WebSocket routes
         .route("/ws/web", get(ws_web::ws_handler))
         .route("/ws/cli", get(ws_cli::ws_handler))
@@ -0,0 +1,87 @@
+//! Email notification preference endpoints
+
+use axum::{extract::State, http::header, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::NotificationPreferences,
+    error::AppError,
+    routes::share::extract_user_id,
+    state::AppState,
+};
+
+#[derive(Debug, Serialize)]
+pub struct PreferencesResponse {
+    pub pause_alerts: bool,
+    /// 0 when offline alerts are off
+    pub offline_alert_minutes: i64,
+    pub daily_digest: bool,
+}
+
+impl From<&NotificationPreferences> for PreferencesResponse {
+    fn from(prefs: &NotificationPreferences) -> Self {
+        Self {
+            pause_alerts: prefs.pause_alerts,
+            offline_alert_minutes: prefs.offline_alert_minutes.unwrap_or(0),
+            daily_digest: prefs.daily_digest,
+        }
+    }
+}
+
+/// Fields left out keep their current value
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    pub pause_alerts: Option<bool>,
+    /// 0 turns offline alerts off
+    pub offline_alert_minutes: Option<i64>,
+    pub daily_digest: Option<bool>,
+}
+
+/// Get the caller's notification preferences
+/// GET /notifications/preferences
+pub async fn get_preferences(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<PreferencesResponse>, AppError> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let user_id = extract_user_id(&state, auth_header).await?;
+
+    let prefs = state.notifier.preferences(&user_id).await?;
+    Ok(Json(PreferencesResponse::from(&prefs)))
+}
+
+/// Change the caller's notification preferences
+/// PUT /notifications/preferences
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<UpdatePreferencesRequest>,
+) -> Result<Json<PreferencesResponse>, AppError> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let user_id = extract_user_id(&state, auth_header).await?;
+
+    let mut prefs = state.notifier.preferences(&user_id).await?;
+    if let Some(pause_alerts) = req.pause_alerts {
+        prefs.pause_alerts = pause_alerts;
+    }
+    if let Some(minutes) = req.offline_alert_minutes {
+        if minutes < 0 {
+            return Err(AppError::BadRequest("offline_alert_minutes can't be negative".to_string()));
+        }
+        prefs.offline_alert_minutes = (minutes > 0).then_some(minutes);
+    }
+    if let Some(daily_digest) = req.daily_digest {
+        // The first digest covers the day after turning it on
+        if daily_digest && !prefs.daily_digest {
+            prefs.last_digest_at = Some(chrono::Utc::now().timestamp());
+        }
+        prefs.daily_digest = daily_digest;
+    }
+    state.db.upsert_notification_preferences(&prefs).await?;
+
+    Ok(Json(PreferencesResponse::from(&prefs)))
+}
@@ -19,7 +19,7 @@ use crate::{
 const WEB_UI_URL: &str = "http://apas.mpaxos.com";
 
 // Helper to extract and verify JWT from Authorization header
-async fn extract_user_id(
+pub(super) async fn extract_user_id(
     state: &AppState,
     auth_header: Option<&str>,
 ) -> Result<String, AppError> {
@@ -86,8 +86,6 @@ pub async fn generate_code(
         created_by: user_id,
         expires_at: expires_at_str.clone(),
         redeemed_by: None,
-        redeemed_at: None,
-        created_at: None,
     };
     state.db.create_invitation_code(&invitation).await?;
 
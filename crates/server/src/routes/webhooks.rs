@@ -0,0 +1,134 @@
+//! Webhook subscription endpoints
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    db::Webhook,
+    error::AppError,
+    routes::share::extract_user_id,
+    state::AppState,
+    webhooks::{check_target, WebhookEvent},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Event names to deliver; omitted or empty means all events
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Signing secret; generated if omitted
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    /// Only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: Option<String>,
+}
+
+impl From<&Webhook> for WebhookResponse {
+    fn from(webhook: &Webhook) -> Self {
+        Self {
+            id: webhook.id.clone(),
+            url: webhook.url.clone(),
+            events: webhook.event_list().into_iter().map(String::from).collect(),
+            secret: None,
+            created_at: webhook.created_at.clone(),
+        }
+    }
+}
+
+/// List the caller's webhooks
+/// GET /webhooks
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Vec<WebhookResponse>>, AppError> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let user_id = extract_user_id(&state, auth_header).await?;
+
+    let webhooks = state.db.get_webhooks_for_user(&user_id).await?;
+    Ok(Json(webhooks.iter().map(WebhookResponse::from).collect()))
+}
+
+/// Subscribe a URL to events
+/// POST /webhooks
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<Json<WebhookResponse>, AppError> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let user_id = extract_user_id(&state, auth_header).await?;
+
+    if let Err(e) = check_target(&req.url).await {
+        return Err(AppError::BadRequest(format!("{:#}", e)));
+    }
+    if let Some(unknown) = req.events.iter().find(|e| WebhookEvent::parse(e).is_none()) {
+        let known: Vec<&str> = WebhookEvent::ALL.iter().map(|e| e.as_str()).collect();
+        return Err(AppError::BadRequest(format!(
+            "Unknown event {:?}; expected one of {}",
+            unknown,
+            known.join(", ")
+        )));
+    }
+
+    let secret = req.secret.filter(|s| !s.is_empty()).unwrap_or_else(|| {
+        rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect()
+    });
+
+    let webhook = Webhook {
+        id: Uuid::new_v4().to_string(),
+        user_id,
+        url: req.url,
+        secret: secret.clone(),
+        events: req.events.join(","),
+        created_at: None,
+    };
+    state.db.create_webhook(&webhook).await?;
+
+    tracing::info!("User {} added webhook {} -> {}", webhook.user_id, webhook.id, webhook.url);
+
+    Ok(Json(WebhookResponse {
+        secret: Some(secret),
+        ..WebhookResponse::from(&webhook)
+    }))
+}
+
+/// Remove one of the caller's webhooks
+/// DELETE /webhooks/:id
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let user_id = extract_user_id(&state, auth_header).await?;
+
+    if !state.db.delete_webhook(&id, &user_id).await? {
+        return Err(AppError::NotFound("Webhook not found".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -6,13 +6,15 @@ use axum::{
     response::IntoResponse,
 };
 use futures::{SinkExt, StreamExt};
-use shared::{CliToServer, ServerToCli, ServerToWeb};
+use shared::transport::{decode_frame, negotiate};
+use shared::{CliToServer, IterationTimeout, MachineInfo, PaneType, ServerToCli, ServerToWeb};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
 use crate::routes::auth::verify_token;
 use crate::state::AppState;
+use crate::webhooks::WebhookEvent;
 
 /// Minimum supported client version (YY.MM.COMMIT format)
 /// Update this when making breaking API changes
@@ -59,13 +61,14 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     // Wait for registration message first
     let cli_id: Uuid;
     let user_id: Uuid;
+    let machine: Option<MachineInfo>;
 
     loop {
         match receiver.next().await {
             Some(Ok(Message::Text(text))) => {
                 let parsed: Result<CliToServer, _> = serde_json::from_str(&text);
                 match parsed {
-                    Ok(CliToServer::Register { token, version }) => {
+                    Ok(CliToServer::Register { token, version, compression, machine: reported_machine }) => {
                         // Check client version
                         let client_version = version.as_deref().unwrap_or("unknown");
                         if !is_version_supported(client_version) {
@@ -79,7 +82,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                 min_version: MIN_CLIENT_VERSION.to_string(),
                             };
                             let text = serde_json::to_string(&response).unwrap();
-                            let _ = sender.send(Message::Text(text.into())).await;
+                            let _ = sender.send(Message::Text(text)).await;
                             return;
                         }
 
@@ -89,15 +92,27 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                 match Uuid::parse_str(&claims.sub) {
                                     Ok(uid) => {
                                         user_id = uid;
+                                        // Connections stay distinct (one machine can run several CLIs);
+                                        // the machine id is what ties them together across reconnects
                                         cli_id = Uuid::new_v4();
+                                        machine = reported_machine;
 
-                                        // Send registration success
-                                        let response = ServerToCli::Registered { cli_id };
+                                        // Send registration success along with the agreed frame codec
+                                        let response = ServerToCli::Registered {
+                                            cli_id,
+                                            compression: negotiate(&compression),
+                                        };
                                         let text = serde_json::to_string(&response).unwrap();
-                                        if sender.send(Message::Text(text.into())).await.is_err() {
+                                        if sender.send(Message::Text(text)).await.is_err() {
                                             return;
                                         }
-                                        tracing::info!("CLI client registered: {} (version: {}, user: {})", cli_id, client_version, user_id);
+                                        tracing::info!(
+                                            "CLI client registered: {} (version: {}, user: {}, machine: {:?})",
+                                            cli_id,
+                                            client_version,
+                                            user_id,
+                                            machine.as_ref().map(|m| m.machine_id)
+                                        );
                                         break;
                                     }
                                     Err(_) => {
@@ -105,7 +120,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                             reason: "Invalid user ID in token".to_string(),
                                         };
                                         let text = serde_json::to_string(&response).unwrap();
-                                        let _ = sender.send(Message::Text(text.into())).await;
+                                        let _ = sender.send(Message::Text(text)).await;
                                         return;
                                     }
                                 }
@@ -116,7 +131,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                     reason: format!("Authentication failed: {}", e),
                                 };
                                 let text = serde_json::to_string(&response).unwrap();
-                                let _ = sender.send(Message::Text(text.into())).await;
+                                let _ = sender.send(Message::Text(text)).await;
                                 return;
                             }
                         }
@@ -143,14 +158,13 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     let (tx, mut rx) = mpsc::channel::<ServerToCli>(32);
 
     // Register this CLI connection with user association
-    state.sessions.register_cli(cli_id, user_id, tx);
+    state.sessions.register_cli(cli_id, user_id, machine.clone(), tx);
 
     // Update database - first ensure user exists (dev mode creates random users)
     let dev_user = crate::db::User {
         id: user_id.to_string(),
         email: format!("dev-{}@local", user_id),
         password_hash: "dev".to_string(),
-        created_at: None,
     };
     if let Err(e) = state.db.create_user(&dev_user).await {
         // Ignore duplicate user errors
@@ -159,16 +173,30 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
         }
     }
 
-    let cli_client = crate::db::CliClient {
-        id: cli_id.to_string(),
+    // One row per machine, so reconnects update the same client
+    let mut client_row_id = machine.as_ref().map(|m| m.machine_id).unwrap_or(cli_id);
+    let mut cli_client = crate::db::CliClient {
+        id: client_row_id.to_string(),
         user_id: user_id.to_string(),
-        name: None,
+        name: machine.as_ref().and_then(|m| m.name.clone()),
+        hostname: machine.as_ref().and_then(|m| m.hostname.clone()),
+        os: machine.as_ref().and_then(|m| m.os.clone()),
+        claude_version: machine.as_ref().and_then(|m| m.claude_version.clone()),
         last_seen: Some(chrono::Utc::now().to_rfc3339()),
         status: "online".to_string(),
-        created_at: None,
     };
-    if let Err(e) = state.db.upsert_cli_client(&cli_client).await {
-        tracing::error!("Failed to upsert cli_client: {}", e);
+    match state.db.upsert_cli_client(&cli_client).await {
+        Ok(true) => {}
+        Ok(false) => {
+            // The machine id belongs to another user; track this connection on its own row
+            tracing::warn!("Machine {} is registered to another user, using connection id {}", client_row_id, cli_id);
+            client_row_id = cli_id;
+            cli_client.id = cli_id.to_string();
+            if let Err(e) = state.db.upsert_cli_client(&cli_client).await {
+                tracing::error!("Failed to upsert cli_client: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("Failed to upsert cli_client: {}", e),
     }
 
     // Track last activity for timeout detection
@@ -182,7 +210,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
             // Handle outgoing messages from channel
             Some(msg) = rx.recv() => {
                 let text = serde_json::to_string(&msg).unwrap();
-                if sender.send(Message::Text(text.into())).await.is_err() {
+                if sender.send(Message::Text(text)).await.is_err() {
                     tracing::warn!("CLI {} send failed, closing connection", cli_id);
                     break;
                 }
@@ -197,7 +225,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                 }
 
                 // Send ping frame
-                if sender.send(Message::Ping(vec![].into())).await.is_err() {
+                if sender.send(Message::Ping(vec![])).await.is_err() {
                     tracing::warn!("CLI {} ping failed, closing connection", cli_id);
                     break;
                 }
@@ -208,137 +236,22 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                 match msg {
                     Some(Ok(Message::Text(text))) => {
                         last_activity = Instant::now();
-                        let parsed: Result<CliToServer, _> = serde_json::from_str(&text);
-                        match parsed {
-                            Ok(CliToServer::SessionStart {
-                                session_id,
-                                working_dir,
-                                hostname,
-                                pane_type: _,
-                            }) => {
-                                // CLI is starting a local session (hybrid mode)
-                                state.sessions.create_cli_session(session_id, cli_id);
-
-                                // Persist session to database
-                                let session = crate::db::Session {
-                                    id: session_id.to_string(),
-                                    user_id: user_id.to_string(),
-                                    cli_client_id: Some(cli_id.to_string()),
-                                    working_dir,
-                                    hostname,
-                                    status: "active".to_string(),
-                                    created_at: None,
-                                    updated_at: None,
-                                };
-                                if let Err(e) = state.db.create_session(&session).await {
-                                    tracing::error!("Failed to persist session to database: {}", e);
-                                }
-
-                                tracing::info!("CLI {} started local session {}", cli_id, session_id);
-                            }
-                            Ok(CliToServer::Output {
-                                session_id,
-                                data,
-                                output_type,
-                            }) => {
-                                // Route output to web client (if attached)
-                                state
-                                    .sessions
-                                    .route_to_web(
-                                        &session_id,
-                                        ServerToWeb::Output {
-                                            content: data,
-                                            output_type,
-                                            pane_type: None,
-                                        },
-                                    )
-                                    .await;
-                            }
-                            Ok(CliToServer::StreamMessage { session_id, message, pane_type }) => {
-                                tracing::info!("Received StreamMessage for session {} with pane_type {:?}", session_id, pane_type);
-
-                                // Save message(s) to file storage
-                                for stored_message in stream_message_to_stored(&session_id, &message, pane_type) {
-                                    if let Err(e) = state.storage.append_message(&session_id, &stored_message).await {
-                                        tracing::error!("Failed to save message to file: {}", e);
-                                    }
-                                }
-
-                                // Route structured stream message to web client
-                                let routed = state
-                                    .sessions
-                                    .route_to_web(
-                                        &session_id,
-                                        ServerToWeb::StreamMessage { session_id, message, pane_type },
-                                    )
-                                    .await;
-                                tracing::info!("StreamMessage routed to web: {}", routed);
-                            }
-                            Ok(CliToServer::UserInput { session_id, text, pane_type }) => {
-                                tracing::info!("Received UserInput for session {}: {}", session_id, text);
-                                // Save user input to file storage
-                                let stored_message = crate::storage::StoredMessage {
-                                    id: Uuid::new_v4().to_string(),
-                                    role: "user".to_string(),
-                                    content: text.clone(),
-                                    message_type: "text".to_string(),
-                                    created_at: chrono::Utc::now().to_rfc3339(),
-                                    pane_type: pane_type.map(|p| format!("{:?}", p).to_lowercase()),
-                                };
-                                if let Err(e) = state.storage.append_message(&session_id, &stored_message).await {
-                                    tracing::error!("Failed to save user input to file: {}", e);
+                        match serde_json::from_str::<CliToServer>(&text) {
+                            Ok(msg) => handle_cli_message(&state, cli_id, user_id, msg).await,
+                            Err(e) => tracing::warn!("Failed to parse CLI message: {}", e),
+                        }
+                    }
+                    Some(Ok(Message::Binary(data))) => {
+                        // Compressed batch of messages (only sent after codec negotiation)
+                        last_activity = Instant::now();
+                        match decode_frame::<CliToServer>(&data) {
+                            Ok(batch) => {
+                                tracing::debug!("CLI {} sent batch of {} messages ({} bytes)", cli_id, batch.len(), data.len());
+                                for msg in batch {
+                                    handle_cli_message(&state, cli_id, user_id, msg).await;
                                 }
-
-                                // Forward user input to web client
-                                state
-                                    .sessions
-                                    .route_to_web(
-                                        &session_id,
-                                        ServerToWeb::UserInput { session_id, text, pane_type },
-                                    )
-                                    .await;
-                            }
-                            Ok(CliToServer::SessionEnd { session_id, reason }) => {
-                                // Update session status in database
-                                let _ = state.db.update_session_status(&session_id.to_string(), "ended").await;
-
-                                state
-                                    .sessions
-                                    .route_to_web(
-                                        &session_id,
-                                        ServerToWeb::SessionStatus {
-                                            status: shared::SessionStatus::Ended,
-                                        },
-                                    )
-                                    .await;
-                                tracing::info!("Session {} ended: {}", session_id, reason);
-                            }
-                            Ok(CliToServer::Heartbeat) => {
-                                state
-                                    .sessions
-                                    .send_to_cli(&cli_id, ServerToCli::Heartbeat)
-                                    .await;
-                            }
-                            Ok(CliToServer::DeadloopStatus { session_id, is_paused }) => {
-                                // Forward deadloop status to web clients
-                                tracing::info!("Deadloop status for session {}: paused={}", session_id, is_paused);
-                                state
-                                    .sessions
-                                    .route_to_web(
-                                        &session_id,
-                                        ServerToWeb::DeadloopStatus {
-                                            session_id,
-                                            is_paused,
-                                        },
-                                    )
-                                    .await;
-                            }
-                            Ok(CliToServer::Register { .. }) => {
-                                // Already registered, ignore
-                            }
-                            Err(e) => {
-                                tracing::warn!("Failed to parse CLI message: {}", e);
                             }
+                            Err(e) => tracing::warn!("Failed to decode CLI frame: {}", e),
                         }
                     }
                     Some(Ok(Message::Pong(_))) => {
@@ -354,10 +267,6 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                         tracing::info!("CLI {} sent close frame", cli_id);
                         break;
                     }
-                    Some(Ok(_)) => {
-                        // Binary or other message types - still counts as activity
-                        last_activity = Instant::now();
-                    }
                     Some(Err(e)) => {
                         tracing::warn!("CLI {} WebSocket error: {}", cli_id, e);
                         break;
@@ -381,10 +290,448 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     }
 
     state.sessions.unregister_cli(&cli_id);
-    let _ = state.db.update_cli_client_status(&cli_id.to_string(), "offline").await;
+    // Another CLI on the same machine may still be connected
+    if !state.sessions.is_machine_online(&client_row_id) {
+        let now = chrono::Utc::now().timestamp();
+        let _ = state.db.mark_cli_client_offline(&client_row_id.to_string(), now).await;
+    }
+    state.webhooks.fire(
+        user_id,
+        WebhookEvent::CliDisconnected,
+        serde_json::json!({
+            "cli_id": cli_id,
+            "machine_id": machine.as_ref().map(|m| m.machine_id),
+            "name": machine.as_ref().and_then(|m| m.display_name()),
+            "sessions": session_ids,
+        }),
+    );
     tracing::info!("CLI client disconnected: {} (marked {} sessions as inactive)", cli_id, session_ids.len());
 }
 
+/// Handle one decoded message from a registered CLI client
+async fn handle_cli_message(state: &AppState, cli_id: Uuid, user_id: Uuid, msg: CliToServer) {
+    match msg {
+        CliToServer::SessionStart {
+            session_id,
+            working_dir,
+            hostname,
+            pane_type: _,
+        } => {
+            // CLI is starting a local session (hybrid mode)
+            state.sessions.create_cli_session(session_id, cli_id);
+
+            state.webhooks.fire(
+                user_id,
+                WebhookEvent::SessionStart,
+                serde_json::json!({
+                    "session_id": session_id,
+                    "cli_id": cli_id,
+                    "working_dir": working_dir,
+                    "hostname": hostname,
+                }),
+            );
+
+            // Persist session to database
+            let session = crate::db::Session {
+                id: session_id.to_string(),
+                user_id: user_id.to_string(),
+                cli_client_id: Some(cli_id.to_string()),
+                working_dir,
+                hostname,
+                status: "active".to_string(),
+                created_at: None,
+            };
+            if let Err(e) = state.db.create_session(&session).await {
+                tracing::error!("Failed to persist session to database: {}", e);
+            }
+
+            tracing::info!("CLI {} started local session {}", cli_id, session_id);
+        }
+        CliToServer::Output {
+            session_id,
+            data,
+            output_type,
+        } => {
+            // Route output to web client (if attached)
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::Output {
+                        content: data,
+                        output_type,
+                        pane_type: None,
+                    },
+                )
+                .await;
+        }
+        CliToServer::StreamMessage { session_id, mut message, pane_type } => {
+            tracing::info!("Received StreamMessage for session {} with pane_type {:?}", session_id, pane_type);
+
+            // Keep huge tool outputs out of messages.jsonl and web payloads
+            offload_large_tool_results(state, &session_id, &mut message).await;
+
+            // A result closes one iteration of the loop (or one chat turn)
+            if let shared::ClaudeStreamMessage::Result { subtype, result, total_cost_usd, duration_ms, is_error, .. } = &message {
+                if pane_type != Some(PaneType::Interactive) && owns_session(state, &session_id, user_id).await {
+                    let now = chrono::Utc::now().timestamp();
+                    if let Err(e) = state
+                        .db
+                        .record_iteration(&session_id.to_string(), *is_error, *total_cost_usd, *duration_ms as i64, now)
+                        .await
+                    {
+                        tracing::error!("Failed to record iteration for session {}: {}", session_id, e);
+                    }
+                }
+                state.webhooks.fire(
+                    user_id,
+                    WebhookEvent::Result,
+                    serde_json::json!({
+                        "session_id": session_id,
+                        "subtype": subtype,
+                        "is_error": is_error,
+                        "result": result,
+                        "total_cost_usd": total_cost_usd,
+                        "duration_ms": duration_ms,
+                    }),
+                );
+            }
+
+            // Save message(s) to file storage
+            for stored_message in stream_message_to_stored(&session_id, &message, pane_type) {
+                if let Err(e) = state.storage.append_message(&session_id, &stored_message).await {
+                    tracing::error!("Failed to save message to file: {}", e);
+                }
+            }
+
+            // Route structured stream message to web client
+            let routed = state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::StreamMessage { session_id, message, pane_type },
+                )
+                .await;
+            tracing::info!("StreamMessage routed to web: {}", routed);
+        }
+        CliToServer::UserInput { session_id, text, pane_type } => {
+            tracing::info!("Received UserInput for session {}: {}", session_id, text);
+            // Save user input to file storage
+            let stored_message = crate::storage::StoredMessage {
+                id: Uuid::new_v4().to_string(),
+                role: "user".to_string(),
+                content: text.clone(),
+                message_type: "text".to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                pane_type: pane_type.map(|p| format!("{:?}", p).to_lowercase()),
+                blob: None,
+            };
+            if let Err(e) = state.storage.append_message(&session_id, &stored_message).await {
+                tracing::error!("Failed to save user input to file: {}", e);
+            }
+
+            // Forward user input to web client
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::UserInput { session_id, text, pane_type },
+                )
+                .await;
+        }
+        CliToServer::SessionEnd { session_id, reason, pane_type: Some(pane_type) } => {
+            // One pane stopped; the session and its other pane carry on
+            tracing::info!("{:?} pane of session {} ended: {}", pane_type, session_id, reason);
+            state.webhooks.fire(
+                user_id,
+                WebhookEvent::SessionEnd,
+                serde_json::json!({ "session_id": session_id, "reason": reason, "pane_type": pane_type }),
+            );
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::Output {
+                        content: format!("[Pane ended: {}]", reason),
+                        output_type: shared::OutputType::Text,
+                        pane_type: Some(pane_type),
+                    },
+                )
+                .await;
+        }
+        CliToServer::SessionEnd { session_id, reason, pane_type: None } => {
+            // Update session status in database
+            let _ = state.db.update_session_status(&session_id.to_string(), "ended").await;
+            state.webhooks.fire(
+                user_id,
+                WebhookEvent::SessionEnd,
+                serde_json::json!({ "session_id": session_id, "reason": reason }),
+            );
+
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::SessionStatus {
+                        status: shared::SessionStatus::Ended,
+                    },
+                )
+                .await;
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::Output {
+                        content: format!("[Session ended: {}]", reason),
+                        output_type: shared::OutputType::Text,
+                        pane_type: None,
+                    },
+                )
+                .await;
+            tracing::info!("Session {} ended: {}", session_id, reason);
+        }
+        CliToServer::Heartbeat => {
+            state
+                .sessions
+                .send_to_cli(&cli_id, ServerToCli::Heartbeat)
+                .await;
+        }
+        CliToServer::DeadloopStatus { session_id, is_paused, reason } => {
+            // Forward deadloop status to web clients
+            tracing::info!("Deadloop status for session {}: paused={} ({:?})", session_id, is_paused, reason);
+            state.webhooks.fire(
+                user_id,
+                WebhookEvent::DeadloopStatus,
+                serde_json::json!({ "session_id": session_id, "is_paused": is_paused, "reason": reason }),
+            );
+            if let (true, Some(reason)) = (is_paused, reason) {
+                state.notifier.deadloop_paused(user_id, session_id, reason);
+            }
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::DeadloopStatus {
+                        session_id,
+                        is_paused,
+                        reason,
+                    },
+                )
+                .await;
+        }
+        CliToServer::IterationTimedOut { session_id, reason, duration_ms } => {
+            tracing::info!("Iteration in session {} timed out ({:?}) after {}ms", session_id, reason, duration_ms);
+            let now = chrono::Utc::now().timestamp();
+            if let Err(e) = state
+                .db
+                .record_timed_out_iteration(&session_id.to_string(), duration_ms as i64, now)
+                .await
+            {
+                tracing::error!("Failed to record iteration for session {}: {}", session_id, e);
+            }
+            state.webhooks.fire(
+                user_id,
+                WebhookEvent::IterationTimeout,
+                serde_json::json!({ "session_id": session_id, "reason": reason, "duration_ms": duration_ms }),
+            );
+
+            let what = match reason {
+                IterationTimeout::Duration => "ran past its time limit",
+                IterationTimeout::Idle => "produced no output for too long",
+            };
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::Output {
+                        content: format!("[Iteration stopped: {} after {}s]", what, duration_ms / 1000),
+                        output_type: shared::OutputType::Error,
+                        pane_type: Some(PaneType::Deadloop),
+                    },
+                )
+                .await;
+        }
+        CliToServer::SandboxViolation { session_id, kind, detail } => {
+            tracing::info!("Sandbox in session {} reported {:?}: {}", session_id, kind, detail);
+            if !owns_session(state, &session_id, user_id).await {
+                return;
+            }
+            let now = chrono::Utc::now().timestamp();
+            let kind_name = serde_json::to_value(kind).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+            if let Err(e) = state.db.record_sandbox_violation(&session_id.to_string(), &kind_name, &detail, now).await {
+                tracing::error!("Failed to record sandbox violation for session {}: {}", session_id, e);
+            }
+            state.webhooks.fire(
+                user_id,
+                WebhookEvent::SandboxViolation,
+                serde_json::json!({ "session_id": session_id, "kind": kind, "detail": detail }),
+            );
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::Output {
+                        content: format!("[Sandbox: {}]", detail),
+                        output_type: shared::OutputType::Error,
+                        pane_type: Some(PaneType::Deadloop),
+                    },
+                )
+                .await;
+        }
+        CliToServer::CommitsMade { session_id, summaries } => {
+            if !owns_session(state, &session_id, user_id).await {
+                return;
+            }
+            let now = chrono::Utc::now().timestamp();
+            for summary in summaries {
+                let summary: String = summary.chars().take(crate::notifications::MAX_SUMMARY_CHARS).collect();
+                if let Err(e) = state.db.record_commit(&session_id.to_string(), &summary, now).await {
+                    tracing::error!("Failed to record commit for session {}: {}", session_id, e);
+                }
+            }
+        }
+        CliToServer::WorkflowStage { session_id, stage, index, total } => {
+            tracing::info!("Session {} entered workflow stage {} ({}/{})", session_id, stage, index + 1, total);
+            state
+                .sessions
+                .route_to_web(&session_id, ServerToWeb::WorkflowStage { session_id, stage, index, total })
+                .await;
+        }
+        CliToServer::VerificationOutput { session_id, line } => {
+            state
+                .sessions
+                .route_to_web(&session_id, ServerToWeb::VerificationOutput { session_id, line })
+                .await;
+        }
+        CliToServer::HookOutput { session_id, hook, line } => {
+            state
+                .sessions
+                .route_to_web(&session_id, ServerToWeb::HookOutput { session_id, hook, line })
+                .await;
+        }
+        CliToServer::ClaudeSessionRotated {
+            session_id,
+            pane_type,
+            previous_claude_session_id,
+            claude_session_id,
+            reason,
+            summary,
+        } => {
+            tracing::info!(
+                "Session {} {:?} pane moved from Claude session {} to {}: {}",
+                session_id,
+                pane_type,
+                previous_claude_session_id,
+                claude_session_id,
+                reason
+            );
+            // Kept in the history so reloading the conversation shows the boundary
+            let boundary = serde_json::json!({
+                "reason": reason,
+                "summary": summary,
+                "claude_session_id": claude_session_id,
+            });
+            let stored_message = crate::storage::StoredMessage {
+                id: Uuid::new_v4().to_string(),
+                role: "system".to_string(),
+                content: boundary.to_string(),
+                message_type: "session_boundary".to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                pane_type: Some(format!("{:?}", pane_type).to_lowercase()),
+                blob: None,
+            };
+            if let Err(e) = state.storage.append_message(&session_id, &stored_message).await {
+                tracing::error!("Failed to save session boundary to file: {}", e);
+            }
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::ClaudeSessionRotated {
+                        session_id,
+                        pane_type,
+                        previous_claude_session_id,
+                        claude_session_id,
+                        reason,
+                        summary,
+                    },
+                )
+                .await;
+        }
+        CliToServer::VerificationFinished { session_id, command, passed, exit_code, timed_out, duration_ms } => {
+            tracing::info!(
+                "Verification `{}` in session {} {} after {}ms (exit code {:?}, timed out: {})",
+                command,
+                session_id,
+                if passed { "passed" } else { "failed" },
+                duration_ms,
+                exit_code,
+                timed_out
+            );
+            state
+                .sessions
+                .route_to_web(
+                    &session_id,
+                    ServerToWeb::VerificationFinished { session_id, command, passed, exit_code, timed_out, duration_ms },
+                )
+                .await;
+        }
+        CliToServer::Register { .. } => {
+            // Already registered, ignore
+        }
+    }
+}
+
+/// Whether `user_id` owns the session, so a CLI can only add to its own
+/// user's history
+async fn owns_session(state: &AppState, session_id: &Uuid, user_id: Uuid) -> bool {
+    match state.db.get_session_owner(&session_id.to_string()).await {
+        Ok(Some(owner)) if owner == user_id.to_string() => true,
+        Ok(_) => {
+            tracing::warn!("User {} reported on session {} they don't own", user_id, session_id);
+            false
+        }
+        Err(e) => {
+            tracing::error!("Failed to look up the owner of session {}: {}", session_id, e);
+            false
+        }
+    }
+}
+
+/// Replace oversized tool results with previews, storing the full text as blobs.
+/// Tool results show up both in assistant and user (tool output) messages.
+async fn offload_large_tool_results(state: &AppState, session_id: &Uuid, message: &mut shared::ClaudeStreamMessage) {
+    use shared::{ClaudeContentBlock, ClaudeStreamMessage};
+
+    let (blocks, tool_use_result) = match message {
+        ClaudeStreamMessage::Assistant { message, .. } => (&mut message.content, None),
+        ClaudeStreamMessage::User { message, tool_use_result, .. } => (&mut message.content, Some(tool_use_result)),
+        _ => return,
+    };
+
+    let mut offloaded = false;
+    for block in blocks.iter_mut() {
+        if let ClaudeContentBlock::ToolResult { content, blob, .. } = block {
+            match state.storage.offload_if_large(session_id, content).await {
+                Ok(Some(blob_ref)) => {
+                    tracing::debug!("Offloaded {} byte tool result to blob {}", blob_ref.size, blob_ref.hash);
+                    *blob = Some(blob_ref);
+                    offloaded = true;
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to store tool result blob: {}", e),
+            }
+        }
+    }
+
+    // The raw tool_use_result duplicates the output we just moved out of line
+    if offloaded {
+        if let Some(tool_use_result) = tool_use_result {
+            *tool_use_result = None;
+        }
+    }
+}
+
 /// Convert a ClaudeStreamMessage to StoredMessages for file storage
 /// Returns a Vec because assistant messages may have multiple content blocks
 fn stream_message_to_stored(
@@ -410,6 +757,7 @@ fn stream_message_to_stored(
                             message_type: "text".to_string(),
                             created_at: chrono::Utc::now().to_rfc3339(),
                             pane_type: pane_type_str.clone(),
+                            blob: None,
                         });
                     }
                     ClaudeContentBlock::ToolUse { id, name, input } => {
@@ -426,9 +774,10 @@ fn stream_message_to_stored(
                             message_type: "tool_use".to_string(),
                             created_at: chrono::Utc::now().to_rfc3339(),
                             pane_type: pane_type_str.clone(),
+                            blob: None,
                         });
                     }
-                    ClaudeContentBlock::ToolResult { tool_use_id, content, is_error } => {
+                    ClaudeContentBlock::ToolResult { tool_use_id, content, is_error, blob } => {
                         // Store tool_result with structured JSON content
                         let result_data = serde_json::json!({
                             "tool_use_id": tool_use_id,
@@ -442,6 +791,7 @@ fn stream_message_to_stored(
                             message_type: "tool_result".to_string(),
                             created_at: chrono::Utc::now().to_rfc3339(),
                             pane_type: pane_type_str.clone(),
+                            blob: blob.clone(),
                         });
                     }
                 }
@@ -455,6 +805,7 @@ fn stream_message_to_stored(
                 message_type: "result".to_string(),
                 created_at: chrono::Utc::now().to_rfc3339(),
                 pane_type: pane_type_str,
+                blob: None,
             });
         }
         _ => {} // Skip system and user messages for now
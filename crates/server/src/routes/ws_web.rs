@@ -34,7 +34,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     let send_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
             let text = serde_json::to_string(&msg).unwrap();
-            if sender.send(Message::Text(text.into())).await.is_err() {
+            if sender.send(Message::Text(text)).await.is_err() {
                 break;
             }
         }
@@ -58,6 +58,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                             match Uuid::parse_str(&claims.sub) {
                                 Ok(uid) => {
                                     user_id = Some(uid);
+                                    state.sessions.authenticate_web(connection_id, uid);
                                     tracing::info!("Web client {} authenticated as user {}", connection_id, uid);
                                     state
                                         .sessions
@@ -107,7 +108,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                     };
 
                     // Only return CLI clients owned by this user
-                    let clients = state.sessions.get_cli_clients_info_for_user(&uid);
+                    let clients = state.sessions.get_cli_clients_info_for_user(&uid).await;
                     state
                         .sessions
                         .send_to_web(
@@ -118,7 +119,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                 }
                 Ok(WebToServer::StartSession { cli_client_id }) => {
                     // Require authentication
-                    let Some(uid) = user_id else {
+                    if user_id.is_none() {
                         state
                             .sessions
                             .send_to_web(
@@ -129,7 +130,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                             )
                             .await;
                         continue;
-                    };
+                    }
 
                     let new_session_id = Uuid::new_v4();
                     session_id = Some(new_session_id);
@@ -137,7 +138,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                     // Create session in manager
                     state
                         .sessions
-                        .create_session(new_session_id, uid, connection_id);
+                        .create_session(new_session_id, connection_id);
 
                     // Try to assign a CLI client
                     let cli_id = cli_client_id.or_else(|| {
@@ -211,6 +212,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                 message_type: "text".to_string(),
                                 created_at: chrono::Utc::now().to_rfc3339(),
                                 pane_type: pane_type.map(|p| format!("{:?}", p).to_lowercase()),
+                                blob: None,
                             };
                             if let Err(e) = state.storage.append_message(&sid, &stored_message).await {
                                 tracing::error!("Failed to save user input to file: {}", e);
@@ -249,7 +251,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                             .await;
                     }
                 }
-                Ok(WebToServer::Signal { signal }) => {
+                Ok(WebToServer::Signal { signal, pane_type }) => {
                     if let Some(sid) = session_id {
                         state
                             .sessions
@@ -258,6 +260,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                 ServerToCli::Signal {
                                     session_id: sid,
                                     signal,
+                                    pane_type,
                                 },
                             )
                             .await;
@@ -305,6 +308,22 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                             .await;
                     }
                 }
+                Ok(WebToServer::SetModel { pane_type, model }) => {
+                    if let Some(sid) = session_id {
+                        tracing::info!("Setting model for session {} ({:?}) to {:?}", sid, pane_type, model);
+                        state
+                            .sessions
+                            .route_to_cli(
+                                &sid,
+                                ServerToCli::SetModel {
+                                    session_id: sid,
+                                    pane_type,
+                                    model,
+                                },
+                            )
+                            .await;
+                    }
+                }
                 Ok(WebToServer::ResumeDeadloop) => {
                     if let Some(sid) = session_id {
                         tracing::info!("Resuming deadloop for session {}", sid);
@@ -400,6 +419,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                         message_type: m.message_type,
                                         created_at: Some(m.created_at),
                                         pane_type: m.pane_type,
+                                        blob: m.blob,
                                     })
                                     .collect();
                                 (messages, has_more)
@@ -528,6 +548,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                                     message_type: m.message_type,
                                     created_at: Some(m.created_at),
                                     pane_type: m.pane_type,
+                                    blob: m.blob,
                                 })
                                 .collect();
                             state
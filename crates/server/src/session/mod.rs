@@ -1,9 +1,22 @@
 use dashmap::DashMap;
-use shared::{CliClientInfo, CliClientStatus, ServerToCli, ServerToWeb};
+use shared::{CliClientInfo, CliClientStatus, MachineInfo, ServerToCli, ServerToWeb};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
-/// Manages active sessions and routes messages between web and CLI clients
+use crate::backplane::{Backplane, BackplaneEvent, Envelope, LocalBackplane, RemoteCli};
+use crate::db::{CliClient, Database};
+
+/// How often each instance re-announces its CLIs to the others
+const PRESENCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Forget another instance's CLIs if it hasn't announced them for this long
+const PRESENCE_TTL: Duration = Duration::from_secs(30);
+
+/// Manages active sessions and routes messages between web and CLI clients.
+/// Connections live on this instance; messages for connections on other
+/// instances go through the backplane.
 pub struct SessionManager {
     /// Map of session ID -> session state
     sessions: DashMap<Uuid, SessionState>,
@@ -11,36 +24,181 @@ pub struct SessionManager {
     cli_senders: DashMap<Uuid, mpsc::Sender<ServerToCli>>,
     /// Map of web connection ID -> sender to web
     web_senders: DashMap<Uuid, mpsc::Sender<ServerToWeb>>,
+    /// Map of web connection ID -> authenticated user ID
+    web_users: DashMap<Uuid, Uuid>,
     /// Map of CLI client ID -> list of session IDs
     cli_sessions: DashMap<Uuid, Vec<Uuid>>,
     /// Map of CLI client ID -> user ID (owner)
     cli_users: DashMap<Uuid, Uuid>,
+    /// Map of CLI client ID -> machine it runs on (if the CLI reported one)
+    cli_machines: DashMap<Uuid, MachineInfo>,
+    /// Identifies this instance on the backplane
+    instance_id: Uuid,
+    backplane: Arc<dyn Backplane>,
+    /// Events waiting to be published, in order
+    outbound: mpsc::UnboundedSender<BackplaneEvent>,
+    outbound_rx: Mutex<Option<mpsc::UnboundedReceiver<BackplaneEvent>>>,
+    /// Map of other instance ID -> (last announcement, CLIs connected there)
+    remote_clis: DashMap<Uuid, (Instant, Vec<RemoteCli>)>,
+    /// Known machines, including offline ones, for the CLI clients list
+    db: Option<Database>,
 }
 
 #[derive(Debug)]
 pub struct SessionState {
-    pub session_id: Uuid,
-    pub user_id: Uuid,
     pub cli_client_id: Option<Uuid>,
     pub web_connection_id: Option<Uuid>,
 }
 
 impl SessionManager {
-    pub fn new() -> Self {
+    pub fn new(backplane: Arc<dyn Backplane>) -> Self {
+        let (outbound, outbound_rx) = mpsc::unbounded_channel();
         Self {
             sessions: DashMap::new(),
             cli_senders: DashMap::new(),
             web_senders: DashMap::new(),
+            web_users: DashMap::new(),
             cli_sessions: DashMap::new(),
             cli_users: DashMap::new(),
+            cli_machines: DashMap::new(),
+            instance_id: Uuid::new_v4(),
+            backplane,
+            outbound,
+            outbound_rx: Mutex::new(Some(outbound_rx)),
+            remote_clis: DashMap::new(),
+            db: None,
+        }
+    }
+
+    /// List machines stored in `db` alongside the live connections
+    pub fn with_database(mut self, db: Database) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Start exchanging messages and presence with other instances
+    pub fn start(self: &Arc<Self>) {
+        let Some(mut outbound_rx) = self.outbound_rx.lock().unwrap().take() else {
+            return; // already started
+        };
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = outbound_rx.recv().await {
+                let envelope = Envelope { origin: manager.instance_id, event };
+                if let Err(e) = manager.backplane.publish(envelope).await {
+                    tracing::warn!("Failed to publish to backplane: {}", e);
+                }
+            }
+        });
+
+        let manager = self.clone();
+        let mut inbound = self.backplane.subscribe();
+        tokio::spawn(async move {
+            while let Some(envelope) = inbound.recv().await {
+                if envelope.origin != manager.instance_id {
+                    manager.handle_backplane_event(envelope.origin, envelope.event).await;
+                }
+            }
+        });
+
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRESENCE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else { break };
+                manager.publish_presence();
+                let before = manager.remote_clis.len();
+                manager.remote_clis.retain(|_, (seen, _)| seen.elapsed() < PRESENCE_TTL);
+                if manager.remote_clis.len() != before {
+                    manager.notify_web_cli_clients();
+                }
+            }
+        });
+    }
+
+    async fn handle_backplane_event(&self, origin: Uuid, event: BackplaneEvent) {
+        match event {
+            BackplaneEvent::ToCli { cli_id, msg } => {
+                if !self.cli_senders.contains_key(&cli_id) {
+                    return;
+                }
+                // Track sessions assigned from another instance so they are
+                // routed and cleaned up here like local ones
+                if let ServerToCli::SessionAssigned { session_id, .. } = &msg {
+                    self.create_cli_session(*session_id, cli_id);
+                }
+                self.send_to_local_cli(&cli_id, msg).await;
+            }
+            BackplaneEvent::SessionToCli { session_id, msg } => {
+                let owner = self
+                    .cli_sessions
+                    .iter()
+                    .find(|entry| entry.value().contains(&session_id))
+                    .map(|entry| *entry.key());
+                if let Some(cli_id) = owner {
+                    self.send_to_local_cli(&cli_id, msg).await;
+                }
+            }
+            BackplaneEvent::SessionToWeb { session_id, msg } => {
+                let web_id = self.sessions.get(&session_id).and_then(|s| s.web_connection_id);
+                if let Some(web_id) = web_id {
+                    self.send_to_web(&web_id, msg).await;
+                }
+            }
+            BackplaneEvent::Presence { clients } => {
+                self.remote_clis.insert(origin, (Instant::now(), clients));
+                self.notify_web_cli_clients();
+            }
         }
     }
 
+    fn publish(&self, event: BackplaneEvent) {
+        let _ = self.outbound.send(event);
+    }
+
+    /// Announce the CLIs connected to this instance
+    fn publish_presence(&self) {
+        let clients = self
+            .cli_senders
+            .iter()
+            .filter_map(|entry| {
+                let user_id = *self.cli_users.get(entry.key())?;
+                Some(RemoteCli { user_id, info: self.cli_client_info(*entry.key()) })
+            })
+            .collect();
+        self.publish(BackplaneEvent::Presence { clients });
+    }
+
+    /// Whether other instances are sharing the backplane
+    fn has_peers(&self) -> bool {
+        !self.remote_clis.is_empty()
+    }
+
+    /// CLIs connected to other instances that have announced themselves recently
+    fn remote_cli_list(&self) -> Vec<RemoteCli> {
+        self.remote_clis
+            .iter()
+            .filter(|entry| entry.value().0.elapsed() < PRESENCE_TTL)
+            .flat_map(|entry| entry.value().1.clone())
+            .collect()
+    }
+
     // CLI client management
-    pub fn register_cli(&self, cli_id: Uuid, user_id: Uuid, sender: mpsc::Sender<ServerToCli>) {
+    pub fn register_cli(
+        &self,
+        cli_id: Uuid,
+        user_id: Uuid,
+        machine: Option<MachineInfo>,
+        sender: mpsc::Sender<ServerToCli>,
+    ) {
         self.cli_senders.insert(cli_id, sender);
         self.cli_sessions.insert(cli_id, Vec::new());
         self.cli_users.insert(cli_id, user_id);
+        if let Some(machine) = machine {
+            self.cli_machines.insert(cli_id, machine);
+        }
         tracing::info!("CLI client registered: {} (user: {})", cli_id, user_id);
         // Broadcast updated client list to all web clients
         self.broadcast_cli_clients_update();
@@ -49,6 +207,7 @@ impl SessionManager {
     pub fn unregister_cli(&self, cli_id: &Uuid) {
         self.cli_senders.remove(cli_id);
         self.cli_users.remove(cli_id);
+        self.cli_machines.remove(cli_id);
         if let Some((_, session_ids)) = self.cli_sessions.remove(cli_id) {
             for session_id in session_ids {
                 if let Some(mut session) = self.sessions.get_mut(&session_id) {
@@ -67,8 +226,15 @@ impl SessionManager {
         tracing::info!("Web client registered: {}", connection_id);
     }
 
+    /// Record the user a web connection authenticated as, so it only sees
+    /// that user's CLI clients
+    pub fn authenticate_web(&self, connection_id: Uuid, user_id: Uuid) {
+        self.web_users.insert(connection_id, user_id);
+    }
+
     pub fn unregister_web(&self, connection_id: &Uuid) {
         self.web_senders.remove(connection_id);
+        self.web_users.remove(connection_id);
         // Find and update any sessions using this web connection
         for mut session in self.sessions.iter_mut() {
             if session.web_connection_id == Some(*connection_id) {
@@ -79,10 +245,8 @@ impl SessionManager {
     }
 
     // Session management
-    pub fn create_session(&self, session_id: Uuid, user_id: Uuid, web_connection_id: Uuid) {
+    pub fn create_session(&self, session_id: Uuid, web_connection_id: Uuid) {
         let state = SessionState {
-            session_id,
-            user_id,
             cli_client_id: None,
             web_connection_id: Some(web_connection_id),
         };
@@ -116,8 +280,6 @@ impl SessionManager {
             );
         } else {
             let state = SessionState {
-                session_id,
-                user_id: Uuid::nil(), // No user for CLI-initiated sessions
                 cli_client_id: Some(cli_id),
                 web_connection_id: None,
             };
@@ -151,8 +313,6 @@ impl SessionManager {
         // Session not in memory - create it (happens after server restart or reconnection)
         tracing::info!("Creating session {} in memory for web attach (cli: {:?})", session_id, cli_client_id);
         let state = SessionState {
-            session_id: *session_id,
-            user_id: Uuid::nil(), // Will be updated when needed
             cli_client_id,
             web_connection_id: Some(web_connection_id),
         };
@@ -185,17 +345,11 @@ impl SessionManager {
             .unwrap_or_default()
     }
 
-    pub fn get_session(&self, session_id: &Uuid) -> Option<SessionState> {
-        self.sessions.get(session_id).map(|s| SessionState {
-            session_id: s.session_id,
-            user_id: s.user_id,
-            cli_client_id: s.cli_client_id,
-            web_connection_id: s.web_connection_id,
-        })
-    }
-
-    /// Check if a session has an active CLI client connected
+    /// Check if a session has an active CLI client connected (to any instance)
     pub fn is_session_active(&self, session_id: &Uuid) -> bool {
+        if self.remote_cli_list().iter().any(|c| c.info.active_session == Some(*session_id)) {
+            return true;
+        }
         // Check if any connected CLI client has this session as their active session
         for entry in self.cli_sessions.iter() {
             let cli_id = entry.key();
@@ -210,132 +364,414 @@ impl SessionManager {
     }
 
     // Message routing
+    async fn send_to_local_cli(&self, cli_id: &Uuid, msg: ServerToCli) -> bool {
+        let sender = self.cli_senders.get(cli_id).map(|s| s.clone());
+        match sender {
+            Some(sender) => sender.send(msg).await.is_ok(),
+            None => false,
+        }
+    }
+
+    /// Send to a CLI on this instance, or through the backplane if another
+    /// instance has it
     pub async fn send_to_cli(&self, cli_id: &Uuid, msg: ServerToCli) -> bool {
-        if let Some(sender) = self.cli_senders.get(cli_id) {
-            if sender.send(msg).await.is_ok() {
-                return true;
-            }
+        if self.cli_senders.contains_key(cli_id) {
+            return self.send_to_local_cli(cli_id, msg).await;
+        }
+        if self.remote_cli_list().iter().any(|c| c.info.id == *cli_id) {
+            self.publish(BackplaneEvent::ToCli { cli_id: *cli_id, msg });
+            return true;
         }
         false
     }
 
     pub async fn send_to_web(&self, connection_id: &Uuid, msg: ServerToWeb) -> bool {
-        if let Some(sender) = self.web_senders.get(connection_id) {
-            if sender.send(msg).await.is_ok() {
-                return true;
-            }
+        let sender = self.web_senders.get(connection_id).map(|s| s.clone());
+        match sender {
+            Some(sender) => sender.send(msg).await.is_ok(),
+            None => false,
         }
-        false
     }
 
     pub async fn route_to_cli(&self, session_id: &Uuid, msg: ServerToCli) -> bool {
-        if let Some(session) = self.sessions.get(session_id) {
-            if let Some(cli_id) = session.cli_client_id {
+        let cli_id = self.sessions.get(session_id).map(|s| s.cli_client_id);
+        match cli_id {
+            Some(Some(cli_id)) => {
                 let cli_exists = self.cli_senders.contains_key(&cli_id);
                 tracing::debug!(
                     "route_to_cli: session {} -> cli {} (cli exists in senders: {})",
                     session_id, cli_id, cli_exists
                 );
-                return self.send_to_cli(&cli_id, msg).await;
-            } else {
-                tracing::warn!("route_to_cli: session {} has no cli_client_id", session_id);
+                if self.send_to_cli(&cli_id, msg.clone()).await {
+                    return true;
+                }
             }
-        } else {
-            tracing::warn!("route_to_cli: session {} not found in memory", session_id);
+            Some(None) => tracing::warn!("route_to_cli: session {} has no cli_client_id", session_id),
+            None => tracing::warn!("route_to_cli: session {} not found in memory", session_id),
+        }
+
+        // The CLI may have reconnected to another instance under a new ID
+        if self.remote_cli_list().iter().any(|c| c.info.active_session == Some(*session_id)) {
+            self.publish(BackplaneEvent::SessionToCli { session_id: *session_id, msg });
+            return true;
         }
         false
     }
 
     pub async fn route_to_web(&self, session_id: &Uuid, msg: ServerToWeb) -> bool {
-        if let Some(session) = self.sessions.get(session_id) {
-            if let Some(web_id) = session.web_connection_id {
+        let web_id = self.sessions.get(session_id).map(|s| s.web_connection_id);
+        match web_id {
+            Some(Some(web_id)) if self.web_senders.contains_key(&web_id) => {
                 tracing::debug!("Routing message to web client {} for session {}", web_id, session_id);
                 return self.send_to_web(&web_id, msg).await;
-            } else {
-                tracing::debug!("No web client attached to session {}", session_id);
             }
-        } else {
-            tracing::debug!("Session {} not found for routing", session_id);
+            Some(_) => tracing::debug!("No web client attached to session {} on this instance", session_id),
+            None => tracing::debug!("Session {} not found for routing", session_id),
+        }
+
+        // The web client may be watching from another instance
+        if self.has_peers() {
+            self.publish(BackplaneEvent::SessionToWeb { session_id: *session_id, msg });
         }
         false
     }
 
     // Get available CLI clients for a user
     pub fn get_online_cli_ids(&self) -> Vec<Uuid> {
-        self.cli_senders.iter().map(|r| *r.key()).collect()
+        let mut ids: Vec<Uuid> = self.cli_senders.iter().map(|r| *r.key()).collect();
+        ids.extend(self.remote_cli_list().into_iter().map(|c| c.info.id));
+        ids
     }
 
-    /// Get CLI clients info for the web UI (all clients)
-    pub fn get_cli_clients_info(&self) -> Vec<CliClientInfo> {
-        self.cli_senders
-            .iter()
-            .map(|entry| {
-                let cli_id = *entry.key();
-                // Get active session for this CLI
-                let active_session = self.get_cli_active_session(&cli_id);
-                let is_busy = active_session.is_some();
-
-                CliClientInfo {
-                    id: cli_id,
-                    name: None, // CLI name not tracked yet
-                    status: if is_busy {
-                        CliClientStatus::Busy
-                    } else {
-                        CliClientStatus::Online
-                    },
-                    last_seen: Some(chrono::Utc::now()),
-                    active_session,
-                }
-            })
-            .collect()
+    /// Whether any connected CLI (on any instance) runs on the given machine
+    pub fn is_machine_online(&self, machine_id: &Uuid) -> bool {
+        self.cli_machines.iter().any(|m| m.machine_id == *machine_id)
+            || self
+                .remote_cli_list()
+                .iter()
+                .any(|c| c.info.machine.as_ref().map(|m| m.machine_id) == Some(*machine_id))
     }
 
-    /// Get CLI clients info for a specific user
-    pub fn get_cli_clients_info_for_user(&self, user_id: &Uuid) -> Vec<CliClientInfo> {
+    fn cli_client_info(&self, cli_id: Uuid) -> CliClientInfo {
+        // Get active session for this CLI
+        let active_session = self.get_cli_active_session(&cli_id);
+        let is_busy = active_session.is_some();
+        let machine = self.cli_machines.get(&cli_id).map(|m| m.clone());
+
+        CliClientInfo {
+            id: cli_id,
+            name: machine.as_ref().and_then(|m| m.display_name()).map(String::from),
+            status: if is_busy {
+                CliClientStatus::Busy
+            } else {
+                CliClientStatus::Online
+            },
+            last_seen: Some(chrono::Utc::now()),
+            active_session,
+            machine,
+        }
+    }
+
+    /// CLI clients connected to any instance for a specific user
+    pub fn live_cli_clients_for_user(&self, user_id: &Uuid) -> Vec<CliClientInfo> {
         self.cli_senders
             .iter()
             .filter(|entry| {
                 // Only include CLIs owned by this user
                 self.cli_users.get(entry.key()).map(|u| *u == *user_id).unwrap_or(false)
             })
-            .map(|entry| {
-                let cli_id = *entry.key();
-                // Get active session for this CLI
-                let active_session = self.get_cli_active_session(&cli_id);
-                let is_busy = active_session.is_some();
-
-                CliClientInfo {
-                    id: cli_id,
-                    name: None, // CLI name not tracked yet
-                    status: if is_busy {
-                        CliClientStatus::Busy
-                    } else {
-                        CliClientStatus::Online
-                    },
-                    last_seen: Some(chrono::Utc::now()),
-                    active_session,
-                }
-            })
+            .map(|entry| self.cli_client_info(*entry.key()))
+            .chain(
+                self.remote_cli_list()
+                    .into_iter()
+                    .filter(|c| c.user_id == *user_id)
+                    .map(|c| c.info),
+            )
             .collect()
     }
 
-    /// Broadcast CLI clients list to all connected web clients
+    /// Every machine a user has connected from, with live connections
+    /// merged over the stored rows
+    pub async fn get_cli_clients_info_for_user(&self, user_id: &Uuid) -> Vec<CliClientInfo> {
+        let live = self.live_cli_clients_for_user(user_id);
+        let stored = match &self.db {
+            Some(db) => db.get_cli_clients_for_user(&user_id.to_string()).await.unwrap_or_else(|e| {
+                tracing::warn!("Failed to load CLI clients for {}: {}", user_id, e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        merge_cli_clients(live, stored)
+    }
+
+    /// Broadcast CLI clients list to all connected web clients, here and on
+    /// other instances
     fn broadcast_cli_clients_update(&self) {
-        let clients = self.get_cli_clients_info();
-        let msg = ServerToWeb::CliClients { clients };
+        self.publish_presence();
+        self.notify_web_cli_clients();
+    }
 
+    /// Send each authenticated web client on this instance its user's CLI
+    /// clients list
+    fn notify_web_cli_clients(&self) {
         for entry in self.web_senders.iter() {
+            let Some(user_id) = self.web_users.get(entry.key()).map(|u| *u) else {
+                continue;
+            };
             let sender = entry.value().clone();
-            let msg_clone = msg.clone();
+            let live = self.live_cli_clients_for_user(&user_id);
+            let db = self.db.clone();
             tokio::spawn(async move {
-                let _ = sender.send(msg_clone).await;
+                let stored = match db {
+                    Some(db) => db.get_cli_clients_for_user(&user_id.to_string()).await.unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                let msg = ServerToWeb::CliClients { clients: merge_cli_clients(live, stored) };
+                let _ = sender.send(msg).await;
             });
         }
     }
 }
 
+/// Add the stored machines that have no live connection, as offline.
+/// Live connections are matched to rows by machine id, or by connection id
+/// for CLIs that don't report a machine.
+fn merge_cli_clients(mut live: Vec<CliClientInfo>, stored: Vec<CliClient>) -> Vec<CliClientInfo> {
+    let live_ids: Vec<Uuid> = live
+        .iter()
+        .map(|c| c.machine.as_ref().map(|m| m.machine_id).unwrap_or(c.id))
+        .collect();
+
+    let mut offline: Vec<CliClientInfo> = stored
+        .into_iter()
+        .filter_map(|row| {
+            let id = Uuid::parse_str(&row.id).ok()?;
+            if live_ids.contains(&id) {
+                return None;
+            }
+            let machine = (row.hostname.is_some() || row.os.is_some()).then(|| MachineInfo {
+                machine_id: id,
+                name: row.name.clone(),
+                hostname: row.hostname,
+                os: row.os,
+                claude_version: row.claude_version,
+            });
+            Some(CliClientInfo {
+                id,
+                name: row.name.or_else(|| machine.as_ref().and_then(|m| m.hostname.clone())),
+                // The row is only marked offline once the connection is gone,
+                // so a row without a live connection is offline either way
+                status: CliClientStatus::Offline,
+                last_seen: row.last_seen.as_deref().and_then(parse_timestamp),
+                active_session: None,
+                machine,
+            })
+        })
+        .collect();
+    offline.sort_by_key(|c| std::cmp::Reverse(c.last_seen));
+
+    live.extend(offline);
+    live
+}
+
+/// Parse a stored timestamp, either RFC 3339 or SQL's `CURRENT_TIMESTAMP` (UTC)
+fn parse_timestamp(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc()))
+        .ok()
+}
+
 impl Default for SessionManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(Arc::new(LocalBackplane::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backplane::{redis_stand_in, RedisBackplane};
+
+    /// Poll until `check` holds, re-announcing presence in case the first
+    /// announcement went out before the other side subscribed
+    async fn eventually(announcer: &SessionManager, check: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !check() {
+                announcer.publish_presence();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("condition not met in time");
+    }
+
+    /// A web client on instance `a` drives a CLI connected to instance `b`
+    async fn exercise(a: Arc<SessionManager>, b: Arc<SessionManager>) {
+        a.start();
+        b.start();
+
+        let user_id = Uuid::new_v4();
+        let cli_id = Uuid::new_v4();
+        let (cli_tx, mut cli_rx) = mpsc::channel(16);
+        b.register_cli(cli_id, user_id, None, cli_tx);
+        eventually(&b, || a.live_cli_clients_for_user(&user_id).len() == 1).await;
+        eventually(&a, || b.has_peers()).await;
+        assert!(a.get_online_cli_ids().contains(&cli_id));
+        assert!(a.live_cli_clients_for_user(&Uuid::new_v4()).is_empty());
+
+        let web_id = Uuid::new_v4();
+        let (web_tx, mut web_rx) = mpsc::channel(16);
+        a.register_web(web_id, web_tx);
+        let session_id = Uuid::new_v4();
+        a.create_session(session_id, web_id);
+        a.assign_cli_to_session(&session_id, cli_id);
+
+        let assigned = ServerToCli::SessionAssigned { session_id, working_dir: None };
+        assert!(a.send_to_cli(&cli_id, assigned).await);
+        let received = tokio::time::timeout(Duration::from_secs(5), cli_rx.recv()).await.unwrap();
+        assert!(matches!(received, Some(ServerToCli::SessionAssigned { .. })));
+        assert_eq!(b.get_cli_session_ids(&cli_id), vec![session_id]);
+
+        let input = ServerToCli::Input { session_id, data: "hello".into() };
+        assert!(a.route_to_cli(&session_id, input).await);
+        let received = tokio::time::timeout(Duration::from_secs(5), cli_rx.recv()).await.unwrap();
+        assert!(matches!(received, Some(ServerToCli::Input { data, .. }) if data == "hello"));
+
+        // The CLI's output on b reaches the web client on a
+        let output = ServerToWeb::Error { message: "from b".into() };
+        b.route_to_web(&session_id, output).await;
+        let received = loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), web_rx.recv()).await.unwrap();
+            // Skip client list updates triggered by presence
+            if !matches!(msg, Some(ServerToWeb::CliClients { .. })) {
+                break msg;
+            }
+        };
+        assert!(matches!(received, Some(ServerToWeb::Error { message }) if message == "from b"));
+
+        b.unregister_cli(&cli_id);
+        eventually(&b, || a.get_online_cli_ids().is_empty()).await;
+    }
+
+    /// Receive the next CLI clients list sent to a web client
+    async fn next_cli_clients(rx: &mut mpsc::Receiver<ServerToWeb>) -> Vec<CliClientInfo> {
+        match tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap() {
+            Some(ServerToWeb::CliClients { clients }) => clients,
+            other => panic!("expected CliClients, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cli_clients_only_reach_their_owner() {
+        let sessions = SessionManager::default();
+        let (alice, mallory) = (Uuid::new_v4(), Uuid::new_v4());
+        let (alice_tx, mut alice_rx) = mpsc::channel(16);
+        let (mallory_tx, mut mallory_rx) = mpsc::channel(16);
+        let (anonymous_tx, mut anonymous_rx) = mpsc::channel(16);
+        let (alice_web, mallory_web) = (Uuid::new_v4(), Uuid::new_v4());
+        sessions.register_web(alice_web, alice_tx);
+        sessions.register_web(mallory_web, mallory_tx);
+        sessions.register_web(Uuid::new_v4(), anonymous_tx);
+        sessions.authenticate_web(alice_web, alice);
+        sessions.authenticate_web(mallory_web, mallory);
+
+        let machine = MachineInfo {
+            machine_id: Uuid::new_v4(),
+            name: None,
+            hostname: Some("alices-laptop".into()),
+            os: None,
+            claude_version: None,
+        };
+        let cli_id = Uuid::new_v4();
+        let (cli_tx, _cli_rx) = mpsc::channel(16);
+        sessions.register_cli(cli_id, alice, Some(machine), cli_tx);
+
+        let clients = next_cli_clients(&mut alice_rx).await;
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].id, cli_id);
+        assert_eq!(clients[0].machine.as_ref().unwrap().hostname.as_deref(), Some("alices-laptop"));
+        assert!(next_cli_clients(&mut mallory_rx).await.is_empty());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(anonymous_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cli_clients_include_offline_machines() {
+        let dir = std::env::temp_dir().join(format!("apas-sessions-{}", Uuid::new_v4()));
+        let config = crate::config::DatabaseConfig {
+            path: dir.join("apas.db").to_string_lossy().into_owned(),
+            ..crate::config::Config::default().database
+        };
+        let db = Database::connect(&config).await.unwrap();
+        db.run_migrations().await.unwrap();
+        let sessions = SessionManager::default().with_database(db.clone());
+
+        let user_id = Uuid::new_v4();
+        db.create_user(&crate::db::User {
+            id: user_id.to_string(),
+            email: "owner@example.com".into(),
+            password_hash: "x".into(),
+        })
+        .await
+        .unwrap();
+        let (desktop, laptop) = (Uuid::new_v4(), Uuid::new_v4());
+        for (machine_id, hostname) in [(desktop, "desktop"), (laptop, "laptop")] {
+            let row = CliClient {
+                id: machine_id.to_string(),
+                user_id: user_id.to_string(),
+                name: None,
+                hostname: Some(hostname.into()),
+                os: None,
+                claude_version: None,
+                last_seen: Some("2026-01-01T00:00:00Z".into()),
+                status: "online".into(),
+            };
+            db.upsert_cli_client(&row).await.unwrap();
+        }
+        db.mark_cli_client_offline(&laptop.to_string(), 0).await.unwrap();
+
+        let machine = MachineInfo {
+            machine_id: desktop,
+            name: None,
+            hostname: Some("desktop".into()),
+            os: None,
+            claude_version: None,
+        };
+        let cli_id = Uuid::new_v4();
+        let (cli_tx, _cli_rx) = mpsc::channel(16);
+        sessions.register_cli(cli_id, user_id, Some(machine), cli_tx);
+
+        let clients = sessions.get_cli_clients_info_for_user(&user_id).await;
+        assert_eq!(clients.len(), 2, "the live connection replaces its machine's row");
+        assert_eq!((clients[0].id, clients[0].status.clone()), (cli_id, CliClientStatus::Online));
+        assert_eq!((clients[1].id, clients[1].status.clone()), (laptop, CliClientStatus::Offline));
+        assert!(clients[1].last_seen.is_some());
+        assert_eq!(clients[1].machine.as_ref().unwrap().hostname.as_deref(), Some("laptop"));
+        assert!(sessions.get_cli_clients_info_for_user(&Uuid::new_v4()).await.is_empty());
+
+        // Once it disconnects the desktop is listed from its row
+        sessions.unregister_cli(&cli_id);
+        let clients = sessions.get_cli_clients_info_for_user(&user_id).await;
+        assert!(clients.iter().all(|c| c.status == CliClientStatus::Offline));
+        assert!(clients.iter().any(|c| c.id == desktop));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_cross_instance_routing_local() {
+        let backplane: Arc<dyn Backplane> = Arc::new(LocalBackplane::new());
+        let a = Arc::new(SessionManager::new(backplane.clone()));
+        let b = Arc::new(SessionManager::new(backplane));
+        exercise(a, b).await;
+    }
+
+    #[tokio::test]
+    async fn test_cross_instance_routing_redis() {
+        let url = format!("redis://{}", redis_stand_in().await);
+        let a = Arc::new(SessionManager::new(Arc::new(RedisBackplane::new(&url, "apas:test").unwrap())));
+        let b = Arc::new(SessionManager::new(Arc::new(RedisBackplane::new(&url, "apas:test").unwrap())));
+        exercise(a, b).await;
     }
 }
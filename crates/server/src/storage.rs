@@ -1,5 +1,7 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::BlobRef;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -14,8 +16,17 @@ pub struct StoredMessage {
     pub created_at: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub pane_type: Option<String>,
+    /// Set when `content` is a preview and the full text lives in the blob store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<BlobRef>,
 }
 
+/// Outputs larger than this are moved to the blob store
+pub const BLOB_THRESHOLD: usize = 32 * 1024;
+
+/// How much of an offloaded output is kept inline as a preview
+pub const BLOB_PREVIEW_BYTES: usize = 4 * 1024;
+
 #[derive(Clone)]
 pub struct FileStorage {
     base_path: PathBuf,
@@ -63,17 +74,6 @@ impl FileStorage {
         Ok(())
     }
 
-    /// Read all messages for a session (with optional limit for recent messages)
-    pub async fn get_messages(&self, session_id: &Uuid) -> Result<Vec<StoredMessage>> {
-        self.get_messages_with_limit(session_id, None).await
-    }
-
-    /// Read messages for a session, optionally limited to the most recent N
-    pub async fn get_messages_with_limit(&self, session_id: &Uuid, limit: Option<usize>) -> Result<Vec<StoredMessage>> {
-        let (messages, _) = self.get_messages_paginated(session_id, limit, None).await?;
-        Ok(messages)
-    }
-
     /// Read messages for a session with pagination support
     /// Returns (messages, has_more)
     pub async fn get_messages_paginated(
@@ -207,32 +207,127 @@ impl FileStorage {
         Ok((combined, has_more))
     }
 
-    /// List all session IDs that have message files
-    pub async fn list_sessions_with_messages(&self) -> Result<Vec<Uuid>> {
-        let sessions_dir = self.base_path.join("sessions");
+    /// Get the path of a blob from its hash (`blobs/ab/cdef...`)
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.base_path.join("blobs").join(&hash[..2]).join(&hash[2..])
+    }
 
-        if !sessions_dir.exists() {
-            return Ok(Vec::new());
+    /// Marker saying a session stored a blob (`sessions/<id>/blobs/<hash>`).
+    /// The store is shared, so a hash alone doesn't grant access.
+    fn blob_owner_path(&self, session_id: &Uuid, hash: &str) -> PathBuf {
+        self.session_dir(session_id).join("blobs").join(hash)
+    }
+
+    /// Store content in the blob store for `session_id`, returning its
+    /// reference. Identical content is only written once.
+    pub async fn put_blob(&self, session_id: &Uuid, content: &[u8]) -> Result<BlobRef> {
+        let hash = hex::encode(Sha256::digest(content));
+        let path = self.blob_path(&hash);
+
+        if !path.exists() {
+            let dir = path.parent().expect("blob path has a parent");
+            fs::create_dir_all(dir).await?;
+            // Write under a temporary name so readers never see a partial blob
+            let tmp_path = dir.join(format!(".{}.{}", &hash[2..], Uuid::new_v4()));
+            fs::write(&tmp_path, content).await?;
+            fs::rename(&tmp_path, &path).await?;
         }
 
-        let mut sessions = Vec::new();
-        let mut entries = fs::read_dir(&sessions_dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
-                let name = entry.file_name();
-                if let Some(name_str) = name.to_str() {
-                    if let Ok(uuid) = Uuid::parse_str(name_str) {
-                        // Check if messages.jsonl exists
-                        let messages_file = entry.path().join("messages.jsonl");
-                        if messages_file.exists() {
-                            sessions.push(uuid);
-                        }
-                    }
-                }
-            }
+        let owner_path = self.blob_owner_path(session_id, &hash);
+        if !owner_path.exists() {
+            fs::create_dir_all(owner_path.parent().expect("owner path has a parent")).await?;
+            fs::write(&owner_path, b"").await?;
         }
 
-        Ok(sessions)
+        Ok(BlobRef {
+            hash,
+            size: content.len() as u64,
+        })
+    }
+
+    /// Read a blob stored by `session_id`. Returns None for unknown or
+    /// malformed hashes, and for blobs only other sessions stored.
+    pub async fn get_blob(&self, session_id: &Uuid, hash: &str) -> Result<Option<Vec<u8>>> {
+        let valid = hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        if !valid || !self.blob_owner_path(session_id, hash).exists() {
+            return Ok(None);
+        }
+
+        match fs::read(self.blob_path(hash)).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Move `content` to the blob store if it exceeds [`BLOB_THRESHOLD`],
+    /// leaving a truncated preview in its place
+    pub async fn offload_if_large(&self, session_id: &Uuid, content: &mut String) -> Result<Option<BlobRef>> {
+        if content.len() <= BLOB_THRESHOLD {
+            return Ok(None);
+        }
+
+        let blob = self.put_blob(session_id, content.as_bytes()).await?;
+        let mut end = BLOB_PREVIEW_BYTES;
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        content.truncate(end);
+        content.push_str(&format!("\n... [truncated, {} bytes total]", blob.size));
+
+        Ok(Some(blob))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_offload_large_content() {
+        let dir = std::env::temp_dir().join(format!("apas-storage-{}", Uuid::new_v4()));
+        let storage = FileStorage::new(&dir);
+        let (session, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut small = "ok".to_string();
+        assert!(storage.offload_if_large(&session, &mut small).await.unwrap().is_none());
+        assert_eq!(small, "ok");
+
+        let original = "é".repeat(BLOB_THRESHOLD);
+        let mut content = original.clone();
+        let blob = storage.offload_if_large(&session, &mut content).await.unwrap().unwrap();
+        assert_eq!(blob.size, original.len() as u64);
+        assert!(content.len() < BLOB_PREVIEW_BYTES + 100);
+        assert!(original.starts_with(content.split("\n...").next().unwrap()));
+
+        // Same content maps to the same blob
+        let again = storage.put_blob(&session, original.as_bytes()).await.unwrap();
+        assert_eq!(again, blob);
+
+        let fetched = storage.get_blob(&session, &blob.hash).await.unwrap().unwrap();
+        assert_eq!(fetched, original.as_bytes());
+        assert!(storage.get_blob(&session, "../../etc/passwd").await.unwrap().is_none());
+        assert!(storage.get_blob(&session, &"0".repeat(64)).await.unwrap().is_none());
+
+        // Knowing the hash isn't enough to read another session's blob
+        assert!(storage.get_blob(&other, &blob.hash).await.unwrap().is_none());
+        storage.put_blob(&other, original.as_bytes()).await.unwrap();
+        assert!(storage.get_blob(&other, &blob.hash).await.unwrap().is_some());
+
+        // Messages that mention a hash don't grant access to it either
+        let mentions = Uuid::new_v4();
+        let message = StoredMessage {
+            id: "m1".into(),
+            role: "assistant".into(),
+            content: content.clone(),
+            message_type: "tool_result".into(),
+            created_at: "2026-01-01T00:00:00Z".into(),
+            pane_type: None,
+            blob: Some(blob.clone()),
+        };
+        storage.append_message(&mentions, &message).await.unwrap();
+        assert!(storage.get_blob(&mentions, &blob.hash).await.unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
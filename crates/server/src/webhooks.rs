@@ -0,0 +1,385 @@
+//! Outbound webhooks
+//!
+//! Users subscribe a URL to session lifecycle and deadloop events. Each
+//! delivery is a JSON POST signed with HMAC-SHA256 over the raw body using the
+//! subscription's secret, sent as `X-Apas-Signature: sha256=<hex>`. Failed
+//! deliveries (network errors, 429 and 5xx) are retried with exponential
+//! backoff; other responses are final.
+//!
+//! Webhook URLs must resolve to public addresses, checked when the webhook is
+//! created and again on every connection, and redirects are not followed, so
+//! a subscription can't be used to reach the server's own network.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::db::{Database, Webhook};
+
+/// Attempts per delivery, including the first
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry; doubles after each failure
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    SessionStart,
+    SessionEnd,
+    DeadloopStatus,
+    Result,
+    IterationTimeout,
+    SandboxViolation,
+    CliDisconnected,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 7] = [
+        WebhookEvent::SessionStart,
+        WebhookEvent::SessionEnd,
+        WebhookEvent::DeadloopStatus,
+        WebhookEvent::Result,
+        WebhookEvent::IterationTimeout,
+        WebhookEvent::SandboxViolation,
+        WebhookEvent::CliDisconnected,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::SessionStart => "session_start",
+            WebhookEvent::SessionEnd => "session_end",
+            WebhookEvent::DeadloopStatus => "deadloop_status",
+            WebhookEvent::Result => "result",
+            WebhookEvent::IterationTimeout => "iteration_timeout",
+            WebhookEvent::SandboxViolation => "sandbox_violation",
+            WebhookEvent::CliDisconnected => "cli_disconnected",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.as_str() == name)
+    }
+}
+
+/// Body of every delivery
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    id: Uuid,
+    event: &'static str,
+    timestamp: String,
+    user_id: &'a str,
+    data: &'a Value,
+}
+
+/// Whether deliveries may go to `ip`: anything but private, loopback,
+/// link-local (including cloud metadata at 169.254.169.254) and other
+/// non-routable ranges
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)) // carrier-grade NAT
+                || (a == 198 && (18..20).contains(&b)) // benchmarking
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00 // unique local
+                    || (first & 0xffc0) == 0xfe80) // link-local
+            }
+        },
+    }
+}
+
+/// Resolve `host`, failing unless every address it has is public
+async fn public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("Could not resolve {}", host))?
+        .collect();
+    if let Some(addr) = addrs.iter().find(|a| !is_public(a.ip())) {
+        bail!("{} resolves to non-public address {}", host, addr.ip());
+    }
+    if addrs.is_empty() {
+        bail!("{} has no addresses", host);
+    }
+    Ok(addrs)
+}
+
+/// Check that a webhook URL is http(s) and points at a public host
+pub async fn check_target(url: &str) -> Result<()> {
+    let url = reqwest::Url::parse(url).context("Invalid webhook URL")?;
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("Webhook URL must be http:// or https://");
+    }
+    let host = url.host_str().context("Webhook URL has no host")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    public_addrs(host, url.port_or_known_default().unwrap_or(80)).await?;
+    Ok(())
+}
+
+/// DNS resolver for deliveries that refuses non-public addresses, so a host
+/// can't be re-pointed at an internal address after it was checked
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs = public_addrs(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// `sha256=<hex>` signature for a delivery body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Clone)]
+pub struct Webhooks {
+    db: Database,
+    client: reqwest::Client,
+    retry_base_delay: Duration,
+    /// Skip the public address check on IP literals (tests deliver to localhost)
+    allow_private_targets: bool,
+}
+
+impl Webhooks {
+    pub fn new(db: Database) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("apas-server/", env!("CARGO_PKG_VERSION")))
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()
+            .expect("default HTTP client");
+        Self { db, client, retry_base_delay: RETRY_BASE_DELAY, allow_private_targets: false }
+    }
+
+    /// Deliver `event` to every matching subscription of the user. Returns
+    /// immediately; deliveries and their retries run in the background.
+    pub fn fire(&self, user_id: Uuid, event: WebhookEvent, data: Value) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let user_id = user_id.to_string();
+            let webhooks = match this.db.get_webhooks_for_user(&user_id).await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    tracing::error!("Failed to load webhooks for user {}: {}", user_id, e);
+                    return;
+                }
+            };
+
+            let payload = Payload {
+                id: Uuid::new_v4(),
+                event: event.as_str(),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                user_id: &user_id,
+                data: &data,
+            };
+            let body = serde_json::to_vec(&payload).expect("payload serializes");
+
+            for webhook in webhooks.into_iter().filter(|w| w.subscribes_to(event.as_str())) {
+                let this = this.clone();
+                let body = body.clone();
+                let delivery_id = payload.id;
+                tokio::spawn(async move { this.deliver(&webhook, event, delivery_id, body).await });
+            }
+        });
+    }
+
+    async fn deliver(&self, webhook: &Webhook, event: WebhookEvent, delivery_id: Uuid, body: Vec<u8>) -> bool {
+        // Host names are checked again by the resolver; this covers IP literals
+        if !self.allow_private_targets {
+            if let Err(e) = check_target(&webhook.url).await {
+                tracing::warn!("Refusing {} delivery {} to webhook {}: {:#}", event.as_str(), delivery_id, webhook.id, e);
+                return false;
+            }
+        }
+
+        let signature = sign(&webhook.secret, &body);
+        let mut delay = self.retry_base_delay;
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let result = self
+                .client
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Apas-Event", event.as_str())
+                .header("X-Apas-Delivery", delivery_id.to_string())
+                .header("X-Apas-Signature", &signature)
+                .body(body.clone())
+                .send()
+                .await;
+
+            let retryable = match result {
+                Ok(response) if response.status().is_success() => {
+                    tracing::debug!("Webhook {} accepted {} delivery {}", webhook.id, event.as_str(), delivery_id);
+                    return true;
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    tracing::warn!("Webhook {} returned {} (attempt {}/{})", webhook.id, status, attempt, MAX_ATTEMPTS);
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(e) => {
+                    tracing::warn!("Webhook {} delivery failed (attempt {}/{}): {}", webhook.id, attempt, MAX_ATTEMPTS, e);
+                    true
+                }
+            };
+
+            if !retryable || attempt == MAX_ATTEMPTS {
+                break;
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+
+        tracing::error!("Giving up on {} delivery {} to webhook {}", event.as_str(), delivery_id, webhook.id);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Vec<u8>)>>>;
+
+    /// Fails the first request with a 503, then accepts
+    async fn receiver(received: Received) -> String {
+        async fn handle(State(received): State<Received>, headers: HeaderMap, body: axum::body::Bytes) -> StatusCode {
+            let mut received = received.lock().unwrap();
+            received.push((headers, body.to_vec()));
+            if received.len() == 1 {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::OK
+            }
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/hook", post(handle)).with_state(received);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/hook", addr)
+    }
+
+    #[test]
+    fn test_sign() {
+        // Reference value from `printf 'hello' | openssl dgst -sha256 -hmac key`
+        assert_eq!(
+            sign("key", b"hello"),
+            "sha256=9307b3b915efb5171ff14d8cb55fbcc798c6c0ef1456d66ded1a6aa723a58b7b"
+        );
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_target() {
+        assert!(check_target("ftp://example.com/hook").await.is_err());
+        assert!(check_target("http://169.254.169.254/latest/meta-data/").await.is_err());
+        assert!(check_target("http://[::1]:8080/hook").await.is_err());
+        assert!(check_target("http://localhost/hook").await.is_err());
+        assert!(check_target("https://93.184.216.34/hook").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_signed_and_retried() {
+        let received: Received = Default::default();
+        let url = receiver(received.clone()).await;
+
+        let dir = std::env::temp_dir().join(format!("apas-webhooks-{}", Uuid::new_v4()));
+        let config = crate::config::DatabaseConfig {
+            path: dir.join("apas.db").to_string_lossy().into_owned(),
+            ..crate::config::Config::default().database
+        };
+        let db = Database::connect(&config).await.unwrap();
+        let webhooks = Webhooks { retry_base_delay: Duration::from_millis(10), ..Webhooks::new(db) };
+
+        // Deliveries to the server's own network are refused
+        let local = Webhook {
+            id: "hook".into(),
+            user_id: "user".into(),
+            url: url.clone(),
+            secret: "topsecret".into(),
+            events: String::new(),
+            created_at: None,
+        };
+        assert!(!webhooks.deliver(&local, WebhookEvent::Result, Uuid::new_v4(), b"{}".to_vec()).await);
+        assert!(received.lock().unwrap().is_empty());
+        let webhooks = Webhooks { allow_private_targets: true, ..webhooks };
+
+        let webhook = Webhook {
+            id: "hook".into(),
+            user_id: "user".into(),
+            url,
+            secret: "topsecret".into(),
+            events: String::new(),
+            created_at: None,
+        };
+        let body = br#"{"event":"result"}"#.to_vec();
+        assert!(webhooks.deliver(&webhook, WebhookEvent::Result, Uuid::new_v4(), body.clone()).await);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2, "first attempt got a 503 and was retried");
+        let (headers, delivered) = &received[1];
+        assert_eq!(delivered, &body);
+        assert_eq!(headers["x-apas-event"], "result");
+        assert_eq!(headers["x-apas-signature"].to_str().unwrap(), sign("topsecret", &body));
+        assert_eq!(headers["x-apas-delivery"], received[0].0["x-apas-delivery"], "retries reuse the delivery id");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
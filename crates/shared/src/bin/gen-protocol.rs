@@ -0,0 +1,24 @@
+//! Writes the protocol's JSON Schema and TypeScript types for the web app
+//!
+//! Usage: `cargo run -p shared --bin gen-protocol [-- <output dir>]`
+//! (defaults to `packages/web/src/lib`).
+
+use std::path::PathBuf;
+
+fn main() -> std::io::Result<()> {
+    let out_dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("packages/web/src/lib"));
+    std::fs::create_dir_all(&out_dir)?;
+
+    let ts_path = out_dir.join("protocol.ts");
+    std::fs::write(&ts_path, shared::schema::typescript())?;
+    println!("Wrote {}", ts_path.display());
+
+    let schema_path = out_dir.join("protocol.schema.json");
+    std::fs::write(&schema_path, shared::schema::json_schema_string())?;
+    println!("Wrote {}", schema_path.display());
+
+    Ok(())
+}
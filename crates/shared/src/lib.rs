@@ -1,3 +1,5 @@
 pub mod messages;
+pub mod schema;
+pub mod transport;
 
 pub use messages::*;
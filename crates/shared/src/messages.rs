@@ -1,12 +1,16 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use ts_rs::TS;
 use uuid::Uuid;
 
+use crate::transport::Compression;
+
 // ============================================================================
 // CLI <-> Server Messages
 // ============================================================================
 
 /// Messages sent from CLI client to server
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum CliToServer {
     /// CLI registers with the server using auth token and version
@@ -14,6 +18,12 @@ pub enum CliToServer {
         token: String,
         #[serde(default)]
         version: Option<String>,
+        /// Binary frame codecs the CLI can send
+        #[serde(default)]
+        compression: Vec<Compression>,
+        /// Which machine this CLI runs on; absent for older clients
+        #[serde(default)]
+        machine: Option<MachineInfo>,
     },
 
     /// CLI starts a local session (hybrid mode)
@@ -34,7 +44,14 @@ pub enum CliToServer {
     },
 
     /// Session has ended
-    SessionEnd { session_id: Uuid, reason: String },
+    SessionEnd {
+        session_id: Uuid,
+        reason: String,
+        /// Set when only that pane of a dual-pane session ended; the session
+        /// itself goes on
+        #[serde(default)]
+        pane_type: Option<PaneType>,
+    },
 
     /// Heartbeat to keep connection alive
     Heartbeat,
@@ -59,15 +76,98 @@ pub enum CliToServer {
     DeadloopStatus {
         session_id: Uuid,
         is_paused: bool,
+        /// Why the loop paused (only set when `is_paused`)
+        #[serde(default)]
+        reason: Option<PauseReason>,
+    },
+
+    /// A deadloop iteration was stopped by the CLI's watchdog before Claude
+    /// produced a result
+    IterationTimedOut {
+        session_id: Uuid,
+        reason: IterationTimeout,
+        #[ts(type = "number")]
+        duration_ms: u64,
+    },
+
+    /// The deadloop's sandbox stopped or blocked something during an
+    /// iteration
+    SandboxViolation {
+        session_id: Uuid,
+        kind: SandboxViolation,
+        /// What happened, for people
+        detail: String,
+    },
+
+    /// Commits that landed on HEAD during a deadloop iteration
+    CommitsMade {
+        session_id: Uuid,
+        /// Subject lines, oldest first
+        summaries: Vec<String>,
+    },
+
+    /// The deadloop moved to another stage of the project's workflow
+    WorkflowStage {
+        session_id: Uuid,
+        /// Stage name from `.apas`
+        stage: String,
+        /// Zero-based position of the stage in the workflow
+        index: u32,
+        /// Number of stages in the workflow
+        total: u32,
+    },
+
+    /// A line printed by the project's verification command, which the CLI
+    /// runs after each deadloop iteration
+    VerificationOutput {
+        session_id: Uuid,
+        line: String,
+    },
+
+    /// The verification command finished
+    VerificationFinished {
+        session_id: Uuid,
+        command: String,
+        passed: bool,
+        /// None if it was killed (timed out or by a signal)
+        exit_code: Option<i32>,
+        timed_out: bool,
+        #[ts(type = "number")]
+        duration_ms: u64,
+    },
+
+    /// A line printed by one of the project's deadloop hooks
+    HookOutput {
+        session_id: Uuid,
+        /// `pre_iteration`, `post_iteration`, `on_error` or `on_pause`
+        hook: String,
+        line: String,
+    },
+
+    /// A pane moved to a new Claude session, starting a new conversation
+    ClaudeSessionRotated {
+        session_id: Uuid,
+        pane_type: PaneType,
+        previous_claude_session_id: Uuid,
+        claude_session_id: Uuid,
+        /// Why, for the conversation boundary in the web UI
+        reason: String,
+        /// Handoff summary the new session was opened with
+        summary: Option<String>,
     },
 }
 
 /// Messages sent from server to CLI client
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerToCli {
-    /// Registration successful
-    Registered { cli_id: Uuid },
+    /// Registration successful; `compression` is the codec the CLI may use
+    /// for binary frames, if any
+    Registered {
+        cli_id: Uuid,
+        #[serde(default)]
+        compression: Option<Compression>,
+    },
 
     /// Registration failed
     RegistrationFailed { reason: String },
@@ -85,7 +185,13 @@ pub enum ServerToCli {
     Input { session_id: Uuid, data: String },
 
     /// Signal to send to Claude process (e.g., SIGINT)
-    Signal { session_id: Uuid, signal: String },
+    Signal {
+        session_id: Uuid,
+        signal: String,
+        /// Which pane's process, in dual-pane mode; the interactive one if unset
+        #[serde(default)]
+        pane_type: Option<PaneType>,
+    },
 
     /// Session disconnected from web
     SessionDisconnected { session_id: Uuid },
@@ -98,6 +204,14 @@ pub enum ServerToCli {
 
     /// Resume the deadloop
     ResumeDeadloop { session_id: Uuid },
+
+    /// Use this model from the pane's next Claude run on; `None` goes back
+    /// to the configured one
+    SetModel {
+        session_id: Uuid,
+        pane_type: PaneType,
+        model: Option<String>,
+    },
 }
 
 // ============================================================================
@@ -105,7 +219,7 @@ pub enum ServerToCli {
 // ============================================================================
 
 /// Messages sent from web client to server
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum WebToServer {
     /// Authenticate with JWT token
@@ -137,7 +251,11 @@ pub enum WebToServer {
     Reject { tool_call_id: String },
 
     /// Send signal (e.g., cancel/interrupt)
-    Signal { signal: String },
+    Signal {
+        signal: String,
+        #[serde(default)]
+        pane_type: Option<PaneType>,
+    },
 
     /// List all sessions (persisted)
     ListSessions,
@@ -156,10 +274,13 @@ pub enum WebToServer {
 
     /// Resume the deadloop session
     ResumeDeadloop,
+
+    /// Change the model a pane uses; `None` goes back to the configured one
+    SetModel { pane_type: PaneType, model: Option<String> },
 }
 
 /// Messages sent from server to web client
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerToWeb {
     /// Authentication successful
@@ -224,11 +345,50 @@ pub enum ServerToWeb {
     DeadloopStatus {
         session_id: Uuid,
         is_paused: bool,
+        #[serde(default)]
+        reason: Option<PauseReason>,
+    },
+    /// Workflow stage the deadloop is running
+    WorkflowStage {
+        session_id: Uuid,
+        stage: String,
+        index: u32,
+        total: u32,
+    },
+    /// A line of the deadloop's verification command output
+    VerificationOutput {
+        session_id: Uuid,
+        line: String,
+    },
+    /// The deadloop's verification command finished
+    VerificationFinished {
+        session_id: Uuid,
+        command: String,
+        passed: bool,
+        exit_code: Option<i32>,
+        timed_out: bool,
+        #[ts(type = "number")]
+        duration_ms: u64,
+    },
+    /// A line of output from one of the deadloop's hooks
+    HookOutput {
+        session_id: Uuid,
+        hook: String,
+        line: String,
+    },
+    /// A pane moved to a new Claude session
+    ClaudeSessionRotated {
+        session_id: Uuid,
+        pane_type: PaneType,
+        previous_claude_session_id: Uuid,
+        claude_session_id: Uuid,
+        reason: String,
+        summary: Option<String>,
     },
 }
 
 /// Information about a persisted session
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct SessionInfo {
     pub id: Uuid,
     pub cli_client_id: Option<Uuid>,
@@ -248,7 +408,7 @@ pub struct SessionInfo {
 }
 
 /// Information about a persisted message
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct MessageInfo {
     pub id: String,
     pub role: String,
@@ -257,6 +417,20 @@ pub struct MessageInfo {
     pub created_at: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub pane_type: Option<String>,
+    /// Full content when `content` is only a preview of a large output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<BlobRef>,
+}
+
+/// Reference to a large payload kept in the server's blob store.
+/// Fetch it with `GET /blobs/{session_id}/{hash}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, TS)]
+pub struct BlobRef {
+    /// Hex-encoded SHA-256 of the content
+    pub hash: String,
+    /// Size of the full content in bytes
+    #[ts(type = "number")]
+    pub size: u64,
 }
 
 // ============================================================================
@@ -264,7 +438,7 @@ pub struct MessageInfo {
 // ============================================================================
 
 /// Pane type for dual-pane mode
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default, JsonSchema, TS)]
 #[serde(rename_all = "snake_case")]
 pub enum PaneType {
     /// Autonomous deadloop worker (left pane)
@@ -275,7 +449,7 @@ pub enum PaneType {
 }
 
 /// Type of output content
-#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, JsonSchema, TS)]
 #[serde(rename_all = "snake_case")]
 pub enum OutputType {
     #[default]
@@ -300,8 +474,68 @@ pub enum OutputType {
     Error,
 }
 
+/// Which watchdog limit stopped an iteration
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum IterationTimeout {
+    /// Ran longer than the per-iteration limit
+    Duration,
+    /// Produced no output for too long
+    Idle,
+}
+
+/// What the deadloop's sandbox stopped or blocked
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxViolation {
+    /// The sandbox itself couldn't be set up
+    SetupFailed,
+    /// Killed for going over the memory limit
+    OutOfMemory,
+    /// Killed for going over the CPU time limit
+    CpuLimit,
+    /// Couldn't start more processes
+    PidLimit,
+    /// Tried to write outside the project and scratch directories
+    BlockedWrite,
+    /// Tried to reach the network with networking off
+    BlockedNetwork,
+}
+
+/// Why a deadloop paused
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseReason {
+    /// Paused from the web UI
+    User,
+    /// A spending limit was reached
+    Budget,
+    /// Several iterations in a row failed
+    Error,
+    /// Several iterations went by without a new commit
+    NoCommits,
+    /// Iterations keep making the same tool calls
+    RepeatedToolCalls,
+    /// Iterations keep ending with the same result text
+    RepeatedResult,
+}
+
+impl PauseReason {
+    /// Short human-readable explanation
+    pub fn description(self) -> &'static str {
+        match self {
+            PauseReason::User => "paused by user",
+            PauseReason::Budget => "spending limit reached",
+            PauseReason::Error => "iterations keep failing",
+            PauseReason::NoCommits => "no new commits",
+            PauseReason::RepeatedToolCalls => "same tool calls every iteration",
+            PauseReason::RepeatedResult => "same result every iteration",
+        }
+    }
+}
+
 /// Session status
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema, TS)]
 #[serde(rename_all = "snake_case")]
 pub enum SessionStatus {
     /// Waiting for CLI client to connect
@@ -315,7 +549,7 @@ pub enum SessionStatus {
 }
 
 /// Information about a CLI client
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct CliClientInfo {
     pub id: Uuid,
     pub name: Option<String>,
@@ -323,10 +557,38 @@ pub struct CliClientInfo {
     pub last_seen: Option<chrono::DateTime<chrono::Utc>>,
     /// Active session ID if the CLI has a local session running
     pub active_session: Option<Uuid>,
+    /// Machine the CLI runs on; connections sharing a machine_id are the same computer
+    #[serde(default)]
+    pub machine: Option<MachineInfo>,
+}
+
+/// Identity of the machine a CLI runs on, reported at registration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, TS)]
+pub struct MachineInfo {
+    /// Generated once per machine and persisted in the CLI config dir
+    pub machine_id: Uuid,
+    /// User-chosen display name (`apas config set name ...`)
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Operating system and architecture, e.g. "linux-x86_64"
+    #[serde(default)]
+    pub os: Option<String>,
+    /// Output of `claude --version`
+    #[serde(default)]
+    pub claude_version: Option<String>,
+}
+
+impl MachineInfo {
+    /// Name to show for this machine: the chosen name, else the hostname
+    pub fn display_name(&self) -> Option<&str> {
+        self.name.as_deref().or(self.hostname.as_deref())
+    }
 }
 
 /// CLI client status
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema, TS)]
 #[serde(rename_all = "snake_case")]
 pub enum CliClientStatus {
     Online,
@@ -340,7 +602,7 @@ pub enum CliClientStatus {
 // ============================================================================
 
 /// Top-level message from Claude CLI stream-json output
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClaudeStreamMessage {
     /// System initialization message
@@ -354,6 +616,7 @@ pub enum ClaudeStreamMessage {
         #[serde(default)]
         cwd: Option<String>,
         #[serde(flatten)]
+        #[ts(skip)]
         extra: serde_json::Value,
     },
     /// Assistant (Claude) message with content blocks
@@ -361,6 +624,7 @@ pub enum ClaudeStreamMessage {
         message: ClaudeAssistantMessage,
         session_id: String,
         #[serde(flatten)]
+        #[ts(skip)]
         extra: serde_json::Value,
     },
     /// User message (typically tool results)
@@ -370,6 +634,7 @@ pub enum ClaudeStreamMessage {
         #[serde(default)]
         tool_use_result: Option<serde_json::Value>,
         #[serde(flatten)]
+        #[ts(skip)]
         extra: serde_json::Value,
     },
     /// Final result message
@@ -380,27 +645,30 @@ pub enum ClaudeStreamMessage {
         #[serde(default)]
         total_cost_usd: f64,
         #[serde(default)]
+        #[ts(type = "number")]
         duration_ms: u64,
         session_id: String,
         #[serde(default)]
         is_error: bool,
         #[serde(flatten)]
+        #[ts(skip)]
         extra: serde_json::Value,
     },
 }
 
 /// Claude assistant message structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct ClaudeAssistantMessage {
     pub content: Vec<ClaudeContentBlock>,
     #[serde(default)]
     pub model: String,
     #[serde(flatten)]
+    #[ts(skip)]
     pub extra: serde_json::Value,
 }
 
 /// Claude user message structure (for tool results)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 pub struct ClaudeUserMessage {
     pub content: Vec<ClaudeContentBlock>,
     #[serde(default)]
@@ -408,7 +676,7 @@ pub struct ClaudeUserMessage {
 }
 
 /// Content block types in Claude messages
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClaudeContentBlock {
     /// Text content from Claude
@@ -427,6 +695,9 @@ pub enum ClaudeContentBlock {
         content: String,
         #[serde(default)]
         is_error: bool,
+        /// Set by the server when `content` was truncated to a preview
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        blob: Option<BlobRef>,
     },
 }
 
@@ -478,8 +749,18 @@ mod tests {
 
     #[test]
     fn test_cli_to_server_register_serialization() {
+        let machine_id = Uuid::new_v4();
         let msg = CliToServer::Register {
             token: "test-token".to_string(),
+            version: Some("26.01.1".to_string()),
+            compression: vec![Compression::Zstd],
+            machine: Some(MachineInfo {
+                machine_id,
+                name: None,
+                hostname: Some("laptop".to_string()),
+                os: Some("linux-x86_64".to_string()),
+                claude_version: None,
+            }),
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"type\":\"register\""));
@@ -487,7 +768,14 @@ mod tests {
 
         let deserialized: CliToServer = serde_json::from_str(&json).unwrap();
         match deserialized {
-            CliToServer::Register { token } => assert_eq!(token, "test-token"),
+            CliToServer::Register { token, version, compression, machine } => {
+                assert_eq!(token, "test-token");
+                assert_eq!(version.as_deref(), Some("26.01.1"));
+                assert_eq!(compression, vec![Compression::Zstd]);
+                let machine = machine.unwrap();
+                assert_eq!(machine.machine_id, machine_id);
+                assert_eq!(machine.display_name(), Some("laptop"));
+            }
             _ => panic!("Expected Register variant"),
         }
     }
@@ -498,6 +786,8 @@ mod tests {
         let msg = CliToServer::SessionStart {
             session_id,
             working_dir: Some("/home/user/project".to_string()),
+            hostname: Some("laptop".to_string()),
+            pane_type: None,
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"type\":\"session_start\""));
@@ -505,9 +795,11 @@ mod tests {
 
         let deserialized: CliToServer = serde_json::from_str(&json).unwrap();
         match deserialized {
-            CliToServer::SessionStart { session_id: sid, working_dir } => {
+            CliToServer::SessionStart { session_id: sid, working_dir, hostname, pane_type } => {
                 assert_eq!(sid, session_id);
                 assert_eq!(working_dir, Some("/home/user/project".to_string()));
+                assert_eq!(hostname, Some("laptop".to_string()));
+                assert_eq!(pane_type, None);
             }
             _ => panic!("Expected SessionStart variant"),
         }
@@ -530,13 +822,13 @@ mod tests {
     #[test]
     fn test_server_to_cli_serialization() {
         let cli_id = Uuid::new_v4();
-        let msg = ServerToCli::Registered { cli_id };
+        let msg = ServerToCli::Registered { cli_id, compression: None };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"type\":\"registered\""));
 
         let deserialized: ServerToCli = serde_json::from_str(&json).unwrap();
         match deserialized {
-            ServerToCli::Registered { cli_id: cid } => assert_eq!(cid, cli_id),
+            ServerToCli::Registered { cli_id: cid, .. } => assert_eq!(cid, cli_id),
             _ => panic!("Expected Registered variant"),
         }
     }
@@ -558,9 +850,10 @@ mod tests {
     fn test_server_to_web_helpers() {
         let msg = ServerToWeb::output("Test output");
         match msg {
-            ServerToWeb::Output { content, output_type } => {
+            ServerToWeb::Output { content, output_type, pane_type } => {
                 assert_eq!(content, "Test output");
                 assert_eq!(output_type, OutputType::Text);
+                assert_eq!(pane_type, None);
             }
             _ => panic!("Expected Output variant"),
         }
@@ -629,6 +922,7 @@ mod tests {
             status: CliClientStatus::Online,
             last_seen: Some(chrono::Utc::now()),
             active_session: None,
+            machine: None,
         };
         let json = serde_json::to_string(&info).unwrap();
         assert!(json.contains("\"name\":\"my-laptop\""));
@@ -727,6 +1021,7 @@ mod tests {
         let msg = CliToServer::StreamMessage {
             session_id,
             message: stream_msg,
+            pane_type: Some(PaneType::Deadloop),
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"type\":\"stream_message\""));
@@ -0,0 +1,83 @@
+//! JSON Schema and TypeScript generation for the wire protocol
+//!
+//! The web app mirrors the message enums in `messages.rs`; instead of keeping
+//! those copies in sync by hand, `cargo run -p shared --bin gen-protocol`
+//! writes both artifacts from the Rust definitions.
+
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Value};
+use ts_rs::TS;
+
+use crate::messages::*;
+use crate::transport::Compression;
+
+/// Header prepended to the generated TypeScript file
+const TS_HEADER: &str = "// This file is generated by `cargo run -p shared --bin gen-protocol`.\n// Do not edit it by hand; change `crates/shared/src/messages.rs` instead.\n";
+
+/// Invoke `$m!(Type)` for every protocol type, in declaration order
+macro_rules! for_each_protocol_type {
+    ($m:ident) => {
+        $m!(CliToServer);
+        $m!(ServerToCli);
+        $m!(WebToServer);
+        $m!(ServerToWeb);
+        $m!(SessionInfo);
+        $m!(MessageInfo);
+        $m!(BlobRef);
+        $m!(PaneType);
+        $m!(OutputType);
+        $m!(SessionStatus);
+        $m!(PauseReason);
+        $m!(IterationTimeout);
+        $m!(SandboxViolation);
+        $m!(CliClientInfo);
+        $m!(MachineInfo);
+        $m!(CliClientStatus);
+        $m!(ClaudeStreamMessage);
+        $m!(ClaudeAssistantMessage);
+        $m!(ClaudeUserMessage);
+        $m!(ClaudeContentBlock);
+        $m!(Compression);
+    };
+}
+
+/// JSON Schema (draft 7) with one definition per protocol type
+pub fn json_schema() -> Value {
+    let mut gen = SchemaSettings::draft07().into_generator();
+    macro_rules! register {
+        ($t:ty) => {
+            gen.subschema_for::<$t>();
+        };
+    }
+    for_each_protocol_type!(register);
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "APAS protocol",
+        "definitions": gen.take_definitions(),
+    })
+}
+
+/// TypeScript declarations for every protocol type
+pub fn typescript() -> String {
+    let mut out = String::from(TS_HEADER);
+    macro_rules! declare {
+        ($t:ty) => {
+            out.push('\n');
+            out.push_str("export ");
+            out.push_str(&<$t as TS>::decl());
+            out.push('\n');
+        };
+    }
+    for_each_protocol_type!(declare);
+    // Free-form JSON fields (tool inputs, tool results) reference this alias
+    declare!(serde_json::Value);
+    out
+}
+
+/// Serialized JSON Schema as written to disk (pretty-printed, trailing newline)
+pub fn json_schema_string() -> String {
+    let mut out = serde_json::to_string_pretty(&json_schema()).expect("schema serializes");
+    out.push('\n');
+    out
+}
@@ -0,0 +1,149 @@
+//! Binary framing for the CLI websocket
+//!
+//! Plain text frames carry exactly one JSON message. When both sides agree on
+//! a codec during registration, the CLI may also send binary frames: a one
+//! byte codec tag followed by a compressed body of newline-delimited JSON
+//! messages. This lets a busy deadloop batch many small stream messages (and
+//! shrink large tool results) into a single frame.
+
+use std::io::Read;
+
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ts_rs::TS;
+
+/// Upper bound on a decompressed frame, so a corrupt or hostile frame can't
+/// exhaust server memory
+pub const MAX_DECODED_FRAME_BYTES: u64 = 64 * 1024 * 1024;
+
+/// zstd level used for outgoing frames (zstd's own default)
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression codec for binary frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// zstd-compressed newline-delimited JSON
+    Zstd,
+}
+
+impl Compression {
+    /// Codecs this build can encode and decode, in order of preference
+    pub const SUPPORTED: &'static [Compression] = &[Compression::Zstd];
+
+    fn tag(self) -> u8 {
+        match self {
+            Compression::Zstd => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Pick the codec to use given what the peer offered
+pub fn negotiate(offered: &[Compression]) -> Option<Compression> {
+    Compression::SUPPORTED.iter().copied().find(|c| offered.contains(c))
+}
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("empty frame")]
+    Empty,
+
+    #[error("unknown frame codec tag {0}")]
+    UnknownCodec(u8),
+
+    #[error("decompressed frame exceeds {MAX_DECODED_FRAME_BYTES} bytes")]
+    TooLarge,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid message in frame: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Encode already-serialized JSON messages into one binary frame
+pub fn encode_frame<S: AsRef<str>>(compression: Compression, messages: &[S]) -> Result<Vec<u8>, FrameError> {
+    let mut body = Vec::new();
+    for msg in messages {
+        body.extend_from_slice(msg.as_ref().as_bytes());
+        body.push(b'\n');
+    }
+
+    let mut frame = vec![compression.tag()];
+    match compression {
+        Compression::Zstd => frame.extend(zstd::stream::encode_all(body.as_slice(), ZSTD_LEVEL)?),
+    }
+    Ok(frame)
+}
+
+/// Decode a binary frame into the messages it carries
+pub fn decode_frame<T: DeserializeOwned>(frame: &[u8]) -> Result<Vec<T>, FrameError> {
+    let (&tag, payload) = frame.split_first().ok_or(FrameError::Empty)?;
+    let compression = Compression::from_tag(tag).ok_or(FrameError::UnknownCodec(tag))?;
+
+    let mut body = Vec::new();
+    match compression {
+        Compression::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(payload)?;
+            decoder.take(MAX_DECODED_FRAME_BYTES + 1).read_to_end(&mut body)?;
+        }
+    }
+    if body.len() as u64 > MAX_DECODED_FRAME_BYTES {
+        return Err(FrameError::TooLarge);
+    }
+
+    body.split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).map_err(FrameError::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CliToServer;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let session_id = Uuid::new_v4();
+        let messages = [
+            CliToServer::output(session_id, "first"),
+            CliToServer::Heartbeat,
+            CliToServer::output(session_id, "x".repeat(100_000)),
+        ];
+        let lines: Vec<String> = messages.iter().map(|m| serde_json::to_string(m).unwrap()).collect();
+
+        let frame = encode_frame(Compression::Zstd, &lines).unwrap();
+        assert!(frame.len() < 1_000, "repetitive output should compress well");
+
+        let decoded: Vec<CliToServer> = decode_frame(&frame).unwrap();
+        assert_eq!(decoded.len(), 3);
+        match &decoded[2] {
+            CliToServer::Output { data, .. } => assert_eq!(data.len(), 100_000),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_frames() {
+        assert!(matches!(decode_frame::<CliToServer>(&[]), Err(FrameError::Empty)));
+        assert!(matches!(decode_frame::<CliToServer>(&[9, 1, 2]), Err(FrameError::UnknownCodec(9))));
+        assert!(decode_frame::<CliToServer>(&[1, 0xde, 0xad]).is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(&[Compression::Zstd]), Some(Compression::Zstd));
+        assert_eq!(negotiate(&[]), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Golden fixture tests for the wire protocol.
+//!
+//! Every file in `tests/fixtures/` holds an array of messages for one enum.
+//! Each message must survive a deserialize/serialize round trip unchanged, and
+//! every variant in the generated schema must have at least one fixture so new
+//! variants can't be added without updating the web client's view of them.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use shared::*;
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn web_lib_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../packages/web/src/lib")
+}
+
+fn load_fixture(file: &str) -> Vec<Value> {
+    let path = fixtures_dir().join(file);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("read {}: {}", path.display(), e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("parse {}: {}", path.display(), e))
+}
+
+/// Variant tag of a fixture value: the `type` field for internally tagged
+/// enums, the string itself for unit variants, or the single key for
+/// externally tagged variants.
+fn fixture_tag(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Object(map) => match map.get("type").and_then(Value::as_str) {
+            Some(tag) => tag.to_string(),
+            None => {
+                assert_eq!(map.len(), 1, "externally tagged variant must have one key: {}", value);
+                map.keys().next().unwrap().clone()
+            }
+        },
+        other => panic!("unexpected fixture value: {}", other),
+    }
+}
+
+/// All variant tags of an enum definition in the generated JSON Schema
+fn schema_tags(definition: &str) -> BTreeSet<String> {
+    let schema = schema::json_schema();
+    let def = &schema["definitions"][definition];
+    let alternatives = def["oneOf"]
+        .as_array()
+        .cloned()
+        .unwrap_or_else(|| vec![def.clone()]);
+
+    let mut tags = BTreeSet::new();
+    for alt in alternatives {
+        if let Some(tag) = alt["properties"]["type"]["enum"][0].as_str() {
+            tags.insert(tag.to_string());
+        } else if let Some(values) = alt["enum"].as_array() {
+            tags.extend(values.iter().filter_map(Value::as_str).map(String::from));
+        } else if let Some(key) = alt["required"][0].as_str() {
+            tags.insert(key.to_string());
+        } else {
+            panic!("cannot determine variant tag in {}: {}", definition, alt);
+        }
+    }
+    tags
+}
+
+fn check<T: Serialize + DeserializeOwned>(file: &str, definition: &str) {
+    let fixtures = load_fixture(file);
+
+    for original in &fixtures {
+        let parsed: T = serde_json::from_value(original.clone())
+            .unwrap_or_else(|e| panic!("{}: failed to deserialize {}: {}", file, original, e));
+        let reserialized = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(&reserialized, original, "{}: round trip changed the message", file);
+    }
+
+    let covered: BTreeSet<String> = fixtures.iter().map(fixture_tag).collect();
+    let expected = schema_tags(definition);
+    let missing: Vec<_> = expected.difference(&covered).collect();
+    assert!(missing.is_empty(), "{}: no fixture for variants {:?}", file, missing);
+}
+
+#[test]
+fn cli_to_server_fixtures() {
+    check::<CliToServer>("cli_to_server.json", "CliToServer");
+}
+
+#[test]
+fn server_to_cli_fixtures() {
+    check::<ServerToCli>("server_to_cli.json", "ServerToCli");
+}
+
+#[test]
+fn web_to_server_fixtures() {
+    check::<WebToServer>("web_to_server.json", "WebToServer");
+}
+
+#[test]
+fn server_to_web_fixtures() {
+    check::<ServerToWeb>("server_to_web.json", "ServerToWeb");
+}
+
+#[test]
+fn output_type_fixtures() {
+    check::<OutputType>("output_type.json", "OutputType");
+}
+
+#[test]
+fn pane_type_fixtures() {
+    check::<PaneType>("pane_type.json", "PaneType");
+}
+
+#[test]
+fn session_status_fixtures() {
+    check::<SessionStatus>("session_status.json", "SessionStatus");
+}
+
+#[test]
+fn cli_client_status_fixtures() {
+    check::<CliClientStatus>("cli_client_status.json", "CliClientStatus");
+}
+
+#[test]
+fn claude_stream_message_fixtures() {
+    check::<ClaudeStreamMessage>("claude_stream_message.json", "ClaudeStreamMessage");
+}
+
+#[test]
+fn claude_content_block_fixtures() {
+    check::<ClaudeContentBlock>("claude_content_block.json", "ClaudeContentBlock");
+}
+
+#[test]
+fn compression_fixtures() {
+    check::<shared::transport::Compression>("compression.json", "Compression");
+}
+
+#[test]
+fn generated_typescript_is_up_to_date() {
+    let on_disk = std::fs::read_to_string(web_lib_dir().join("protocol.ts")).unwrap();
+    assert!(
+        on_disk == schema::typescript(),
+        "packages/web/src/lib/protocol.ts is stale; run `cargo run -p shared --bin gen-protocol`"
+    );
+}
+
+#[test]
+fn generated_json_schema_is_up_to_date() {
+    let on_disk = std::fs::read_to_string(web_lib_dir().join("protocol.schema.json")).unwrap();
+    assert!(
+        on_disk == schema::json_schema_string(),
+        "packages/web/src/lib/protocol.schema.json is stale; run `cargo run -p shared --bin gen-protocol`"
+    );
+}
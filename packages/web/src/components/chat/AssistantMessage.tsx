@@ -68,6 +68,7 @@ function renderContent(message: Message, outputType: Message["outputType"]) {
         <ToolCard
           tool={outputType.tool}
           result={message.content}
+          blob={message.blob}
           success={outputType.success}
           type="result"
         />
@@ -2,6 +2,7 @@
 
 import { useRef, useCallback, useEffect, useState } from "react";
 import { useStore, Message, PaneType } from "@/lib/store";
+import type { PauseReason } from "@/lib/protocol";
 import { UserMessage } from "./UserMessage";
 import { AssistantMessage } from "./AssistantMessage";
 import { InputBox } from "./InputBox";
@@ -17,6 +18,22 @@ function getScrollKey(sessionId: string | null, paneType: PaneType): string {
   return `${sessionId || 'none'}-${paneType}`;
 }
 
+const PAUSE_REASON_LABELS: Record<PauseReason, string> = {
+  user: "Paused",
+  budget: "Paused: spending limit",
+  error: "Paused: repeated errors",
+  no_commits: "Paused: no new commits",
+  repeated_tool_calls: "Paused: repeated tool calls",
+  repeated_result: "Paused: repeated result",
+};
+
+// Models offered in the pane header; "" keeps whatever the CLI is configured with
+const MODEL_CHOICES = ["", "opus", "sonnet", "haiku"];
+
+function pauseLabel(reason: PauseReason | null): string {
+  return reason ? PAUSE_REASON_LABELS[reason] : "Paused";
+}
+
 export function DualPaneView() {
   const deadloopMessages = useStore((state) => state.deadloopMessages);
   const interactiveMessages = useStore((state) => state.interactiveMessages);
@@ -25,6 +42,7 @@ export function DualPaneView() {
   const isLoadingMore = useStore((state) => state.isLoadingMore);
   const hasMoreMessages = useStore((state) => state.hasMoreMessages);
   const isDeadloopPaused = useStore((state) => state.isDeadloopPaused);
+  const deadloopPauseReason = useStore((state) => state.deadloopPauseReason);
   const pauseDeadloop = useStore((state) => state.pauseDeadloop);
   const resumeDeadloop = useStore((state) => state.resumeDeadloop);
   const isAttached = useStore((state) => state.isAttached);
@@ -42,7 +60,7 @@ export function DualPaneView() {
               : "text-gray-500 hover:bg-gray-100 dark:hover:bg-gray-800"
           }`}
         >
-          Deadloop {isDeadloopPaused && "(Paused)"}
+          Deadloop {isDeadloopPaused && `(${pauseLabel(deadloopPauseReason)})`}
         </button>
         {activePane === "deadloop" && isAttached && (
           <button
@@ -115,8 +133,13 @@ interface PaneHeaderProps {
 
 function PaneHeader({ title, type, className }: PaneHeaderProps) {
   const isDeadloopPaused = useStore((state) => state.isDeadloopPaused);
+  const deadloopPauseReason = useStore((state) => state.deadloopPauseReason);
   const pauseDeadloop = useStore((state) => state.pauseDeadloop);
   const resumeDeadloop = useStore((state) => state.resumeDeadloop);
+  const interruptPane = useStore((state) => state.interruptPane);
+  const setModel = useStore((state) => state.setModel);
+  const workflowStage = useStore((state) => state.workflowStage);
+  const [model, setModelChoice] = useState("");
   const isAttached = useStore((state) => state.isAttached);
 
   return (
@@ -131,21 +154,50 @@ function PaneHeader({ title, type, className }: PaneHeaderProps) {
           : "text-cyan-700 dark:text-cyan-300"
       }`}>
         {title}
+        {type === "deadloop" && workflowStage && (
+          <span className="ml-2 text-xs font-normal text-amber-600 dark:text-amber-400">
+            Stage {workflowStage.index + 1}/{workflowStage.total}: {workflowStage.name}
+          </span>
+        )}
         {type === "deadloop" && isDeadloopPaused && (
-          <span className="ml-2 text-xs font-normal text-amber-600 dark:text-amber-400">(Paused)</span>
+          <span className="ml-2 text-xs font-normal text-amber-600 dark:text-amber-400">({pauseLabel(deadloopPauseReason)})</span>
         )}
       </h2>
-      {type === "deadloop" && isAttached && (
-        <button
-          onClick={isDeadloopPaused ? resumeDeadloop : pauseDeadloop}
-          className={`px-3 py-1 text-xs font-medium rounded transition-colors ${
-            isDeadloopPaused
-              ? "bg-green-500 hover:bg-green-600 text-white"
-              : "bg-amber-500 hover:bg-amber-600 text-white"
-          }`}
-        >
-          {isDeadloopPaused ? "Resume" : "Pause"}
-        </button>
+      {isAttached && (
+        <div className="flex gap-2">
+          <select
+            value={model}
+            onChange={(e) => {
+              setModelChoice(e.target.value);
+              setModel(type, e.target.value || null);
+            }}
+            title={type === "deadloop" ? "Model for the next iteration" : "Model for the next message"}
+            className="px-2 py-1 text-xs rounded bg-gray-200 dark:bg-gray-700 text-gray-700 dark:text-gray-200"
+          >
+            {MODEL_CHOICES.map((m) => (
+              <option key={m} value={m}>{m || "Default model"}</option>
+            ))}
+          </select>
+          <button
+            onClick={() => interruptPane(type)}
+            title="Interrupt the current turn (SIGINT)"
+            className="px-3 py-1 text-xs font-medium rounded transition-colors bg-gray-200 hover:bg-gray-300 dark:bg-gray-700 dark:hover:bg-gray-600 text-gray-700 dark:text-gray-200"
+          >
+            Interrupt
+          </button>
+          {type === "deadloop" && (
+            <button
+              onClick={isDeadloopPaused ? resumeDeadloop : pauseDeadloop}
+              className={`px-3 py-1 text-xs font-medium rounded transition-colors ${
+                isDeadloopPaused
+                  ? "bg-green-500 hover:bg-green-600 text-white"
+                  : "bg-amber-500 hover:bg-amber-600 text-white"
+              }`}
+            >
+              {isDeadloopPaused ? "Resume" : "Pause"}
+            </button>
+          )}
+        </div>
       )}
     </div>
   );
@@ -305,6 +357,24 @@ function MessageComponent({ message }: { message: Message }) {
     case "assistant":
       return <AssistantMessage message={message} />;
     case "system":
+      if (message.outputType?.type === "session_boundary") {
+        const { summary } = message.outputType;
+        return (
+          <div className="py-2 text-xs text-gray-500">
+            <div className="flex items-center gap-2">
+              <div className="flex-1 border-t border-gray-300 dark:border-gray-600" />
+              <span>{message.content}</span>
+              <div className="flex-1 border-t border-gray-300 dark:border-gray-600" />
+            </div>
+            {summary && (
+              <details className="mt-1 text-center">
+                <summary className="cursor-pointer">Handoff summary</summary>
+                <p className="mt-1 text-left whitespace-pre-wrap">{summary}</p>
+              </details>
+            )}
+          </div>
+        );
+      }
       return (
         <div className="text-center text-xs text-gray-500 py-1">
           <span>{message.content}</span>
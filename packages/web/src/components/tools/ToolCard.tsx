@@ -12,11 +12,14 @@ import {
   XCircle,
   Wrench,
 } from "lucide-react";
+import { useStore } from "@/lib/store";
+import type { BlobRef } from "@/lib/protocol";
 
 interface ToolCardProps {
   tool: string;
   input?: unknown;
   result?: string;
+  blob?: BlobRef; // Set when `result` is a truncated preview
   success?: boolean;
   type: "use" | "result";
 }
@@ -30,8 +33,25 @@ const toolIcons: Record<string, React.ReactNode> = {
   Glob: <Search className="w-4 h-4" />,
 };
 
-export function ToolCard({ tool, input, result, success, type }: ToolCardProps) {
+export function ToolCard({ tool, input, result, blob, success, type }: ToolCardProps) {
   const [expanded, setExpanded] = useState(false);
+  const [fullResult, setFullResult] = useState<string | null>(null);
+  const [loadingFull, setLoadingFull] = useState(false);
+  const [loadError, setLoadError] = useState<string | null>(null);
+  const fetchBlob = useStore((state) => state.fetchBlob);
+
+  const loadFullResult = async () => {
+    if (!blob) return;
+    setLoadingFull(true);
+    setLoadError(null);
+    try {
+      setFullResult(await fetchBlob(blob.hash));
+    } catch (e) {
+      setLoadError(e instanceof Error ? e.message : String(e));
+    } finally {
+      setLoadingFull(false);
+    }
+  };
 
   const icon = toolIcons[tool] || <Wrench className="w-4 h-4" />;
 
@@ -87,8 +107,18 @@ export function ToolCard({ tool, input, result, success, type }: ToolCardProps)
       {expanded && result && (
         <div className="px-3 py-2 bg-gray-100 dark:bg-gray-900 text-sm">
           <pre className="overflow-x-auto text-gray-600 dark:text-gray-300 whitespace-pre-wrap">
-            {result}
+            {fullResult ?? result}
           </pre>
+          {blob && fullResult === null && (
+            <button
+              onClick={loadFullResult}
+              disabled={loadingFull}
+              className="mt-2 text-xs text-blue-500 hover:underline disabled:opacity-50"
+            >
+              {loadingFull ? "Loading..." : `Load full output (${Math.ceil(blob.size / 1024)} KB)`}
+            </button>
+          )}
+          {loadError && <div className="mt-1 text-xs text-red-500">{loadError}</div>}
         </div>
       )}
     </div>
@@ -0,0 +1,155 @@
+// This file is generated by `cargo run -p shared --bin gen-protocol`.
+// Do not edit it by hand; change `crates/shared/src/messages.rs` instead.
+
+export type CliToServer = { "type": "register", token: string, version: string | null, 
+/**
+ * Binary frame codecs the CLI can send
+ */
+compression: Array<Compression>, 
+/**
+ * Which machine this CLI runs on; absent for older clients
+ */
+machine: MachineInfo | null, } | { "type": "session_start", session_id: string, working_dir: string | null, hostname: string | null, pane_type: PaneType | null, } | { "type": "output", session_id: string, data: string, output_type: OutputType, } | { "type": "session_end", session_id: string, reason: string, 
+/**
+ * Set when only that pane of a dual-pane session ended; the session
+ * itself goes on
+ */
+pane_type: PaneType | null, } | { "type": "heartbeat" } | { "type": "stream_message", session_id: string, message: ClaudeStreamMessage, pane_type: PaneType | null, } | { "type": "user_input", session_id: string, text: string, pane_type: PaneType | null, } | { "type": "deadloop_status", session_id: string, is_paused: boolean, 
+/**
+ * Why the loop paused (only set when `is_paused`)
+ */
+reason: PauseReason | null, } | { "type": "iteration_timed_out", session_id: string, reason: IterationTimeout, duration_ms: number, } | { "type": "sandbox_violation", session_id: string, kind: SandboxViolation, 
+/**
+ * What happened, for people
+ */
+detail: string, } | { "type": "commits_made", session_id: string, 
+/**
+ * Subject lines, oldest first
+ */
+summaries: Array<string>, } | { "type": "workflow_stage", session_id: string, 
+/**
+ * Stage name from `.apas`
+ */
+stage: string, 
+/**
+ * Zero-based position of the stage in the workflow
+ */
+index: number, 
+/**
+ * Number of stages in the workflow
+ */
+total: number, } | { "type": "verification_output", session_id: string, line: string, } | { "type": "verification_finished", session_id: string, command: string, passed: boolean, 
+/**
+ * None if it was killed (timed out or by a signal)
+ */
+exit_code: number | null, timed_out: boolean, duration_ms: number, } | { "type": "hook_output", session_id: string, 
+/**
+ * `pre_iteration`, `post_iteration`, `on_error` or `on_pause`
+ */
+hook: string, line: string, } | { "type": "claude_session_rotated", session_id: string, pane_type: PaneType, previous_claude_session_id: string, claude_session_id: string, 
+/**
+ * Why, for the conversation boundary in the web UI
+ */
+reason: string, 
+/**
+ * Handoff summary the new session was opened with
+ */
+summary: string | null, };
+
+export type ServerToCli = { "type": "registered", cli_id: string, compression: Compression | null, } | { "type": "registration_failed", reason: string, } | { "type": "version_unsupported", client_version: string, min_version: string, } | { "type": "session_assigned", session_id: string, working_dir: string | null, } | { "type": "input", session_id: string, data: string, } | { "type": "signal", session_id: string, signal: string, 
+/**
+ * Which pane's process, in dual-pane mode; the interactive one if unset
+ */
+pane_type: PaneType | null, } | { "type": "session_disconnected", session_id: string, } | { "type": "heartbeat" } | { "type": "pause_deadloop", session_id: string, } | { "type": "resume_deadloop", session_id: string, } | { "type": "set_model", session_id: string, pane_type: PaneType, model: string | null, };
+
+export type WebToServer = { "type": "authenticate", token: string, } | { "type": "list_cli_clients" } | { "type": "start_session", cli_client_id: string | null, } | { "type": "resume_session", session_id: string, } | { "type": "attach_session", session_id: string, } | { "type": "input", text: string, pane_type: PaneType | null, } | { "type": "approve", tool_call_id: string, } | { "type": "reject", tool_call_id: string, } | { "type": "signal", signal: string, pane_type: PaneType | null, } | { "type": "list_sessions" } | { "type": "get_session_messages", session_id: string, limit: number | null, before_id: string | null, } | { "type": "pause_deadloop" } | { "type": "resume_deadloop" } | { "type": "set_model", pane_type: PaneType, model: string | null, };
+
+export type ServerToWeb = { "type": "authenticated", user_id: string, } | { "type": "authentication_failed", reason: string, } | { "type": "session_started", session_id: string, pane_type: PaneType | null, } | { "type": "session_status", status: SessionStatus, } | { "type": "output", content: string, output_type: OutputType, pane_type: PaneType | null, } | { "type": "error", message: string, } | { "type": "cli_clients", clients: Array<CliClientInfo>, } | { "type": "stream_message", session_id: string, message: ClaudeStreamMessage, pane_type: PaneType | null, } | { "type": "sessions", sessions: Array<SessionInfo>, } | { "type": "session_messages", session_id: string, messages: Array<MessageInfo>, has_more: boolean, } | { "type": "user_input", session_id: string, text: string, pane_type: PaneType | null, } | { "type": "deadloop_status", session_id: string, is_paused: boolean, reason: PauseReason | null, } | { "type": "workflow_stage", session_id: string, stage: string, index: number, total: number, } | { "type": "verification_output", session_id: string, line: string, } | { "type": "verification_finished", session_id: string, command: string, passed: boolean, exit_code: number | null, timed_out: boolean, duration_ms: number, } | { "type": "hook_output", session_id: string, hook: string, line: string, } | { "type": "claude_session_rotated", session_id: string, pane_type: PaneType, previous_claude_session_id: string, claude_session_id: string, reason: string, summary: string | null, };
+
+export type SessionInfo = { id: string, cli_client_id: string | null, working_dir: string | null, hostname: string | null, status: string, created_at: string | null, 
+/**
+ * True if this session is shared with the user (not owned)
+ */
+is_shared: boolean, 
+/**
+ * Email of the session owner (only set if is_shared is true)
+ */
+owner_email?: string | null, 
+/**
+ * True if this session has an active CLI client connected
+ */
+is_active: boolean, };
+
+export type MessageInfo = { id: string, role: string, content: string, message_type: string, created_at: string | null, pane_type?: string | null, 
+/**
+ * Full content when `content` is only a preview of a large output
+ */
+blob?: BlobRef | null, };
+
+export type BlobRef = { 
+/**
+ * Hex-encoded SHA-256 of the content
+ */
+hash: string, 
+/**
+ * Size of the full content in bytes
+ */
+size: number, };
+
+export type PaneType = "deadloop" | "interactive";
+
+export type OutputType = "text" | { "code": { language: string | null, } } | { "tool_use": { tool: string, input: JsonValue, } } | { "tool_result": { tool: string, success: boolean, } } | { "approval_request": { tool_call_id: string, tool: string, description: string, } } | "system" | "error";
+
+export type SessionStatus = "pending" | "connected" | "disconnected" | "ended";
+
+export type PauseReason = "user" | "budget" | "error" | "no_commits" | "repeated_tool_calls" | "repeated_result";
+
+export type IterationTimeout = "duration" | "idle";
+
+export type SandboxViolation = "setup_failed" | "out_of_memory" | "cpu_limit" | "pid_limit" | "blocked_write" | "blocked_network";
+
+export type CliClientInfo = { id: string, name: string | null, status: CliClientStatus, last_seen: string | null, 
+/**
+ * Active session ID if the CLI has a local session running
+ */
+active_session: string | null, 
+/**
+ * Machine the CLI runs on; connections sharing a machine_id are the same computer
+ */
+machine: MachineInfo | null, };
+
+export type MachineInfo = { 
+/**
+ * Generated once per machine and persisted in the CLI config dir
+ */
+machine_id: string, 
+/**
+ * User-chosen display name (`apas config set name ...`)
+ */
+name: string | null, hostname: string | null, 
+/**
+ * Operating system and architecture, e.g. "linux-x86_64"
+ */
+os: string | null, 
+/**
+ * Output of `claude --version`
+ */
+claude_version: string | null, };
+
+export type CliClientStatus = "online" | "offline" | "busy";
+
+export type ClaudeStreamMessage = { "type": "system", subtype: string, session_id: string, tools: Array<string>, model: string, cwd: string | null, } | { "type": "assistant", message: ClaudeAssistantMessage, session_id: string, } | { "type": "user", message: ClaudeUserMessage, session_id: string, tool_use_result: JsonValue | null, } | { "type": "result", subtype: string, result: string, total_cost_usd: number, duration_ms: number, session_id: string, is_error: boolean, };
+
+export type ClaudeAssistantMessage = { content: Array<ClaudeContentBlock>, model: string, };
+
+export type ClaudeUserMessage = { content: Array<ClaudeContentBlock>, role: string, };
+
+export type ClaudeContentBlock = { "type": "text", text: string, } | { "type": "tool_use", id: string, name: string, input: JsonValue, } | { "type": "tool_result", tool_use_id: string, content: string, is_error: boolean, 
+/**
+ * Set by the server when `content` was truncated to a preview
+ */
+blob?: BlobRef | null, };
+
+export type Compression = "zstd";
+
+export type JsonValue = number | string | boolean | Array<JsonValue> | { [key in string]?: JsonValue } | null;
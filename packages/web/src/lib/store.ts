@@ -1,4 +1,5 @@
 import { create } from "zustand";
+import type { BlobRef, CliClientInfo, PaneType, PauseReason } from "./protocol";
 
 // UUID generator with fallback for environments without crypto.randomUUID
 function generateId(): string {
@@ -19,6 +20,7 @@ export interface Message {
   content: string;
   timestamp: Date;
   outputType?: OutputType;
+  blob?: BlobRef; // Full content when `content` is a truncated preview
 }
 
 export interface CliClient {
@@ -27,6 +29,11 @@ export interface CliClient {
   status: "online" | "offline" | "busy";
   lastSeen?: string;
   activeSession?: string;
+  // Connections with the same machineId are the same computer
+  machineId?: string;
+  hostname?: string;
+  os?: string;
+  claudeVersion?: string;
 }
 
 export interface SessionInfo {
@@ -48,9 +55,10 @@ export type OutputType =
   | { type: "tool_result"; tool: string; success: boolean }
   | { type: "approval_request"; toolCallId: string; tool: string; description: string }
   | { type: "system" }
+  | { type: "session_boundary"; summary?: string }
   | { type: "error" };
 
-export type PaneType = "deadloop" | "interactive";
+export type { PaneType };
 
 interface AppState {
   // Auth state
@@ -86,6 +94,9 @@ interface AppState {
 
   // Deadloop control
   isDeadloopPaused: boolean;
+  deadloopPauseReason: PauseReason | null;
+  // Workflow stage the deadloop is running, when `.apas` defines stages
+  workflowStage: { name: string; index: number; total: number } | null;
 
   // Auth actions
   login: (token: string, userId: string) => void;
@@ -112,9 +123,13 @@ interface AppState {
   stopAutoRefresh: () => void;
   pauseDeadloop: () => void;
   resumeDeadloop: () => void;
+  interruptPane: (paneType: PaneType) => void; // SIGINT the pane's running Claude turn
+  setModel: (paneType: PaneType, model: string | null) => void; // null restores the configured model
+  fetchBlob: (hash: string) => Promise<string>; // Load a full offloaded tool output
 }
 
 const WS_URL = process.env.NEXT_PUBLIC_WS_URL || "ws://apas.mpaxos.com:8080";
+const API_URL = process.env.NEXT_PUBLIC_API_URL || "http://apas.mpaxos.com:8080";
 
 export const useStore = create<AppState>((set, get) => ({
   // Auth state - initialize from localStorage if available
@@ -139,6 +154,8 @@ export const useStore = create<AppState>((set, get) => ({
   deadloopMessages: [],
   interactiveMessages: [],
   isDeadloopPaused: false,
+  deadloopPauseReason: null,
+  workflowStage: null,
 
   login: (token: string, userId: string) => {
     localStorage.setItem("apas_token", token);
@@ -549,8 +566,64 @@ export const useStore = create<AppState>((set, get) => ({
       ws.send(JSON.stringify({ type: "resume_deadloop" }));
     }
   },
+
+  interruptPane: (paneType: PaneType) => {
+    const { ws } = get();
+    if (ws && ws.readyState === WebSocket.OPEN) {
+      ws.send(JSON.stringify({ type: "signal", signal: "SIGINT", pane_type: paneType }));
+    }
+  },
+
+  setModel: (paneType: PaneType, model: string | null) => {
+    const { ws } = get();
+    if (ws && ws.readyState === WebSocket.OPEN) {
+      ws.send(JSON.stringify({ type: "set_model", pane_type: paneType, model }));
+    }
+  },
+
+  fetchBlob: async (hash: string) => {
+    const { token, sessionId } = get();
+    if (!token || !sessionId) {
+      throw new Error("Not attached to a session");
+    }
+    const response = await fetch(`${API_URL}/blobs/${sessionId}/${hash}`, {
+      headers: { Authorization: `Bearer ${token}` },
+    });
+    if (!response.ok) {
+      throw new Error(`Failed to load output (${response.status})`);
+    }
+    return response.text();
+  },
 }));
 
+// Message collecting the output of the command the deadloop is running
+// itself (verification or a hook), so each run shows as one block
+let commandOutput: { key: string; id: string } | null = null;
+
+function appendCommandOutput(
+  set: (partial: Partial<AppState> | ((state: AppState) => Partial<AppState>)) => void,
+  get: () => AppState,
+  key: string,
+  line: string
+) {
+  const { deadloopMessages } = get();
+  const last = deadloopMessages[deadloopMessages.length - 1];
+  if (last && commandOutput && commandOutput.key === key && last.id === commandOutput.id) {
+    const updated = { ...last, content: `${last.content}\n${line}` };
+    set({ deadloopMessages: [...deadloopMessages.slice(0, -1), updated] });
+    return;
+  }
+  commandOutput = { key, id: generateId() };
+  const message: Message = {
+    id: commandOutput.id,
+    role: "system",
+    content: key === "verification" ? line : `[${key}]\n${line}`,
+    timestamp: new Date(),
+    outputType: { type: "code" },
+  };
+  addMessageWithPaneRouting(set, get, message, "deadloop");
+}
+
 // Helper function to route messages to correct array based on pane type
 function addMessageWithPaneRouting(
   set: (partial: Partial<AppState> | ((state: AppState) => Partial<AppState>)) => void,
@@ -616,14 +689,18 @@ function handleServerMessage(
       break;
 
     case "cli_clients": {
-      const clients = (data.clients as Array<Record<string, unknown>>) || [];
+      const clients = (data.clients as CliClientInfo[]) || [];
       set({
         cliClients: clients.map((c) => ({
-          id: c.id as string,
-          name: c.name as string | undefined,
-          status: (c.status as "online" | "offline" | "busy") || "offline",
-          lastSeen: c.last_seen as string | undefined,
-          activeSession: c.active_session as string | undefined,
+          id: c.id,
+          name: c.name ?? undefined,
+          status: c.status || "offline",
+          lastSeen: c.last_seen ?? undefined,
+          activeSession: c.active_session ?? undefined,
+          machineId: c.machine?.machine_id,
+          hostname: c.machine?.hostname ?? undefined,
+          os: c.machine?.os ?? undefined,
+          claudeVersion: c.machine?.claude_version ?? undefined,
         })),
       });
       break;
@@ -724,6 +801,14 @@ function handleServerMessage(
           }
         } else if (messageType === "result" || messageType === "system") {
           outputType = { type: "system" };
+        } else if (messageType === "session_boundary") {
+          try {
+            const boundary = JSON.parse(content);
+            outputType = { type: "session_boundary", summary: boundary.summary ?? undefined };
+            displayContent = `New Claude session: ${boundary.reason}`;
+          } catch {
+            outputType = { type: "system" };
+          }
         } else {
           outputType = { type: "text" };
         }
@@ -734,6 +819,7 @@ function handleServerMessage(
           content: displayContent,
           timestamp: new Date(m.created_at as string || Date.now()),
           outputType,
+          blob: m.blob as BlobRef | undefined,
         };
       });
 
@@ -882,7 +968,58 @@ function handleServerMessage(
     case "deadloop_status": {
       const isPaused = data.is_paused as boolean;
       console.log("Deadloop status update:", isPaused ? "paused" : "running");
-      set({ isDeadloopPaused: isPaused });
+      const reason = isPaused ? ((data.reason as PauseReason | null) ?? null) : null;
+      set({ isDeadloopPaused: isPaused, deadloopPauseReason: reason });
+      break;
+    }
+
+    case "workflow_stage": {
+      set({
+        workflowStage: {
+          name: data.stage as string,
+          index: data.index as number,
+          total: data.total as number,
+        },
+      });
+      break;
+    }
+
+    case "verification_output":
+      appendCommandOutput(set, get, "verification", data.line as string);
+      break;
+
+    case "hook_output":
+      appendCommandOutput(set, get, `hook ${data.hook}`, data.line as string);
+      break;
+
+    case "claude_session_rotated": {
+      const message: Message = {
+        id: generateId(),
+        role: "system",
+        content: `New Claude session: ${data.reason}`,
+        timestamp: new Date(),
+        outputType: { type: "session_boundary", summary: (data.summary as string | null) ?? undefined },
+      };
+      addMessageWithPaneRouting(set, get, message, data.pane_type as string);
+      break;
+    }
+
+    case "verification_finished": {
+      commandOutput = null;
+      const seconds = Math.round((data.duration_ms as number) / 1000);
+      const outcome = data.passed
+        ? `passed in ${seconds}s`
+        : data.timed_out
+          ? `timed out after ${seconds}s`
+          : `failed (exit code ${data.exit_code ?? "none"})`;
+      const message: Message = {
+        id: generateId(),
+        role: "system",
+        content: `Verification \`${data.command}\` ${outcome}`,
+        timestamp: new Date(),
+        outputType: data.passed ? { type: "system" } : { type: "error" },
+      };
+      addMessageWithPaneRouting(set, get, message, "deadloop");
       break;
     }
 